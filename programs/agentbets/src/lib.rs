@@ -1,12 +1,121 @@
 use anchor_lang::prelude::*;
 use anchor_lang::system_program::{Transfer, transfer};
+use anchor_spl::associated_token::AssociatedToken;
+use anchor_spl::token::{self, Mint, Token, TokenAccount};
+use solana_sha256_hasher::hashv;
 
 declare_id!("FtNvaXJs5ZUbxPPq91XayvM4MauZyPgxJRrV16fGfn6H");
 
+// === Parimutuel Market Kinds ===
+pub const MARKET_KIND_CATEGORICAL: u8 = 0; // One outcome wins the whole pool
+pub const MARKET_KIND_SCALAR: u8 = 1; // Outcomes are buckets of a numeric range
+pub const SCALAR_WEIGHT_SCALE: u64 = 1_000_000_000; // Scalar bucket weights sum to this
+pub const DUST_SWEEP_DELAY: i64 = 30 * 24 * 60 * 60; // Claim window after resolution_time before sweep_dust
+pub const SELL_FEE_BPS: u64 = 100; // Exit fee on sell_shares, in bps of the amount sold
+
 // === CLOB Constants ===
-pub const MAX_ORDERS: usize = 50;
-pub const SHARE_PAYOUT: u64 = 10_000; // Lamports per share if wins
+pub const MAX_ORDERS: usize = 50; // Default order book capacity per side, and place_orders batch limit
+// Largest per-side capacity whose OrderBook can still be created in one instruction
+pub const MAX_ORDER_BOOK_CAPACITY: usize =
+    (anchor_lang::solana_program::entrypoint::MAX_PERMITTED_DATA_INCREASE - OrderBook::space(0)) / (2 * Order::INIT_SPACE);
+pub const SHARE_PAYOUT: u64 = 10_000; // Default payout_per_share of a CLOB market
 pub const BPS_MAX: u64 = 10_000;
+pub const VOID_PAYOUT_BPS: u64 = 5_000; // Each share is worth half a payout in a void market
+pub const MAX_RESOLVERS: usize = 7;
+pub const PRICE_HISTORY_LEN: usize = 32; // Fills kept per CLOB market's PriceHistory
+pub const MAX_REGISTRY_MARKETS: usize = 256; // Markets listed per authority's MarketRegistry
+pub const MAX_GROUP_MARKETS: usize = 16; // Markets one MarketGroup can link
+pub const EMERGENCY_WITHDRAW_DELAY: i64 = 180 * 24 * 60 * 60; // Wait after resolution_time before emergency_withdraw
+
+// === CLOB Matching Modes ===
+pub const MATCHING_MODE_FIFO: u8 = 0; // Strict price-time priority
+pub const MATCHING_MODE_PRO_RATA: u8 = 1; // Split each price level by size and age
+
+// === CLOB Order Types ===
+pub const ORDER_TYPE_LIMIT: u8 = 0; // Match up to the limit, rest the remainder
+pub const ORDER_TYPE_TAKE_THEN_MAKE: u8 = 1; // Only take price improvement, rest at the limit
+pub const ORDER_TYPE_IOC: u8 = 2; // Match up to the limit, refund the remainder
+pub const ORDER_TYPE_FOK: u8 = 3; // Match the full size immediately or revert
+pub const ORDER_TYPE_POST_ONLY: u8 = 4; // Rest without matching, revert if it would cross
+
+// === CLOB Oracle Comparisons (YES wins when `feed value <op> threshold`) ===
+pub const ORACLE_GREATER_THAN: u8 = 0;
+pub const ORACLE_GREATER_OR_EQUAL: u8 = 1;
+pub const ORACLE_LESS_THAN: u8 = 2;
+pub const ORACLE_LESS_OR_EQUAL: u8 = 3;
+
+// === Position Change Reasons (PositionChanged.reason) ===
+pub const POSITION_CHANGE_BUY: u8 = 0;
+pub const POSITION_CHANGE_FILL: u8 = 1; // A CLOB order matched on placement
+pub const POSITION_CHANGE_CLAIM: u8 = 2;
+pub const POSITION_CHANGE_REFUND: u8 = 3; // Void or cancelled market payout
+pub const POSITION_CHANGE_SELL: u8 = 4;
+pub const POSITION_CHANGE_TRANSFER: u8 = 5; // Shares moved to or from another wallet
+
+// === Market Status (get_market_state, get_clob_market_state) ===
+pub const MARKET_STATUS_OPEN: u8 = 0; // Taking buys or orders
+pub const MARKET_STATUS_PAUSED: u8 = 1; // Halted by the authority before resolution_time
+pub const MARKET_STATUS_AWAITING_RESOLUTION: u8 = 2; // Past resolution_time, no outcome yet
+pub const MARKET_STATUS_RESOLVED: u8 = 3;
+pub const MARKET_STATUS_VOID: u8 = 4; // Void or cancelled, stakes are refunded
+
+// === Market Horizon ===
+pub const MAX_MARKET_DURATION: i64 = 5 * 365 * 24 * 60 * 60; // Furthest resolution_time may be from now
+
+// === Resolution Metadata ===
+pub const MAX_RESOLUTION_URI_LEN: usize = 200; // Longest resolution_uri a resolver may record
+
+// === Account Layout Versions ===
+// Stored in the trailing `version` byte of Market, ClobMarket and OrderBook
+// and upgraded by migrate_market.
+//   0: created before versioning, with every field that precedes `version`
+//      already in place. The byte reads as 0 from the account's unused
+//      space, so these differ from version 1 only by lacking it.
+//   1: adds `version`.
+//   2: Market only, adds `outcome_weights` after `version`.
+//   3 (ClobMarket 2): adds `authority_cannot_trade`, appended last.
+//   ClobMarket 3: adds `allow_emergency`, appended last.
+//   ClobMarket 4: adds `insurance_balance`, appended last.
+//   4 (ClobMarket 5): adds `resolution_uri` and `resolution_source`,
+//      appended last.
+//   ClobMarket 6: adds `market_group`, appended last.
+//   ClobMarket 7: adds `yes_outstanding` and `no_outstanding`, appended
+//      last. Shares credited before it weren't counted per side, so
+//      migrate_market starts both at open_interest, which bounds them.
+//   ClobMarket 8: adds `closed`, appended last.
+//   5: Market only, adds `commit_phase_start`, `reveal_deadline` and
+//      `pending_commitments`, appended last.
+//   6: Market only, adds `fee_round_up`, appended last.
+//   OrderBook 2: adds `filled` to each Order. This widens orders already
+//      on the book, so migrate_market rewrites them, with filled = 0.
+pub const MARKET_VERSION: u8 = 6;
+pub const CLOB_MARKET_VERSION: u8 = 8;
+pub const ORDER_BOOK_VERSION: u8 = 2;
+
+// Accounts created by the original program are not version 0 and can't be
+// migrated. Market and ClobMarket kept `bump` right after created_at and
+// total_no_volume, before nearly every field added since; OrderBook had no
+// order_seq, size totals or capacity, and its orders no `expiry`. Their
+// sizes were fixed, so migrate_market recognises them by size and rejects
+// them rather than misread them.
+pub const ORIGINAL_MARKET_SPACE: usize = 8 + 32 + 36 + 260 + 4 + 10*36 + 4 + 10*8 + 8 + 8 + 1 + 2 + 8 + 1;
+pub const ORIGINAL_CLOB_MARKET_SPACE: usize = 8 + 32 + 36 + 260 + 8 + 1 + 2 + 8 + 8 + 8 + 1;
+pub const ORIGINAL_ORDER_BOOK_SPACE: usize = 8 + 32 + 2 * (4 + 50 * (32 + 8 + 8 + 8 + 8)) + 1;
+
+// === Market Addresses ===
+// Parimutuel and CLOB markets use different seed prefixes, so the same
+// market_id can name one market of each type. Within a type an id is taken
+// once: a second create fails with the system program's "already in use".
+
+/// PDA of the parimutuel market (lamport or token) created with this market_id
+pub fn market_address(market_id: &str) -> Pubkey {
+    Pubkey::find_program_address(&[b"market", market_id.as_bytes()], &crate::ID).0
+}
+
+/// PDA of the CLOB market (lamport or token) created with this market_id
+pub fn clob_market_address(market_id: &str) -> Pubkey {
+    Pubkey::find_program_address(&[b"clob_market", market_id.as_bytes()], &crate::ID).0
+}
 
 #[program]
 pub mod agentbets {
@@ -16,44 +125,79 @@ pub mod agentbets {
     // PARIMUTUEL INSTRUCTIONS (existing markets)
     // ===========================================
 
-    /// Create a new prediction market (parimutuel)
+    /// Create a new prediction market (parimutuel). Its address comes from
+    /// market_id, see market_address.
     pub fn create_market(
         ctx: Context<CreateMarket>,
         market_id: String,
         question: String,
         outcomes: Vec<String>,
         resolution_time: i64,
+        config: MarketConfig,
     ) -> Result<()> {
-        require!(outcomes.len() >= 2 && outcomes.len() <= 10, ErrorCode::InvalidOutcomeCount);
-        require!(market_id.len() <= 32, ErrorCode::MarketIdTooLong);
-        require!(question.len() <= 256, ErrorCode::QuestionTooLong);
-        
         let market = &mut ctx.accounts.market;
-        market.authority = ctx.accounts.authority.key();
-        market.market_id = market_id;
-        market.question = question;
-        market.outcomes = outcomes.clone();
-        market.outcome_pools = vec![0u64; outcomes.len()];
-        market.total_pool = 0;
-        market.resolution_time = resolution_time;
-        market.resolved = false;
-        market.winning_outcome = None;
-        market.created_at = Clock::get()?.unix_timestamp;
+        market.init(ctx.accounts.authority.key(), market_id, question, outcomes, resolution_time, &config)?;
+        market.mint = Pubkey::default();
         market.bump = ctx.bumps.market;
 
+        let registry = &mut ctx.accounts.registry;
+        registry.init_if_needed(market.authority, ctx.bumps.registry);
+        registry.add(market.key())?;
+
+        emit!(MarketCreated {
+            market: market.key(),
+            market_id: market.market_id.clone(),
+            authority: market.authority,
+            resolution_time,
+        });
+
         msg!("Market created: {}", market.question);
         Ok(())
     }
 
-    /// Buy shares in an outcome (parimutuel)
+    /// Create a parimutuel market whose stakes and payouts are in an SPL
+    /// token held by the market's associated token account
+    pub fn create_token_market(
+        ctx: Context<CreateTokenMarket>,
+        market_id: String,
+        question: String,
+        outcomes: Vec<String>,
+        resolution_time: i64,
+        config: MarketConfig,
+    ) -> Result<()> {
+        let market = &mut ctx.accounts.market;
+        market.init(ctx.accounts.authority.key(), market_id, question, outcomes, resolution_time, &config)?;
+        // Commitments are funded in lamports
+        require!(config.commit_phase_start == 0, ErrorCode::CollateralMismatch);
+        market.mint = ctx.accounts.mint.key();
+        market.bump = ctx.bumps.market;
+
+        let registry = &mut ctx.accounts.registry;
+        registry.init_if_needed(market.authority, ctx.bumps.registry);
+        registry.add(market.key())?;
+
+        emit!(MarketCreated {
+            market: market.key(),
+            market_id: market.market_id.clone(),
+            authority: market.authority,
+            resolution_time,
+        });
+
+        msg!("Token market created: {} (mint {})", market.question, market.mint);
+        Ok(())
+    }
+
+    /// Buy shares in an outcome (parimutuel). The first referrer given is
+    /// kept on the position and earns referral_fee_bps of its claim fee;
+    /// its ReferralAccount must then be passed to the claim.
     pub fn buy_shares(
         ctx: Context<BuyShares>,
         outcome_index: u8,
         amount: u64,
+        referrer: Option<Pubkey>,
     ) -> Result<()> {
         let market = &mut ctx.accounts.market;
-        require!(!market.resolved, ErrorCode::MarketResolved);
-        require!((outcome_index as usize) < market.outcomes.len(), ErrorCode::InvalidOutcome);
+        market.record_buy(ctx.accounts.buyer.key(), outcome_index, amount, Clock::get()?.unix_timestamp)?;
         
         let cpi_context = CpiContext::new(
             ctx.accounts.system_program.to_account_info(),
@@ -65,66 +209,604 @@ pub mod agentbets {
         anchor_lang::system_program::transfer(cpi_context, amount)?;
 
         let shares = amount;
-        market.outcome_pools[outcome_index as usize] += shares;
-        market.total_pool += amount;
+        let position = &mut ctx.accounts.position;
+        position.init_if_needed(ctx.accounts.buyer.key(), market, ctx.bumps.position);
+        position.record_stake(amount, market.max_trader_notional)?;
+        position.record_referrer(referrer)?;
+        position.shares[outcome_index as usize] = position.shares[outcome_index as usize]
+            .checked_add(shares)
+            .ok_or(ErrorCode::Overflow)?;
+
+        position.emit_changed(POSITION_CHANGE_BUY);
+
+        let realized_pnl = &mut ctx.accounts.realized_pnl;
+        realized_pnl.init_if_needed(ctx.accounts.buyer.key(), ctx.bumps.realized_pnl);
+        realized_pnl.record_stake(amount);
+
+        msg!("Bought {} shares of outcome {}", shares, outcome_index);
+        Ok(())
+    }
+
+    /// Buy shares in an outcome of a token market (parimutuel)
+    pub fn buy_shares_token(
+        ctx: Context<BuySharesToken>,
+        outcome_index: u8,
+        amount: u64,
+    ) -> Result<()> {
+        let market = &mut ctx.accounts.market;
+        market.record_buy(ctx.accounts.buyer.key(), outcome_index, amount, Clock::get()?.unix_timestamp)?;
+        
+        let cpi_context = CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            token::Transfer {
+                from: ctx.accounts.buyer_token_account.to_account_info(),
+                to: ctx.accounts.vault.to_account_info(),
+                authority: ctx.accounts.buyer.to_account_info(),
+            },
+        );
+        token::transfer(cpi_context, amount)?;
 
+        let shares = amount;
         let position = &mut ctx.accounts.position;
-        if position.shares.is_empty() {
-            position.owner = ctx.accounts.buyer.key();
-            position.market = market.key();
-            position.shares = vec![0u64; market.outcomes.len()];
-            position.bump = ctx.bumps.position;
-        }
-        position.shares[outcome_index as usize] += shares;
+        position.init_if_needed(ctx.accounts.buyer.key(), market, ctx.bumps.position);
+        position.record_stake(amount, market.max_trader_notional)?;
+        position.shares[outcome_index as usize] = position.shares[outcome_index as usize]
+            .checked_add(shares)
+            .ok_or(ErrorCode::Overflow)?;
+        position.emit_changed(POSITION_CHANGE_BUY);
 
         msg!("Bought {} shares of outcome {}", shares, outcome_index);
         Ok(())
     }
 
-    /// Resolve market with winning outcome (parimutuel)
+    /// Buy during the commit phase without revealing the outcome: fund
+    /// `amount` now and store `commitment`, the SHA-256 of the outcome
+    /// index, `amount` as 8 little-endian bytes and a 32-byte nonce. One
+    /// commitment per buyer is open at a time.
+    pub fn commit_buy(ctx: Context<CommitBuy>, commitment: [u8; 32], amount: u64) -> Result<()> {
+        let market = &mut ctx.accounts.market;
+        let now = Clock::get()?.unix_timestamp;
+        market.check_buy_open(ctx.accounts.buyer.key(), now)?;
+        require!(market.is_commit_phase_at(now), ErrorCode::NotInCommitPhase);
+        require!(amount > 0, ErrorCode::InvalidSize);
+        require!(amount >= market.min_bet, ErrorCode::BelowMinimumBet);
+        // The stake counts toward max_trader_notional now, so the reveal
+        // can't later be refused for it
+        let position = &mut ctx.accounts.position;
+        position.init_if_needed(ctx.accounts.buyer.key(), market, ctx.bumps.position);
+        position.record_stake(amount, market.max_trader_notional)?;
+        let pending = market.pending_commitments.checked_add(amount).ok_or(ErrorCode::Overflow)?;
+        if market.max_total_pool > 0 {
+            require!(market.total_pool.saturating_add(pending) <= market.max_total_pool, ErrorCode::PoolCapExceeded);
+        }
+        market.pending_commitments = pending;
+        
+        let cpi_context = CpiContext::new(
+            ctx.accounts.system_program.to_account_info(),
+            anchor_lang::system_program::Transfer {
+                from: ctx.accounts.buyer.to_account_info(),
+                to: market.to_account_info(),
+            },
+        );
+        anchor_lang::system_program::transfer(cpi_context, amount)?;
+        
+        let buy_commitment = &mut ctx.accounts.commitment;
+        buy_commitment.market = market.key();
+        buy_commitment.buyer = ctx.accounts.buyer.key();
+        buy_commitment.commitment = commitment;
+        buy_commitment.amount = amount;
+        buy_commitment.bump = ctx.bumps.commitment;
+        
+        msg!("Committed {} lamports", amount);
+        Ok(())
+    }
+
+    /// Reveal a commitment between resolution_time and reveal_deadline and
+    /// credit its shares as a buy_shares would have. The stake was already
+    /// counted toward max_trader_notional by commit_buy.
+    pub fn reveal_buy(ctx: Context<RevealBuy>, outcome_index: u8, nonce: [u8; 32]) -> Result<()> {
+        let market = &mut ctx.accounts.market;
+        let now = Clock::get()?.unix_timestamp;
+        require!(!market.cancelled, ErrorCode::MarketCancelled);
+        require!(
+            now >= market.resolution_time && now < market.reveal_deadline,
+            ErrorCode::NotInRevealPhase
+        );
+        let amount = ctx.accounts.commitment.amount;
+        let hash = hashv(&[&[outcome_index][..], &amount.to_le_bytes()[..], &nonce[..]]);
+        require!(hash.to_bytes() == ctx.accounts.commitment.commitment, ErrorCode::CommitmentMismatch);
+        
+        market.pending_commitments = market.pending_commitments
+            .checked_sub(amount)
+            .ok_or(ErrorCode::Overflow)?;
+        market.add_to_pool(outcome_index, amount)?;
+        
+        let position = &mut ctx.accounts.position;
+        position.init_if_needed(ctx.accounts.buyer.key(), market, ctx.bumps.position);
+        position.shares[outcome_index as usize] = position.shares[outcome_index as usize]
+            .checked_add(amount)
+            .ok_or(ErrorCode::Overflow)?;
+        position.emit_changed(POSITION_CHANGE_BUY);
+        
+        let realized_pnl = &mut ctx.accounts.realized_pnl;
+        realized_pnl.init_if_needed(ctx.accounts.buyer.key(), ctx.bumps.realized_pnl);
+        realized_pnl.record_stake(amount);
+        
+        msg!("Revealed {} shares of outcome {}", amount, outcome_index);
+        Ok(())
+    }
+
+    /// Return an unrevealed commitment's lamports once the market is
+    /// cancelled or void
+    pub fn refund_commitment(ctx: Context<RefundCommitment>) -> Result<()> {
+        let market = &mut ctx.accounts.market;
+        require!(market.is_refundable_at(Clock::get()?.unix_timestamp), ErrorCode::MarketNotCancelled);
+        let amount = ctx.accounts.commitment.amount;
+        market.pending_commitments = market.pending_commitments
+            .checked_sub(amount)
+            .ok_or(ErrorCode::Overflow)?;
+        
+        pay_from_market(&market.to_account_info(), &ctx.accounts.buyer.to_account_info(), amount)?;
+        
+        msg!("Refunded a {} lamport commitment", amount);
+        Ok(())
+    }
+
+    /// Forfeit a commitment left unrevealed past reveal_deadline once the
+    /// market is resolved. Its lamports stay in the market for sweep_dust and
+    /// its stake no longer counts toward the buyer's max_trader_notional.
+    /// Refunding it instead would let a buyer commit to every outcome and
+    /// reveal only the winner.
+    pub fn forfeit_commitment(ctx: Context<ForfeitCommitment>) -> Result<()> {
+        let market = &mut ctx.accounts.market;
+        require!(ctx.accounts.authority.key() == market.authority, ErrorCode::Unauthorized);
+        require!(market.resolved, ErrorCode::MarketNotResolved);
+        let amount = ctx.accounts.commitment.amount;
+        market.pending_commitments = market.pending_commitments
+            .checked_sub(amount)
+            .ok_or(ErrorCode::Overflow)?;
+        
+        let position = &mut ctx.accounts.position;
+        position.staked = position.staked.checked_sub(amount).ok_or(ErrorCode::Overflow)?;
+        
+        msg!("Forfeited a {} lamport commitment", amount);
+        Ok(())
+    }
+
+    /// Move shares of one outcome to another wallet's position, creating it
+    /// if needed (parimutuel). Pools are untouched, and the sender keeps
+    /// their stake and referrer.
+    pub fn transfer_position(
+        ctx: Context<TransferPosition>,
+        new_owner: Pubkey,
+        outcome_index: u8,
+        amount: u64,
+    ) -> Result<()> {
+        require!(new_owner != ctx.accounts.owner.key(), ErrorCode::InvalidRecipient);
+        let market = &ctx.accounts.market;
+        require!((outcome_index as usize) < market.outcomes.len(), ErrorCode::InvalidOutcome);
+        require!(amount > 0, ErrorCode::InvalidSize);
+        
+        let source = &mut ctx.accounts.source;
+        require!(source.market == market.key(), ErrorCode::PositionMarketMismatch);
+        let destination = &mut ctx.accounts.destination;
+        destination.init_if_needed(new_owner, market, ctx.bumps.destination);
+        require!(destination.market == market.key(), ErrorCode::PositionMarketMismatch);
+        
+        let sent = &mut source.shares[outcome_index as usize];
+        *sent = sent.checked_sub(amount).ok_or(ErrorCode::InsufficientShares)?;
+        let received = &mut destination.shares[outcome_index as usize];
+        *received = received.checked_add(amount).ok_or(ErrorCode::Overflow)?;
+        source.emit_changed(POSITION_CHANGE_TRANSFER);
+        destination.emit_changed(POSITION_CHANGE_TRANSFER);
+        
+        msg!("Transferred {} shares of outcome {} to {}", amount, outcome_index, new_owner);
+        Ok(())
+    }
+
+    /// Sell shares of an outcome back to the pool before resolution
+    /// (parimutuel). Returns the stake behind them less the SELL_FEE_BPS
+    /// exit fee.
+    pub fn sell_shares(
+        ctx: Context<ClaimWinnings>,
+        outcome_index: u8,
+        amount: u64,
+    ) -> Result<()> {
+        let payout = ctx.accounts.market.record_sell(
+            &mut ctx.accounts.position,
+            outcome_index,
+            amount,
+            Clock::get()?.unix_timestamp,
+        )?;
+        ctx.accounts.position.emit_changed(POSITION_CHANGE_SELL);
+
+        pay_from_market(
+            &ctx.accounts.market.to_account_info(),
+            &ctx.accounts.claimer.to_account_info(),
+            payout,
+        )?;
+
+        let realized_pnl = &mut ctx.accounts.realized_pnl;
+        realized_pnl.init_if_needed(ctx.accounts.claimer.key(), ctx.bumps.realized_pnl);
+        realized_pnl.record_refund(payout);
+
+        msg!("Sold {} shares of outcome {} for {} lamports", amount, outcome_index, payout);
+        Ok(())
+    }
+
+    /// Sell shares of an outcome of a token market back to the pool (parimutuel)
+    pub fn sell_shares_token(
+        ctx: Context<ClaimWinningsToken>,
+        outcome_index: u8,
+        amount: u64,
+    ) -> Result<()> {
+        let payout = ctx.accounts.market.record_sell(
+            &mut ctx.accounts.position,
+            outcome_index,
+            amount,
+            Clock::get()?.unix_timestamp,
+        )?;
+        ctx.accounts.position.emit_changed(POSITION_CHANGE_SELL);
+
+        pay_from_token_vault(
+            &ctx.accounts.market,
+            &ctx.accounts.vault,
+            &ctx.accounts.claimer_token_account,
+            &ctx.accounts.token_program,
+            payout,
+        )?;
+
+        msg!("Sold {} shares of outcome {} for {} tokens", amount, outcome_index, payout);
+        Ok(())
+    }
+
+    /// Resolve market with winning outcome (parimutuel). Waits for
+    /// resolution_time unless the market was created with early_resolution.
     pub fn resolve_market(
         ctx: Context<ResolveMarket>,
         winning_outcome: u8,
+        resolution_uri: Option<String>,
+        resolution_source: Option<Pubkey>,
     ) -> Result<()> {
         let market = &mut ctx.accounts.market;
         require!(!market.resolved, ErrorCode::MarketAlreadyResolved);
         require!(ctx.accounts.authority.key() == market.authority, ErrorCode::Unauthorized);
+        require!(!market.cancelled, ErrorCode::MarketCancelled);
+        let now = Clock::get()?.unix_timestamp;
+        require!(!market.is_void_at(now), ErrorCode::MarketVoid);
         require!((winning_outcome as usize) < market.outcomes.len(), ErrorCode::InvalidOutcome);
+        require!(market.market_kind == MARKET_KIND_CATEGORICAL, ErrorCode::WrongMarketKind);
+        require!(market.early_resolution || now >= market.resolution_time, ErrorCode::ResolutionTimeNotReached);
+        require!(now >= market.reveal_deadline, ErrorCode::RevealPhaseActive);
+
+        let resolution_uri = resolution_uri.unwrap_or_default();
+        require!(resolution_uri.len() <= MAX_RESOLUTION_URI_LEN, ErrorCode::ResolutionUriTooLong);
 
         market.resolved = true;
         market.winning_outcome = Some(winning_outcome);
+        market.resolution_uri = resolution_uri;
+        market.resolution_source = resolution_source.unwrap_or_default();
+
+        emit!(MarketResolved {
+            market: market.key(),
+            market_id: market.market_id.clone(),
+            authority: market.authority,
+            winning_outcome,
+            resolved_at: now,
+            resolution_uri: market.resolution_uri.clone(),
+            resolution_source: market.resolution_source,
+        });
 
         msg!("Market resolved: outcome {} wins", winning_outcome);
         Ok(())
     }
 
+    /// Resolve a scalar market with the value the question settled at. The
+    /// pool is split between the outcome buckets nearest to it, see
+    /// Market::scalar_weights.
+    pub fn resolve_scalar_market(
+        ctx: Context<ResolveMarket>,
+        settlement_value: i64,
+    ) -> Result<()> {
+        let market = &mut ctx.accounts.market;
+        require!(!market.resolved, ErrorCode::MarketAlreadyResolved);
+        require!(ctx.accounts.authority.key() == market.authority, ErrorCode::Unauthorized);
+        require!(!market.cancelled, ErrorCode::MarketCancelled);
+        let now = Clock::get()?.unix_timestamp;
+        require!(!market.is_void_at(now), ErrorCode::MarketVoid);
+        require!(market.market_kind == MARKET_KIND_SCALAR, ErrorCode::WrongMarketKind);
+        require!(market.early_resolution || now >= market.resolution_time, ErrorCode::ResolutionTimeNotReached);
+        require!(now >= market.reveal_deadline, ErrorCode::RevealPhaseActive);
+
+        market.resolved = true;
+        market.settlement_value = settlement_value;
+
+        emit!(ScalarMarketResolved {
+            market: market.key(),
+            market_id: market.market_id.clone(),
+            authority: market.authority,
+            settlement_value,
+            resolved_at: now,
+        });
+
+        msg!("Scalar market resolved at {}", settlement_value);
+        Ok(())
+    }
+
+    /// Push an unresolved market's resolution_time later (parimutuel).
+    /// Trading stays open until the new time.
+    pub fn update_resolution_time(
+        ctx: Context<ResolveMarket>,
+        new_resolution_time: i64,
+    ) -> Result<()> {
+        let market = &mut ctx.accounts.market;
+        require!(!market.resolved, ErrorCode::MarketAlreadyResolved);
+        require!(ctx.accounts.authority.key() == market.authority, ErrorCode::Unauthorized);
+        require!(!market.cancelled, ErrorCode::MarketCancelled);
+        // A void market already refunds everyone; extending it can't undo that
+        let now = Clock::get()?.unix_timestamp;
+        require!(!market.is_void_at(now), ErrorCode::MarketVoid);
+        require!(new_resolution_time > market.resolution_time, ErrorCode::InvalidResolutionTime);
+        require!(
+            new_resolution_time.saturating_sub(now) <= MAX_MARKET_DURATION,
+            ErrorCode::ResolutionTimeTooFar
+        );
+
+        let previous_resolution_time = market.resolution_time;
+        market.resolution_time = new_resolution_time;
+
+        emit!(ResolutionTimeUpdated {
+            market: market.key(),
+            previous_resolution_time,
+            resolution_time: new_resolution_time,
+        });
+
+        msg!("Resolution time moved to {}", new_resolution_time);
+        Ok(())
+    }
+
+    /// Propose a new authority (parimutuel). Nothing changes until the
+    /// proposed key signs accept_authority; proposing again replaces it.
+    pub fn propose_authority(ctx: Context<ResolveMarket>, new_authority: Pubkey) -> Result<()> {
+        let market = &mut ctx.accounts.market;
+        require!(ctx.accounts.authority.key() == market.authority, ErrorCode::Unauthorized);
+        
+        market.pending_authority = Some(new_authority);
+        msg!("Proposed authority {}", new_authority);
+        Ok(())
+    }
+
+    /// Complete an authority transfer, signed by the proposed authority
+    pub fn accept_authority(ctx: Context<AcceptAuthority>) -> Result<()> {
+        let market = &mut ctx.accounts.market;
+        let new_authority = ctx.accounts.new_authority.key();
+        require!(market.pending_authority == Some(new_authority), ErrorCode::NotPendingAuthority);
+        
+        let previous_authority = market.authority;
+        market.authority = new_authority;
+        market.pending_authority = None;
+        
+        emit!(AuthorityTransferred {
+            market: market.key(),
+            previous_authority,
+            new_authority,
+        });
+        
+        msg!("Authority transferred to {}", new_authority);
+        Ok(())
+    }
+
+    /// Halt new buys without resolving the market (parimutuel). Resolution,
+    /// claims and refunds keep working so users can still exit.
+    pub fn pause_market(ctx: Context<ResolveMarket>) -> Result<()> {
+        set_market_paused(ctx, true)
+    }
+
+    /// Reopen a paused market to buys (parimutuel)
+    pub fn unpause_market(ctx: Context<ResolveMarket>) -> Result<()> {
+        set_market_paused(ctx, false)
+    }
+
+    /// Cancel an unresolved market (parimutuel). Trading stops and every
+    /// position can take back its full stake with claim_refund.
+    pub fn cancel_market(ctx: Context<ResolveMarket>) -> Result<()> {
+        let market = &mut ctx.accounts.market;
+        require!(!market.resolved, ErrorCode::MarketAlreadyResolved);
+        require!(!market.cancelled, ErrorCode::MarketCancelled);
+        require!(ctx.accounts.authority.key() == market.authority, ErrorCode::Unauthorized);
+
+        market.cancelled = true;
+
+        emit!(MarketCancelled {
+            market: market.key(),
+            market_id: market.market_id.clone(),
+            authority: market.authority,
+            cancelled_at: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Market cancelled");
+        Ok(())
+    }
+
+    /// Refund a position's full stake across all outcomes from a cancelled
+    /// market, or one abandoned unresolved past its max_resolution_delay,
+    /// with no claim fee (parimutuel)
+    pub fn claim_refund(ctx: Context<ClaimWinnings>) -> Result<()> {
+        require!(
+            ctx.accounts.market.is_refundable_at(Clock::get()?.unix_timestamp),
+            ErrorCode::MarketNotCancelled
+        );
+        let refund = ctx.accounts.market.settle_refund(&mut ctx.accounts.position)?;
+        ctx.accounts.position.emit_changed(POSITION_CHANGE_REFUND);
+
+        pay_from_market(
+            &ctx.accounts.market.to_account_info(),
+            &ctx.accounts.claimer.to_account_info(),
+            refund,
+        )?;
+
+        let realized_pnl = &mut ctx.accounts.realized_pnl;
+        realized_pnl.init_if_needed(ctx.accounts.claimer.key(), ctx.bumps.realized_pnl);
+        realized_pnl.record_refund(refund);
+
+        msg!("Market cancelled or void, refunded {} lamports", refund);
+        Ok(())
+    }
+
     /// Claim winnings after resolution (parimutuel)
+    ///
+    /// If the market was never resolved within its max_resolution_delay it
+    /// is treated as void and the position's full stake is refunded instead,
+    /// as it is for a cancelled market.
     pub fn claim_winnings(ctx: Context<ClaimWinnings>) -> Result<()> {
-        let market = &ctx.accounts.market;
-        let position = &mut ctx.accounts.position;
-        
-        require!(market.resolved, ErrorCode::MarketNotResolved);
+        let realized_pnl = &mut ctx.accounts.realized_pnl;
+        realized_pnl.init_if_needed(ctx.accounts.claimer.key(), ctx.bumps.realized_pnl);
+        claim_lamports(
+            &mut ctx.accounts.market,
+            &mut ctx.accounts.position,
+            &ctx.accounts.claimer,
+            realized_pnl,
+            ctx.remaining_accounts,
+        )
+    }
+
+    /// claim_winnings, then close the settled position and return its rent,
+    /// in one transaction (parimutuel). A claim always takes every claimable
+    /// share, so the position is settled once it succeeds.
+    pub fn claim_and_close(ctx: Context<ClaimAndClose>) -> Result<()> {
+        let realized_pnl = &mut ctx.accounts.realized_pnl;
+        realized_pnl.init_if_needed(ctx.accounts.claimer.key(), ctx.bumps.realized_pnl);
+        claim_lamports(
+            &mut ctx.accounts.market,
+            &mut ctx.accounts.position,
+            &ctx.accounts.claimer,
+            realized_pnl,
+            ctx.remaining_accounts,
+        )?;
         
-        let winning_outcome = market.winning_outcome.unwrap() as usize;
-        let winner_shares = position.shares[winning_outcome];
-        require!(winner_shares > 0, ErrorCode::NoWinningShares);
+        let now = Clock::get()?.unix_timestamp;
+        require!(ctx.accounts.market.is_settled(&ctx.accounts.position, now)?, ErrorCode::PositionNotSettled);
+        msg!("Position closed");
+        Ok(())
+    }
 
-        let total_winning_shares = market.outcome_pools[winning_outcome];
-        let payout = (winner_shares as u128)
-            .checked_mul(market.total_pool as u128)
-            .unwrap()
-            .checked_div(total_winning_shares as u128)
-            .unwrap() as u64;
+    /// Claim winnings, or a void refund, from a token market (parimutuel)
+    pub fn claim_winnings_token(ctx: Context<ClaimWinningsToken>) -> Result<()> {
+        require!(
+            ctx.accounts.position.market == ctx.accounts.market.key(),
+            ErrorCode::PositionMarketMismatch
+        );
+        let claim = ctx.accounts.market.settle_claim(
+            &mut ctx.accounts.position,
+            Clock::get()?.unix_timestamp,
+        )?;
+        ctx.accounts.position.emit_changed(if claim.void_refund {
+            POSITION_CHANGE_REFUND
+        } else {
+            POSITION_CHANGE_CLAIM
+        });
 
-        let fee = payout / 50; // 2%
-        let net_payout = payout - fee;
+        pay_from_token_vault(
+            &ctx.accounts.market,
+            &ctx.accounts.vault,
+            &ctx.accounts.claimer_token_account,
+            &ctx.accounts.token_program,
+            claim.payout,
+        )?;
 
-        position.shares[winning_outcome] = 0;
+        if claim.void_refund {
+            msg!("Market void, refunded {} tokens", claim.payout);
+        } else {
+            msg!("Claimed {} tokens (fee: {})", claim.payout, claim.fee);
+        }
+        Ok(())
+    }
+
+    /// Close a settled position and return its rent to the owner. Allowed once
+    /// the market is resolved and the winning shares are claimed, or once a
+    /// void or cancelled market's refund has been claimed.
+    pub fn close_position(ctx: Context<ClosePosition>) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        require!(ctx.accounts.market.is_settled(&ctx.accounts.position, now)?, ErrorCode::PositionNotSettled);
+        
+        msg!("Position closed");
+        Ok(())
+    }
+
+    /// Withdraw accrued claim fees to a destination account (parimutuel)
+    pub fn withdraw_fees(ctx: Context<WithdrawFees>) -> Result<()> {
+        let market = &ctx.accounts.market;
+        require!(ctx.accounts.authority.key() == market.authority, ErrorCode::Unauthorized);
+        require!(market.fee_collected > 0, ErrorCode::NoFeesToWithdraw);
+        
+        let market_info = market.to_account_info();
+        let rent_minimum = Rent::get()?.minimum_balance(market_info.data_len());
+        let amount = market.withdrawable_fees(market_info.lamports().saturating_sub(rent_minimum));
+        require!(amount > 0, ErrorCode::NoFeesToWithdraw);
+        
+        pay_from_market(&market_info, &ctx.accounts.destination, amount)?;
+        
+        let market = &mut ctx.accounts.market;
+        market.fee_collected -= amount;
+        
+        msg!("Withdrew {} lamports of fees", amount);
+        Ok(())
+    }
 
-        **ctx.accounts.market.to_account_info().try_borrow_mut_lamports()? -= net_payout;
-        **ctx.accounts.claimer.to_account_info().try_borrow_mut_lamports()? += net_payout;
+    /// Withdraw accrued claim fees from a token market's vault (parimutuel)
+    pub fn withdraw_fees_token(ctx: Context<WithdrawFeesToken>) -> Result<()> {
+        let market = &ctx.accounts.market;
+        require!(ctx.accounts.authority.key() == market.authority, ErrorCode::Unauthorized);
+        require!(market.fee_collected > 0, ErrorCode::NoFeesToWithdraw);
+        
+        let amount = market.withdrawable_fees(ctx.accounts.vault.amount);
+        require!(amount > 0, ErrorCode::NoFeesToWithdraw);
+        
+        pay_from_token_vault(
+            market,
+            &ctx.accounts.vault,
+            &ctx.accounts.destination,
+            &ctx.accounts.token_program,
+            amount,
+        )?;
+        
+        let market = &mut ctx.accounts.market;
+        market.fee_collected -= amount;
+        
+        msg!("Withdrew {} tokens of fees", amount);
+        Ok(())
+    }
 
-        msg!("Claimed {} lamports (fee: {})", net_payout, fee);
+    /// Sweep lamports a resolved market holds beyond its rent, unwithdrawn
+    /// fees and what unpaid winners are still owed to a treasury (parimutuel).
+    /// Runs once every winning share is claimed, or after DUST_SWEEP_DELAY
+    /// past resolution_time. A market nobody backed the winner of owes no
+    /// one, so its whole pool is swept.
+    pub fn sweep_dust(ctx: Context<SweepDust>) -> Result<()> {
+        let market = &ctx.accounts.market;
+        require!(ctx.accounts.authority.key() == market.authority, ErrorCode::Unauthorized);
+        require!(market.resolved, ErrorCode::MarketNotResolved);
+        let all_claimed = market.all_winners_claimed()?;
+        let now = Clock::get()?.unix_timestamp;
+        require!(
+            all_claimed || now >= market.resolution_time.saturating_add(DUST_SWEEP_DELAY),
+            ErrorCode::ClaimWindowOpen
+        );
+        
+        let owed = if all_claimed { 0 } else { market.total_pool.saturating_sub(market.total_claimed) };
+        let market_info = market.to_account_info();
+        let rent_minimum = Rent::get()?.minimum_balance(market_info.data_len());
+        let amount = market_info
+            .lamports()
+            .saturating_sub(rent_minimum)
+            .saturating_sub(owed)
+            .saturating_sub(market.fee_collected)
+            .saturating_sub(market.pending_commitments);
+        require!(amount > 0, ErrorCode::NoDustToSweep);
+        
+        pay_from_market(&market_info, &ctx.accounts.treasury, amount)?;
+        
+        msg!("Swept {} lamports of dust", amount);
         Ok(())
     }
 
@@ -132,38 +814,49 @@ pub mod agentbets {
     // CLOB INSTRUCTIONS (new order book markets)
     // ===========================================
 
-    /// Create a CLOB market with order book
+    /// Create a CLOB market with order book. Its address comes from
+    /// market_id, see clob_market_address.
     pub fn create_clob_market(
         ctx: Context<CreateClobMarket>,
         market_id: String,
         question: String,
         resolution_time: i64,
+        config: ClobMarketConfig,
     ) -> Result<()> {
-        require!(market_id.len() <= 32, ClobError::MarketIdTooLong);
-        require!(question.len() <= 256, ClobError::QuestionTooLong);
+        // Vault just holds lamports, doesn't need account data. It keeps the
+        // rent-exempt minimum plus an optional buffer that payouts never touch.
+        let rent = Rent::get()?;
+        let vault_reserve = rent.minimum_balance(0)
+            .checked_add(config.vault_rent_buffer)
+            .ok_or(ClobError::Overflow)?;
         
         let market = &mut ctx.accounts.market;
-        market.authority = ctx.accounts.authority.key();
-        market.market_id = market_id;
-        market.question = question;
-        market.resolution_time = resolution_time;
-        market.resolved = false;
-        market.winning_side = None;
-        market.created_at = Clock::get()?.unix_timestamp;
-        market.total_yes_volume = 0;
-        market.total_no_volume = 0;
+        market.init(ctx.accounts.authority.key(), market_id, question, resolution_time, &config)?;
+        market.vault_reserve = vault_reserve;
+        market.mint = Pubkey::default();
         market.bump = ctx.bumps.market;
 
-        let order_book = &mut ctx.accounts.order_book;
-        order_book.market = market.key();
-        order_book.yes_bids = Vec::new();
-        order_book.yes_asks = Vec::new();
-        order_book.bump = ctx.bumps.order_book;
+        ctx.accounts.order_book.init(market.key(), config.order_book_capacity(), ctx.bumps.order_book);
+        ctx.accounts.price_history.init(market.key(), ctx.bumps.price_history);
+
+        let registry = &mut ctx.accounts.registry;
+        registry.init_if_needed(market.authority, ctx.bumps.registry);
+        registry.add(market.key())?;
+
+        // The authority's position exists from the start so seed orders can
+        // be filled on markets that pay maker rebates
+        ctx.accounts.position.init_if_needed(market.authority, market.key(), ctx.bumps.position);
+        let seed_collateral = match &config.seed_liquidity {
+            Some(seed) => {
+                let collateral = seed_order_book(market, &mut ctx.accounts.order_book, seed)?;
+                let realized_pnl = &mut ctx.accounts.realized_pnl;
+                realized_pnl.init_if_needed(market.authority, ctx.bumps.realized_pnl);
+                realized_pnl.record_stake(collateral);
+                collateral
+            }
+            None => 0,
+        };
 
-        // Fund the vault with minimum rent-exempt balance
-        // Vault just holds lamports, doesn't need account data
-        let rent = Rent::get()?;
-        let vault_lamports = rent.minimum_balance(0);
         let cpi_ctx = CpiContext::new(
             ctx.accounts.system_program.to_account_info(),
             Transfer {
@@ -171,480 +864,5773 @@ pub mod agentbets {
                 to: ctx.accounts.vault.to_account_info(),
             },
         );
-        transfer(cpi_ctx, vault_lamports)?;
+        transfer(cpi_ctx, vault_reserve.checked_add(seed_collateral).ok_or(ClobError::Overflow)?)?;
+
+        emit!(ClobMarketCreated {
+            market: market.key(),
+            market_id: market.market_id.clone(),
+            authority: market.authority,
+            resolution_time,
+        });
 
         msg!("CLOB Market created: {}", market.question);
         Ok(())
     }
 
+    /// Create a CLOB market whose collateral and payouts are in an SPL token
+    /// held by the market's associated token account
+    pub fn create_token_clob_market(
+        ctx: Context<CreateTokenClobMarket>,
+        market_id: String,
+        question: String,
+        resolution_time: i64,
+        config: ClobMarketConfig,
+    ) -> Result<()> {
+        let market = &mut ctx.accounts.market;
+        market.init(ctx.accounts.authority.key(), market_id, question, resolution_time, &config)?;
+        // Seed orders and emergency withdrawals are in lamports, so only
+        // lamport markets take them
+        require!(
+            config.seed_liquidity.is_none() && !config.allow_emergency,
+            ClobError::CollateralMismatch
+        );
+        market.vault_reserve = 0; // Token balances carry no rent to protect
+        market.mint = ctx.accounts.mint.key();
+        market.bump = ctx.bumps.market;
+
+        ctx.accounts.order_book.init(market.key(), config.order_book_capacity(), ctx.bumps.order_book);
+        ctx.accounts.price_history.init(market.key(), ctx.bumps.price_history);
+
+        let registry = &mut ctx.accounts.registry;
+        registry.init_if_needed(market.authority, ctx.bumps.registry);
+        registry.add(market.key())?;
+
+        emit!(ClobMarketCreated {
+            market: market.key(),
+            market_id: market.market_id.clone(),
+            authority: market.authority,
+            resolution_time,
+        });
+
+        msg!("Token CLOB Market created: {} (mint {})", market.question, market.mint);
+        Ok(())
+    }
+
     /// Place an order in the CLOB
     /// side: 0 = BID (buy), 1 = ASK (sell)
     /// is_yes: true = YES shares, false = NO shares
     /// price: basis points 0-10000 (0% to 100%)
     /// size: number of shares
-    pub fn place_order(
-        ctx: Context<PlaceOrder>,
+    /// order_type: ORDER_TYPE_LIMIT, ORDER_TYPE_TAKE_THEN_MAKE, ORDER_TYPE_IOC,
+    /// ORDER_TYPE_FOK or ORDER_TYPE_POST_ONLY
+    /// expiry: unix timestamp after which a resting order stops matching, 0 = good-till-cancelled
+    /// min_fill_size: smallest size any single match may have, 0 = no minimum.
+    /// Resting orders smaller than this are skipped, like expired ones.
+    /// avg_price_limit: worst volume-weighted average fill price, in bps of
+    /// the order's own side, 0 = no limit. A buy reverts if it pays more on
+    /// average, a sell if it receives less.
+    /// reduce_only: the order may only shrink the trader's net exposure. Its
+    /// size is cut to the opposing net balance (a YES sell to yes_shares
+    /// minus no_shares), rounded down to the lot size, and it fails with
+    /// ReduceOnlyNothingToReduce if that leaves nothing.
+    ///
+    /// With a min_fill_size an IOC order refunds whatever the skipped orders
+    /// leave unfilled, a FOK order fails unless it still fills completely,
+    /// and a resting order type rests the remainder as usual.
+    ///
+    /// If the market has a min_order_size, orders below it are rejected and
+    /// a remainder left below it after matching is refunded instead of
+    /// resting. A maker order partially filled below it is cancelled and
+    /// refunded to its owner, whose wallet must be passed in
+    /// remaining_accounts (DustOwnerMissing otherwise).
+    ///
+    /// On a permissioned market the trader's AllowlistEntry must also be in
+    /// remaining_accounts (TraderNotAllowed otherwise).
+    ///
+    /// Makers' positions passed writable in remaining_accounts have their
+    /// volume_traded credited for each fill. Makers left out are not credited,
+    /// unless the market pays a maker rebate: then every filled maker's
+    /// position must be passed (MakerPositionMissing otherwise).
+    ///
+    /// A take-then-make order only matches resting orders strictly better
+    /// than its limit and rests the remainder at the limit. A resting order
+    /// sitting exactly at the limit is left alone, so the book can end up
+    /// locked (best bid == best ask) until the next taker arrives.
+    ///
+    /// With a referrer, referral_fee_bps of the taker fee left after maker
+    /// rebates is paid to the referrer's ReferralAccount, which must then be
+    /// passed writable in remaining_accounts (ReferralAccountMissing otherwise).
+    #[allow(clippy::too_many_arguments)]
+    pub fn place_order<'info>(
+        ctx: Context<'_, '_, '_, 'info, PlaceOrder<'info>>,
         side: u8,
         is_yes: bool,
         price: u64,
         size: u64,
+        order_type: u8,
+        expiry: i64,
+        min_fill_size: u64,
+        avg_price_limit: u64,
+        reduce_only: bool,
+        referrer: Option<Pubkey>,
     ) -> Result<()> {
-        require!(price > 0 && price < BPS_MAX, ClobError::InvalidPrice);
-        require!(size > 0, ClobError::InvalidSize);
+        let request = OrderRequest {
+            side,
+            is_yes,
+            price,
+            size,
+            order_type,
+            expiry,
+            min_fill_size,
+            avg_price_limit,
+            reduce_only,
+            referrer,
+        };
+        let trader = ctx.accounts.trader.key();
+        let market_key = ctx.accounts.market.key();
+        require!(referrer != Some(trader), ClobError::SelfReferral);
+        require_allowed(&ctx.accounts.market, trader, ctx.remaining_accounts)?;
+        ctx.accounts.position.init_if_needed(trader, market_key, ctx.bumps.position);
         
-        let market = &ctx.accounts.market;
-        require!(!market.resolved, ClobError::MarketResolved);
+        let settlement = execute_order(
+            &mut ctx.accounts.market,
+            &mut ctx.accounts.order_book,
+            &mut ctx.accounts.position,
+            &mut ctx.accounts.price_history,
+            trader,
+            &request,
+        )?;
         
-        let clock = Clock::get()?;
-        require!(clock.unix_timestamp < market.resolution_time, ClobError::MarketExpired);
+        // Refunds are netted against the collateral up front, so the trader
+        // only ever sends the difference
+        let deposit = settlement.net_deposit();
+        if deposit > 0 {
+            let cpi_ctx = CpiContext::new(
+                ctx.accounts.system_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.trader.to_account_info(),
+                    to: ctx.accounts.vault.to_account_info(),
+                },
+            );
+            transfer(cpi_ctx, deposit)?;
+        }
+        if settlement.referral_fee > 0 {
+            let referral = find_referral(ctx.remaining_accounts, referrer.unwrap_or_default())?;
+            let cpi_ctx = CpiContext::new(
+                ctx.accounts.system_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.trader.to_account_info(),
+                    to: referral.clone(),
+                },
+            );
+            transfer(cpi_ctx, settlement.referral_fee)?;
+            credit_referral(referral, settlement.referral_fee)?;
+        }
+        refund_dust(
+            &ctx.accounts.market,
+            &ctx.accounts.vault,
+            ctx.remaining_accounts,
+            &settlement.dust,
+        )?;
+        credit_makers(market_key, ctx.remaining_accounts, &settlement.maker_fills)?;
         
-        // Convert to YES-denominated order
-        let (effective_side, effective_price) = if is_yes {
-            (side, price)
-        } else {
-            let flipped_side = if side == 0 { 1 } else { 0 };
-            (flipped_side, BPS_MAX - price)
+        let realized_pnl = &mut ctx.accounts.realized_pnl;
+        realized_pnl.init_if_needed(trader, ctx.bumps.realized_pnl);
+        realized_pnl.record_stake(settlement.collateral);
+        realized_pnl.record_refund(settlement.price_improvement);
+        realized_pnl.record_refund(settlement.unfilled_refund);
+        realized_pnl.record_stake(settlement.taker_fee);
+        Ok(())
+    }
+
+    /// Place an order in a token CLOB market. Same arguments and matching
+    /// as place_order, with collateral moved in the market's token. Dust
+    /// refunds go to the owners' token accounts in remaining_accounts.
+    /// Referral accounts hold lamports, so there is no referrer here.
+    #[allow(clippy::too_many_arguments)]
+    pub fn place_order_token<'info>(
+        ctx: Context<'_, '_, 'info, 'info, PlaceOrderToken<'info>>,
+        side: u8,
+        is_yes: bool,
+        price: u64,
+        size: u64,
+        order_type: u8,
+        expiry: i64,
+        min_fill_size: u64,
+        avg_price_limit: u64,
+        reduce_only: bool,
+    ) -> Result<()> {
+        let request = OrderRequest {
+            side,
+            is_yes,
+            price,
+            size,
+            order_type,
+            expiry,
+            min_fill_size,
+            avg_price_limit,
+            reduce_only,
+            referrer: None,
         };
+        let trader = ctx.accounts.trader.key();
+        let market_key = ctx.accounts.market.key();
+        require_allowed(&ctx.accounts.market, trader, ctx.remaining_accounts)?;
+        ctx.accounts.position.init_if_needed(trader, market_key, ctx.bumps.position);
         
-        // Calculate required collateral
-        let collateral_required = if effective_side == 0 {
-            effective_price.checked_mul(size).ok_or(ClobError::Overflow)?
-        } else {
-            (BPS_MAX - effective_price).checked_mul(size).ok_or(ClobError::Overflow)?
-        };
+        let settlement = execute_order(
+            &mut ctx.accounts.market,
+            &mut ctx.accounts.order_book,
+            &mut ctx.accounts.position,
+            &mut ctx.accounts.price_history,
+            trader,
+            &request,
+        )?;
         
-        // Transfer collateral from user to vault
-        let cpi_ctx = CpiContext::new(
-            ctx.accounts.system_program.to_account_info(),
-            Transfer {
-                from: ctx.accounts.trader.to_account_info(),
-                to: ctx.accounts.vault.to_account_info(),
-            },
-        );
-        transfer(cpi_ctx, collateral_required)?;
+        let deposit = settlement.net_deposit();
+        if deposit > 0 {
+            let cpi_ctx = CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                token::Transfer {
+                    from: ctx.accounts.trader_token_account.to_account_info(),
+                    to: ctx.accounts.vault.to_account_info(),
+                    authority: ctx.accounts.trader.to_account_info(),
+                },
+            );
+            token::transfer(cpi_ctx, deposit)?;
+        }
+        refund_dust_tokens(
+            &ctx.accounts.market,
+            &ctx.accounts.vault,
+            &ctx.accounts.token_program,
+            ctx.remaining_accounts,
+            &settlement.dust,
+        )?;
+        credit_makers(market_key, ctx.remaining_accounts, &settlement.maker_fills)?;
+        Ok(())
+    }
+
+    /// Place a ladder of good-till-cancelled limit orders in one transaction.
+    /// Each order matches and rests in sequence exactly as place_order would,
+    /// and the combined collateral moves in a single transfer.
+    pub fn place_orders<'info>(
+        ctx: Context<'_, '_, '_, 'info, PlaceOrder<'info>>,
+        orders: Vec<OrderParams>,
+    ) -> Result<()> {
+        require!(!orders.is_empty() && orders.len() <= MAX_ORDERS, ClobError::InvalidBatchSize);
         
-        let order_book = &mut ctx.accounts.order_book;
-        let position = &mut ctx.accounts.position;
+        let trader = ctx.accounts.trader.key();
+        let market_key = ctx.accounts.market.key();
+        require_allowed(&ctx.accounts.market, trader, ctx.remaining_accounts)?;
+        ctx.accounts.position.init_if_needed(trader, market_key, ctx.bumps.position);
         
-        // Initialize position if needed
-        if position.owner == Pubkey::default() {
-            position.owner = ctx.accounts.trader.key();
-            position.market = market.key();
-            position.yes_shares = 0;
-            position.no_shares = 0;
-            position.bump = ctx.bumps.position;
-        }
-        
-        let order_id = clock.unix_timestamp as u64;
-        let mut remaining_size = size;
-        
-        if effective_side == 0 {
-            // Buying YES: match against asks
-            remaining_size = match_against_asks(
-                order_book,
-                position,
-                effective_price,
-                remaining_size,
-            )?;
-            
-            if remaining_size > 0 {
-                require!(order_book.yes_bids.len() < MAX_ORDERS, ClobError::OrderBookFull);
-                
-                let order = Order {
-                    owner: ctx.accounts.trader.key(),
-                    price: effective_price,
-                    size: remaining_size,
-                    timestamp: clock.unix_timestamp,
-                    order_id,
-                };
-                
-                let insert_idx = order_book.yes_bids
-                    .iter()
-                    .position(|o| o.price < effective_price)
-                    .unwrap_or(order_book.yes_bids.len());
-                order_book.yes_bids.insert(insert_idx, order);
-                
-                msg!("Resting BID: {} YES @ {} bps", remaining_size, effective_price);
-            }
-        } else {
-            // Selling YES: match against bids
-            remaining_size = match_against_bids(
-                order_book,
-                position,
-                effective_price,
-                remaining_size,
+        let mut deposit = 0u64;
+        let mut summaries = Vec::with_capacity(orders.len());
+        let mut dust = Vec::new();
+        let mut maker_fills = Vec::new();
+        let realized_pnl = &mut ctx.accounts.realized_pnl;
+        realized_pnl.init_if_needed(trader, ctx.bumps.realized_pnl);
+        for params in &orders {
+            let request = OrderRequest {
+                side: params.side,
+                is_yes: params.is_yes,
+                price: params.price,
+                size: params.size,
+                order_type: ORDER_TYPE_LIMIT,
+                expiry: 0,
+                min_fill_size: 0,
+                avg_price_limit: 0,
+                reduce_only: false,
+                referrer: None,
+            };
+            let settlement = execute_order(
+                &mut ctx.accounts.market,
+                &mut ctx.accounts.order_book,
+                &mut ctx.accounts.position,
+                &mut ctx.accounts.price_history,
+                trader,
+                &request,
             )?;
             
-            if remaining_size > 0 {
-                require!(order_book.yes_asks.len() < MAX_ORDERS, ClobError::OrderBookFull);
-                
-                let order = Order {
-                    owner: ctx.accounts.trader.key(),
-                    price: effective_price,
-                    size: remaining_size,
-                    timestamp: clock.unix_timestamp,
-                    order_id,
-                };
-                
-                let insert_idx = order_book.yes_asks
-                    .iter()
-                    .position(|o| o.price > effective_price)
-                    .unwrap_or(order_book.yes_asks.len());
-                order_book.yes_asks.insert(insert_idx, order);
-                
-                msg!("Resting ASK: {} YES @ {} bps", remaining_size, effective_price);
-            }
+            deposit = deposit.checked_add(settlement.net_deposit()).ok_or(ClobError::Overflow)?;
+            realized_pnl.record_stake(settlement.collateral);
+            realized_pnl.record_refund(settlement.price_improvement);
+            realized_pnl.record_refund(settlement.unfilled_refund);
+            realized_pnl.record_stake(settlement.taker_fee);
+            summaries.push(OrderSummary {
+                filled: settlement.filled,
+                resting_order_id: settlement.resting_order_id,
+            });
+            dust.extend(settlement.dust);
+            maker_fills.extend(settlement.maker_fills);
         }
         
-        let filled = size - remaining_size;
-        msg!("Order placed: {} shares, {} filled, {} resting", size, filled, remaining_size);
+        if deposit > 0 {
+            let cpi_ctx = CpiContext::new(
+                ctx.accounts.system_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.trader.to_account_info(),
+                    to: ctx.accounts.vault.to_account_info(),
+                },
+            );
+            transfer(cpi_ctx, deposit)?;
+        }
+        refund_dust(&ctx.accounts.market, &ctx.accounts.vault, ctx.remaining_accounts, &dust)?;
+        credit_makers(market_key, ctx.remaining_accounts, &maker_fills)?;
+        
+        emit!(OrdersPlaced {
+            market: market_key,
+            trader,
+            summaries,
+        });
         Ok(())
     }
 
-    /// Cancel an order
+    /// Cancel an order and refund its collateral. Also works once the market
+    /// is resolved or void, so collateral left resting on the book is never stuck.
     pub fn cancel_order(
         ctx: Context<CancelOrder>,
         is_bid: bool,
         order_index: u8,
     ) -> Result<()> {
+        let trader = ctx.accounts.trader.key();
+        let order = ctx.accounts.order_book.remove_order(trader, is_bid, order_index as usize)?;
+        refund_cancelled(ctx, &[(is_bid, order)])
+    }
+
+    /// Cancel one of the trader's resting orders by its order_id, which,
+    /// unlike an index, doesn't shift as other orders fill or cancel
+    pub fn cancel_order_by_id(ctx: Context<CancelOrder>, order_id: u64) -> Result<()> {
+        let trader = ctx.accounts.trader.key();
+        let order_book = &mut ctx.accounts.order_book;
+        let (is_bid, order_index) = order_book.find_order(trader, order_id)?;
+        let order = order_book.remove_order(trader, is_bid, order_index)?;
+        refund_cancelled(ctx, &[(is_bid, order)])
+    }
+
+    /// Cancel every resting order the trader owns on both sides of the book
+    /// and refund their combined collateral in one transfer
+    pub fn cancel_all_orders(ctx: Context<CancelOrder>) -> Result<()> {
+        let trader = ctx.accounts.trader.key();
+        let cancelled = ctx.accounts.order_book.remove_all_orders(trader);
+        refund_cancelled(ctx, &cancelled)
+    }
+
+    /// cancel_order_by_id for a token CLOB market
+    pub fn cancel_order_by_id_token(ctx: Context<CancelOrderToken>, order_id: u64) -> Result<()> {
+        let trader = ctx.accounts.trader.key();
         let order_book = &mut ctx.accounts.order_book;
+        let (is_bid, order_index) = order_book.find_order(trader, order_id)?;
+        let order = order_book.remove_order(trader, is_bid, order_index)?;
+        refund_cancelled_tokens(ctx, &[(is_bid, order)])
+    }
+
+    /// cancel_all_orders for a token CLOB market
+    pub fn cancel_all_orders_token(ctx: Context<CancelOrderToken>) -> Result<()> {
         let trader = ctx.accounts.trader.key();
+        let cancelled = ctx.accounts.order_book.remove_all_orders(trader);
+        refund_cancelled_tokens(ctx, &cancelled)
+    }
+
+    /// Unwind the book of a resolved or void lamport market: cancel up to
+    /// `max_orders` resting orders, bids first, and refund each owner's
+    /// collateral from the vault. Every owner's wallet must be in
+    /// remaining_accounts. Settled orders leave the book, so repeated calls
+    /// page through it until it is empty.
+    pub fn settle_order_book<'info>(
+        ctx: Context<'_, '_, '_, 'info, SettleOrderBook<'info>>,
+        max_orders: u16,
+    ) -> Result<()> {
+        let market = &ctx.accounts.market;
+        require!(ctx.accounts.authority.key() == market.authority, ClobError::Unauthorized);
+        require!(
+            market.resolved || market.is_void_at(Clock::get()?.unix_timestamp),
+            ClobError::NotResolved
+        );
+        require!(max_orders > 0, ClobError::InvalidSize);
         
-        let orders = if is_bid {
-            &mut order_book.yes_bids
-        } else {
-            &mut order_book.yes_asks
-        };
-        
-        require!((order_index as usize) < orders.len(), ClobError::InvalidOrderIndex);
-        
-        let order = &orders[order_index as usize];
-        require!(order.owner == trader, ClobError::NotOrderOwner);
+        let order_book = &mut ctx.accounts.order_book;
+        let settled = order_book.remove_front(max_orders as usize);
+        refund_to_owners(
+            market,
+            &ctx.accounts.vault,
+            ctx.remaining_accounts,
+            &settled,
+            ClobError::OrderOwnerMissing,
+        )?;
         
-        let refund = if is_bid {
-            order.price.checked_mul(order.size).ok_or(ClobError::Overflow)?
-        } else {
-            (BPS_MAX - order.price).checked_mul(order.size).ok_or(ClobError::Overflow)?
-        };
+        msg!(
+            "Settled {} orders, {} still resting",
+            settled.len(),
+            order_book.yes_bids.len() + order_book.yes_asks.len()
+        );
+        Ok(())
+    }
+
+    /// Change the price and/or size of a resting order in place. new_price
+    /// is the YES price in bps, as the order sits on the book. A size
+    /// reduction at the same price keeps the order's time priority; any other
+    /// change moves it to the back of its new price level. The amended order
+    /// must not cross the book (PostOnlyWouldCross).
+    ///
+    /// Extra collateral is collected from the trader, and counted against
+    /// max_trader_notional like a new order's, and any surplus refunded.
+    pub fn amend_order(ctx: Context<AmendOrder>, order_id: u64, new_price: u64, new_size: u64) -> Result<()> {
+        let trader = ctx.accounts.trader.key();
+        require_allowed(&ctx.accounts.market, trader, ctx.remaining_accounts)?;
+        let (collect, refund) = amend_resting_order(
+            &ctx.accounts.market,
+            &mut ctx.accounts.order_book,
+            trader,
+            order_id,
+            new_price,
+            new_size,
+        )?;
+        ctx.accounts.position.record_notional(collect, ctx.accounts.market.max_trader_notional)?;
         
-        orders.remove(order_index as usize);
+        if collect > 0 {
+            let cpi_ctx = CpiContext::new(
+                ctx.accounts.system_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.trader.to_account_info(),
+                    to: ctx.accounts.vault.to_account_info(),
+                },
+            );
+            transfer(cpi_ctx, collect)?;
+        }
+        if refund > 0 {
+            pay_from_vault(
+                &ctx.accounts.vault,
+                &ctx.accounts.trader.to_account_info(),
+                refund,
+                ctx.accounts.market.vault_floor(),
+            )?;
+        }
         
-        **ctx.accounts.vault.try_borrow_mut_lamports()? -= refund;
-        **ctx.accounts.trader.try_borrow_mut_lamports()? += refund;
+        let realized_pnl = &mut ctx.accounts.realized_pnl;
+        realized_pnl.init_if_needed(trader, ctx.bumps.realized_pnl);
+        realized_pnl.record_stake(collect);
+        realized_pnl.record_refund(refund);
+        Ok(())
+    }
+
+    /// amend_order for a token CLOB market
+    pub fn amend_order_token(ctx: Context<AmendOrderToken>, order_id: u64, new_price: u64, new_size: u64) -> Result<()> {
+        require_allowed(&ctx.accounts.market, ctx.accounts.trader.key(), ctx.remaining_accounts)?;
+        let (collect, refund) = amend_resting_order(
+            &ctx.accounts.market,
+            &mut ctx.accounts.order_book,
+            ctx.accounts.trader.key(),
+            order_id,
+            new_price,
+            new_size,
+        )?;
+        ctx.accounts.position.record_notional(collect, ctx.accounts.market.max_trader_notional)?;
         
-        msg!("Order cancelled, refunded {} lamports", refund);
+        if collect > 0 {
+            let cpi_ctx = CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                token::Transfer {
+                    from: ctx.accounts.trader_token_account.to_account_info(),
+                    to: ctx.accounts.vault.to_account_info(),
+                    authority: ctx.accounts.trader.to_account_info(),
+                },
+            );
+            token::transfer(cpi_ctx, collect)?;
+        }
+        if refund > 0 {
+            pay_from_clob_token_vault(
+                &ctx.accounts.market,
+                &ctx.accounts.vault,
+                &ctx.accounts.trader_token_account,
+                &ctx.accounts.token_program,
+                refund,
+            )?;
+        }
         Ok(())
     }
 
-    /// Resolve the CLOB market
+    /// Resolve the CLOB market. Markets with resolvers resolve through
+    /// vote_clob_resolution instead. Either way resolution waits for
+    /// resolution_time unless the market was created with early_resolution.
     pub fn resolve_clob_market(
         ctx: Context<ResolveClobMarket>,
         winning_side: u8,
+        resolution_uri: Option<String>,
+        resolution_source: Option<Pubkey>,
     ) -> Result<()> {
         require!(winning_side <= 1, ClobError::InvalidOutcome);
         
         let market = &mut ctx.accounts.market;
         require!(!market.resolved, ClobError::AlreadyResolved);
         require!(ctx.accounts.authority.key() == market.authority, ClobError::Unauthorized);
+        require!(market.resolvers.is_empty(), ClobError::ResolverVoteRequired);
+        require!(market.market_group == Pubkey::default(), ClobError::MarketInGroup);
+        let now = Clock::get()?.unix_timestamp;
+        require!(!market.is_void_at(now), ClobError::MarketVoid);
+        let resolution_uri = resolution_uri.unwrap_or_default();
+        require!(resolution_uri.len() <= MAX_RESOLUTION_URI_LEN, ClobError::ResolutionUriTooLong);
+        market.resolution_uri = resolution_uri;
+        market.resolution_source = resolution_source.unwrap_or_default();
         
-        market.resolved = true;
-        market.winning_side = Some(winning_side);
+        finalize_clob_resolution(market, winning_side, now)
+    }
+
+    /// Resolve a market bound to an oracle from the feed's current value.
+    /// Anyone can call it once resolution_time has passed; the authority
+    /// can still resolve by hand if the feed stops updating.
+    pub fn resolve_from_oracle(ctx: Context<ResolveFromOracle>) -> Result<()> {
+        let market = &mut ctx.accounts.market;
+        require!(!market.resolved, ClobError::AlreadyResolved);
+        require!(market.oracle != Pubkey::default(), ClobError::NoOracle);
+        let now = Clock::get()?.unix_timestamp;
+        require!(now >= market.resolution_time, ClobError::ResolutionTimeNotReached);
+        require!(!market.is_void_at(now), ClobError::MarketVoid);
+        
+        let data = ctx.accounts.oracle.try_borrow_data()?;
+        let offset = market.oracle_offset as usize;
+        let bytes = data.get(offset..offset + 8).ok_or(ClobError::InvalidOracleData)?;
+        let value = i64::from_le_bytes(bytes.try_into().unwrap());
         
-        msg!("CLOB Market resolved: {} wins", if winning_side == 0 { "YES" } else { "NO" });
+        let threshold = market.oracle_threshold;
+        let yes_wins = match market.oracle_comparison {
+            ORACLE_GREATER_THAN => value > threshold,
+            ORACLE_GREATER_OR_EQUAL => value >= threshold,
+            ORACLE_LESS_THAN => value < threshold,
+            _ => value <= threshold,
+        };
+        msg!("Oracle value {} against threshold {}", value, threshold);
+        market.resolution_source = market.oracle;
+        finalize_clob_resolution(market, if yes_wins { 0 } else { 1 }, now)
+    }
+
+    /// Create a group for mutually exclusive CLOB markets, such as one "will
+    /// win" market per candidate. Its address comes from the authority and
+    /// group_id.
+    pub fn create_market_group(ctx: Context<CreateMarketGroup>, group_id: String) -> Result<()> {
+        require!(group_id.len() <= 32, ClobError::MarketIdTooLong);
+        let group = &mut ctx.accounts.group;
+        group.authority = ctx.accounts.authority.key();
+        group.group_id = group_id;
+        group.markets = Vec::new();
+        group.winner = None;
+        group.bump = ctx.bumps.group;
+        msg!("Market group created: {}", group.group_id);
         Ok(())
     }
 
-    /// Claim winnings from a CLOB market
-    pub fn claim_clob_winnings(ctx: Context<ClaimClobWinnings>) -> Result<()> {
-        let market = &ctx.accounts.market;
+    /// Link an unresolved CLOB market into a group. From then on it can only
+    /// resolve through group_resolve, so markets with resolvers or an oracle
+    /// can't join. Neither can markets that void after max_resolution_delay:
+    /// one void member would keep the whole group from resolving.
+    pub fn add_to_market_group(ctx: Context<AddToMarketGroup>) -> Result<()> {
+        let group = &mut ctx.accounts.group;
+        let market = &mut ctx.accounts.market;
+        require!(market.authority == group.authority, ClobError::Unauthorized);
+        require!(!market.resolved && group.winner.is_none(), ClobError::AlreadyResolved);
+        require!(!market.voided, ClobError::MarketVoid);
+        require!(market.market_group == Pubkey::default(), ClobError::MarketAlreadyGrouped);
+        require!(market.max_resolution_delay == 0, ClobError::GroupedMarketVoidable);
+        require!(
+            market.resolvers.is_empty() && market.oracle == Pubkey::default(),
+            ClobError::GroupedMarketResolver
+        );
+        require!(group.markets.len() < MAX_GROUP_MARKETS, ClobError::GroupFull);
+        
+        group.markets.push(market.key());
+        market.market_group = group.key();
+        msg!("Market {} added to group {}", market.key(), group.group_id);
+        Ok(())
+    }
+
+    /// Resolve every market of a group at once: the market at
+    /// `winner_index` resolves YES and all the others NO, so exactly one
+    /// wins. The group's markets go in remaining_accounts, writable and in
+    /// group order.
+    pub fn group_resolve<'info>(
+        ctx: Context<'_, '_, 'info, 'info, GroupResolve<'info>>,
+        winner_index: u8,
+    ) -> Result<()> {
+        let group = &mut ctx.accounts.group;
+        require!(group.winner.is_none(), ClobError::AlreadyResolved);
+        require!((winner_index as usize) < group.markets.len(), ClobError::InvalidOutcome);
+        require!(ctx.remaining_accounts.len() == group.markets.len(), ClobError::GroupMarketsMismatch);
+        
+        let now = Clock::get()?.unix_timestamp;
+        for (index, (info, key)) in ctx.remaining_accounts.iter().zip(&group.markets).enumerate() {
+            require!(info.key() == *key, ClobError::GroupMarketsMismatch);
+            let mut market = Account::<ClobMarket>::try_from(info)?;
+            require!(!market.resolved, ClobError::AlreadyResolved);
+            require!(!market.is_void_at(now), ClobError::MarketVoid);
+            let winning_side = if index == winner_index as usize { 0 } else { 1 };
+            finalize_clob_resolution(&mut market, winning_side, now)?;
+            market.exit(&crate::ID)?;
+        }
+        
+        group.winner = Some(group.markets[winner_index as usize]);
+        msg!("Market group {} resolved: market {} wins", group.group_id, winner_index);
+        Ok(())
+    }
+
+    /// Cast or change a resolver's vote on a market with resolvers. The
+    /// market resolves as soon as resolver_threshold votes agree on a side.
+    pub fn vote_clob_resolution(
+        ctx: Context<VoteClobResolution>,
+        winning_side: u8,
+    ) -> Result<()> {
+        require!(winning_side <= 1, ClobError::InvalidOutcome);
+        
+        let market = &mut ctx.accounts.market;
+        require!(!market.resolved, ClobError::AlreadyResolved);
+        let resolver_index = market
+            .resolvers
+            .iter()
+            .position(|r| *r == ctx.accounts.resolver.key())
+            .ok_or(ClobError::NotResolver)?;
+        let now = Clock::get()?.unix_timestamp;
+        require!(!market.is_void_at(now), ClobError::MarketVoid);
+        
+        let votes = &mut ctx.accounts.votes;
+        if votes.market == Pubkey::default() {
+            votes.market = market.key();
+            votes.votes = vec![None; market.resolvers.len()];
+            votes.bump = ctx.bumps.votes;
+        }
+        votes.votes[resolver_index] = Some(winning_side);
+        
+        let agreeing = votes.votes.iter().filter(|v| **v == Some(winning_side)).count();
+        msg!(
+            "Resolver voted {}: {} of {} needed",
+            if winning_side == 0 { "YES" } else { "NO" },
+            agreeing,
+            market.resolver_threshold
+        );
+        if agreeing >= market.resolver_threshold as usize {
+            finalize_clob_resolution(market, winning_side, now)?;
+        }
+        Ok(())
+    }
+
+    /// Change the winning side of a resolved market while its dispute
+    /// period is still running. Does not restart the dispute period.
+    pub fn override_resolution(
+        ctx: Context<ResolveClobMarket>,
+        winning_side: u8,
+    ) -> Result<()> {
+        require!(winning_side <= 1, ClobError::InvalidOutcome);
+        
+        let market = &mut ctx.accounts.market;
         require!(market.resolved, ClobError::NotResolved);
+        require!(ctx.accounts.authority.key() == market.authority, ClobError::Unauthorized);
+        // Flipping one market could leave its group with two winners or none
+        require!(market.market_group == Pubkey::default(), ClobError::MarketInGroup);
+        require!(
+            market.in_dispute_period_at(Clock::get()?.unix_timestamp),
+            ClobError::DisputePeriodOver
+        );
         
-        let position = &mut ctx.accounts.position;
-        let winning_side = market.winning_side.unwrap();
+        let previous_winning_side = market.winning_side.unwrap();
+        market.winning_side = Some(winning_side);
         
-        let payout = if winning_side == 0 {
-            position.yes_shares.checked_mul(SHARE_PAYOUT).ok_or(ClobError::Overflow)?
-        } else {
-            position.no_shares.checked_mul(SHARE_PAYOUT).ok_or(ClobError::Overflow)?
-        };
+        emit!(ResolutionOverridden {
+            market: market.key(),
+            previous_winning_side,
+            winning_side,
+        });
         
-        require!(payout > 0, ClobError::NoWinnings);
+        msg!("CLOB Market resolution overridden: {} wins", if winning_side == 0 { "YES" } else { "NO" });
+        Ok(())
+    }
+
+    /// Push an unresolved CLOB market's resolution_time later
+    pub fn update_clob_resolution_time(
+        ctx: Context<ResolveClobMarket>,
+        new_resolution_time: i64,
+    ) -> Result<()> {
+        let market = &mut ctx.accounts.market;
+        require!(!market.resolved, ClobError::AlreadyResolved);
+        require!(ctx.accounts.authority.key() == market.authority, ClobError::Unauthorized);
+        let now = Clock::get()?.unix_timestamp;
+        require!(!market.is_void_at(now), ClobError::MarketVoid);
+        require!(new_resolution_time > market.resolution_time, ClobError::InvalidResolutionTime);
+        require!(
+            new_resolution_time.saturating_sub(now) <= MAX_MARKET_DURATION,
+            ClobError::ResolutionTimeTooFar
+        );
         
-        position.yes_shares = 0;
-        position.no_shares = 0;
+        let previous_resolution_time = market.resolution_time;
+        market.resolution_time = new_resolution_time;
         
-        **ctx.accounts.vault.try_borrow_mut_lamports()? -= payout;
-        **ctx.accounts.claimer.try_borrow_mut_lamports()? += payout;
+        emit!(ResolutionTimeUpdated {
+            market: market.key(),
+            previous_resolution_time,
+            resolution_time: new_resolution_time,
+        });
         
-        msg!("Claimed {} lamports", payout);
+        msg!("CLOB resolution time moved to {}", new_resolution_time);
         Ok(())
     }
-}
 
-// === Matching Engine ===
+    /// Propose a new authority for a CLOB market, pending until accepted
+    pub fn propose_clob_authority(ctx: Context<ResolveClobMarket>, new_authority: Pubkey) -> Result<()> {
+        let market = &mut ctx.accounts.market;
+        require!(ctx.accounts.authority.key() == market.authority, ClobError::Unauthorized);
+        
+        market.pending_authority = Some(new_authority);
+        msg!("Proposed CLOB authority {}", new_authority);
+        Ok(())
+    }
 
-fn match_against_asks(
-    order_book: &mut OrderBook,
-    position: &mut ClobPosition,
-    max_price: u64,
-    mut size: u64,
-) -> Result<u64> {
-    while size > 0 && !order_book.yes_asks.is_empty() {
-        let best_ask = &order_book.yes_asks[0];
+    /// Complete a CLOB authority transfer, signed by the proposed authority
+    pub fn accept_clob_authority(ctx: Context<AcceptClobAuthority>) -> Result<()> {
+        let market = &mut ctx.accounts.market;
+        let new_authority = ctx.accounts.new_authority.key();
+        require!(market.pending_authority == Some(new_authority), ClobError::NotPendingAuthority);
         
-        if max_price < best_ask.price {
-            break;
-        }
+        let previous_authority = market.authority;
+        market.authority = new_authority;
+        market.pending_authority = None;
         
-        let fill_size = size.min(best_ask.size);
-        let fill_price = best_ask.price;
+        emit!(AuthorityTransferred {
+            market: market.key(),
+            previous_authority,
+            new_authority,
+        });
         
-        position.yes_shares = position.yes_shares
-            .checked_add(fill_size)
-            .ok_or(ClobError::Overflow)?;
+        msg!("CLOB authority transferred to {}", new_authority);
+        Ok(())
+    }
+
+    /// Halt new orders on a CLOB market. Cancels, resolution and claims keep
+    /// working so users can still exit.
+    pub fn pause_clob_market(ctx: Context<ResolveClobMarket>) -> Result<()> {
+        set_clob_market_paused(ctx, true)
+    }
+
+    /// Reopen a paused CLOB market to new orders
+    pub fn unpause_clob_market(ctx: Context<ResolveClobMarket>) -> Result<()> {
+        set_clob_market_paused(ctx, false)
+    }
+
+    /// Allow a trader to place orders on a permissioned market
+    pub fn add_trader(ctx: Context<AddTrader>, trader: Pubkey) -> Result<()> {
+        let market = &ctx.accounts.market;
+        require!(ctx.accounts.authority.key() == market.authority, ClobError::Unauthorized);
         
-        if fill_size == order_book.yes_asks[0].size {
-            order_book.yes_asks.remove(0);
-        } else {
-            order_book.yes_asks[0].size -= fill_size;
-        }
+        let entry = &mut ctx.accounts.allowlist_entry;
+        entry.market = market.key();
+        entry.trader = trader;
+        entry.bump = ctx.bumps.allowlist_entry;
         
-        size -= fill_size;
-        msg!("Matched {} YES @ {} bps", fill_size, fill_price);
+        msg!("Added {} to the allowlist", trader);
+        Ok(())
     }
-    
-    Ok(size)
-}
 
-fn match_against_bids(
-    order_book: &mut OrderBook,
-    position: &mut ClobPosition,
-    min_price: u64,
-    mut size: u64,
-) -> Result<u64> {
-    while size > 0 && !order_book.yes_bids.is_empty() {
-        let best_bid = &order_book.yes_bids[0];
+    /// Remove a trader from a permissioned market's allowlist. Their resting
+    /// orders stay on the book and remain cancellable.
+    pub fn remove_trader(ctx: Context<RemoveTrader>, trader: Pubkey) -> Result<()> {
+        require!(
+            ctx.accounts.authority.key() == ctx.accounts.market.authority,
+            ClobError::Unauthorized
+        );
         
-        if min_price > best_bid.price {
-            break;
-        }
+        msg!("Removed {} from the allowlist", trader);
+        Ok(())
+    }
+
+    /// Void an unresolved CLOB market whose question no longer has a
+    /// meaningful answer. Trading stops, every share is worth VOID_PAYOUT_BPS
+    /// of the payout and resting orders stay cancellable for their collateral.
+    /// Grouped markets can't be voided on their own, since group_resolve
+    /// needs every member to resolve.
+    pub fn void_clob_market(ctx: Context<ResolveClobMarket>) -> Result<()> {
+        let market = &mut ctx.accounts.market;
+        require!(!market.resolved, ClobError::AlreadyResolved);
+        require!(ctx.accounts.authority.key() == market.authority, ClobError::Unauthorized);
+        require!(!market.voided, ClobError::MarketVoid);
+        require!(market.market_group == Pubkey::default(), ClobError::MarketInGroup);
         
-        let fill_size = size.min(best_bid.size);
-        let fill_price = best_bid.price;
+        market.voided = true;
         
-        position.no_shares = position.no_shares
-            .checked_add(fill_size)
-            .ok_or(ClobError::Overflow)?;
+        emit!(ClobMarketVoided {
+            market: market.key(),
+            market_id: market.market_id.clone(),
+            authority: market.authority,
+            voided_at: Clock::get()?.unix_timestamp,
+        });
         
-        if fill_size == order_book.yes_bids[0].size {
-            order_book.yes_bids.remove(0);
-        } else {
-            order_book.yes_bids[0].size -= fill_size;
+        msg!("CLOB Market voided");
+        Ok(())
+    }
+
+    /// Claim the void payout of a voided market: VOID_PAYOUT_BPS of a share's
+    /// payout for every YES and NO share, since each matched pair put up BPS_MAX
+    pub fn claim_void_refund(ctx: Context<ClaimClobWinnings>) -> Result<()> {
+        require!(
+            ctx.accounts.market.is_void_at(Clock::get()?.unix_timestamp),
+            ClobError::MarketNotVoid
+        );
+        claim_clob_winnings(ctx, 0)
+    }
+
+    /// Claim winnings from a CLOB market
+    ///
+    /// If the market was never resolved within its max_resolution_delay it
+    /// is treated as void and every share pays out at VOID_PAYOUT_BPS.
+    ///
+    /// shares: how many claimable shares to settle, 0 = all. A partial claim
+    /// lets a large position draw down a vault that can't yet cover all of it.
+    pub fn claim_clob_winnings(ctx: Context<ClaimClobWinnings>, shares: u64) -> Result<()> {
+        let market = &mut ctx.accounts.market;
+        require!(ctx.accounts.position.market == market.key(), ClobError::PositionMarketMismatch);
+        let now = Clock::get()?.unix_timestamp;
+        let reason = if market.is_void_at(now) { POSITION_CHANGE_REFUND } else { POSITION_CHANGE_CLAIM };
+        let payout = market.settle_claim(&mut ctx.accounts.position, shares, now)?;
+        ctx.accounts.position.emit_changed(reason);
+        
+        // Collateral pays first and the insurance buffer covers any
+        // shortfall. Report a short vault as insolvency rather than a
+        // generic reserve error.
+        let collateral = ctx.accounts.vault.lamports().saturating_sub(market.vault_floor());
+        let shortfall = payout.saturating_sub(collateral);
+        require!(shortfall <= market.insurance_balance, ClobError::VaultInsolvent);
+        if shortfall > 0 {
+            market.insurance_balance -= shortfall;
+            msg!("Insurance covered {} lamports of the claim", shortfall);
         }
         
-        size -= fill_size;
-        msg!("Matched {} YES @ {} bps", fill_size, fill_price);
+        pay_from_vault(
+            &ctx.accounts.vault,
+            &ctx.accounts.claimer.to_account_info(),
+            payout,
+            market.vault_reserve,
+        )?;
+        
+        let realized_pnl = &mut ctx.accounts.realized_pnl;
+        realized_pnl.init_if_needed(ctx.accounts.claimer.key(), ctx.bumps.realized_pnl);
+        realized_pnl.record_claim(payout);
+        
+        msg!("Claimed {} lamports", payout);
+        Ok(())
     }
-    
-    Ok(size)
-}
 
-// ===========================================
-// PARIMUTUEL ACCOUNT STRUCTURES
-// ===========================================
+    /// Claim winnings, or a void payout, from a token CLOB market
+    pub fn claim_clob_winnings_token(ctx: Context<ClaimClobWinningsToken>, shares: u64) -> Result<()> {
+        let market = &mut ctx.accounts.market;
+        require!(ctx.accounts.position.market == market.key(), ClobError::PositionMarketMismatch);
+        let now = Clock::get()?.unix_timestamp;
+        let reason = if market.is_void_at(now) { POSITION_CHANGE_REFUND } else { POSITION_CHANGE_CLAIM };
+        let payout = market.settle_claim(&mut ctx.accounts.position, shares, now)?;
+        ctx.accounts.position.emit_changed(reason);
+        require!(payout <= ctx.accounts.vault.amount, ClobError::VaultInsolvent);
+        
+        pay_from_clob_token_vault(
+            &ctx.accounts.market,
+            &ctx.accounts.vault,
+            &ctx.accounts.claimer_token_account,
+            &ctx.accounts.token_program,
+            payout,
+        )?;
+        
+        msg!("Claimed {} tokens", payout);
+        Ok(())
+    }
 
-#[account]
-pub struct Market {
-    pub authority: Pubkey,
-    pub market_id: String,
-    pub question: String,
-    pub outcomes: Vec<String>,
-    pub outcome_pools: Vec<u64>,
-    pub total_pool: u64,
-    pub resolution_time: i64,
-    pub resolved: bool,
-    pub winning_outcome: Option<u8>,
-    pub created_at: i64,
-    pub bump: u8,
-}
+    /// Withdraw accrued taker fees from the vault to a destination account
+    pub fn withdraw_clob_fees(ctx: Context<WithdrawClobFees>) -> Result<()> {
+        let market = &mut ctx.accounts.market;
+        require!(ctx.accounts.authority.key() == market.authority, ClobError::Unauthorized);
+        
+        let amount = market.fees_accrued;
+        require!(amount > 0, ClobError::NoFeesToWithdraw);
+        market.fees_accrued = 0;
+        
+        pay_from_vault(
+            &ctx.accounts.vault,
+            &ctx.accounts.destination,
+            amount,
+            market.vault_floor(),
+        )?;
+        
+        msg!("Withdrew {} lamports of CLOB fees", amount);
+        Ok(())
+    }
 
-#[account]
-pub struct Position {
-    pub owner: Pubkey,
-    pub market: Pubkey,
-    pub shares: Vec<u64>,
-    pub bump: u8,
-}
+    /// Withdraw accrued taker fees from a token CLOB market's vault
+    pub fn withdraw_clob_fees_token(ctx: Context<WithdrawClobFeesToken>) -> Result<()> {
+        let market = &mut ctx.accounts.market;
+        require!(ctx.accounts.authority.key() == market.authority, ClobError::Unauthorized);
+        
+        let amount = market.fees_accrued;
+        require!(amount > 0, ClobError::NoFeesToWithdraw);
+        market.fees_accrued = 0;
+        
+        pay_from_clob_token_vault(
+            &ctx.accounts.market,
+            &ctx.accounts.vault,
+            &ctx.accounts.destination,
+            &ctx.accounts.token_program,
+            amount,
+        )?;
+        
+        msg!("Withdrew {} tokens of CLOB fees", amount);
+        Ok(())
+    }
 
-// ===========================================
-// CLOB ACCOUNT STRUCTURES
-// ===========================================
+    /// Deposit lamports into a CLOB vault as an insurance buffer. It sits
+    /// outside the collateral, fees and rent reserve, and is drawn on only
+    /// when a winning claim finds the collateral short.
+    pub fn fund_insurance(ctx: Context<ClobInsurance>, amount: u64) -> Result<()> {
+        require!(ctx.accounts.authority.key() == ctx.accounts.market.authority, ClobError::Unauthorized);
+        require!(amount > 0, ClobError::InvalidSize);
+        require!(!ctx.accounts.market.closed, ClobError::MarketClosed);
+        
+        let cpi_ctx = CpiContext::new(
+            ctx.accounts.system_program.to_account_info(),
+            Transfer {
+                from: ctx.accounts.authority.to_account_info(),
+                to: ctx.accounts.vault.to_account_info(),
+            },
+        );
+        transfer(cpi_ctx, amount)?;
+        
+        let market = &mut ctx.accounts.market;
+        market.insurance_balance = market.insurance_balance
+            .checked_add(amount)
+            .ok_or(ClobError::Overflow)?;
+        
+        msg!("Insurance buffer funded with {} lamports, now {}", amount, market.insurance_balance);
+        Ok(())
+    }
+
+    /// Return the unused insurance buffer to the authority once the market
+    /// is settled: claims open, no winning shares unclaimed and no rebates
+    /// owed. Losing shares never claim, so they don't hold it back.
+    pub fn withdraw_insurance(ctx: Context<ClobInsurance>) -> Result<()> {
+        let market = &mut ctx.accounts.market;
+        require!(ctx.accounts.authority.key() == market.authority, ClobError::Unauthorized);
+        require!(market.is_settled_at(Clock::get()?.unix_timestamp), ClobError::MarketNotSettled);
+        let amount = market.insurance_balance;
+        require!(amount > 0, ClobError::NoInsuranceToWithdraw);
+        market.insurance_balance = 0;
+        
+        pay_from_vault(
+            &ctx.accounts.vault,
+            &ctx.accounts.authority.to_account_info(),
+            amount,
+            market.vault_reserve,
+        )?;
+        
+        msg!("Withdrew {} lamports of unused insurance", amount);
+        Ok(())
+    }
+
+    /// Pay out the maker rebates credited to the caller's position
+    pub fn claim_rebates(ctx: Context<ClaimRebates>) -> Result<()> {
+        let position = &mut ctx.accounts.position;
+        let amount = position.rebates_owed;
+        require!(amount > 0, ClobError::NoRebatesToClaim);
+        position.rebates_owed = 0;
+        let market = &mut ctx.accounts.market;
+        market.rebates_owed = market.rebates_owed.saturating_sub(amount);
+        
+        pay_from_vault(
+            &ctx.accounts.vault,
+            &ctx.accounts.owner.to_account_info(),
+            amount,
+            ctx.accounts.market.vault_floor(),
+        )?;
+        
+        msg!("Claimed {} lamports of maker rebates", amount);
+        Ok(())
+    }
+
+    /// Pay out the maker rebates credited to the caller's position in a
+    /// token CLOB market
+    pub fn claim_rebates_token(ctx: Context<ClaimRebatesToken>) -> Result<()> {
+        let position = &mut ctx.accounts.position;
+        let amount = position.rebates_owed;
+        require!(amount > 0, ClobError::NoRebatesToClaim);
+        position.rebates_owed = 0;
+        let market = &mut ctx.accounts.market;
+        market.rebates_owed = market.rebates_owed.saturating_sub(amount);
+        
+        pay_from_clob_token_vault(
+            &ctx.accounts.market,
+            &ctx.accounts.vault,
+            &ctx.accounts.owner_token_account,
+            &ctx.accounts.token_program,
+            amount,
+        )?;
+        
+        msg!("Claimed {} tokens of maker rebates", amount);
+        Ok(())
+    }
+
+    /// Close a fully settled lamport CLOB market: the order book and price
+    /// history accounts are closed and the vault drained, all to the
+    /// authority. The market must be resolved (claims open) or void, with no
+    /// winning shares unclaimed (any share, once void), no unclaimed maker
+    /// rebates and an empty book.
+    /// Unwithdrawn fees go to the authority with the rest of the vault.
+    /// The market account stays behind marked closed: positions and votes
+    /// keyed by it outlive it, so its id must never be created again.
+    pub fn close_clob_market(ctx: Context<CloseClobMarket>) -> Result<()> {
+        let market = &mut ctx.accounts.market;
+        require!(ctx.accounts.authority.key() == market.authority, ClobError::Unauthorized);
+        require!(!market.closed, ClobError::MarketClosed);
+        let order_book = &ctx.accounts.order_book;
+        require!(
+            market.is_settled_at(Clock::get()?.unix_timestamp)
+                && order_book.yes_bids.is_empty()
+                && order_book.yes_asks.is_empty(),
+            ClobError::MarketNotSettled
+        );
+        
+        let drained = ctx.accounts.vault.lamports();
+        pay_from_vault(
+            &ctx.accounts.vault,
+            &ctx.accounts.authority.to_account_info(),
+            drained,
+            0,
+        )?;
+        market.closed = true;
+        market.fees_accrued = 0;
+        market.insurance_balance = 0;
+        
+        msg!("CLOB market closed, {} lamports drained from the vault", drained);
+        Ok(())
+    }
+
+    /// Last-resort recovery for a stuck lamport CLOB market: drain the whole
+    /// vault to the authority, whatever positions are still owed. Only on
+    /// markets created with allow_emergency, and only EMERGENCY_WITHDRAW_DELAY
+    /// after resolution_time.
+    pub fn emergency_withdraw(ctx: Context<EmergencyWithdraw>) -> Result<()> {
+        let market = &ctx.accounts.market;
+        require!(ctx.accounts.authority.key() == market.authority, ClobError::Unauthorized);
+        require!(market.allow_emergency, ClobError::EmergencyWithdrawDisabled);
+        let now = Clock::get()?.unix_timestamp;
+        require!(
+            now >= market.resolution_time.saturating_add(EMERGENCY_WITHDRAW_DELAY),
+            ClobError::EmergencyTimelockActive
+        );
+        
+        let drained = ctx.accounts.vault.lamports();
+        pay_from_vault(
+            &ctx.accounts.vault,
+            &ctx.accounts.authority.to_account_info(),
+            drained,
+            0,
+        )?;
+        
+        emit!(EmergencyWithdrawal {
+            market: market.key(),
+            authority: market.authority,
+            amount: drained,
+            open_interest: market.open_interest,
+            timestamp: now,
+        });
+        msg!("EMERGENCY WITHDRAW: {} lamports drained from the vault of {}", drained, market.key());
+        Ok(())
+    }
+
+    /// Transfer a whole CLOB position to another wallet
+    pub fn transfer_clob_position(
+        ctx: Context<TransferClobPosition>,
+        new_owner: Pubkey,
+    ) -> Result<()> {
+        let yes_shares = ctx.accounts.source.yes_shares;
+        let no_shares = ctx.accounts.source.no_shares;
+        transfer_clob_shares(ctx, new_owner, yes_shares, no_shares)
+    }
+
+    /// Transfer part of a CLOB position to another wallet, creating its
+    /// position if needed. Resting orders, notional and rebates stay with
+    /// the sender; only shares move.
+    pub fn transfer_clob_shares(
+        ctx: Context<TransferClobPosition>,
+        new_owner: Pubkey,
+        yes_shares: u64,
+        no_shares: u64,
+    ) -> Result<()> {
+        require!(new_owner != ctx.accounts.owner.key(), ClobError::InvalidRecipient);
+        require!(yes_shares > 0 || no_shares > 0, ClobError::EmptyPosition);
+        
+        let market_key = ctx.accounts.market.key();
+        let source = &mut ctx.accounts.source;
+        require!(source.market == market_key, ClobError::PositionMarketMismatch);
+        
+        let destination = &mut ctx.accounts.destination;
+        destination.init_if_needed(new_owner, market_key, ctx.bumps.destination);
+        require!(destination.market == market_key, ClobError::PositionMarketMismatch);
+        
+        source.yes_shares = source.yes_shares
+            .checked_sub(yes_shares)
+            .ok_or(ClobError::InsufficientShares)?;
+        source.no_shares = source.no_shares
+            .checked_sub(no_shares)
+            .ok_or(ClobError::InsufficientShares)?;
+        destination.yes_shares = destination.yes_shares
+            .checked_add(yes_shares)
+            .ok_or(ClobError::Overflow)?;
+        destination.no_shares = destination.no_shares
+            .checked_add(no_shares)
+            .ok_or(ClobError::Overflow)?;
+        source.emit_changed(POSITION_CHANGE_TRANSFER);
+        destination.emit_changed(POSITION_CHANGE_TRANSFER);
+        
+        msg!("Transferred {} YES / {} NO shares to {}", yes_shares, no_shares, new_owner);
+        Ok(())
+    }
+
+    /// Deposit payout_per_share per set and receive that many YES and NO
+    /// shares. A complete set pays out exactly its deposit whatever the
+    /// outcome, so this sources inventory without crossing the book.
+    /// Permissioned markets need the trader's AllowlistEntry in
+    /// remaining_accounts, as for orders.
+    pub fn mint_complete_set(ctx: Context<CompleteSet>, sets: u64) -> Result<()> {
+        let trader = ctx.accounts.trader.key();
+        let market_key = ctx.accounts.market.key();
+        require_allowed(&ctx.accounts.market, trader, ctx.remaining_accounts)?;
+        ctx.accounts.position.init_if_needed(trader, market_key, ctx.bumps.position);
+        
+        let collateral = ctx.accounts.market.mint_complete_sets(
+            &mut ctx.accounts.position,
+            sets,
+            Clock::get()?.unix_timestamp,
+        )?;
+        ctx.accounts.position.record_notional(collateral, ctx.accounts.market.max_trader_notional)?;
+        let cpi_ctx = CpiContext::new(
+            ctx.accounts.system_program.to_account_info(),
+            Transfer {
+                from: ctx.accounts.trader.to_account_info(),
+                to: ctx.accounts.vault.to_account_info(),
+            },
+        );
+        transfer(cpi_ctx, collateral)?;
+        
+        let realized_pnl = &mut ctx.accounts.realized_pnl;
+        realized_pnl.init_if_needed(trader, ctx.bumps.realized_pnl);
+        realized_pnl.record_stake(collateral);
+        
+        emit!(CompleteSetsChanged { market: market_key, owner: trader, sets, minted: true });
+        msg!("Minted {} complete sets for {} lamports", sets, collateral);
+        Ok(())
+    }
+
+    /// Burn matching YES and NO shares and withdraw payout_per_share per set.
+    /// Works at any point, resolved and void markets included.
+    pub fn redeem_complete_set(ctx: Context<CompleteSet>, sets: u64) -> Result<()> {
+        let trader = ctx.accounts.trader.key();
+        let market_key = ctx.accounts.market.key();
+        ctx.accounts.position.init_if_needed(trader, market_key, ctx.bumps.position);
+        
+        let market = &mut ctx.accounts.market;
+        let payout = market.redeem_complete_sets(&mut ctx.accounts.position, sets)?;
+        let available = ctx.accounts.vault.lamports().saturating_sub(market.vault_floor());
+        require!(payout <= available, ClobError::VaultInsolvent);
+        
+        pay_from_vault(
+            &ctx.accounts.vault,
+            &ctx.accounts.trader.to_account_info(),
+            payout,
+            market.vault_floor(),
+        )?;
+        
+        let realized_pnl = &mut ctx.accounts.realized_pnl;
+        realized_pnl.init_if_needed(trader, ctx.bumps.realized_pnl);
+        realized_pnl.record_refund(payout);
+        
+        emit!(CompleteSetsChanged { market: market_key, owner: trader, sets, minted: false });
+        msg!("Redeemed {} complete sets for {} lamports", sets, payout);
+        Ok(())
+    }
+
+    /// Token market version of mint_complete_set
+    pub fn mint_complete_set_token(ctx: Context<CompleteSetToken>, sets: u64) -> Result<()> {
+        let trader = ctx.accounts.trader.key();
+        let market_key = ctx.accounts.market.key();
+        require_allowed(&ctx.accounts.market, trader, ctx.remaining_accounts)?;
+        ctx.accounts.position.init_if_needed(trader, market_key, ctx.bumps.position);
+        
+        let collateral = ctx.accounts.market.mint_complete_sets(
+            &mut ctx.accounts.position,
+            sets,
+            Clock::get()?.unix_timestamp,
+        )?;
+        ctx.accounts.position.record_notional(collateral, ctx.accounts.market.max_trader_notional)?;
+        let cpi_ctx = CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            token::Transfer {
+                from: ctx.accounts.trader_token_account.to_account_info(),
+                to: ctx.accounts.vault.to_account_info(),
+                authority: ctx.accounts.trader.to_account_info(),
+            },
+        );
+        token::transfer(cpi_ctx, collateral)?;
+        
+        emit!(CompleteSetsChanged { market: market_key, owner: trader, sets, minted: true });
+        msg!("Minted {} complete sets for {} tokens", sets, collateral);
+        Ok(())
+    }
+
+    /// Token market version of redeem_complete_set
+    pub fn redeem_complete_set_token(ctx: Context<CompleteSetToken>, sets: u64) -> Result<()> {
+        let trader = ctx.accounts.trader.key();
+        let market_key = ctx.accounts.market.key();
+        ctx.accounts.position.init_if_needed(trader, market_key, ctx.bumps.position);
+        
+        let payout = ctx.accounts.market.redeem_complete_sets(&mut ctx.accounts.position, sets)?;
+        require!(payout <= ctx.accounts.vault.amount, ClobError::VaultInsolvent);
+        
+        pay_from_clob_token_vault(
+            &ctx.accounts.market,
+            &ctx.accounts.vault,
+            &ctx.accounts.trader_token_account,
+            &ctx.accounts.token_program,
+            payout,
+        )?;
+        
+        emit!(CompleteSetsChanged { market: market_key, owner: trader, sets, minted: false });
+        msg!("Redeemed {} complete sets for {} tokens", sets, payout);
+        Ok(())
+    }
+
+    /// Read-only: best live bid and ask with the total size resting at each
+    /// price. Call via simulation to quote a market without fetching the book.
+    pub fn get_top_of_book(ctx: Context<ViewOrderBook>) -> Result<TopOfBook> {
+        Ok(ctx.accounts.order_book.top_of_book(Clock::get()?.unix_timestamp))
+    }
+
+    /// Read-only: how an order with place_order's side, is_yes, price and
+    /// size would fill against the book right now, for `taker`. Call via
+    /// simulation; returns filled, unfilled, cost, taker fee and average
+    /// price without touching the book.
+    pub fn quote_order(
+        ctx: Context<ViewOrderBook>,
+        side: u8,
+        is_yes: bool,
+        price: u64,
+        size: u64,
+        taker: Pubkey,
+    ) -> Result<OrderQuote> {
+        let market = &ctx.accounts.market;
+        market.check_order_terms(price, size)?;
+        let (effective_side, effective_price) = yes_terms(side, is_yes, price);
+        let params = MatchParams::new(market, taker, Clock::get()?.unix_timestamp, 0);
+
+        // Walk a copy of the opposite side exactly as matching would
+        let (unfilled, fills, _) = if effective_side == 0 {
+            let mut asks = ctx.accounts.order_book.yes_asks.clone();
+            match_orders(&mut asks, |ask_price| effective_price >= ask_price, size, &params)?
+        } else {
+            let mut bids = ctx.accounts.order_book.yes_bids.clone();
+            match_orders(&mut bids, |bid_price| effective_price <= bid_price, size, &params)?
+        };
+        let filled = size - unfilled;
+        let notional = fills
+            .iter()
+            .try_fold(0u64, |acc, fill| fill.price.checked_mul(fill.size).and_then(|n| acc.checked_add(n)))
+            .ok_or(ClobError::Overflow)?;
+        // Collateral behind the filled shares, as execute_order charges it
+        let taker_cost = if effective_side == 0 {
+            notional
+        } else {
+            own_notional(false, filled, notional)?
+        };
+        let quote = OrderQuote {
+            filled,
+            unfilled,
+            cost: market.to_collateral(taker_cost)?,
+            taker_fee: market.taker_fee(taker_cost)?,
+            avg_price: own_notional(is_yes, filled, notional)?.checked_div(filled).unwrap_or(0),
+        };
+        msg!("Quote: {} filled at avg {} bps, {} unfilled", quote.filled, quote.avg_price, quote.unfilled);
+        Ok(quote)
+    }
+
+    /// Read-only: where a resting order sits in the fill queue. side is the
+    /// YES book side as in place_order (0 = bids, 1 = asks). Under pro-rata
+    /// matching size_ahead only covers better-priced levels fully; orders at
+    /// the same price share fills rather than queueing.
+    pub fn get_order_rank(ctx: Context<ViewOrderBook>, side: u8, order_id: u64) -> Result<OrderRank> {
+        ctx.accounts.order_book.order_rank(side == 0, order_id, Clock::get()?.unix_timestamp)
+    }
+
+    /// Read-only: implied YES probability in bps as the mid of the best bid
+    /// and ask (or the one live quote on a one-sided book), plus the last
+    /// trade price
+    pub fn get_mid_price(ctx: Context<ViewOrderBook>) -> Result<MidPrice> {
+        let top = ctx.accounts.order_book.top_of_book(Clock::get()?.unix_timestamp);
+        Ok(MidPrice {
+            mid_price: top.mid_price(),
+            last_trade_price: ctx.accounts.market.last_trade_price,
+        })
+    }
+
+    /// Read-only: one-call snapshot of a parimutuel market for UIs, with
+    /// each outcome's share of the pool as its implied odds
+    pub fn get_market_state(ctx: Context<ViewMarket>) -> Result<MarketState> {
+        let market = &ctx.accounts.market;
+        let now = Clock::get()?.unix_timestamp;
+        let status = if market.is_refundable_at(now) {
+            MARKET_STATUS_VOID
+        } else if market.resolved {
+            MARKET_STATUS_RESOLVED
+        } else if now >= market.resolution_time {
+            MARKET_STATUS_AWAITING_RESOLUTION
+        } else if market.paused {
+            MARKET_STATUS_PAUSED
+        } else {
+            MARKET_STATUS_OPEN
+        };
+        let odds_bps = market
+            .outcome_pools
+            .iter()
+            .map(|pool| {
+                (*pool as u128 * BPS_MAX as u128)
+                    .checked_div(market.total_pool as u128)
+                    .unwrap_or(0) as u64
+            })
+            .collect();
+        
+        Ok(MarketState {
+            status,
+            winning_outcome: market.winning_outcome,
+            total_pool: market.total_pool,
+            outcome_pools: market.outcome_pools.clone(),
+            odds_bps,
+            seconds_to_resolution: market.resolution_time.saturating_sub(now).max(0),
+        })
+    }
+
+    /// Read-only: one-call snapshot of a CLOB market for UIs, combining its
+    /// status with get_top_of_book and get_mid_price
+    pub fn get_clob_market_state(ctx: Context<ViewOrderBook>) -> Result<ClobMarketState> {
+        let market = &ctx.accounts.market;
+        let now = Clock::get()?.unix_timestamp;
+        let status = if market.is_void_at(now) {
+            MARKET_STATUS_VOID
+        } else if market.resolved {
+            MARKET_STATUS_RESOLVED
+        } else if now >= market.resolution_time {
+            MARKET_STATUS_AWAITING_RESOLUTION
+        } else if market.paused {
+            MARKET_STATUS_PAUSED
+        } else {
+            MARKET_STATUS_OPEN
+        };
+        let top = ctx.accounts.order_book.top_of_book(now);
+        
+        Ok(ClobMarketState {
+            status,
+            winning_side: market.winning_side,
+            seconds_to_resolution: market.resolution_time.saturating_sub(now).max(0),
+            best_bid: top.best_bid,
+            best_ask: top.best_ask,
+            mid_price: top.mid_price(),
+            last_trade_price: market.last_trade_price,
+            open_interest: market.open_interest,
+        })
+    }
+
+    /// Read-only: what claim_winnings would pay the position right now,
+    /// including the void refund case. Fails the same way the claim would.
+    pub fn estimate_winnings(ctx: Context<ViewPosition>) -> Result<WinningsEstimate> {
+        // Settle copies so nothing is written back
+        let mut market = (*ctx.accounts.market).clone();
+        let mut position = (*ctx.accounts.position).clone();
+        let claim = market.settle_claim(&mut position, Clock::get()?.unix_timestamp)?;
+        let estimate = WinningsEstimate {
+            payout: claim.payout.checked_add(claim.fee).ok_or(ErrorCode::Overflow)?,
+            fee: claim.fee,
+            net_payout: claim.payout,
+        };
+        msg!("Estimated payout {} less {} fee = {}", estimate.payout, estimate.fee, estimate.net_payout);
+        Ok(estimate)
+    }
+
+    /// Read-only: what claim_clob_winnings would pay the position right
+    /// now. CLOB claims carry no fee, so fee is always 0.
+    pub fn estimate_clob_winnings(ctx: Context<ViewClobPosition>) -> Result<WinningsEstimate> {
+        let mut market = (*ctx.accounts.market).clone();
+        let mut position = (*ctx.accounts.position).clone();
+        let payout = market.settle_claim(&mut position, 0, Clock::get()?.unix_timestamp)?;
+        msg!("Estimated payout {}", payout);
+        Ok(WinningsEstimate { payout, fee: 0, net_payout: payout })
+    }
+
+    /// Read-only: a CLOB position in the trader's own terms. Fills are
+    /// matched in YES terms, so a NO buy shows up as no_shares from the YES
+    /// bid side; this nets YES against NO and values what's left at the
+    /// mid price (the last trade on an empty book, or the settled value once
+    /// resolved or void). Entry prices aren't stored, so cost basis is not
+    /// reported.
+    pub fn get_clob_position(ctx: Context<ViewClobPositionValue>) -> Result<ClobPositionView> {
+        let market = &ctx.accounts.market;
+        let position = &ctx.accounts.position;
+        let now = Clock::get()?.unix_timestamp;
+        let mark_price = if market.is_void_at(now) {
+            Some(VOID_PAYOUT_BPS)
+        } else if let Some(winning_side) = market.winning_side {
+            Some(if winning_side == 0 { BPS_MAX } else { 0 })
+        } else {
+            ctx.accounts.order_book
+                .top_of_book(now)
+                .mid_price()
+                .or(Some(market.last_trade_price).filter(|price| *price > 0))
+        };
+        
+        let (yes_value, no_value) = match mark_price {
+            Some(price) => (
+                market.to_collateral(position.yes_shares.checked_mul(price).ok_or(ClobError::Overflow)?)?,
+                market.to_collateral(position.no_shares.checked_mul(BPS_MAX - price).ok_or(ClobError::Overflow)?)?,
+            ),
+            None => (0, 0),
+        };
+        let view = ClobPositionView {
+            yes_shares: position.yes_shares,
+            no_shares: position.no_shares,
+            net_yes: position.yes_shares.saturating_sub(position.no_shares),
+            net_no: position.no_shares.saturating_sub(position.yes_shares),
+            mark_price,
+            yes_value,
+            no_value,
+            unrealized_value: yes_value.checked_add(no_value).ok_or(ClobError::Overflow)?,
+        };
+        msg!(
+            "Net {} YES / {} NO, worth {} at mark {:?}",
+            view.net_yes,
+            view.net_no,
+            view.unrealized_value,
+            view.mark_price
+        );
+        Ok(view)
+    }
+
+    /// Read-only: total claimable across many lamport-market positions of
+    /// the signer. remaining_accounts holds (market, position) pairs, each
+    /// parimutuel or CLOB. Positions with nothing to claim yet count as 0.
+    pub fn batch_estimate<'info>(ctx: Context<'_, '_, '_, 'info, BatchEstimate<'info>>) -> Result<WinningsEstimate> {
+        let pairs = ctx.remaining_accounts;
+        require!(!pairs.is_empty() && pairs.len().is_multiple_of(2), ErrorCode::InvalidAccountPairs);
+        
+        let owner = ctx.accounts.owner.key();
+        let now = Clock::get()?.unix_timestamp;
+        let mut total = WinningsEstimate::default();
+        for pair in pairs.chunks(2) {
+            let estimate = estimate_pair(&pair[0], &pair[1], owner, now)?;
+            msg!("{}: {} less {} fee = {}", pair[0].key(), estimate.payout, estimate.fee, estimate.net_payout);
+            total.payout = total.payout.checked_add(estimate.payout).ok_or(ErrorCode::Overflow)?;
+            total.fee = total.fee.checked_add(estimate.fee).ok_or(ErrorCode::Overflow)?;
+            total.net_payout = total.net_payout.checked_add(estimate.net_payout).ok_or(ErrorCode::Overflow)?;
+        }
+        msg!("Total: {} less {} fee = {}", total.payout, total.fee, total.net_payout);
+        Ok(total)
+    }
+
+    // ===========================================
+    // MARKET REGISTRY
+    // ===========================================
+
+    /// Drop a market from its authority's registry. Only markets that can no
+    /// longer trade or settle normally qualify: closed accounts, cancelled
+    /// or void parimutuel markets, and void CLOB markets.
+    pub fn remove_from_registry(ctx: Context<RemoveFromRegistry>) -> Result<()> {
+        let market = &ctx.accounts.market;
+        let now = Clock::get()?.unix_timestamp;
+        let live = if market.owner != &crate::ID || market.data_is_empty() {
+            false
+        } else if let Ok(m) = Market::try_deserialize(&mut &market.data.borrow()[..]) {
+            !m.is_refundable_at(now)
+        } else if let Ok(m) = ClobMarket::try_deserialize(&mut &market.data.borrow()[..]) {
+            !m.is_void_at(now)
+        } else {
+            false
+        };
+        require!(!live, ErrorCode::MarketStillActive);
+        
+        ctx.accounts.registry.remove(market.key())?;
+        msg!("Removed {} from registry", market.key());
+        Ok(())
+    }
+
+    /// Open the caller's ReferralAccount so front-ends can be named as
+    /// referrer in buy_shares and place_order
+    pub fn init_referral(ctx: Context<InitReferral>) -> Result<()> {
+        let referral = &mut ctx.accounts.referral;
+        referral.referrer = ctx.accounts.referrer.key();
+        referral.unclaimed = 0;
+        referral.total_earned = 0;
+        referral.bump = ctx.bumps.referral;
+        msg!("Referral account opened for {}", referral.referrer);
+        Ok(())
+    }
+
+    /// Upgrade a Market, ClobMarket or OrderBook created under an older
+    /// layout to the current one: grow it if needed, with the payer covering
+    /// the extra rent, and stamp the current version. Existing fields are
+    /// left as they are and new ones start zeroed, so anyone may pay for it.
+    /// Accounts from the original program predate version 0 and are
+    /// rejected, see ORIGINAL_MARKET_SPACE.
+    pub fn migrate_market(ctx: Context<MigrateMarket>) -> Result<()> {
+        let account = ctx.accounts.market.to_account_info();
+        require!(
+            account.owner == &crate::ID && account.data_len() >= 8,
+            ErrorCode::UnknownAccountType
+        );
+        let discriminator = account.data.borrow()[..8].to_vec();
+        let original_space = if discriminator == Market::DISCRIMINATOR {
+            ORIGINAL_MARKET_SPACE
+        } else if discriminator == ClobMarket::DISCRIMINATOR {
+            ORIGINAL_CLOB_MARKET_SPACE
+        } else if discriminator == OrderBook::DISCRIMINATOR {
+            ORIGINAL_ORDER_BOOK_SPACE
+        } else {
+            0
+        };
+        require!(account.data_len() != original_space, ErrorCode::UnsupportedLayout);
+        
+        let payer = &ctx.accounts.payer;
+        let system_program = &ctx.accounts.system_program;
+        if discriminator == Market::DISCRIMINATOR {
+            migrate_account::<Market>(&account, payer, system_program)
+        } else if discriminator == ClobMarket::DISCRIMINATOR {
+            migrate_account::<ClobMarket>(&account, payer, system_program)
+        } else if discriminator == OrderBook::DISCRIMINATOR {
+            upgrade_legacy_orders(&account, payer, system_program)?;
+            migrate_account::<OrderBook>(&account, payer, system_program)
+        } else {
+            err!(ErrorCode::UnknownAccountType)
+        }
+    }
+
+    /// Pay out the referral fees credited to the caller's ReferralAccount
+    pub fn claim_referral_fees(ctx: Context<ClaimReferralFees>) -> Result<()> {
+        let referral = &mut ctx.accounts.referral;
+        let amount = referral.unclaimed;
+        require!(amount > 0, ErrorCode::NoReferralFees);
+        referral.unclaimed = 0;
+        
+        pay_from_market(&referral.to_account_info(), &ctx.accounts.referrer.to_account_info(), amount)?;
+        
+        msg!("Claimed {} lamports of referral fees", amount);
+        Ok(())
+    }
+}
+
+// === Account Migration ===
+
+/// Accounts whose layout carries a version byte, upgraded by migrate_market
+trait Versioned: AccountSerialize + AccountDeserialize {
+    const CURRENT_VERSION: u8;
+    /// Bytes the current layout appends to a version 0 account, at least
+    /// when its new fields are empty
+    const ADDED_SINCE_V0: usize;
+    fn version(&self) -> u8;
+    fn set_version(&mut self, version: u8);
+    /// Size the account needs under the current layout
+    fn space(&self) -> usize;
+    /// Fill in appended fields that shouldn't start at zero, for an account
+    /// upgraded from version `from`
+    fn backfill(&mut self, _from: u8) {}
+}
+
+impl Versioned for Market {
+    const CURRENT_VERSION: u8 = MARKET_VERSION;
+    const ADDED_SINCE_V0: usize = 1 + 4 + 1 + 4 + 32 + 8 + 8 + 8 + 1;
+    fn version(&self) -> u8 { self.version }
+    fn set_version(&mut self, version: u8) { self.version = version; }
+    fn space(&self) -> usize { Market::SPACE }
+}
+
+impl Versioned for ClobMarket {
+    const CURRENT_VERSION: u8 = CLOB_MARKET_VERSION;
+    const ADDED_SINCE_V0: usize = 1 + 1 + 1 + 8 + 4 + 32 + 32 + 8 + 8 + 1;
+    fn version(&self) -> u8 { self.version }
+    fn set_version(&mut self, version: u8) { self.version = version; }
+    fn space(&self) -> usize { 8 + ClobMarket::INIT_SPACE }
+    fn backfill(&mut self, from: u8) {
+        if from < 7 {
+            self.yes_outstanding = self.open_interest;
+            self.no_outstanding = self.open_interest;
+        }
+    }
+}
+
+impl Versioned for OrderBook {
+    const CURRENT_VERSION: u8 = ORDER_BOOK_VERSION;
+    const ADDED_SINCE_V0: usize = 1;
+    fn version(&self) -> u8 { self.version }
+    fn set_version(&mut self, version: u8) { self.version = version; }
+    fn space(&self) -> usize { OrderBook::space(self.capacity as usize) }
+}
+
+fn migrate_account<'info, T: Versioned>(
+    account: &AccountInfo<'info>,
+    payer: &Signer<'info>,
+    system_program: &Program<'info, System>,
+) -> Result<()> {
+    // Older accounts only lack the fields appended since, which read as
+    // zero from unused space. One with no unused space left can't be read
+    // until it has room for them.
+    if T::try_deserialize(&mut &account.data.borrow()[..]).is_err() {
+        grow_account(account, payer, system_program, account.data_len() + T::ADDED_SINCE_V0)?;
+    }
+    let mut data = T::try_deserialize(&mut &account.data.borrow()[..])?;
+    let from = data.version();
+    require!(from < T::CURRENT_VERSION, ErrorCode::AlreadyMigrated);
+    
+    let space = data.space();
+    if account.data_len() < space {
+        grow_account(account, payer, system_program, space)?;
+    }
+    data.backfill(from);
+    data.set_version(T::CURRENT_VERSION);
+    data.try_serialize(&mut &mut account.data.borrow_mut()[..])?;
+    
+    msg!("Migrated {} from version {} to {}", account.key(), from, T::CURRENT_VERSION);
+    Ok(())
+}
+
+/// An Order as laid out before OrderBook version 2, without `filled`
+#[derive(AnchorDeserialize)]
+struct LegacyOrder {
+    owner: Pubkey,
+    price: u64,
+    size: u64,
+    timestamp: i64,
+    order_id: u64,
+    expiry: i64,
+}
+
+#[derive(AnchorDeserialize)]
+struct LegacyOrderBook {
+    market: Pubkey,
+    yes_bids: Vec<LegacyOrder>,
+    yes_asks: Vec<LegacyOrder>,
+    order_seq: u64,
+    total_bid_size: u64,
+    total_ask_size: u64,
+    capacity: u16,
+    bump: u8,
+}
+
+/// Rewrite an order book from before version 2 in the current Order
+/// layout, growing it to fit, and leave the version for migrate_account to
+/// stamp. Fills before the upgrade weren't recorded, so `filled` starts at 0.
+/// Books already in the current layout are left alone.
+fn upgrade_legacy_orders<'info>(
+    account: &AccountInfo<'info>,
+    payer: &Signer<'info>,
+    system_program: &Program<'info, System>,
+) -> Result<()> {
+    let (legacy, version) = {
+        let data = account.data.borrow();
+        let mut rest = &data[8..];
+        let Ok(legacy) = LegacyOrderBook::deserialize(&mut rest) else {
+            return Ok(());
+        };
+        // Version 0 books have no version byte, and may have no room for one
+        let version = rest.first().copied().unwrap_or(0);
+        // A current book read with the old layout won't add up
+        let total = |orders: &[LegacyOrder]| orders.iter().try_fold(0u64, |acc, o| acc.checked_add(o.size));
+        if version >= 2
+            || total(&legacy.yes_bids) != Some(legacy.total_bid_size)
+            || total(&legacy.yes_asks) != Some(legacy.total_ask_size)
+        {
+            return Ok(());
+        }
+        (legacy, version)
+    };
+    
+    let upgrade = |orders: Vec<LegacyOrder>| -> Vec<Order> {
+        orders
+            .into_iter()
+            .map(|o| Order {
+                owner: o.owner,
+                price: o.price,
+                size: o.size,
+                timestamp: o.timestamp,
+                order_id: o.order_id,
+                expiry: o.expiry,
+                filled: 0,
+            })
+            .collect()
+    };
+    let book = OrderBook {
+        market: legacy.market,
+        yes_bids: upgrade(legacy.yes_bids),
+        yes_asks: upgrade(legacy.yes_asks),
+        order_seq: legacy.order_seq,
+        total_bid_size: legacy.total_bid_size,
+        total_ask_size: legacy.total_ask_size,
+        capacity: legacy.capacity,
+        bump: legacy.bump,
+        version,
+    };
+    let space = book.space();
+    if account.data_len() < space {
+        grow_account(account, payer, system_program, space)?;
+    }
+    book.try_serialize(&mut &mut account.data.borrow_mut()[..])?;
+    Ok(())
+}
+
+/// Resize a program account, topping up its rent from the payer
+fn grow_account<'info>(
+    account: &AccountInfo<'info>,
+    payer: &Signer<'info>,
+    system_program: &Program<'info, System>,
+    len: usize,
+) -> Result<()> {
+    let shortfall = Rent::get()?.minimum_balance(len).saturating_sub(account.lamports());
+    if shortfall > 0 {
+        let cpi_ctx = CpiContext::new(
+            system_program.to_account_info(),
+            Transfer {
+                from: payer.to_account_info(),
+                to: account.clone(),
+            },
+        );
+        transfer(cpi_ctx, shortfall)?;
+    }
+    account.resize(len)?;
+    Ok(())
+}
+
+// === Payout Estimates ===
+
+/// What one (market, position) pair of batch_estimate would pay its owner
+/// now, settled on copies. A claim that would fail counts as nothing owed.
+fn estimate_pair(market: &AccountInfo, position: &AccountInfo, owner: Pubkey, now: i64) -> Result<WinningsEstimate> {
+    require!(
+        market.owner == &crate::ID && position.owner == &crate::ID,
+        ErrorCode::InvalidAccountPairs
+    );
+    if let Ok(mut market_data) = Market::try_deserialize(&mut &market.data.borrow()[..]) {
+        let mut position_data = Position::try_deserialize(&mut &position.data.borrow()[..])?;
+        require!(position_data.market == market.key(), ErrorCode::InvalidAccountPairs);
+        require!(position_data.owner == owner, ErrorCode::Unauthorized);
+        require!(market_data.mint == Pubkey::default(), ErrorCode::CollateralMismatch);
+        return Ok(match market_data.settle_claim(&mut position_data, now) {
+            Ok(claim) => WinningsEstimate {
+                payout: claim.payout.checked_add(claim.fee).ok_or(ErrorCode::Overflow)?,
+                fee: claim.fee,
+                net_payout: claim.payout,
+            },
+            Err(_) => WinningsEstimate::default(),
+        });
+    }
+    
+    let mut market_data = ClobMarket::try_deserialize(&mut &market.data.borrow()[..])?;
+    let mut position_data = ClobPosition::try_deserialize(&mut &position.data.borrow()[..])?;
+    require!(position_data.market == market.key(), ErrorCode::InvalidAccountPairs);
+    require!(position_data.owner == owner, ErrorCode::Unauthorized);
+    require!(market_data.mint == Pubkey::default(), ErrorCode::CollateralMismatch);
+    let payout = market_data.settle_claim(&mut position_data, 0, now).unwrap_or(0);
+    Ok(WinningsEstimate { payout, fee: 0, net_payout: payout })
+}
+
+// === Vault Helpers ===
+
+/// Settle a parimutuel claim and move the payout out of the market account.
+/// Any fee stays in the market account until the authority withdraws it,
+/// less the referrer's cut, which moves to their referral account now.
+fn claim_lamports<'info>(
+    market: &mut Account<'info, Market>,
+    position: &mut Account<'info, Position>,
+    claimer: &Signer<'info>,
+    realized_pnl: &mut RealizedPnl,
+    remaining_accounts: &[AccountInfo],
+) -> Result<()> {
+    require!(position.market == market.key(), ErrorCode::PositionMarketMismatch);
+    let claim = market.settle_claim(position, Clock::get()?.unix_timestamp)?;
+    position.emit_changed(if claim.void_refund { POSITION_CHANGE_REFUND } else { POSITION_CHANGE_CLAIM });
+    
+    pay_from_market(&market.to_account_info(), &claimer.to_account_info(), claim.payout)?;
+    
+    let referral_fee = referral_cut(claim.fee, market.referral_fee_bps, position.referrer);
+    if referral_fee > 0 {
+        let referral = find_referral(remaining_accounts, position.referrer)?;
+        market.fee_collected -= referral_fee;
+        pay_from_market(&market.to_account_info(), referral, referral_fee)?;
+        credit_referral(referral, referral_fee)?;
+    }
+    
+    if claim.void_refund {
+        realized_pnl.record_refund(claim.payout);
+        msg!("Market void, refunded {} lamports", claim.payout);
+    } else {
+        realized_pnl.record_claim(claim.payout);
+        msg!("Claimed {} lamports (fee: {})", claim.payout, claim.fee);
+    }
+    Ok(())
+}
+
+/// Move lamports out of a program-owned account (a parimutuel market or a
+/// referral account), never dipping below its rent-exempt minimum.
+///
+/// Callers settle the position or zero the balance being paid before calling,
+/// so a failed transfer reverts the whole instruction and no path reads state
+/// that shows a claim as both unpaid and paid.
+fn pay_from_market(from: &AccountInfo, recipient: &AccountInfo, amount: u64) -> Result<()> {
+    let rent_minimum = Rent::get()?.minimum_balance(from.data_len());
+    let from_balance = from
+        .lamports()
+        .checked_sub(amount)
+        .filter(|balance| *balance >= rent_minimum)
+        .ok_or(ErrorCode::VaultInsolvent)?;
+    let recipient_balance = recipient.lamports().checked_add(amount).ok_or(ErrorCode::Overflow)?;
+    **from.try_borrow_mut_lamports()? = from_balance;
+    **recipient.try_borrow_mut_lamports()? = recipient_balance;
+    Ok(())
+}
+
+/// Move tokens out of a token market's vault, signed by the market PDA
+fn pay_from_token_vault<'info>(
+    market: &Account<'info, Market>,
+    vault: &Account<'info, TokenAccount>,
+    recipient: &Account<'info, TokenAccount>,
+    token_program: &Program<'info, Token>,
+    amount: u64,
+) -> Result<()> {
+    let bump = [market.bump];
+    let signer_seeds: &[&[&[u8]]] = &[&[b"market", market.market_id.as_bytes(), &bump]];
+    transfer_tokens_signed(market.to_account_info(), vault, recipient, token_program, signer_seeds, amount)
+}
+
+/// Move tokens out of a token CLOB market's vault, signed by the market PDA
+fn pay_from_clob_token_vault<'info>(
+    market: &Account<'info, ClobMarket>,
+    vault: &Account<'info, TokenAccount>,
+    recipient: &Account<'info, TokenAccount>,
+    token_program: &Program<'info, Token>,
+    amount: u64,
+) -> Result<()> {
+    let bump = [market.bump];
+    let signer_seeds: &[&[&[u8]]] = &[&[b"clob_market", market.market_id.as_bytes(), &bump]];
+    transfer_tokens_signed(market.to_account_info(), vault, recipient, token_program, signer_seeds, amount)
+}
+
+fn transfer_tokens_signed<'info>(
+    authority: AccountInfo<'info>,
+    vault: &Account<'info, TokenAccount>,
+    recipient: &Account<'info, TokenAccount>,
+    token_program: &Program<'info, Token>,
+    signer_seeds: &[&[&[u8]]],
+    amount: u64,
+) -> Result<()> {
+    let cpi_ctx = CpiContext::new_with_signer(
+        token_program.to_account_info(),
+        token::Transfer {
+            from: vault.to_account_info(),
+            to: recipient.to_account_info(),
+            authority,
+        },
+        signer_seeds,
+    );
+    token::transfer(cpi_ctx, amount)
+}
+
+// === Referrals ===
+
+/// The referrer's share of a fee, nothing without a referrer
+fn referral_cut(fee: u64, referral_fee_bps: u64, referrer: Pubkey) -> u64 {
+    if referrer == Pubkey::default() {
+        return 0;
+    }
+    (fee as u128 * referral_fee_bps as u128 / BPS_MAX as u128) as u64
+}
+
+/// The referrer's ReferralAccount among the remaining accounts
+fn find_referral<'a, 'info>(remaining_accounts: &'a [AccountInfo<'info>], referrer: Pubkey) -> Result<&'a AccountInfo<'info>> {
+    let (key, _) = Pubkey::find_program_address(&[b"referral", referrer.as_ref()], &crate::ID);
+    remaining_accounts
+        .iter()
+        .find(|a| a.key() == key && a.is_writable)
+        .ok_or(error!(ErrorCode::ReferralAccountMissing))
+}
+
+/// Book lamports already moved into a referral account as claimable
+fn credit_referral(account: &AccountInfo, amount: u64) -> Result<()> {
+    let mut referral = ReferralAccount::try_deserialize(&mut &account.data.borrow()[..])?;
+    referral.unclaimed = referral.unclaimed.checked_add(amount).ok_or(ErrorCode::Overflow)?;
+    referral.total_earned = referral.total_earned.saturating_add(amount);
+    referral.try_serialize(&mut &mut account.data.borrow_mut()[..])?;
+    Ok(())
+}
+
+/// Lock in a CLOB market's outcome and start its dispute period
+fn finalize_clob_resolution(market: &mut Account<ClobMarket>, winning_side: u8, now: i64) -> Result<()> {
+    require!(market.early_resolution || now >= market.resolution_time, ClobError::ResolutionTimeNotReached);
+    market.resolved = true;
+    market.winning_side = Some(winning_side);
+    market.resolved_at = now;
+    
+    emit!(ClobMarketResolved {
+        market: market.key(),
+        market_id: market.market_id.clone(),
+        authority: market.authority,
+        winning_side,
+        resolved_at: now,
+        resolution_uri: market.resolution_uri.clone(),
+        resolution_source: market.resolution_source,
+    });
+    
+    msg!("CLOB Market resolved: {} wins", if winning_side == 0 { "YES" } else { "NO" });
+    Ok(())
+}
+
+fn set_market_paused(ctx: Context<ResolveMarket>, paused: bool) -> Result<()> {
+    let market = &mut ctx.accounts.market;
+    require!(ctx.accounts.authority.key() == market.authority, ErrorCode::Unauthorized);
+    
+    market.paused = paused;
+    emit!(MarketPauseChanged { market: market.key(), paused });
+    msg!("Market {}", if paused { "paused" } else { "unpaused" });
+    Ok(())
+}
+
+fn set_clob_market_paused(ctx: Context<ResolveClobMarket>, paused: bool) -> Result<()> {
+    let market = &mut ctx.accounts.market;
+    require!(ctx.accounts.authority.key() == market.authority, ClobError::Unauthorized);
+    
+    market.paused = paused;
+    emit!(MarketPauseChanged { market: market.key(), paused });
+    msg!("CLOB Market {}", if paused { "paused" } else { "unpaused" });
+    Ok(())
+}
+
+/// Move lamports out of a CLOB vault, never dipping below its reserve
+fn pay_from_vault<'info>(
+    vault: &AccountInfo<'info>,
+    recipient: &AccountInfo<'info>,
+    amount: u64,
+    reserve: u64,
+) -> Result<()> {
+    let available = vault.lamports().saturating_sub(reserve);
+    require!(amount <= available, ClobError::VaultReserveBreached);
+    
+    let vault_balance = vault.lamports().checked_sub(amount).ok_or(ClobError::VaultInsolvent)?;
+    let recipient_balance = recipient.lamports().checked_add(amount).ok_or(ClobError::Overflow)?;
+    **vault.try_borrow_mut_lamports()? = vault_balance;
+    **recipient.try_borrow_mut_lamports()? = recipient_balance;
+    Ok(())
+}
+
+/// Emit OrderCancelled for each removed order and return the combined
+/// refund in the market's collateral units
+fn settle_cancelled(
+    market: &Account<ClobMarket>,
+    owner: Pubkey,
+    cancelled: &[(bool, Order)],
+) -> Result<u64> {
+    let mut refund = 0u64;
+    for (is_bid, order) in cancelled {
+        let order_refund = market.to_collateral(order.collateral(*is_bid)?)?;
+        refund = refund.checked_add(order_refund).ok_or(ClobError::Overflow)?;
+        emit!(OrderCancelled {
+            market: market.key(),
+            owner,
+            order_id: order.order_id,
+            is_bid: *is_bid,
+            size: order.size,
+            filled: order.filled,
+            refund: order_refund,
+        });
+    }
+    Ok(refund)
+}
+
+/// Refund orders already removed from a lamport market's book
+fn refund_cancelled(ctx: Context<CancelOrder>, cancelled: &[(bool, Order)]) -> Result<()> {
+    let trader = ctx.accounts.trader.key();
+    let refund = settle_cancelled(&ctx.accounts.market, trader, cancelled)?;
+    
+    if refund > 0 {
+        pay_from_vault(
+            &ctx.accounts.vault,
+            &ctx.accounts.trader.to_account_info(),
+            refund,
+            ctx.accounts.market.vault_floor(),
+        )?;
+    }
+    
+    let realized_pnl = &mut ctx.accounts.realized_pnl;
+    realized_pnl.init_if_needed(trader, ctx.bumps.realized_pnl);
+    realized_pnl.record_refund(refund);
+    
+    msg!("Cancelled {} orders, refunded {} lamports", cancelled.len(), refund);
+    Ok(())
+}
+
+/// Refund orders already removed from a token market's book
+fn refund_cancelled_tokens(ctx: Context<CancelOrderToken>, cancelled: &[(bool, Order)]) -> Result<()> {
+    let refund = settle_cancelled(&ctx.accounts.market, ctx.accounts.trader.key(), cancelled)?;
+    
+    if refund > 0 {
+        pay_from_clob_token_vault(
+            &ctx.accounts.market,
+            &ctx.accounts.vault,
+            &ctx.accounts.trader_token_account,
+            &ctx.accounts.token_program,
+            refund,
+        )?;
+    }
+    
+    msg!("Cancelled {} orders, refunded {} tokens", cancelled.len(), refund);
+    Ok(())
+}
+
+/// Apply an amendment to the trader's resting order and return the
+/// collateral to collect from and refund to them, in collateral units.
+/// The new terms go through the same checks as a new order.
+fn amend_resting_order(
+    market: &ClobMarket,
+    order_book: &mut OrderBook,
+    trader: Pubkey,
+    order_id: u64,
+    new_price: u64,
+    new_size: u64,
+) -> Result<(u64, u64)> {
+    market.check_order_terms(new_price, new_size)?;
+    require!(
+        new_price >= market.min_price && new_price <= market.max_price,
+        ClobError::PriceOutOfBand
+    );
+    require!(!market.resolved, ClobError::MarketResolved);
+    require!(!market.voided, ClobError::MarketVoid);
+    require!(!market.paused, ClobError::MarketPaused);
+    let now = Clock::get()?.unix_timestamp;
+    require!(now < market.resolution_time, ClobError::MarketExpired);
+    
+    let (is_bid, old) = order_book.amend_order(trader, order_id, new_price, new_size, now)?;
+    let old_collateral = market.to_collateral(old.collateral(is_bid)?)?;
+    let amended = Order { price: new_price, size: new_size, ..old.clone() };
+    let new_collateral = market.to_collateral(amended.collateral(is_bid)?)?;
+    
+    emit!(OrderAmended {
+        market: order_book.market,
+        owner: trader,
+        order_id,
+        is_bid,
+        old_price: old.price,
+        old_size: old.size,
+        new_price,
+        new_size,
+        filled: old.filled,
+    });
+    msg!("Amended order {}: {} @ {} bps -> {} @ {} bps", order_id, old.size, old.price, new_size, new_price);
+    Ok((
+        new_collateral.saturating_sub(old_collateral),
+        old_collateral.saturating_sub(new_collateral),
+    ))
+}
+
+/// Reject the authority on a market that bars it from trading, and on a
+/// permissioned market require the trader's AllowlistEntry among
+/// remaining_accounts. Program ownership plus the account discriminator
+/// mean only add_trader can have created it.
+fn require_allowed(market: &Account<ClobMarket>, trader: Pubkey, remaining_accounts: &[AccountInfo]) -> Result<()> {
+    require!(!market.authority_cannot_trade || trader != market.authority, ClobError::AuthorityCannotTrade);
+    if !market.permissioned {
+        return Ok(());
+    }
+    let allowed = remaining_accounts.iter().any(|a| {
+        a.owner == &crate::ID
+            && AllowlistEntry::try_deserialize(&mut &a.data.borrow()[..])
+                .is_ok_and(|entry| entry.market == market.key() && entry.trader == trader)
+    });
+    require!(allowed, ClobError::TraderNotAllowed);
+    Ok(())
+}
+
+/// Credit each fill's notional to the volume_traded, and its rebate to the
+/// rebates_owed, of maker positions found in remaining_accounts. Anything
+/// that isn't a writable position of this market is ignored, so the other
+/// remaining accounts can share the list. Volume for makers left out is
+/// dropped, but a maker owed a rebate must be present.
+fn credit_makers(market: Pubkey, remaining_accounts: &[AccountInfo], maker_fills: &[MakerFill]) -> Result<()> {
+    if maker_fills.is_empty() {
+        return Ok(());
+    }
+    let mut credited = Vec::new();
+    for account in remaining_accounts.iter().filter(|a| a.is_writable && a.owner == &crate::ID) {
+        let Ok(mut position) = ClobPosition::try_deserialize(&mut &account.data.borrow()[..]) else {
+            continue;
+        };
+        // A position listed twice is only credited once
+        if position.market != market || credited.contains(&position.owner) {
+            continue;
+        }
+        let fills = maker_fills.iter().filter(|fill| fill.maker == position.owner);
+        let (volume, rebate) = fills.fold((0u64, 0u64), |(volume, rebate), fill| {
+            (volume.saturating_add(fill.notional), rebate.saturating_add(fill.rebate))
+        });
+        if volume > 0 {
+            position.volume_traded = position.volume_traded.saturating_add(volume);
+            position.rebates_owed = position.rebates_owed.checked_add(rebate).ok_or(ClobError::Overflow)?;
+            position.try_serialize(&mut &mut account.data.borrow_mut()[..])?;
+            credited.push(position.owner);
+        }
+    }
+    require!(
+        maker_fills.iter().all(|fill| fill.rebate == 0 || credited.contains(&fill.maker)),
+        ClobError::MakerPositionMissing
+    );
+    Ok(())
+}
+
+/// Refund maker orders matching left below min_order_size. Each owner's
+/// wallet must be in remaining_accounts.
+fn refund_dust<'info>(
+    market: &Account<'info, ClobMarket>,
+    vault: &AccountInfo<'info>,
+    remaining_accounts: &[AccountInfo<'info>],
+    dust: &[(bool, Order)],
+) -> Result<()> {
+    refund_to_owners(market, vault, remaining_accounts, dust, ClobError::DustOwnerMissing)
+}
+
+/// Refund orders already removed from a lamport market's book to their
+/// owners' wallets in remaining_accounts, failing with `missing` when one
+/// isn't there
+fn refund_to_owners<'info>(
+    market: &Account<'info, ClobMarket>,
+    vault: &AccountInfo<'info>,
+    remaining_accounts: &[AccountInfo<'info>],
+    orders: &[(bool, Order)],
+    missing: ClobError,
+) -> Result<()> {
+    for entry in orders {
+        let owner = entry.1.owner;
+        let wallet = remaining_accounts
+            .iter()
+            .find(|a| a.key() == owner)
+            .ok_or(missing)?;
+        let refund = settle_cancelled(market, owner, std::slice::from_ref(entry))?;
+        if refund > 0 {
+            pay_from_vault(vault, wallet, refund, market.vault_floor())?;
+        }
+    }
+    Ok(())
+}
+
+/// Token version of refund_dust. Each owner's token account for the
+/// market's mint must be in remaining_accounts.
+fn refund_dust_tokens<'info>(
+    market: &Account<'info, ClobMarket>,
+    vault: &Account<'info, TokenAccount>,
+    token_program: &Program<'info, Token>,
+    remaining_accounts: &'info [AccountInfo<'info>],
+    dust: &[(bool, Order)],
+) -> Result<()> {
+    for entry in dust {
+        let owner = entry.1.owner;
+        let recipient = remaining_accounts
+            .iter()
+            .filter_map(|a| Account::<TokenAccount>::try_from(a).ok())
+            .find(|a| a.owner == owner && a.mint == market.mint)
+            .ok_or(ClobError::DustOwnerMissing)?;
+        let refund = settle_cancelled(market, owner, std::slice::from_ref(entry))?;
+        if refund > 0 {
+            pay_from_clob_token_vault(market, vault, &recipient, token_program, refund)?;
+        }
+    }
+    Ok(())
+}
+
+// === Order Execution ===
+
+/// Arguments of one place_order call
+#[derive(Clone, Copy)]
+struct OrderRequest {
+    side: u8,
+    is_yes: bool,
+    price: u64,
+    size: u64,
+    order_type: u8,
+    expiry: i64,
+    min_fill_size: u64,
+    avg_price_limit: u64, // Worst average fill price in the order's own terms, 0 = no limit
+    reduce_only: bool, // Cap the size at the trader's opposing net balance
+    referrer: Option<Pubkey>, // Front-end owed referral_fee_bps of the net taker fee
+}
+
+/// Collateral owed for one order, in the market's collateral units
+struct OrderSettlement {
+    collateral: u64, // Reserved for the full order size
+    price_improvement: u64,
+    unfilled_refund: u64,
+    taker_fee: u64,
+    referral_fee: u64, // Part of taker_fee paid straight to the referrer, not the vault
+    filled: u64, // Shares matched immediately
+    resting_order_id: Option<u64>, // Set when the remainder rests on the book
+    dust: Vec<(bool, Order)>, // Maker orders left below min_order_size, already off the book
+    maker_fills: Vec<MakerFill>, // One entry per fill, credited to the makers' positions
+}
+
+impl OrderSettlement {
+    /// What the trader sends to the vault once refunds are netted out
+    fn net_deposit(&self) -> u64 {
+        self.collateral + self.taker_fee - self.referral_fee - self.price_improvement - self.unfilled_refund
+    }
+}
+
+/// The YES-denominated side and price of an order: buying NO at p is
+/// selling YES at BPS_MAX - p, and the other way round
+fn yes_terms(side: u8, is_yes: bool, price: u64) -> (u8, u64) {
+    if is_yes {
+        (side, price)
+    } else {
+        let flipped_side = if side == 0 { 1 } else { 0 };
+        (flipped_side, BPS_MAX - price)
+    }
+}
+
+/// Total price of `filled` shares in the order's own terms, from the sum of
+/// their YES fill prices. A NO order's fills are priced at BPS_MAX minus
+/// the YES price.
+fn own_notional(is_yes: bool, filled: u64, filled_notional: u64) -> Result<u64> {
+    if is_yes {
+        return Ok(filled_notional);
+    }
+    Ok(BPS_MAX
+        .checked_mul(filled)
+        .and_then(|pairs| pairs.checked_sub(filled_notional))
+        .ok_or(ClobError::Overflow)?)
+}
+
+/// Rest the seed_liquidity bid and ask on a new market's book for the
+/// authority and return the collateral they lock, in collateral units. The
+/// orders follow the same price, size and band rules as place_order.
+fn seed_order_book(market: &mut ClobMarket, order_book: &mut OrderBook, seed: &SeedLiquidity) -> Result<u64> {
+    require!(seed.half_spread > 0, ClobError::InvalidSeed);
+    require!(!market.authority_cannot_trade, ClobError::AuthorityCannotTrade);
+    let bid_price = seed.price.checked_sub(seed.half_spread).ok_or(ClobError::InvalidPrice)?;
+    let ask_price = seed.price.checked_add(seed.half_spread).ok_or(ClobError::InvalidPrice)?;
+    let now = Clock::get()?.unix_timestamp;
+    
+    let mut collateral = 0u64;
+    for (is_bid, price) in [(true, bid_price), (false, ask_price)] {
+        market.check_order_terms(price, seed.size)?;
+        require!(price >= market.min_price && price <= market.max_price, ClobError::PriceOutOfBand);
+        let order = Order {
+            owner: market.authority,
+            price,
+            size: seed.size,
+            timestamp: now,
+            order_id: order_book.next_order_id()?,
+            expiry: 0,
+            filled: 0,
+        };
+        collateral = collateral.checked_add(order.collateral(is_bid)?).ok_or(ClobError::Overflow)?;
+        if is_bid {
+            require!(order_book.yes_bids.len() < order_book.capacity as usize, ClobError::OrderBookFull);
+            order_book.yes_bids.push(order);
+            order_book.total_bid_size = seed.size;
+        } else {
+            require!(order_book.yes_asks.len() < order_book.capacity as usize, ClobError::OrderBookFull);
+            order_book.yes_asks.push(order);
+            order_book.total_ask_size = seed.size;
+        }
+    }
+    market.seeded = true;
+    
+    msg!("Seeded {} YES @ {} / {} bps", seed.size, bid_price, ask_price);
+    market.to_collateral(collateral)
+}
+
+/// Size a reduce-only order may have: the requested size capped at the net
+/// balance it offsets, rounded down to the lot size. Buying YES or selling
+/// NO adds YES shares, so it can only offset a net NO balance, and the
+/// other way round.
+fn reduce_only_size(market: &ClobMarket, position: &ClobPosition, side: u8, is_yes: bool, size: u64) -> Result<u64> {
+    let adds_yes = (side == 0) == is_yes;
+    let offsettable = if adds_yes {
+        position.no_shares.saturating_sub(position.yes_shares)
+    } else {
+        position.yes_shares.saturating_sub(position.no_shares)
+    };
+    let capped = size.min(offsettable);
+    let capped = capped - capped % market.lot_size;
+    require!(capped > 0, ClobError::ReduceOnlyNothingToReduce);
+    if capped < size {
+        msg!("Reduce-only order cut from {} to {}", size, capped);
+    }
+    Ok(capped)
+}
+
+/// Validate, match and rest one order. Moves no funds; the caller collects
+/// `net_deposit()` in whatever collateral the market uses.
+fn execute_order(
+    market: &mut ClobMarket,
+    order_book: &mut OrderBook,
+    position: &mut ClobPosition,
+    price_history: &mut PriceHistory,
+    trader: Pubkey,
+    request: &OrderRequest,
+) -> Result<OrderSettlement> {
+    let OrderRequest {
+        side,
+        is_yes,
+        price,
+        size,
+        order_type,
+        expiry,
+        min_fill_size,
+        avg_price_limit,
+        reduce_only,
+        referrer,
+    } = *request;
+    let size = if reduce_only { reduce_only_size(market, position, side, is_yes, size)? } else { size };
+    market.check_order_terms(price, size)?;
+    require!(order_type <= ORDER_TYPE_POST_ONLY, ClobError::InvalidOrderType);
+    
+    require!(!market.resolved, ClobError::MarketResolved);
+    require!(!market.voided, ClobError::MarketVoid);
+    require!(!market.paused, ClobError::MarketPaused);
+    
+    let clock = Clock::get()?;
+    require!(clock.unix_timestamp < market.resolution_time, ClobError::MarketExpired);
+    require!(expiry == 0 || expiry > clock.unix_timestamp, ClobError::InvalidExpiry);
+    
+    let (effective_side, effective_price) = yes_terms(side, is_yes, price);
+    // The band is on the YES price, so a NO order is checked at its YES equivalent
+    require!(
+        effective_price >= market.min_price && effective_price <= market.max_price,
+        ClobError::PriceOutOfBand
+    );
+    
+    // Calculate required collateral
+    let collateral_required = if effective_side == 0 {
+        effective_price.checked_mul(size).ok_or(ClobError::Overflow)?
+    } else {
+        (BPS_MAX - effective_price).checked_mul(size).ok_or(ClobError::Overflow)?
+    };
+    
+    let mut remaining_size = size;
+    let mut resting_order_id = None;
+    let price_improvement: u64; // Reserved at the limit but not needed at the fill prices
+    let taker_cost: u64; // Collateral backing the filled shares, before fees
+    let filled_notional: u64; // Sum of YES fill price * size, in bps
+    let take_then_make = order_type == ORDER_TYPE_TAKE_THEN_MAKE;
+    let mut rests_remainder = order_type != ORDER_TYPE_IOC && order_type != ORDER_TYPE_FOK;
+    
+    if order_type == ORDER_TYPE_POST_ONLY {
+        let crosses = if effective_side == 0 {
+            best_live_price(&order_book.yes_asks, clock.unix_timestamp)
+                .is_some_and(|best_ask| effective_price >= best_ask)
+        } else {
+            best_live_price(&order_book.yes_bids, clock.unix_timestamp)
+                .is_some_and(|best_bid| effective_price <= best_bid)
+        };
+        require!(!crosses, ClobError::PostOnlyWouldCross);
+    }
+    let match_params = MatchParams::new(market, trader, clock.unix_timestamp, min_fill_size);
+    let dust;
+    let maker_fills;
+    
+    if effective_side == 0 {
+        // Buying YES: match against asks (strictly below the limit for take-then-make)
+        let max_price = if take_then_make { effective_price - 1 } else { effective_price };
+        let SideMatch { remaining, notional, dust: dust_asks, maker_fills: fills } = match_against_asks(
+            market,
+            order_book,
+            position,
+            price_history,
+            max_price,
+            remaining_size,
+            &match_params,
+        )?;
+        remaining_size = remaining;
+        dust = dust_asks;
+        maker_fills = fills;
+        filled_notional = notional;
+        // A remainder below min_order_size would itself be dust, so it is
+        // refunded instead of resting
+        rests_remainder &= remaining_size >= market.min_order_size;
+        
+        // Fills happen at the resting ask's price, so anything reserved
+        // above it for the filled shares goes back to the taker
+        price_improvement = effective_price
+            .checked_mul(size - remaining_size)
+            .and_then(|reserved| reserved.checked_sub(notional))
+            .ok_or(ClobError::Overflow)?;
+        taker_cost = notional;
+        
+        if remaining_size > 0 && rests_remainder {
+            require!(order_book.yes_bids.len() < order_book.capacity as usize, ClobError::OrderBookFull);
+            
+            let order_id = order_book.next_order_id()?;
+            resting_order_id = Some(order_id);
+            let order = Order {
+                owner: trader,
+                price: effective_price,
+                size: remaining_size,
+                timestamp: clock.unix_timestamp,
+                order_id,
+                expiry,
+                filled: size - remaining_size,
+            };
+            
+            let insert_idx = queue_index(&order_book.yes_bids, true, &order);
+            order_book.yes_bids.insert(insert_idx, order);
+            order_book.total_bid_size = order_book.total_bid_size
+                .checked_add(remaining_size)
+                .ok_or(ClobError::Overflow)?;
+            
+            msg!("Resting BID: {} YES @ {} bps", remaining_size, effective_price);
+        }
+    } else {
+        // Selling YES: match against bids (strictly above the limit for take-then-make)
+        let min_price = if take_then_make { effective_price + 1 } else { effective_price };
+        let SideMatch { remaining, notional, dust: dust_bids, maker_fills: fills } = match_against_bids(
+            market,
+            order_book,
+            position,
+            price_history,
+            min_price,
+            remaining_size,
+            &match_params,
+        )?;
+        remaining_size = remaining;
+        dust = dust_bids;
+        maker_fills = fills;
+        filled_notional = notional;
+        rests_remainder &= remaining_size >= market.min_order_size;
+        taker_cost = BPS_MAX
+            .checked_mul(size - remaining_size)
+            .and_then(|pairs| pairs.checked_sub(notional))
+            .ok_or(ClobError::Overflow)?;
+        // Fills happen at the resting bid's price, so the filled shares only
+        // need BPS_MAX minus that price; the rest of what was reserved at
+        // the limit goes back to the taker
+        price_improvement = (BPS_MAX - effective_price)
+            .checked_mul(size - remaining_size)
+            .and_then(|reserved| reserved.checked_sub(taker_cost))
+            .ok_or(ClobError::Overflow)?;
+        
+        if remaining_size > 0 && rests_remainder {
+            require!(order_book.yes_asks.len() < order_book.capacity as usize, ClobError::OrderBookFull);
+            
+            let order_id = order_book.next_order_id()?;
+            resting_order_id = Some(order_id);
+            let order = Order {
+                owner: trader,
+                price: effective_price,
+                size: remaining_size,
+                timestamp: clock.unix_timestamp,
+                order_id,
+                expiry,
+                filled: size - remaining_size,
+            };
+            
+            let insert_idx = queue_index(&order_book.yes_asks, false, &order);
+            order_book.yes_asks.insert(insert_idx, order);
+            order_book.total_ask_size = order_book.total_ask_size
+                .checked_add(remaining_size)
+                .ok_or(ClobError::Overflow)?;
+            
+            msg!("Resting ASK: {} YES @ {} bps", remaining_size, effective_price);
+        }
+    }
+    
+    if order_type == ORDER_TYPE_FOK {
+        require!(remaining_size == 0, ClobError::FillOrKillUnfilled);
+    }
+    let filled = size - remaining_size;
+    if avg_price_limit > 0 && filled > 0 {
+        // Compare totals rather than a rounded average. A NO order's fills
+        // are priced at BPS_MAX minus the YES price.
+        let own_notional = own_notional(is_yes, filled, filled_notional)?;
+        let limit_notional = avg_price_limit.checked_mul(filled).ok_or(ClobError::Overflow)?;
+        let within_limit = if side == 0 {
+            own_notional <= limit_notional
+        } else {
+            own_notional >= limit_notional
+        };
+        require!(within_limit, ClobError::SlippageExceeded);
+    }
+    
+    // An IOC remainder never rests, so its collateral goes straight back.
+    // The same goes for a remainder below min_order_size.
+    let unfilled_refund = if rests_remainder {
+        0
+    } else {
+        collateral_required / size * remaining_size
+    };
+    
+    // Amounts so far are in bps of one share's payout; convert them to the
+    // market's collateral units. The taker fee is charged on top of the
+    // filled collateral so the vault stays fully backed.
+    let taker_fee = market.taker_fee(taker_cost)?;
+    // Rebates are carved out of the fee, which covers them since
+    // maker_rebate_bps <= taker_fee_bps
+    let maker_rebates = maker_fills
+        .iter()
+        .try_fold(0u64, |acc: u64, fill: &MakerFill| acc.checked_add(fill.rebate))
+        .ok_or(ClobError::Overflow)?;
+    // The referrer's cut comes out of what the operator keeps after rebates
+    let referral_fee = referral_cut(
+        taker_fee - maker_rebates,
+        market.referral_fee_bps,
+        referrer.unwrap_or_default(),
+    );
+    let settlement = OrderSettlement {
+        collateral: market.to_collateral(collateral_required)?,
+        price_improvement: market.to_collateral(price_improvement)?,
+        unfilled_refund: market.to_collateral(unfilled_refund)?,
+        taker_fee,
+        referral_fee,
+        filled,
+        resting_order_id,
+        dust,
+        maker_fills,
+    };
+    let committed = settlement.collateral - settlement.price_improvement - settlement.unfilled_refund;
+    position.record_notional(committed, market.max_trader_notional)?;
+    if settlement.price_improvement > 0 {
+        msg!("Refunded {} of price improvement", settlement.price_improvement);
+    }
+    if settlement.unfilled_refund > 0 {
+        msg!("Refunded {} for {} unfilled shares", settlement.unfilled_refund, remaining_size);
+    }
+    if taker_fee > 0 {
+        market.fees_accrued = market.fees_accrued
+            .checked_add(taker_fee - maker_rebates - referral_fee)
+            .ok_or(ClobError::Overflow)?;
+        market.rebates_owed = market.rebates_owed
+            .checked_add(maker_rebates)
+            .ok_or(ClobError::Overflow)?;
+        msg!("Taker fee: {}, maker rebates: {}, referral fee: {}", taker_fee, maker_rebates, referral_fee);
+    }
+    
+    if settlement.filled > 0 {
+        position.emit_changed(POSITION_CHANGE_FILL);
+    }
+    let resting = if rests_remainder { remaining_size } else { 0 };
+    msg!("Order placed: {} shares, {} filled, {} resting", size, settlement.filled, resting);
+    Ok(settlement)
+}
+
+// === Matching Engine ===
+
+/// Matching settings for one place_order call, taken from the market
+struct MatchParams {
+    matching_mode: u8,
+    pro_rata_aging_bps: u64,
+    min_order_size: u64,
+    taker: Pubkey,
+    now: i64,
+    min_fill_size: u64, // Set by the taker, 0 = any fill size
+}
+
+impl MatchParams {
+    fn new(market: &ClobMarket, taker: Pubkey, now: i64, min_fill_size: u64) -> Self {
+        Self {
+            matching_mode: market.matching_mode,
+            pro_rata_aging_bps: market.pro_rata_aging_bps,
+            min_order_size: market.min_order_size,
+            taker,
+            now,
+            min_fill_size,
+        }
+    }
+    
+    /// Expired orders, the taker's own orders (self-trade prevention) and
+    /// orders smaller than the taker's min_fill_size are skipped by
+    /// matching and left resting on the book. Orders this match already
+    /// filled are skipped until they are compacted away.
+    fn can_match(&self, order: &Order) -> bool {
+        order.size > 0
+            && !order.is_expired_at(self.now)
+            && order.owner != self.taker
+            && order.size >= self.min_fill_size
+    }
+}
+
+/// A single match between the incoming order and a resting order
+struct Fill {
+    maker: Pubkey,
+    order_id: u64,
+    price: u64,
+    size: u64,
+}
+
+/// What one fill credits to its maker's position
+struct MakerFill {
+    maker: Pubkey,
+    notional: u64, // price * size, in YES bps
+    rebate: u64, // In collateral units, owed out of the taker fee
+}
+
+impl MakerFill {
+    /// `taker_cost` is the collateral (in bps) the taker put up for the
+    /// fill, the same base the taker fee is charged on
+    fn new(market: &ClobMarket, fill: &Fill, notional: u64, taker_cost: u64) -> Result<Self> {
+        let rebate = market
+            .to_collateral(taker_cost)?
+            .checked_mul(market.maker_rebate_bps)
+            .ok_or(ClobError::Overflow)?
+            / BPS_MAX;
+        Ok(Self { maker: fill.maker, notional, rebate })
+    }
+}
+
+/// Result of matching an incoming order against one side of the book
+struct SideMatch {
+    remaining: u64, // Unfilled size
+    notional: u64, // Sum of price * size over the fills, in YES bps
+    dust: Vec<(bool, Order)>, // Maker orders removed for falling below min_order_size
+    maker_fills: Vec<MakerFill>, // One entry per fill, credited to the makers' positions
+}
+
+fn match_against_asks(
+    market: &mut ClobMarket,
+    order_book: &mut OrderBook,
+    position: &mut ClobPosition,
+    price_history: &mut PriceHistory,
+    max_price: u64,
+    size: u64,
+    params: &MatchParams,
+) -> Result<SideMatch> {
+    let (remaining, fills, dust) = match_orders(
+        &mut order_book.yes_asks,
+        |ask_price| max_price >= ask_price,
+        size,
+        params,
+    )?;
+    
+    let mut notional = 0u64;
+    let mut maker_fills = Vec::with_capacity(fills.len());
+    for fill in fills {
+        let fill_notional = fill.price.checked_mul(fill.size).ok_or(ClobError::Overflow)?;
+        notional = notional.checked_add(fill_notional).ok_or(ClobError::Overflow)?;
+        position.volume_traded = position.volume_traded.saturating_add(fill_notional);
+        maker_fills.push(MakerFill::new(market, &fill, fill_notional, fill_notional)?);
+        position.yes_shares = position.yes_shares
+            .checked_add(fill.size)
+            .ok_or(ClobError::Overflow)?;
+        market.yes_outstanding = market.yes_outstanding.checked_add(fill.size).ok_or(ClobError::Overflow)?;
+        market.last_trade_price = fill.price;
+        price_history.record(fill.price, fill.size, params.now);
+        market.open_interest = market.open_interest.checked_add(fill.size).ok_or(ClobError::Overflow)?;
+        order_book.reduce_depth(false, fill.size);
+        emit_fill(position, &fill, true);
+        msg!("Matched {} YES @ {} bps", fill.size, fill.price);
+    }
+    for order in &dust {
+        order_book.reduce_depth(false, order.size);
+    }
+    
+    Ok(SideMatch {
+        remaining,
+        notional,
+        dust: dust.into_iter().map(|o| (false, o)).collect(),
+        maker_fills,
+    })
+}
+
+fn match_against_bids(
+    market: &mut ClobMarket,
+    order_book: &mut OrderBook,
+    position: &mut ClobPosition,
+    price_history: &mut PriceHistory,
+    min_price: u64,
+    size: u64,
+    params: &MatchParams,
+) -> Result<SideMatch> {
+    let (remaining, fills, dust) = match_orders(
+        &mut order_book.yes_bids,
+        |bid_price| min_price <= bid_price,
+        size,
+        params,
+    )?;
+    
+    let mut notional = 0u64;
+    let mut maker_fills = Vec::with_capacity(fills.len());
+    for fill in fills {
+        let fill_notional = fill.price.checked_mul(fill.size).ok_or(ClobError::Overflow)?;
+        notional = notional.checked_add(fill_notional).ok_or(ClobError::Overflow)?;
+        position.volume_traded = position.volume_traded.saturating_add(fill_notional);
+        let fill_cost = BPS_MAX
+            .checked_mul(fill.size)
+            .and_then(|pairs| pairs.checked_sub(fill_notional))
+            .ok_or(ClobError::Overflow)?;
+        maker_fills.push(MakerFill::new(market, &fill, fill_notional, fill_cost)?);
+        position.no_shares = position.no_shares
+            .checked_add(fill.size)
+            .ok_or(ClobError::Overflow)?;
+        market.no_outstanding = market.no_outstanding.checked_add(fill.size).ok_or(ClobError::Overflow)?;
+        market.last_trade_price = fill.price;
+        price_history.record(fill.price, fill.size, params.now);
+        market.open_interest = market.open_interest.checked_add(fill.size).ok_or(ClobError::Overflow)?;
+        order_book.reduce_depth(true, fill.size);
+        emit_fill(position, &fill, false);
+        msg!("Matched {} YES @ {} bps", fill.size, fill.price);
+    }
+    for order in &dust {
+        order_book.reduce_depth(true, order.size);
+    }
+    
+    Ok(SideMatch {
+        remaining,
+        notional,
+        dust: dust.into_iter().map(|o| (true, o)).collect(),
+        maker_fills,
+    })
+}
+
+/// One OrderFilled event per resting order hit, so a sweep across several
+/// price levels (or several makers at one level) emits one event for each.
+fn emit_fill(taker_position: &ClobPosition, fill: &Fill, taker_buys_yes: bool) {
+    emit!(OrderFilled {
+        market: taker_position.market,
+        maker: fill.maker,
+        taker: taker_position.owner,
+        order_id: fill.order_id,
+        price: fill.price,
+        size: fill.size,
+        taker_buys_yes,
+    });
+}
+
+/// Where a new order joins one side of the book under price-time priority:
+/// behind every order at a better price, and behind equal-priced orders
+/// that are no newer than it, so each level fills oldest first
+fn queue_index(orders: &[Order], is_bid: bool, order: &Order) -> usize {
+    orders
+        .iter()
+        .position(|o| {
+            let worse_price = if is_bid { o.price < order.price } else { o.price > order.price };
+            worse_price || (o.price == order.price && o.timestamp > order.timestamp)
+        })
+        .unwrap_or(orders.len())
+}
+
+/// Price of the best order on one side of the book that has not expired
+fn best_live_price(orders: &[Order], now: i64) -> Option<u64> {
+    orders.iter().find(|o| !o.is_expired_at(now)).map(|o| o.price)
+}
+
+/// Fill up to `size` against one side of the book, best price level first.
+/// Every fill is at least `min_fill_size`. Returns the unfilled remainder,
+/// the individual fills and any maker orders a partial fill left below
+/// `min_order_size`, which are taken off the book.
+fn match_orders(
+    orders: &mut Vec<Order>,
+    crosses: impl Fn(u64) -> bool,
+    mut size: u64,
+    params: &MatchParams,
+) -> Result<(u64, Vec<Fill>, Vec<Order>)> {
+    let mut fills = Vec::new();
+    // Levels are walked in place with a cursor and filled orders are only
+    // removed by one compaction pass at the end, so a sweep shifts the
+    // vector once instead of once per level. Removal isn't O(1): that pass,
+    // like inserting a resting order in execute_order, is still O(n) in the
+    // length of the book.
+    let mut cursor = 0;
+    
+    while size > 0 && size >= params.min_fill_size {
+        // Skipped orders stay on the book until their owner cancels them,
+        // which is what refunds an expired order's collateral.
+        let Some(offset) = orders[cursor..].iter().position(|o| params.can_match(o)) else {
+            break;
+        };
+        let start = cursor + offset;
+        let level_price = orders[start].price;
+        if !crosses(level_price) {
+            break;
+        }
+        
+        // Orders are sorted best price first, FIFO within a price level.
+        // Skipped orders in the level are allocated as if they had no size.
+        let level_len = orders[start..].iter().take_while(|o| o.price == level_price).count();
+        let level: Vec<Order> = orders[start..start + level_len]
+            .iter()
+            .map(|o| Order {
+                size: if params.can_match(o) { o.size } else { 0 },
+                ..o.clone()
+            })
+            .collect();
+        let allocations = if params.matching_mode == MATCHING_MODE_PRO_RATA {
+            pro_rata_allocations(&level, size, params)?
+        } else {
+            fifo_allocations(&level, size)
+        };
+        
+        let fills_before = fills.len();
+        for (order, fill_size) in orders[start..].iter_mut().zip(allocations) {
+            // Allocations under the taker's minimum are dropped rather than filled
+            if fill_size == 0 || fill_size < params.min_fill_size {
+                continue;
+            }
+            order.size -= fill_size;
+            order.filled += fill_size;
+            size -= fill_size;
+            fills.push(Fill {
+                maker: order.owner,
+                order_id: order.order_id,
+                price: level_price,
+                size: fill_size,
+            });
+        }
+        
+        if fills.len() == fills_before {
+            // Every allocation at this level was below min_fill_size
+            break;
+        }
+        cursor = start + level_len;
+    }
+    
+    // One pass drops filled orders and takes partially filled makers left
+    // below min_order_size off the book as dust
+    let mut dust = Vec::new();
+    if !fills.is_empty() {
+        orders.retain(|o| {
+            if o.size == 0 {
+                return false;
+            }
+            let is_dust = o.size < params.min_order_size
+                && fills.iter().any(|f| f.order_id == o.order_id);
+            if is_dust {
+                dust.push(o.clone());
+            }
+            !is_dust
+        });
+    }
+    
+    Ok((size, fills, dust))
+}
+
+fn fifo_allocations(level: &[Order], mut size: u64) -> Vec<u64> {
+    level
+        .iter()
+        .map(|order| {
+            let fill_size = size.min(order.size);
+            size -= fill_size;
+            fill_size
+        })
+        .collect()
+}
+
+/// Split `size` across one price level in proportion to each order's
+/// weight, where weight = size * (1 + aging_bps * age_secs / BPS_MAX).
+/// Rounding leftovers go to the oldest orders first.
+fn pro_rata_allocations(level: &[Order], size: u64, params: &MatchParams) -> Result<Vec<u64>> {
+    let level_size = level
+        .iter()
+        .try_fold(0u64, |acc, o| acc.checked_add(o.size))
+        .ok_or(ClobError::Overflow)?;
+    if size >= level_size {
+        return Ok(level.iter().map(|o| o.size).collect());
+    }
+    
+    let weights = level
+        .iter()
+        .map(|o| {
+            let age = params.now.saturating_sub(o.timestamp).max(0) as u128;
+            (params.pro_rata_aging_bps as u128)
+                .checked_mul(age)
+                .and_then(|aging| aging.checked_add(BPS_MAX as u128))
+                .and_then(|factor| factor.checked_mul(o.size as u128))
+                .ok_or(ClobError::Overflow)
+        })
+        .collect::<std::result::Result<Vec<u128>, _>>()?;
+    let total_weight = weights
+        .iter()
+        .try_fold(0u128, |acc, w| acc.checked_add(*w))
+        .ok_or(ClobError::Overflow)?;
+    
+    let mut allocations = Vec::with_capacity(level.len());
+    let mut allocated = 0u64;
+    for (order, weight) in level.iter().zip(&weights) {
+        let share = (size as u128)
+            .checked_mul(*weight)
+            .ok_or(ClobError::Overflow)?
+            / total_weight;
+        let fill_size = (share as u64).min(order.size);
+        allocated += fill_size;
+        allocations.push(fill_size);
+    }
+    
+    let mut leftover = size - allocated;
+    for (order, fill_size) in level.iter().zip(allocations.iter_mut()) {
+        let extra = leftover.min(order.size - *fill_size);
+        *fill_size += extra;
+        leftover -= extra;
+    }
+    
+    Ok(allocations)
+}
+
+// ===========================================
+// PARIMUTUEL ACCOUNT STRUCTURES
+// ===========================================
+
+#[account]
+pub struct Market {
+    pub authority: Pubkey,
+    pub market_id: String,
+    pub question: String,
+    pub outcomes: Vec<String>,
+    pub outcome_pools: Vec<u64>,
+    pub total_pool: u64,
+    pub resolution_time: i64,
+    pub resolved: bool,
+    pub winning_outcome: Option<u8>,
+    pub created_at: i64,
+    pub max_outcome_shares: u64, // Per-outcome share cap, 0 = uncapped
+    pub max_resolution_delay: i64, // Seconds past resolution_time before the market is void, 0 = never
+    pub fee_collected: u64, // Claim fees accrued and not yet withdrawn
+    pub total_claimed: u64, // Pool lamports paid out by claims, fees included
+    pub winning_shares_claimed: u64, // Winning shares already paid, so the last claim can sweep dust
+    pub mint: Pubkey, // Collateral token, Pubkey::default() for native lamports
+    pub min_bet: u64, // Smallest amount a single buy may stake, 0 = no minimum
+    pub cancelled: bool, // Cancelled by the authority, every stake is refundable
+    pub pending_authority: Option<Pubkey>, // Proposed new authority, set by propose_authority
+    pub paused: bool, // Buys halted by the authority; resolution and claims still work
+    pub max_total_pool: u64, // Cap on total_pool across all outcomes, 0 = uncapped
+    pub market_kind: u8, // MARKET_KIND_CATEGORICAL or MARKET_KIND_SCALAR
+    pub scalar_min: i64, // Scalar only: low end of the range the outcome buckets split
+    pub scalar_max: i64, // Scalar only: high end of the range
+    pub settlement_value: i64, // Scalar only: value set by resolve_scalar_market
+    pub early_resolution: bool, // The authority may resolve before resolution_time
+    pub fee_bps: u64, // Claim fee on winning payouts
+    pub max_trader_notional: u64, // Cap on what one position may stake in total, 0 = unlimited
+    pub referral_fee_bps: u64, // Referrer's share of the claim fee, in bps of the fee
+    pub bump: u8,
+    pub version: u8, // Layout version; fields below it were added by later versions
+    pub outcome_weights: Vec<u64>, // Payout weight of each outcome in bps, empty = BPS_MAX for all
+    pub authority_cannot_trade: bool, // Negated authority_can_trade, so older accounts read as allowed
+    pub resolution_uri: String, // Link to the evidence behind the resolution, empty = none given
+    pub resolution_source: Pubkey, // Who or what the outcome was taken from, default = none given
+    pub commit_phase_start: i64, // Buys must go through commit_buy from here to resolution_time, 0 = never
+    pub reveal_deadline: i64, // End of the reveal phase that follows resolution_time, 0 without commits
+    pub pending_commitments: u64, // Lamports committed and not yet revealed, refunded or forfeited
+    pub fee_round_up: bool, // Claim fees round up, toward the pool, instead of down
+}
+
+/// Amounts moved by one parimutuel claim
+pub struct ClaimSettlement {
+    pub payout: u64, // Sent to the claimer, net of fees
+    pub fee: u64,
+    pub void_refund: bool,
+}
+
+impl Market {
+    /// Account size, discriminator included, for up to 10 outcomes
+    pub const SPACE: usize = 8 + 32 + 36 + 260 + 4 + 10*36 + 4 + 10*8 + 8 + 8 + 1 + 2 + 8 + 8 + 8 + 8 + 8 + 8 + 32 + 8 + 1 + 33 + 1 + 8 + 1 + 8 + 8 + 8 + 1 + 8 + 8 + 8 + 1 + 1 + 4 + 10*8 + 1 + 4 + MAX_RESOLUTION_URI_LEN + 32 + 8 + 8 + 8 + 1;
+    
+    /// An unresolved market becomes void once max_resolution_delay has
+    /// passed since resolution_time. Checked lazily, no crank needed.
+    pub fn is_void_at(&self, now: i64) -> bool {
+        !self.resolved
+            && self.max_resolution_delay > 0
+            && now > self.resolution_time.saturating_add(self.max_resolution_delay)
+    }
+
+    /// Cancelled and void markets both refund every stake in full
+    pub fn is_refundable_at(&self, now: i64) -> bool {
+        self.cancelled || self.is_void_at(now)
+    }
+
+    /// Whether the position has nothing left to claim: no shares at all once
+    /// the market is refundable, or no winning shares once it is resolved
+    pub fn is_settled(&self, position: &Position, now: i64) -> Result<bool> {
+        if self.is_refundable_at(now) {
+            return Ok(position.shares.iter().all(|s| *s == 0));
+        }
+        require!(self.resolved, ErrorCode::MarketNotResolved);
+        if self.market_kind == MARKET_KIND_SCALAR {
+            let weights = self.scalar_weights();
+            Ok(position.shares.iter().zip(&weights).all(|(s, w)| *s == 0 || *w == 0))
+        } else {
+            let winning_outcome = self.winning_outcome.unwrap() as usize;
+            Ok(position.shares[winning_outcome] == 0)
+        }
+    }
+
+    /// Validate and store the settings shared by lamport and token markets.
+    /// The caller sets `mint` and `bump`.
+    fn init(
+        &mut self,
+        authority: Pubkey,
+        market_id: String,
+        question: String,
+        outcomes: Vec<String>,
+        resolution_time: i64,
+        config: &MarketConfig,
+    ) -> Result<()> {
+        require!(outcomes.len() >= 2 && outcomes.len() <= 10, ErrorCode::InvalidOutcomeCount);
+        for (i, outcome) in outcomes.iter().enumerate() {
+            // Space reserves 32 bytes per outcome name
+            require!(!outcome.is_empty() && outcome.len() <= 32, ErrorCode::InvalidOutcomeName);
+            require!(!outcomes[..i].contains(outcome), ErrorCode::InvalidOutcomeName);
+        }
+        require!(market_id.len() <= 32, ErrorCode::MarketIdTooLong);
+        require!(question.len() <= 256, ErrorCode::QuestionTooLong);
+        require!(config.max_resolution_delay >= 0, ErrorCode::InvalidResolutionDelay);
+        let now = Clock::get()?.unix_timestamp;
+        // A market already past resolution_time could never take a bet
+        require!(resolution_time > now, ErrorCode::ResolutionTimeInPast);
+        require!(resolution_time - now <= MAX_MARKET_DURATION, ErrorCode::ResolutionTimeTooFar);
+        require!(config.market_kind <= MARKET_KIND_SCALAR, ErrorCode::InvalidMarketKind);
+        if config.market_kind == MARKET_KIND_SCALAR {
+            require!(config.scalar_min < config.scalar_max, ErrorCode::InvalidScalarRange);
+        }
+        require!(config.fee_bps <= BPS_MAX, ErrorCode::InvalidFee);
+        require!(config.referral_fee_bps <= BPS_MAX, ErrorCode::InvalidFee);
+        if config.commit_phase_start != 0 {
+            // The reveal phase must end before the market could go void, and
+            // a commitment's outcome isn't known in time to check a per-outcome cap
+            require!(
+                config.commit_phase_start > 0
+                    && config.commit_phase_start < resolution_time
+                    && config.reveal_period > 0
+                    && !config.early_resolution
+                    && config.max_outcome_shares == 0
+                    && (config.max_resolution_delay == 0 || config.max_resolution_delay > config.reveal_period),
+                ErrorCode::InvalidCommitPhase
+            );
+        }
+        require!(
+            config.outcome_weights.is_empty()
+                || (config.market_kind == MARKET_KIND_CATEGORICAL
+                    && config.outcome_weights.len() == outcomes.len()
+                    && config.outcome_weights.iter().all(|w| *w > 0 && *w <= BPS_MAX)),
+            ErrorCode::InvalidOutcomeWeights
+        );
+        
+        self.authority = authority;
+        self.market_id = market_id;
+        self.question = question;
+        self.outcome_pools = vec![0u64; outcomes.len()];
+        self.outcomes = outcomes;
+        self.total_pool = 0;
+        self.resolution_time = resolution_time;
+        self.resolved = false;
+        self.winning_outcome = None;
+        self.created_at = now;
+        self.max_outcome_shares = config.max_outcome_shares;
+        self.max_resolution_delay = config.max_resolution_delay;
+        self.min_bet = config.min_bet;
+        self.max_total_pool = config.max_total_pool;
+        self.market_kind = config.market_kind;
+        self.scalar_min = config.scalar_min;
+        self.scalar_max = config.scalar_max;
+        self.settlement_value = 0;
+        self.early_resolution = config.early_resolution;
+        self.fee_bps = config.fee_bps;
+        self.max_trader_notional = config.max_trader_notional;
+        self.referral_fee_bps = config.referral_fee_bps;
+        self.version = MARKET_VERSION;
+        self.outcome_weights = config.outcome_weights.clone();
+        self.authority_cannot_trade = !config.authority_can_trade;
+        self.resolution_uri = String::new();
+        self.resolution_source = Pubkey::default();
+        self.commit_phase_start = config.commit_phase_start;
+        self.reveal_deadline = if config.commit_phase_start != 0 {
+            resolution_time.checked_add(config.reveal_period).ok_or(ErrorCode::Overflow)?
+        } else {
+            0
+        };
+        self.pending_commitments = 0;
+        self.fee_round_up = config.fee_round_up;
+        Ok(())
+    }
+
+    /// Whether buys at `now` must be committed rather than made in the open
+    pub fn is_commit_phase_at(&self, now: i64) -> bool {
+        self.commit_phase_start != 0 && now >= self.commit_phase_start && now < self.resolution_time
+    }
+
+    /// Check the market is taking buys from `buyer`
+    fn check_buy_open(&self, buyer: Pubkey, now: i64) -> Result<()> {
+        require!(!self.resolved, ErrorCode::MarketResolved);
+        require!(!self.authority_cannot_trade || buyer != self.authority, ErrorCode::AuthorityCannotTrade);
+        require!(!self.cancelled, ErrorCode::MarketCancelled);
+        require!(!self.paused, ErrorCode::MarketPaused);
+        // Trading closes at resolution_time; this also covers void markets
+        require!(now < self.resolution_time, ErrorCode::MarketExpired);
+        Ok(())
+    }
+
+    /// Check a buy is allowed and add it to the outcome pool
+    fn record_buy(&mut self, buyer: Pubkey, outcome_index: u8, amount: u64, now: i64) -> Result<()> {
+        self.check_buy_open(buyer, now)?;
+        require!(!self.is_commit_phase_at(now), ErrorCode::CommitPhaseActive);
+        self.add_to_pool(outcome_index, amount)
+    }
+
+    /// Check a buy against the outcome and size limits and add it to its
+    /// outcome pool. Committed lamports count towards max_total_pool.
+    fn add_to_pool(&mut self, outcome_index: u8, amount: u64) -> Result<()> {
+        require!((outcome_index as usize) < self.outcomes.len(), ErrorCode::InvalidOutcome);
+        // Even with min_bet = 0, an empty buy would only make the buyer pay position rent
+        require!(amount > 0, ErrorCode::InvalidSize);
+        require!(amount >= self.min_bet, ErrorCode::BelowMinimumBet);
+        if self.max_outcome_shares > 0 {
+            let outcome_shares = self.outcome_pools[outcome_index as usize].saturating_add(amount);
+            require!(outcome_shares <= self.max_outcome_shares, ErrorCode::OutcomeShareCapExceeded);
+        }
+        
+        let total_pool = self.total_pool.checked_add(amount).ok_or(ErrorCode::Overflow)?;
+        if self.max_total_pool > 0 {
+            require!(
+                total_pool.saturating_add(self.pending_commitments) <= self.max_total_pool,
+                ErrorCode::PoolCapExceeded
+            );
+        }
+        
+        let outcome_pool = &mut self.outcome_pools[outcome_index as usize];
+        *outcome_pool = outcome_pool.checked_add(amount).ok_or(ErrorCode::Overflow)?;
+        self.total_pool = total_pool;
+        Ok(())
+    }
+
+    /// Check a sell is allowed and take it back out of the outcome pool.
+    /// Shares are bought 1:1 with collateral, so a seller withdraws only
+    /// their own principal, but that principal leaves total_pool too: the
+    /// pool the eventual winners split shrinks, and with it every other
+    /// position's payout. Returns the amount owed to the seller; the
+    /// SELL_FEE_BPS exit fee stays behind as fee_collected.
+    fn record_sell(&mut self, position: &mut Position, outcome_index: u8, amount: u64, now: i64) -> Result<u64> {
+        require!(!self.resolved, ErrorCode::MarketResolved);
+        require!(!self.cancelled, ErrorCode::MarketCancelled);
+        require!(!self.paused, ErrorCode::MarketPaused);
+        require!(now < self.resolution_time, ErrorCode::MarketExpired);
+        require!((outcome_index as usize) < self.outcomes.len(), ErrorCode::InvalidOutcome);
+        require!(amount > 0, ErrorCode::InvalidSize);
+        
+        let held = &mut position.shares[outcome_index as usize];
+        *held = held.checked_sub(amount).ok_or(ErrorCode::InsufficientShares)?;
+        let outcome_pool = &mut self.outcome_pools[outcome_index as usize];
+        *outcome_pool = outcome_pool.checked_sub(amount).ok_or(ErrorCode::Overflow)?;
+        self.total_pool = self.total_pool.checked_sub(amount).ok_or(ErrorCode::Overflow)?;
+        
+        let fee = (amount as u128 * SELL_FEE_BPS as u128 / BPS_MAX as u128) as u64;
+        self.fee_collected = self.fee_collected.checked_add(fee).ok_or(ErrorCode::Overflow)?;
+        Ok(amount - fee)
+    }
+
+    /// Zero the position's claimable shares and book the claim. A void
+    /// market refunds every share; a resolved one pays the winning shares'
+    /// slice of the pool less the fee_bps fee, which stays behind as fee_collected.
+    /// With outcome_weights the winners split only the winning outcome's
+    /// weight of the pool; the rest is left for sweep_dust.
+    fn settle_claim(&mut self, position: &mut Position, now: i64) -> Result<ClaimSettlement> {
+        if self.is_refundable_at(now) {
+            let refund = self.settle_refund(position)?;
+            return Ok(ClaimSettlement { payout: refund, fee: 0, void_refund: true });
+        }
+        
+        require!(self.resolved, ErrorCode::MarketNotResolved);
+        if self.market_kind == MARKET_KIND_SCALAR {
+            return self.settle_scalar_claim(position);
+        }
+        
+        let winning_outcome = self.winning_outcome.unwrap() as usize;
+        let winner_shares = position.shares[winning_outcome];
+        require!(winner_shares > 0, ErrorCode::NoWinningShares);
+
+        let total_winning_shares = self.outcome_pools[winning_outcome];
+        let winners_pool = self.winners_pool(winning_outcome);
+        let unclaimed_pool = winners_pool.saturating_sub(self.total_claimed);
+        let winning_shares_claimed = self.winning_shares_claimed
+            .checked_add(winner_shares)
+            .ok_or(ErrorCode::Overflow)?;
+        // Each pro-rata payout rounds down, so the last winning claim takes
+        // whatever the earlier ones left behind
+        let payout = if winning_shares_claimed >= total_winning_shares {
+            unclaimed_pool
+        } else {
+            ((winner_shares as u128)
+                .checked_mul(winners_pool as u128)
+                .unwrap()
+                .checked_div(total_winning_shares as u128)
+                .unwrap() as u64)
+                .min(unclaimed_pool)
+        };
+
+        let fee = self.claim_fee(payout);
+
+        position.shares[winning_outcome] = 0;
+        self.winning_shares_claimed = winning_shares_claimed;
+        self.fee_collected = self.fee_collected.checked_add(fee).ok_or(ErrorCode::Overflow)?;
+        self.total_claimed = self.total_claimed.checked_add(payout).ok_or(ErrorCode::Overflow)?;
+
+        Ok(ClaimSettlement { payout: payout - fee, fee, void_refund: false })
+    }
+
+    /// The part of the pool a categorical outcome's winners split: all of it
+    /// unless outcome_weights scales it down
+    fn winners_pool(&self, outcome: usize) -> u64 {
+        match self.outcome_weights.get(outcome) {
+            Some(weight) => (self.total_pool as u128 * *weight as u128 / BPS_MAX as u128) as u64,
+            None => self.total_pool,
+        }
+    }
+
+    /// The fee_bps cut of a winning payout. Rounds down, in the claimer's
+    /// favour, unless fee_round_up is set. Either way fee_bps <= BPS_MAX
+    /// keeps the fee at most the payout.
+    fn claim_fee(&self, payout: u64) -> u64 {
+        let gross = payout as u128 * self.fee_bps as u128;
+        let fee = if self.fee_round_up {
+            gross.div_ceil(BPS_MAX as u128)
+        } else {
+            gross / BPS_MAX as u128
+        };
+        fee as u64
+    }
+
+    /// Scalar version of settle_claim: each weighted bucket's share of the
+    /// pool is split pro rata among its shares, less the same claim fee
+    fn settle_scalar_claim(&mut self, position: &mut Position) -> Result<ClaimSettlement> {
+        let weights = self.scalar_weights();
+        let mut winner_shares = 0u64;
+        let mut total_winning_shares = 0u64;
+        let mut pro_rata_payout = 0u128;
+        for (i, weight) in weights.iter().enumerate() {
+            if *weight == 0 {
+                continue;
+            }
+            total_winning_shares = total_winning_shares
+                .checked_add(self.outcome_pools[i])
+                .ok_or(ErrorCode::Overflow)?;
+            let shares = position.shares[i];
+            winner_shares = winner_shares.checked_add(shares).ok_or(ErrorCode::Overflow)?;
+            
+            let bucket_pool = self.total_pool as u128 * *weight as u128 / SCALAR_WEIGHT_SCALE as u128;
+            let bucket_payout = (shares as u128)
+                .checked_mul(bucket_pool)
+                .ok_or(ErrorCode::Overflow)?
+                / self.outcome_pools[i] as u128;
+            pro_rata_payout += bucket_payout;
+            position.shares[i] = 0;
+        }
+        require!(winner_shares > 0, ErrorCode::NoWinningShares);
+        
+        let unclaimed_pool = self.total_pool.saturating_sub(self.total_claimed);
+        let winning_shares_claimed = self.winning_shares_claimed
+            .checked_add(winner_shares)
+            .ok_or(ErrorCode::Overflow)?;
+        // As with categorical claims, the last winning claim sweeps rounding dust
+        let payout = if winning_shares_claimed >= total_winning_shares {
+            unclaimed_pool
+        } else {
+            (pro_rata_payout as u64).min(unclaimed_pool)
+        };
+        
+        let fee = self.claim_fee(payout);
+        
+        self.winning_shares_claimed = winning_shares_claimed;
+        self.fee_collected = self.fee_collected.checked_add(fee).ok_or(ErrorCode::Overflow)?;
+        self.total_claimed = self.total_claimed.checked_add(payout).ok_or(ErrorCode::Overflow)?;
+        
+        Ok(ClaimSettlement { payout: payout - fee, fee, void_refund: false })
+    }
+    
+    /// Pool weight of each outcome of a resolved scalar market, summing to
+    /// SCALAR_WEIGHT_SCALE. Outcome i is the bucket centred at
+    /// scalar_min + (i + 0.5) * (scalar_max - scalar_min) / outcomes. The
+    /// settlement value is interpolated between the nearest staked bucket
+    /// centres on either side, so those two split the pool by closeness;
+    /// beyond every staked centre the nearest bucket takes it all.
+    pub fn scalar_weights(&self) -> Vec<u64> {
+        let n = self.outcomes.len();
+        let range = self.scalar_max as i128 - self.scalar_min as i128;
+        // Everything is scaled by 2n so bucket centres are whole numbers
+        let scale = 2 * n as i128;
+        let centre = |i: usize| scale * self.scalar_min as i128 + (2 * i as i128 + 1) * range;
+        let value = scale * self.settlement_value as i128;
+        
+        let staked = |i: &usize| self.outcome_pools[*i] > 0;
+        let below = (0..n).filter(staked).rfind(|i| centre(*i) <= value);
+        let above = (0..n).filter(staked).find(|i| centre(*i) >= value);
+        
+        let mut weights = vec![0u64; n];
+        match (below, above) {
+            (Some(b), Some(a)) if a != b => {
+                let span = centre(a) - centre(b);
+                weights[b] = ((centre(a) - value) * SCALAR_WEIGHT_SCALE as i128 / span) as u64;
+                weights[a] = SCALAR_WEIGHT_SCALE - weights[b];
+            }
+            (Some(i), _) | (None, Some(i)) => weights[i] = SCALAR_WEIGHT_SCALE,
+            (None, None) => {}
+        }
+        weights
+    }
+
+    /// Zero every outcome of the position and return its total stake, fee free
+    fn settle_refund(&mut self, position: &mut Position) -> Result<u64> {
+        let refund = position.shares
+            .iter()
+            .try_fold(0u64, |acc, s| acc.checked_add(*s))
+            .ok_or(ErrorCode::Overflow)?;
+        require!(refund > 0, ErrorCode::NoWinningShares);
+        
+        position.shares.iter_mut().for_each(|s| *s = 0);
+        self.total_claimed = self.total_claimed.checked_add(refund).ok_or(ErrorCode::Overflow)?;
+        Ok(refund)
+    }
+
+    /// Whether every winning share has been paid, so the pool owes no one.
+    /// True as well when nobody holds a winning share.
+    fn all_winners_claimed(&self) -> Result<bool> {
+        let total_winning_shares = if self.market_kind == MARKET_KIND_SCALAR {
+            self.scalar_weights()
+                .iter()
+                .zip(&self.outcome_pools)
+                .filter(|(weight, _)| **weight > 0)
+                .try_fold(0u64, |acc, (_, pool)| acc.checked_add(*pool))
+                .ok_or(ErrorCode::Overflow)?
+        } else {
+            self.outcome_pools[self.winning_outcome.unwrap() as usize]
+        };
+        Ok(self.winning_shares_claimed >= total_winning_shares)
+    }
+
+    /// Fees that can leave the market without touching the pool still owed to
+    /// positions, given the collateral balance above any rent minimum
+    fn withdrawable_fees(&self, available: u64) -> u64 {
+        let owed = self.total_pool.saturating_sub(self.total_claimed);
+        self.fee_collected.min(available.saturating_sub(owed))
+    }
+}
+
+/// Creation-time settings for a parimutuel market
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct MarketConfig {
+    /// Maximum shares any single outcome pool may hold (0 = uncapped). A buy
+    /// that would pass it is rejected whole rather than partially filled.
+    pub max_outcome_shares: u64,
+    /// Seconds after resolution_time before an unresolved market is void (0 = never).
+    /// The backstop if the authority never resolves: every stake is refundable.
+    pub max_resolution_delay: i64,
+    /// Smallest amount a single buy may stake (0 = no minimum)
+    pub min_bet: u64,
+    /// Maximum total_pool across all outcomes (0 = uncapped)
+    pub max_total_pool: u64,
+    /// MARKET_KIND_CATEGORICAL or MARKET_KIND_SCALAR
+    pub market_kind: u8,
+    /// Scalar only: the outcomes split [scalar_min, scalar_max) into equal
+    /// buckets, in order
+    pub scalar_min: i64,
+    pub scalar_max: i64,
+    /// Allow resolving before resolution_time, for events that can conclude early
+    pub early_resolution: bool,
+    /// Fee taken from each winning claim, in bps of the payout (200 = 2%)
+    pub fee_bps: u64,
+    /// Most any single position may stake over its lifetime, 0 = unlimited.
+    /// Sells don't free up room under the cap.
+    pub max_trader_notional: u64,
+    /// Share of the claim fee paid to a position's referrer, in bps of the fee
+    pub referral_fee_bps: u64,
+    /// Categorical only: share of the pool each outcome's winners split if
+    /// it wins, in bps, one per outcome (empty = the whole pool for every
+    /// outcome). Handicaps a favourite by paying its backers less.
+    pub outcome_weights: Vec<u64>,
+    /// Start of the commit phase, when buys go through commit_buy and stay
+    /// hidden until revealed after resolution_time (0 = no commit phase;
+    /// lamport markets only). Needs reveal_period, and rules out
+    /// early_resolution and max_outcome_shares.
+    pub commit_phase_start: i64,
+    /// Seconds after resolution_time to reveal commitments. The market
+    /// can't be resolved until it has passed. Commitments left unrevealed
+    /// stay out of the pool, and sweep_dust leaves their lamports alone
+    /// until forfeit_commitment releases them.
+    pub reveal_period: i64,
+    /// Let the authority, who resolves the market, buy shares in it
+    pub authority_can_trade: bool,
+    /// Round each claim fee up instead of down, so the odd lamport goes to
+    /// fee_collected rather than the claimer
+    pub fee_round_up: bool,
+}
+
+#[account]
+pub struct Position {
+    pub owner: Pubkey,
+    pub market: Pubkey,
+    pub shares: Vec<u64>,
+    pub staked: u64, // Collateral bought in with over the position's lifetime
+    pub referrer: Pubkey, // Set by the first buy that names one, Pubkey::default() = none
+    pub bump: u8,
+}
+
+impl Position {
+    fn emit_changed(&self, reason: u8) {
+        emit!(PositionChanged {
+            market: self.market,
+            owner: self.owner,
+            shares: self.shares.clone(),
+            reason,
+        });
+    }
+
+    fn init_if_needed(&mut self, owner: Pubkey, market: &Account<Market>, bump: u8) {
+        if self.shares.is_empty() {
+            self.owner = owner;
+            self.market = market.key();
+            self.shares = vec![0u64; market.outcomes.len()];
+            self.staked = 0;
+            self.referrer = Pubkey::default();
+            self.bump = bump;
+        }
+    }
+
+    /// Count a buy toward the market's max_trader_notional
+    fn record_stake(&mut self, amount: u64, cap: u64) -> Result<()> {
+        let staked = self.staked.checked_add(amount).ok_or(ErrorCode::Overflow)?;
+        require!(cap == 0 || staked <= cap, ErrorCode::PositionLimitExceeded);
+        self.staked = staked;
+        Ok(())
+    }
+
+    /// Attribute the position to the first referrer named by a buy
+    fn record_referrer(&mut self, referrer: Option<Pubkey>) -> Result<()> {
+        if let Some(referrer) = referrer {
+            require!(referrer != self.owner, ErrorCode::SelfReferral);
+            if self.referrer == Pubkey::default() {
+                self.referrer = referrer;
+            }
+        }
+        Ok(())
+    }
+}
+
+// ===========================================
+// CLOB ACCOUNT STRUCTURES
+// ===========================================
+
+#[account]
+#[derive(InitSpace)]
+pub struct ClobMarket {
+    pub authority: Pubkey,
+    #[max_len(32)]
+    pub market_id: String,
+    #[max_len(256)]
+    pub question: String,
+    pub resolution_time: i64,
+    pub resolved: bool,
+    pub winning_side: Option<u8>,
+    pub created_at: i64,
+    pub total_yes_volume: u64,
+    pub total_no_volume: u64,
+    pub vault_reserve: u64, // Lamports the vault must always retain (rent + buffer)
+    pub matching_mode: u8,
+    pub pro_rata_aging_bps: u64,
+    pub max_resolution_delay: i64, // Seconds past resolution_time before the market is void, 0 = never
+    pub taker_fee_bps: u64,
+    pub fees_accrued: u64, // Taker fees held in the vault, net of maker rebates, not yet withdrawn
+    pub maker_rebate_bps: u64, // Share of the taker fee credited to the maker of each fill
+    pub mint: Pubkey, // Collateral token, Pubkey::default() for native lamports
+    pub payout_per_share: u64, // Collateral units paid per winning share
+    pub voided: bool, // Voided by the authority, every share pays VOID_PAYOUT_BPS
+    pub pending_authority: Option<Pubkey>, // Proposed new authority, set by propose_clob_authority
+    pub paused: bool, // New orders halted by the authority; cancels and claims still work
+    pub last_trade_price: u64, // YES price in bps of the most recent fill, 0 = no trades yet
+    pub open_interest: u64, // Matched YES/NO pairs not yet settled by a claim
+    pub min_price: u64, // Lowest YES price in bps an order may carry
+    pub max_price: u64, // Highest YES price in bps an order may carry
+    pub lot_size: u64, // Order sizes must be a multiple of this
+    pub tick_size: u64, // Prices must be a multiple of this, which divides BPS_MAX
+    pub min_order_size: u64, // Smallest size an order may be placed or left resting at, 0 = no minimum
+    pub permissioned: bool, // Only traders with an AllowlistEntry may place orders
+    pub dispute_period: i64, // Seconds after resolution before claims open, 0 = immediately
+    pub resolved_at: i64, // Set on resolution, 0 while unresolved
+    #[max_len(MAX_RESOLVERS)]
+    pub resolvers: Vec<Pubkey>, // Empty = the authority resolves alone
+    pub resolver_threshold: u8, // Matching votes needed to resolve, 0 when resolvers is empty
+    pub oracle: Pubkey, // Feed account read by resolve_from_oracle, Pubkey::default() = none
+    pub oracle_offset: u32, // Byte offset of the little-endian i64 value in the feed's data
+    pub oracle_threshold: i64,
+    pub oracle_comparison: u8, // ORACLE_* comparison of the feed value against oracle_threshold
+    pub early_resolution: bool, // Resolution may happen before resolution_time
+    pub max_trader_notional: u64, // Cap on the collateral one position may commit, 0 = unlimited
+    pub referral_fee_bps: u64, // Referrer's share of the net taker fee, in bps of it
+    pub rebates_owed: u64, // Maker rebates credited to positions and not yet claimed
+    pub seeded: bool, // Created with seed_liquidity orders from the authority
+    pub bump: u8,
+    pub version: u8, // Layout version; fields below it were added by later versions
+    pub authority_cannot_trade: bool, // Negated authority_can_trade, so older accounts read as allowed
+    pub allow_emergency: bool, // The authority may drain the vault with emergency_withdraw
+    pub insurance_balance: u64, // Lamports from fund_insurance held in the vault to cover short claims
+    #[max_len(MAX_RESOLUTION_URI_LEN)]
+    pub resolution_uri: String, // Link to the evidence behind the resolution, empty = none given
+    pub resolution_source: Pubkey, // Who or what the outcome was taken from, default = none given
+    pub market_group: Pubkey, // MarketGroup this market resolves with, default = none
+    pub yes_outstanding: u64, // YES shares credited to positions and not yet claimed or burned
+    pub no_outstanding: u64, // NO shares credited to positions and not yet claimed or burned
+    pub closed: bool, // Closed by close_clob_market; kept as a tombstone so the id can't be reused
+}
+
+impl ClobMarket {
+    /// An unresolved market becomes void once max_resolution_delay has
+    /// passed since resolution_time. Checked lazily, no crank needed.
+    /// The authority can also void it explicitly with void_clob_market.
+    pub fn is_void_at(&self, now: i64) -> bool {
+        self.voided
+            || (!self.resolved
+                && self.max_resolution_delay > 0
+                && now > self.resolution_time.saturating_add(self.max_resolution_delay))
+    }
+
+    /// A resolved market's outcome can still be overridden, and claims stay
+    /// closed, until dispute_period has passed since resolved_at
+    pub fn in_dispute_period_at(&self, now: i64) -> bool {
+        self.resolved && now < self.resolved_at.saturating_add(self.dispute_period)
+    }
+
+    /// Taker fee on `taker_cost` bps of filled collateral, in collateral units
+    fn taker_fee(&self, taker_cost: u64) -> Result<u64> {
+        Ok(self
+            .to_collateral(taker_cost)?
+            .checked_mul(self.taker_fee_bps)
+            .ok_or(ClobError::Overflow)?
+            / BPS_MAX)
+    }
+
+    /// Price and size rules every order must meet. Ticks divide BPS_MAX, so
+    /// a price passes for YES exactly when its NO equivalent does.
+    fn check_order_terms(&self, price: u64, size: u64) -> Result<()> {
+        require!(price > 0 && price < BPS_MAX, ClobError::InvalidPrice);
+        require!(size > 0, ClobError::InvalidSize);
+        require!(size >= self.min_order_size, ClobError::BelowMinOrderSize);
+        require!(size.is_multiple_of(self.lot_size), ClobError::InvalidLot);
+        require!(price.is_multiple_of(self.tick_size), ClobError::InvalidTick);
+        Ok(())
+    }
+
+    /// Every share owed a payout has been claimed and every rebate paid,
+    /// and no new trades or claims can change that. Once resolved only the
+    /// winning side's shares are owed anything; once void, both sides are.
+    pub fn is_settled_at(&self, now: i64) -> bool {
+        let owed = if self.is_void_at(now) {
+            self.yes_outstanding.saturating_add(self.no_outstanding)
+        } else if self.resolved && !self.in_dispute_period_at(now) {
+            if self.winning_side == Some(0) { self.yes_outstanding } else { self.no_outstanding }
+        } else {
+            return false;
+        };
+        owed == 0 && self.rebates_owed == 0
+    }
+
+    /// Validate and store the settings shared by lamport and token markets.
+    /// The caller sets `vault_reserve`, `mint` and `bump`.
+    fn init(
+        &mut self,
+        authority: Pubkey,
+        market_id: String,
+        question: String,
+        resolution_time: i64,
+        config: &ClobMarketConfig,
+    ) -> Result<()> {
+        require!(market_id.len() <= 32, ClobError::MarketIdTooLong);
+        require!(question.len() <= 256, ClobError::QuestionTooLong);
+        require!(config.matching_mode <= MATCHING_MODE_PRO_RATA, ClobError::InvalidMatchingMode);
+        require!(config.max_resolution_delay >= 0, ClobError::InvalidResolutionDelay);
+        let now = Clock::get()?.unix_timestamp;
+        // A market already past resolution_time could never take an order
+        require!(resolution_time > now, ClobError::ResolutionTimeInPast);
+        require!(resolution_time - now <= MAX_MARKET_DURATION, ClobError::ResolutionTimeTooFar);
+        require!(config.dispute_period >= 0, ClobError::InvalidDisputePeriod);
+        require!(config.resolvers.len() <= MAX_RESOLVERS, ClobError::InvalidResolverSet);
+        for (i, resolver) in config.resolvers.iter().enumerate() {
+            require!(!config.resolvers[..i].contains(resolver), ClobError::InvalidResolverSet);
+        }
+        require!(
+            (config.resolver_threshold as usize) <= config.resolvers.len()
+                && (config.resolver_threshold > 0 || config.resolvers.is_empty()),
+            ClobError::InvalidResolverSet
+        );
+        require!(config.oracle_comparison <= ORACLE_LESS_OR_EQUAL, ClobError::InvalidOracleConfig);
+        require!(
+            config.oracle == Pubkey::default() || config.resolvers.is_empty(),
+            ClobError::InvalidOracleConfig
+        );
+        require!(config.taker_fee_bps <= BPS_MAX, ClobError::InvalidFee);
+        require!(config.maker_rebate_bps <= config.taker_fee_bps, ClobError::InvalidRebate);
+        require!(config.referral_fee_bps <= BPS_MAX, ClobError::InvalidFee);
+        require!(config.order_book_capacity as usize <= MAX_ORDER_BOOK_CAPACITY, ClobError::InvalidCapacity);
+        // Prices are in bps of the payout, so each bps must be a whole unit
+        let payout_per_share = if config.payout_per_share == 0 {
+            SHARE_PAYOUT
+        } else {
+            config.payout_per_share
+        };
+        require!(payout_per_share % BPS_MAX == 0, ClobError::InvalidPayoutPerShare);
+        let min_price = if config.min_price == 0 { 1 } else { config.min_price };
+        let max_price = if config.max_price == 0 { BPS_MAX - 1 } else { config.max_price };
+        require!(min_price <= max_price && max_price < BPS_MAX, ClobError::InvalidPriceBand);
+        let lot_size = config.lot_size.max(1);
+        let tick_size = config.tick_size.max(1);
+        // Dividing BPS_MAX keeps YES and NO prices on the same tick grid
+        require!(BPS_MAX.is_multiple_of(tick_size), ClobError::InvalidTick);
+        
+        self.authority = authority;
+        self.market_id = market_id;
+        self.question = question;
+        self.resolution_time = resolution_time;
+        self.resolved = false;
+        self.winning_side = None;
+        self.created_at = now;
+        self.total_yes_volume = 0;
+        self.total_no_volume = 0;
+        self.matching_mode = config.matching_mode;
+        self.pro_rata_aging_bps = config.pro_rata_aging_bps;
+        self.max_resolution_delay = config.max_resolution_delay;
+        self.taker_fee_bps = config.taker_fee_bps;
+        self.fees_accrued = 0;
+        self.rebates_owed = 0;
+        self.seeded = false;
+        self.maker_rebate_bps = config.maker_rebate_bps;
+        self.payout_per_share = payout_per_share;
+        self.min_price = min_price;
+        self.max_price = max_price;
+        self.lot_size = lot_size;
+        self.tick_size = tick_size;
+        self.min_order_size = config.min_order_size;
+        self.permissioned = config.permissioned;
+        self.dispute_period = config.dispute_period;
+        self.resolvers = config.resolvers.clone();
+        self.resolver_threshold = config.resolver_threshold;
+        self.oracle = config.oracle;
+        self.oracle_offset = config.oracle_offset;
+        self.oracle_threshold = config.oracle_threshold;
+        self.oracle_comparison = config.oracle_comparison;
+        self.early_resolution = config.early_resolution;
+        self.max_trader_notional = config.max_trader_notional;
+        self.referral_fee_bps = config.referral_fee_bps;
+        self.version = CLOB_MARKET_VERSION;
+        self.authority_cannot_trade = !config.authority_can_trade;
+        self.allow_emergency = config.allow_emergency;
+        self.insurance_balance = 0;
+        self.resolution_uri = String::new();
+        self.resolution_source = Pubkey::default();
+        self.market_group = Pubkey::default();
+        self.yes_outstanding = 0;
+        self.no_outstanding = 0;
+        self.closed = false;
+        Ok(())
+    }
+
+    /// Lamports ordinary vault payouts must leave behind: the rent reserve
+    /// plus the insurance buffer, which only winning claims may draw on
+    pub fn vault_floor(&self) -> u64 {
+        self.vault_reserve.saturating_add(self.insurance_balance)
+    }
+
+    /// Convert an amount in bps of one share's payout (price * size) into
+    /// collateral units. Exact because payout_per_share is a multiple of BPS_MAX.
+    pub fn to_collateral(&self, bps_amount: u64) -> Result<u64> {
+        Ok(bps_amount
+            .checked_mul(self.payout_per_share / BPS_MAX)
+            .ok_or(ClobError::Overflow)?)
+    }
+
+    /// Take `shares` claimable shares off the position (0 = all of them) and
+    /// return what they are owed: payout_per_share for each winning share,
+    /// or VOID_PAYOUT_BPS of it for every share once void. A void claim
+    /// takes YES shares before NO shares. Losing shares are cleared along
+    /// with the last winning share.
+    ///
+    /// Every matched pair has exactly one winning share (or, once void, one
+    /// YES share), so that many pairs leave open_interest.
+    fn settle_claim(&mut self, position: &mut ClobPosition, shares: u64, now: i64) -> Result<u64> {
+        require!(!self.closed, ClobError::MarketClosed);
+        let (payout, settled_pairs) = if self.is_void_at(now) {
+            let held = position.yes_shares
+                .checked_add(position.no_shares)
+                .ok_or(ClobError::Overflow)?;
+            let taken = if shares == 0 { held } else { shares };
+            require!(taken <= held, ClobError::InsufficientShares);
+            let payout = taken
+                .checked_mul(self.payout_per_share / BPS_MAX * VOID_PAYOUT_BPS)
+                .ok_or(ClobError::Overflow)?;
+            let yes_taken = taken.min(position.yes_shares);
+            position.yes_shares -= yes_taken;
+            position.no_shares -= taken - yes_taken;
+            self.retire_shares(yes_taken, taken - yes_taken);
+            (payout, yes_taken)
+        } else {
+            require!(self.resolved, ClobError::NotResolved);
+            require!(!self.in_dispute_period_at(now), ClobError::DisputePeriodActive);
+            
+            let yes_wins = self.winning_side.unwrap() == 0;
+            let held = if yes_wins { position.yes_shares } else { position.no_shares };
+            let taken = if shares == 0 { held } else { shares };
+            require!(taken <= held, ClobError::InsufficientShares);
+            let payout = taken.checked_mul(self.payout_per_share).ok_or(ClobError::Overflow)?;
+            let (yes_taken, no_taken) = if taken == held {
+                (position.yes_shares, position.no_shares)
+            } else if yes_wins {
+                (taken, 0)
+            } else {
+                (0, taken)
+            };
+            position.yes_shares -= yes_taken;
+            position.no_shares -= no_taken;
+            self.retire_shares(yes_taken, no_taken);
+            (payout, taken)
+        };
+        
+        require!(payout > 0, ClobError::NoWinnings);
+        
+        self.open_interest = self.open_interest.saturating_sub(settled_pairs);
+        Ok(payout)
+    }
+    
+    /// Credit `sets` YES/NO pairs to a position and return the collateral
+    /// owed for them. Open only while the market is trading.
+    fn mint_complete_sets(&mut self, position: &mut ClobPosition, sets: u64, now: i64) -> Result<u64> {
+        require!(sets > 0, ClobError::InvalidSize);
+        require!(!self.resolved, ClobError::MarketResolved);
+        require!(!self.voided, ClobError::MarketVoid);
+        require!(!self.paused, ClobError::MarketPaused);
+        require!(now < self.resolution_time, ClobError::MarketExpired);
+        
+        let collateral = sets.checked_mul(self.payout_per_share).ok_or(ClobError::Overflow)?;
+        position.yes_shares = position.yes_shares.checked_add(sets).ok_or(ClobError::Overflow)?;
+        position.no_shares = position.no_shares.checked_add(sets).ok_or(ClobError::Overflow)?;
+        self.open_interest = self.open_interest.checked_add(sets).ok_or(ClobError::Overflow)?;
+        self.yes_outstanding = self.yes_outstanding.checked_add(sets).ok_or(ClobError::Overflow)?;
+        self.no_outstanding = self.no_outstanding.checked_add(sets).ok_or(ClobError::Overflow)?;
+        Ok(collateral)
+    }
+    
+    /// Burn `sets` YES/NO pairs from a position and return the collateral
+    /// they release. Each pair is worth payout_per_share in every outcome,
+    /// void included, so redeeming never needs the market's state.
+    fn redeem_complete_sets(&mut self, position: &mut ClobPosition, sets: u64) -> Result<u64> {
+        require!(sets > 0, ClobError::InvalidSize);
+        require!(
+            position.yes_shares >= sets && position.no_shares >= sets,
+            ClobError::InsufficientShares
+        );
+        
+        let payout = sets.checked_mul(self.payout_per_share).ok_or(ClobError::Overflow)?;
+        position.yes_shares -= sets;
+        position.no_shares -= sets;
+        self.open_interest = self.open_interest.saturating_sub(sets);
+        self.retire_shares(sets, sets);
+        Ok(payout)
+    }
+    
+    /// Take claimed or burned shares out of the outstanding counts.
+    /// Saturating, since migrated markets start them at an upper bound.
+    fn retire_shares(&mut self, yes_shares: u64, no_shares: u64) {
+        self.yes_outstanding = self.yes_outstanding.saturating_sub(yes_shares);
+        self.no_outstanding = self.no_outstanding.saturating_sub(no_shares);
+    }
+}
+
+/// Creation-time settings for a CLOB market
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct ClobMarketConfig {
+    /// Extra lamports kept in the vault on top of the rent-exempt minimum
+    pub vault_rent_buffer: u64,
+    /// MATCHING_MODE_FIFO or MATCHING_MODE_PRO_RATA
+    pub matching_mode: u8,
+    /// Pro-rata only: extra weight (bps) a resting order gains per second of age
+    pub pro_rata_aging_bps: u64,
+    /// Seconds after resolution_time before an unresolved market is void (0 = never).
+    /// The backstop if the authority never resolves: a YES/NO pair pays back
+    /// a full share's payout.
+    pub max_resolution_delay: i64,
+    /// Fee charged to takers on the collateral of their filled shares
+    pub taker_fee_bps: u64,
+    /// Rebate credited to makers on the same base, at most taker_fee_bps
+    pub maker_rebate_bps: u64,
+    /// Collateral units paid per winning share, a multiple of 10000 (0 = SHARE_PAYOUT)
+    pub payout_per_share: u64,
+    /// Lowest YES price in bps an order may carry (0 = 1)
+    pub min_price: u64,
+    /// Highest YES price in bps an order may carry (0 = 9999)
+    pub max_price: u64,
+    /// Order sizes must be a multiple of this (0 = 1)
+    pub lot_size: u64,
+    /// Prices in bps must be a multiple of this and it must divide 10000 (0 = 1)
+    pub tick_size: u64,
+    /// Smallest order size; partially filled orders left below it are cancelled (0 = no minimum)
+    pub min_order_size: u64,
+    /// Restrict order placement to traders the authority adds with add_trader
+    pub permissioned: bool,
+    /// Seconds after resolution during which the authority can override it
+    /// and claims are closed (0 = no dispute window)
+    pub dispute_period: i64,
+    /// Wallets that vote on the outcome with vote_clob_resolution, at most
+    /// MAX_RESOLVERS (empty = the authority resolves with resolve_clob_market)
+    pub resolvers: Vec<Pubkey>,
+    /// Votes for the same side needed to resolve, 1..=resolvers.len()
+    /// (must be 0 without resolvers)
+    pub resolver_threshold: u8,
+    /// Feed account for resolve_from_oracle (Pubkey::default() = no oracle).
+    /// Can't be combined with resolvers.
+    pub oracle: Pubkey,
+    /// Byte offset of the feed's value, read as a little-endian i64
+    pub oracle_offset: u32,
+    /// Value the feed is compared against at resolution
+    pub oracle_threshold: i64,
+    /// ORACLE_* operator; YES wins when `value <op> oracle_threshold` holds
+    pub oracle_comparison: u8,
+    /// Allow resolving before resolution_time, for events that can conclude
+    /// early. Oracle resolution always waits for resolution_time.
+    pub early_resolution: bool,
+    /// Most collateral any single position may commit to orders and
+    /// complete sets over its lifetime, 0 = unlimited. Counted when an order
+    /// is placed, net of immediate refunds and excluding fees, or a set is
+    /// minted; cancelling or redeeming doesn't free it up.
+    pub max_trader_notional: u64,
+    /// Resting orders each side of the book can hold, up to
+    /// MAX_ORDER_BOOK_CAPACITY (0 = MAX_ORDERS). The order book account is
+    /// sized, and its rent paid, for this many.
+    pub order_book_capacity: u16,
+    /// Share of the taker fee, after maker rebates, paid to the order's
+    /// referrer, in bps of that fee
+    pub referral_fee_bps: u64,
+    /// Opening bid and ask placed for the authority at creation, with their
+    /// collateral paid by the authority (lamport markets only)
+    pub seed_liquidity: Option<SeedLiquidity>,
+    /// Let the authority, who resolves the market, place orders in it.
+    /// Must be true to seed liquidity.
+    pub authority_can_trade: bool,
+    /// Let the authority drain the vault with emergency_withdraw once
+    /// EMERGENCY_WITHDRAW_DELAY has passed since resolution_time (lamport
+    /// markets only). Markets meant to be trustless leave this off.
+    pub allow_emergency: bool,
+}
+
+/// Symmetric opening quotes for a new CLOB market: a YES bid at
+/// price - half_spread and a YES ask at price + half_spread, each of `size`
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct SeedLiquidity {
+    /// Starting YES probability in bps
+    pub price: u64,
+    pub half_spread: u64,
+    pub size: u64,
+}
+
+impl ClobMarketConfig {
+    fn order_book_capacity(&self) -> u16 {
+        if self.order_book_capacity == 0 {
+            MAX_ORDERS as u16
+        } else {
+            self.order_book_capacity
+        }
+    }
+    
+    /// Order book account size for this config. Clamped so an oversized
+    /// capacity fails validation with InvalidCapacity rather than at
+    /// account creation.
+    fn order_book_space(&self) -> usize {
+        OrderBook::space((self.order_book_capacity() as usize).min(MAX_ORDER_BOOK_CAPACITY))
+    }
+}
+
+/// Best price on each side of a CLOB book, None when that side has no live orders
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct TopOfBook {
+    pub best_bid: Option<u64>,
+    pub best_bid_size: u64, // Total live size at best_bid, 0 when empty
+    pub best_ask: Option<u64>,
+    pub best_ask_size: u64, // Total live size at best_ask, 0 when empty
+}
+
+impl TopOfBook {
+    /// Mid of the best bid and ask, or the one live quote on a one-sided book
+    pub fn mid_price(&self) -> Option<u64> {
+        match (self.best_bid, self.best_ask) {
+            (Some(bid), Some(ask)) => Some((bid + ask) / 2),
+            (Some(quote), None) | (None, Some(quote)) => Some(quote),
+            (None, None) => None,
+        }
+    }
+}
+
+/// How an order would fill against the book right now, from quote_order
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct OrderQuote {
+    pub filled: u64,
+    pub unfilled: u64, // Would rest or be refunded, depending on order type
+    pub cost: u64, // Collateral behind the filled shares, before the taker fee
+    pub taker_fee: u64,
+    pub avg_price: u64, // Average fill price in the order's own terms, in bps, rounded down
+}
+
+/// Where a resting order sits in its side of the book, from get_order_rank
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct OrderRank {
+    pub index: u32, // Position in the sorted side, 0 = next to fill
+    pub size_ahead: u64, // Live size at equal or better prices that fills first
+}
+
+/// A CLOB position netted and valued in the trader's terms, from get_clob_position
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct ClobPositionView {
+    pub yes_shares: u64,
+    pub no_shares: u64,
+    pub net_yes: u64, // YES shares not offset by NO shares, which pair into complete sets
+    pub net_no: u64, // NO shares not offset by YES shares
+    pub mark_price: Option<u64>, // YES price in bps the shares are valued at, None with no book or trades
+    pub yes_value: u64, // In collateral units, 0 without a mark price
+    pub no_value: u64, // In collateral units, valued at BPS_MAX - mark_price
+    pub unrealized_value: u64, // yes_value + no_value
+}
+
+/// A position's payout if it claimed now, from estimate_winnings or
+/// estimate_clob_winnings, or summed over many by batch_estimate
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Default)]
+pub struct WinningsEstimate {
+    pub payout: u64, // Before fees
+    pub fee: u64,
+    pub net_payout: u64, // What the claimer receives
+}
+
+/// One entry of a place_orders batch, placed as a good-till-cancelled limit order
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct OrderParams {
+    pub side: u8,
+    pub is_yes: bool,
+    pub price: u64,
+    pub size: u64,
+}
+
+/// What happened to one order of a place_orders batch
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct OrderSummary {
+    pub filled: u64,
+    pub resting_order_id: Option<u64>, // None when the order filled completely
+}
+
+/// Snapshot of a parimutuel market, from get_market_state
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct MarketState {
+    pub status: u8, // One of MARKET_STATUS_*
+    pub winning_outcome: Option<u8>,
+    pub total_pool: u64,
+    pub outcome_pools: Vec<u64>,
+    pub odds_bps: Vec<u64>, // Each outcome's pool in bps of total_pool, all 0 while empty
+    pub seconds_to_resolution: i64, // 0 once resolution_time has passed
+}
+
+/// Snapshot of a CLOB market, from get_clob_market_state
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct ClobMarketState {
+    pub status: u8, // One of MARKET_STATUS_*
+    pub winning_side: Option<u8>,
+    pub seconds_to_resolution: i64, // 0 once resolution_time has passed
+    pub best_bid: Option<u64>,
+    pub best_ask: Option<u64>,
+    pub mid_price: Option<u64>, // Implied YES probability in bps, None on an empty book
+    pub last_trade_price: u64, // 0 until the first fill
+    pub open_interest: u64,
+}
+
+/// Implied YES probability of a CLOB market, in bps
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct MidPrice {
+    pub mid_price: Option<u64>, // None when the book has no live orders
+    pub last_trade_price: u64, // 0 until the first fill
+}
+
+#[account]
+pub struct OrderBook {
+    pub market: Pubkey,
+    pub yes_bids: Vec<Order>, // At most `capacity`
+    pub yes_asks: Vec<Order>, // At most `capacity`
+    pub order_seq: u64, // Next order_id to hand out, unique per market
+    pub total_bid_size: u64, // Sum of yes_bids sizes, kept in step with the vec
+    pub total_ask_size: u64, // Sum of yes_asks sizes, kept in step with the vec
+    pub capacity: u16, // Resting orders each side can hold, fixed at creation
+    pub bump: u8,
+    pub version: u8, // Layout version, kept last so older accounts read it as 0
+}
+
+impl OrderBook {
+    /// Account size, discriminator included, for `capacity` orders per side
+    pub const fn space(capacity: usize) -> usize {
+        8 + 32 + 2 * (4 + capacity * Order::INIT_SPACE) + 8 + 8 + 8 + 2 + 1 + 1
+    }
+    
+    fn init(&mut self, market: Pubkey, capacity: u16, bump: u8) {
+        self.market = market;
+        self.yes_bids = Vec::new();
+        self.yes_asks = Vec::new();
+        self.order_seq = 0;
+        self.total_bid_size = 0;
+        self.total_ask_size = 0;
+        self.capacity = capacity;
+        self.bump = bump;
+        self.version = ORDER_BOOK_VERSION;
+    }
+    
+    /// Best live price on each side and the size resting there. Expired
+    /// orders are skipped the same way matching skips them.
+    pub fn top_of_book(&self, now: i64) -> TopOfBook {
+        let level = |orders: &[Order]| -> (Option<u64>, u64) {
+            match best_live_price(orders, now) {
+                Some(price) => {
+                    let size = orders
+                        .iter()
+                        .filter(|o| o.price == price && !o.is_expired_at(now))
+                        .map(|o| o.size)
+                        .sum();
+                    (Some(price), size)
+                }
+                None => (None, 0),
+            }
+        };
+        let (best_bid, best_bid_size) = level(&self.yes_bids);
+        let (best_ask, best_ask_size) = level(&self.yes_asks);
+        TopOfBook { best_bid, best_bid_size, best_ask, best_ask_size }
+    }
+    
+    /// Queue position of an order on one side of the book. Expired orders
+    /// ahead of it are skipped by matching, so they don't count.
+    fn order_rank(&self, is_bid: bool, order_id: u64, now: i64) -> Result<OrderRank> {
+        let orders = if is_bid { &self.yes_bids } else { &self.yes_asks };
+        let index = orders
+            .iter()
+            .position(|o| o.order_id == order_id)
+            .ok_or(ClobError::OrderNotFound)?;
+        let size_ahead = orders[..index]
+            .iter()
+            .filter(|o| !o.is_expired_at(now))
+            .map(|o| o.size)
+            .sum();
+        Ok(OrderRank { index: index as u32, size_ahead })
+    }
+    
+    /// Side and index of the owner's resting order with this ID
+    fn find_order(&self, owner: Pubkey, order_id: u64) -> Result<(bool, usize)> {
+        [(true, &self.yes_bids), (false, &self.yes_asks)]
+            .into_iter()
+            .find_map(|(is_bid, orders)| {
+                orders
+                    .iter()
+                    .position(|o| o.order_id == order_id && o.owner == owner)
+                    .map(|index| (is_bid, index))
+            })
+            .ok_or(ClobError::OrderNotFound.into())
+    }
+    
+    /// Take the owner's order at `index` off one side of the book
+    fn remove_order(&mut self, owner: Pubkey, is_bid: bool, index: usize) -> Result<Order> {
+        let orders = if is_bid {
+            &mut self.yes_bids
+        } else {
+            &mut self.yes_asks
+        };
+        
+        require!(index < orders.len(), ClobError::InvalidOrderIndex);
+        require!(orders[index].owner == owner, ClobError::NotOrderOwner);
+        let order = orders.remove(index);
+        self.reduce_depth(is_bid, order.size);
+        Ok(order)
+    }
+    
+    /// Reprice and/or resize the owner's resting order, returning its side
+    /// and the order as it was. A pure size reduction stays in its queue
+    /// slot; anything else re-queues behind the orders at the new price.
+    fn amend_order(
+        &mut self,
+        owner: Pubkey,
+        order_id: u64,
+        price: u64,
+        size: u64,
+        now: i64,
+    ) -> Result<(bool, Order)> {
+        let (is_bid, index) = self.find_order(owner, order_id)?;
+        let (orders, opposite) = if is_bid {
+            (&mut self.yes_bids, &self.yes_asks)
+        } else {
+            (&mut self.yes_asks, &self.yes_bids)
+        };
+        let crosses = best_live_price(opposite, now).is_some_and(|best| {
+            if is_bid { price >= best } else { price <= best }
+        });
+        require!(!crosses, ClobError::PostOnlyWouldCross);
+        
+        let old = orders[index].clone();
+        if price == old.price && size <= old.size {
+            orders[index].size = size;
+        } else {
+            let mut order = orders.remove(index);
+            order.price = price;
+            order.size = size;
+            order.timestamp = now;
+            let insert_idx = queue_index(orders, is_bid, &order);
+            orders.insert(insert_idx, order);
+        }
+        
+        self.reduce_depth(is_bid, old.size);
+        let total = if is_bid {
+            &mut self.total_bid_size
+        } else {
+            &mut self.total_ask_size
+        };
+        *total = total.checked_add(size).ok_or(ClobError::Overflow)?;
+        Ok((is_bid, old))
+    }
+    
+    /// Take every resting order the owner has off the book, tagged is_bid
+    fn remove_all_orders(&mut self, owner: Pubkey) -> Vec<(bool, Order)> {
+        let mut removed = Vec::new();
+        for (orders, is_bid) in [(&mut self.yes_bids, true), (&mut self.yes_asks, false)] {
+            let (mine, rest): (Vec<Order>, Vec<Order>) =
+                orders.drain(..).partition(|o| o.owner == owner);
+            *orders = rest;
+            removed.extend(mine.into_iter().map(|order| (is_bid, order)));
+        }
+        for (is_bid, order) in &removed {
+            self.reduce_depth(*is_bid, order.size);
+        }
+        removed
+    }
+    
+    /// Remove up to `max_orders` orders from the front of the book, bids
+    /// first, whoever owns them
+    fn remove_front(&mut self, max_orders: usize) -> Vec<(bool, Order)> {
+        let mut removed = Vec::new();
+        for (orders, is_bid) in [(&mut self.yes_bids, true), (&mut self.yes_asks, false)] {
+            let take = orders.len().min(max_orders - removed.len());
+            removed.extend(orders.drain(..take).map(|order| (is_bid, order)));
+        }
+        for (is_bid, order) in &removed {
+            self.reduce_depth(*is_bid, order.size);
+        }
+        removed
+    }
+    
+    /// Take filled or cancelled size off one side's running total
+    fn reduce_depth(&mut self, is_bid: bool, size: u64) {
+        let total = if is_bid {
+            &mut self.total_bid_size
+        } else {
+            &mut self.total_ask_size
+        };
+        *total = total.saturating_sub(size);
+    }
+    
+    pub fn next_order_id(&mut self) -> Result<u64> {
+        let order_id = self.order_seq;
+        self.order_seq = self.order_seq.checked_add(1).ok_or(ClobError::Overflow)?;
+        Ok(order_id)
+    }
+}
+
+/// The last PRICE_HISTORY_LEN fills of a CLOB market, for charting
+#[account]
+#[derive(InitSpace)]
+pub struct PriceHistory {
+    pub market: Pubkey,
+    #[max_len(PRICE_HISTORY_LEN)]
+    pub points: Vec<PricePoint>, // Oldest first until full, then the oldest is at `next`
+    pub next: u8, // Slot the next fill overwrites once the buffer is full
+    pub bump: u8,
+}
+
+impl PriceHistory {
+    fn init(&mut self, market: Pubkey, bump: u8) {
+        self.market = market;
+        self.points = Vec::new();
+        self.next = 0;
+        self.bump = bump;
+    }
+    
+    /// Append a fill, overwriting the oldest one once the buffer is full
+    fn record(&mut self, price: u64, size: u64, timestamp: i64) {
+        let point = PricePoint { price, size, timestamp };
+        if self.points.len() < PRICE_HISTORY_LEN {
+            self.points.push(point);
+        } else {
+            self.points[self.next as usize] = point;
+            self.next = ((self.next as usize + 1) % PRICE_HISTORY_LEN) as u8;
+        }
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, InitSpace)]
+pub struct PricePoint {
+    pub price: u64, // YES price in bps
+    pub size: u64,
+    pub timestamp: i64,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, InitSpace)]
+pub struct Order {
+    pub owner: Pubkey,
+    pub price: u64,
+    pub size: u64,
+    pub timestamp: i64,
+    pub order_id: u64,
+    pub expiry: i64, // Unix timestamp after which the order no longer matches, 0 = good-till-cancelled
+    pub filled: u64, // Shares filled so far, including on placement; size is what's left
+}
+
+impl Order {
+    pub fn is_expired_at(&self, now: i64) -> bool {
+        self.expiry != 0 && self.expiry < now
+    }
+    
+    /// Collateral held in the vault for this order, mirroring what
+    /// place_order reserved: price * size for bids, (BPS_MAX - price) * size for asks
+    pub fn collateral(&self, is_bid: bool) -> Result<u64> {
+        let per_share = if is_bid { self.price } else { BPS_MAX - self.price };
+        Ok(per_share.checked_mul(self.size).ok_or(ClobError::Overflow)?)
+    }
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct ClobPosition {
+    pub owner: Pubkey,
+    pub market: Pubkey,
+    pub yes_shares: u64,
+    pub no_shares: u64,
+    pub volume_traded: u64, // Filled notional (price * size, in YES bps) as maker or taker
+    pub rebates_owed: u64, // Maker rebates not yet claimed, in collateral units
+    pub notional: u64, // Collateral committed to orders, counted against max_trader_notional
+    pub bump: u8,
+}
+
+impl ClobPosition {
+    fn emit_changed(&self, reason: u8) {
+        emit!(PositionChanged {
+            market: self.market,
+            owner: self.owner,
+            shares: vec![self.yes_shares, self.no_shares],
+            reason,
+        });
+    }
+    
+    fn init_if_needed(&mut self, owner: Pubkey, market: Pubkey, bump: u8) {
+        if self.owner == Pubkey::default() {
+            self.owner = owner;
+            self.market = market;
+            self.yes_shares = 0;
+            self.no_shares = 0;
+            self.volume_traded = 0;
+            self.rebates_owed = 0;
+            self.notional = 0;
+            self.bump = bump;
+        }
+    }
+    
+    /// Count collateral committed to orders or minted sets toward the
+    /// market's max_trader_notional
+    fn record_notional(&mut self, amount: u64, cap: u64) -> Result<()> {
+        let notional = self.notional.checked_add(amount).ok_or(ClobError::Overflow)?;
+        require!(cap == 0 || notional <= cap, ClobError::PositionLimitExceeded);
+        self.notional = notional;
+        Ok(())
+    }
+}
+
+/// Marks a trader as allowed to place orders on a permissioned CLOB market
+#[account]
+#[derive(InitSpace)]
+pub struct AllowlistEntry {
+    pub market: Pubkey,
+    pub trader: Pubkey,
+    pub bump: u8,
+}
+
+/// Resolver votes for a CLOB market, one slot per entry in market.resolvers
+#[account]
+#[derive(InitSpace)]
+pub struct ResolutionVotes {
+    pub market: Pubkey,
+    #[max_len(MAX_RESOLVERS)]
+    pub votes: Vec<Option<u8>>, // Winning side each resolver voted for, None = not voted
+    pub bump: u8,
+}
+
+// ===========================================
+// REPORTING ACCOUNT STRUCTURES
+// ===========================================
+
+/// Running settlement history for a wallet across lamport-denominated
+/// parimutuel and CLOB markets. Token markets are not tracked here.
+#[account]
+#[derive(InitSpace)]
+pub struct RealizedPnl {
+    pub owner: Pubkey,
+    pub total_staked: u64,  // Lamports committed, net of refunds
+    pub total_claimed: u64, // Lamports paid out by claims
+    pub net_result: i64,    // total_claimed - total_staked
+    pub bump: u8,
+}
+
+impl RealizedPnl {
+    fn init_if_needed(&mut self, owner: Pubkey, bump: u8) {
+        if self.owner == Pubkey::default() {
+            self.owner = owner;
+            self.bump = bump;
+        }
+    }
+
+    // Reporting counters saturate rather than fail so they can never block
+    // a trade or a payout.
+    fn record_stake(&mut self, amount: u64) {
+        self.total_staked = self.total_staked.saturating_add(amount);
+        self.net_result = self.net_result.saturating_sub_unsigned(amount);
+    }
+
+    fn record_refund(&mut self, amount: u64) {
+        self.total_staked = self.total_staked.saturating_sub(amount);
+        self.net_result = self.net_result.saturating_add_unsigned(amount);
+    }
+
+    fn record_claim(&mut self, amount: u64) {
+        self.total_claimed = self.total_claimed.saturating_add(amount);
+        self.net_result = self.net_result.saturating_add_unsigned(amount);
+    }
+}
+
+/// Every market an authority has created, parimutuel and CLOB alike, so
+/// clients can discover an operator's markets from a single account.
+/// Entries are removed with remove_from_registry.
+#[account]
+#[derive(InitSpace)]
+pub struct MarketRegistry {
+    pub authority: Pubkey,
+    #[max_len(MAX_REGISTRY_MARKETS)]
+    pub markets: Vec<Pubkey>, // In creation order
+    pub bump: u8,
+}
+
+impl MarketRegistry {
+    fn init_if_needed(&mut self, authority: Pubkey, bump: u8) {
+        if self.authority == Pubkey::default() {
+            self.authority = authority;
+            self.bump = bump;
+        }
+    }
+
+    fn add(&mut self, market: Pubkey) -> Result<()> {
+        require!(self.markets.len() < MAX_REGISTRY_MARKETS, ErrorCode::RegistryFull);
+        self.markets.push(market);
+        Ok(())
+    }
+
+    fn remove(&mut self, market: Pubkey) -> Result<()> {
+        let index = self.markets.iter().position(|m| *m == market).ok_or(ErrorCode::MarketNotInRegistry)?;
+        self.markets.remove(index);
+        Ok(())
+    }
+}
+
+/// Mutually exclusive binary CLOB markets, such as one "will win" market
+/// per candidate. Exactly one resolves YES, through group_resolve.
+#[account]
+#[derive(InitSpace)]
+pub struct MarketGroup {
+    pub authority: Pubkey,
+    #[max_len(32)]
+    pub group_id: String,
+    #[max_len(MAX_GROUP_MARKETS)]
+    pub markets: Vec<Pubkey>, // In the order group_resolve takes them
+    pub winner: Option<Pubkey>, // The market that resolved YES, None until group_resolve
+    pub bump: u8,
+}
+
+/// Referral fees earned by a front-end across lamport markets. The account
+/// holds the unclaimed lamports on top of its rent.
+#[account]
+#[derive(InitSpace)]
+pub struct ReferralAccount {
+    pub referrer: Pubkey,
+    pub unclaimed: u64,
+    pub total_earned: u64,
+    pub bump: u8,
+}
+
+/// A hidden parimutuel buy made during the commit phase, closed when it
+/// is revealed or refunded
+#[account]
+#[derive(InitSpace)]
+pub struct BuyCommitment {
+    pub market: Pubkey,
+    pub buyer: Pubkey,
+    pub commitment: [u8; 32], // SHA-256 of outcome index, amount and nonce
+    pub amount: u64, // Lamports held by the market for it
+    pub bump: u8,
+}
+
+// ===========================================
+// PARIMUTUEL CONTEXTS
+// ===========================================
+
+#[derive(Accounts)]
+#[instruction(market_id: String)]
+pub struct CreateMarket<'info> {
+    #[account(
+        init,
+        payer = authority,
+        space = Market::SPACE,
+        seeds = [b"market", market_id.as_bytes()],
+        bump
+    )]
+    pub market: Account<'info, Market>,
+    
+    #[account(
+        init_if_needed,
+        payer = authority,
+        space = 8 + MarketRegistry::INIT_SPACE,
+        seeds = [b"market_registry", authority.key().as_ref()],
+        bump
+    )]
+    pub registry: Account<'info, MarketRegistry>,
+    
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(market_id: String)]
+pub struct CreateTokenMarket<'info> {
+    #[account(
+        init,
+        payer = authority,
+        space = Market::SPACE,
+        seeds = [b"market", market_id.as_bytes()],
+        bump
+    )]
+    pub market: Account<'info, Market>,
+    
+    #[account(
+        init_if_needed,
+        payer = authority,
+        space = 8 + MarketRegistry::INIT_SPACE,
+        seeds = [b"market_registry", authority.key().as_ref()],
+        bump
+    )]
+    pub registry: Account<'info, MarketRegistry>,
+    
+    pub mint: Account<'info, Mint>,
+    
+    #[account(
+        init,
+        payer = authority,
+        associated_token::mint = mint,
+        associated_token::authority = market
+    )]
+    pub vault: Account<'info, TokenAccount>,
+    
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct BuyShares<'info> {
+    #[account(mut, constraint = market.mint == Pubkey::default() @ ErrorCode::CollateralMismatch)]
+    pub market: Account<'info, Market>,
+    
+    #[account(
+        init_if_needed,
+        payer = buyer,
+        space = 8 + 32 + 32 + 4 + 10*8 + 8 + 32 + 1,
+        seeds = [b"position", market.key().as_ref(), buyer.key().as_ref()],
+        bump
+    )]
+    pub position: Account<'info, Position>,
+    
+    #[account(
+        init_if_needed,
+        payer = buyer,
+        space = 8 + RealizedPnl::INIT_SPACE,
+        seeds = [b"realized_pnl", buyer.key().as_ref()],
+        bump
+    )]
+    pub realized_pnl: Account<'info, RealizedPnl>,
+    
+    #[account(mut)]
+    pub buyer: Signer<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CommitBuy<'info> {
+    #[account(mut, constraint = market.mint == Pubkey::default() @ ErrorCode::CollateralMismatch)]
+    pub market: Account<'info, Market>,
+    
+    #[account(
+        init,
+        payer = buyer,
+        space = 8 + BuyCommitment::INIT_SPACE,
+        seeds = [b"commitment", market.key().as_ref(), buyer.key().as_ref()],
+        bump
+    )]
+    pub commitment: Account<'info, BuyCommitment>,
+    
+    #[account(
+        init_if_needed,
+        payer = buyer,
+        space = 8 + 32 + 32 + 4 + 10*8 + 8 + 32 + 1,
+        seeds = [b"position", market.key().as_ref(), buyer.key().as_ref()],
+        bump
+    )]
+    pub position: Account<'info, Position>,
+    
+    #[account(mut)]
+    pub buyer: Signer<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct RevealBuy<'info> {
+    #[account(mut)]
+    pub market: Account<'info, Market>,
+    
+    #[account(
+        mut,
+        close = buyer,
+        seeds = [b"commitment", market.key().as_ref(), buyer.key().as_ref()],
+        bump = commitment.bump
+    )]
+    pub commitment: Account<'info, BuyCommitment>,
+    
+    #[account(
+        init_if_needed,
+        payer = buyer,
+        space = 8 + 32 + 32 + 4 + 10*8 + 8 + 32 + 1,
+        seeds = [b"position", market.key().as_ref(), buyer.key().as_ref()],
+        bump
+    )]
+    pub position: Account<'info, Position>,
+    
+    #[account(
+        init_if_needed,
+        payer = buyer,
+        space = 8 + RealizedPnl::INIT_SPACE,
+        seeds = [b"realized_pnl", buyer.key().as_ref()],
+        bump
+    )]
+    pub realized_pnl: Account<'info, RealizedPnl>,
+    
+    #[account(mut)]
+    pub buyer: Signer<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct RefundCommitment<'info> {
+    #[account(mut)]
+    pub market: Account<'info, Market>,
+    
+    #[account(
+        mut,
+        close = buyer,
+        seeds = [b"commitment", market.key().as_ref(), buyer.key().as_ref()],
+        bump = commitment.bump
+    )]
+    pub commitment: Account<'info, BuyCommitment>,
+    
+    #[account(mut)]
+    pub buyer: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ForfeitCommitment<'info> {
+    #[account(mut)]
+    pub market: Account<'info, Market>,
+    
+    #[account(
+        mut,
+        close = buyer,
+        seeds = [b"commitment", market.key().as_ref(), buyer.key().as_ref()],
+        bump = commitment.bump
+    )]
+    pub commitment: Account<'info, BuyCommitment>,
+    
+    #[account(
+        mut,
+        seeds = [b"position", market.key().as_ref(), buyer.key().as_ref()],
+        bump = position.bump
+    )]
+    pub position: Account<'info, Position>,
+    
+    /// CHECK: The committing buyer, only paid the commitment account's rent
+    #[account(mut)]
+    pub buyer: AccountInfo<'info>,
+    
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ResolveMarket<'info> {
+    #[account(mut)]
+    pub market: Account<'info, Market>,
+    
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct AcceptAuthority<'info> {
+    #[account(mut)]
+    pub market: Account<'info, Market>,
+    
+    pub new_authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimWinnings<'info> {
+    #[account(mut, constraint = market.mint == Pubkey::default() @ ErrorCode::CollateralMismatch)]
+    pub market: Account<'info, Market>,
+    
+    #[account(
+        mut,
+        seeds = [b"position", market.key().as_ref(), claimer.key().as_ref()],
+        bump = position.bump,
+        constraint = position.owner == claimer.key()
+    )]
+    pub position: Account<'info, Position>,
+    
+    #[account(
+        init_if_needed,
+        payer = claimer,
+        space = 8 + RealizedPnl::INIT_SPACE,
+        seeds = [b"realized_pnl", claimer.key().as_ref()],
+        bump
+    )]
+    pub realized_pnl: Account<'info, RealizedPnl>,
+    
+    #[account(mut)]
+    pub claimer: Signer<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(new_owner: Pubkey)]
+pub struct TransferPosition<'info> {
+    pub market: Account<'info, Market>,
+    
+    #[account(
+        mut,
+        seeds = [b"position", market.key().as_ref(), owner.key().as_ref()],
+        bump = source.bump,
+        constraint = source.owner == owner.key()
+    )]
+    pub source: Account<'info, Position>,
+    
+    #[account(
+        init_if_needed,
+        payer = owner,
+        space = 8 + 32 + 32 + 4 + 10*8 + 8 + 32 + 1,
+        seeds = [b"position", market.key().as_ref(), new_owner.as_ref()],
+        bump
+    )]
+    pub destination: Account<'info, Position>,
+    
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ClosePosition<'info> {
+    pub market: Account<'info, Market>,
+    
+    #[account(
+        mut,
+        close = owner,
+        seeds = [b"position", market.key().as_ref(), owner.key().as_ref()],
+        bump = position.bump,
+        constraint = position.owner == owner.key()
+    )]
+    pub position: Account<'info, Position>,
+    
+    #[account(mut)]
+    pub owner: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimAndClose<'info> {
+    #[account(mut, constraint = market.mint == Pubkey::default() @ ErrorCode::CollateralMismatch)]
+    pub market: Account<'info, Market>,
+    
+    #[account(
+        mut,
+        close = claimer,
+        seeds = [b"position", market.key().as_ref(), claimer.key().as_ref()],
+        bump = position.bump,
+        constraint = position.owner == claimer.key()
+    )]
+    pub position: Account<'info, Position>,
+    
+    #[account(
+        init_if_needed,
+        payer = claimer,
+        space = 8 + RealizedPnl::INIT_SPACE,
+        seeds = [b"realized_pnl", claimer.key().as_ref()],
+        bump
+    )]
+    pub realized_pnl: Account<'info, RealizedPnl>,
+    
+    #[account(mut)]
+    pub claimer: Signer<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawFees<'info> {
+    #[account(mut, constraint = market.mint == Pubkey::default() @ ErrorCode::CollateralMismatch)]
+    pub market: Account<'info, Market>,
+    
+    /// CHECK: Any account chosen by the authority to receive the fees
+    #[account(mut)]
+    pub destination: AccountInfo<'info>,
+    
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SweepDust<'info> {
+    #[account(mut, constraint = market.mint == Pubkey::default() @ ErrorCode::CollateralMismatch)]
+    pub market: Account<'info, Market>,
+    
+    /// CHECK: Any account chosen by the authority to receive the dust
+    #[account(mut)]
+    pub treasury: AccountInfo<'info>,
+    
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct BuySharesToken<'info> {
+    #[account(mut, has_one = mint @ ErrorCode::CollateralMismatch)]
+    pub market: Account<'info, Market>,
+    
+    pub mint: Account<'info, Mint>,
+    
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = market
+    )]
+    pub vault: Account<'info, TokenAccount>,
+    
+    #[account(
+        mut,
+        token::mint = mint,
+        token::authority = buyer
+    )]
+    pub buyer_token_account: Account<'info, TokenAccount>,
+    
+    #[account(
+        init_if_needed,
+        payer = buyer,
+        space = 8 + 32 + 32 + 4 + 10*8 + 8 + 32 + 1,
+        seeds = [b"position", market.key().as_ref(), buyer.key().as_ref()],
+        bump
+    )]
+    pub position: Account<'info, Position>,
+    
+    #[account(mut)]
+    pub buyer: Signer<'info>,
+    
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimWinningsToken<'info> {
+    #[account(mut, has_one = mint @ ErrorCode::CollateralMismatch)]
+    pub market: Account<'info, Market>,
+    
+    pub mint: Account<'info, Mint>,
+    
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = market
+    )]
+    pub vault: Account<'info, TokenAccount>,
+    
+    #[account(
+        mut,
+        seeds = [b"position", market.key().as_ref(), claimer.key().as_ref()],
+        bump = position.bump,
+        constraint = position.owner == claimer.key()
+    )]
+    pub position: Account<'info, Position>,
+    
+    #[account(
+        mut,
+        token::mint = mint,
+        token::authority = claimer
+    )]
+    pub claimer_token_account: Account<'info, TokenAccount>,
+    
+    pub claimer: Signer<'info>,
+    
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawFeesToken<'info> {
+    #[account(mut, has_one = mint @ ErrorCode::CollateralMismatch)]
+    pub market: Account<'info, Market>,
+    
+    pub mint: Account<'info, Mint>,
+    
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = market
+    )]
+    pub vault: Account<'info, TokenAccount>,
+    
+    #[account(mut, token::mint = mint)]
+    pub destination: Account<'info, TokenAccount>,
+    
+    pub authority: Signer<'info>,
+    
+    pub token_program: Program<'info, Token>,
+}
+
+// ===========================================
+// CLOB CONTEXTS
+// ===========================================
+
+#[derive(Accounts)]
+#[instruction(market_id: String, question: String, resolution_time: i64, config: ClobMarketConfig)]
+pub struct CreateClobMarket<'info> {
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + ClobMarket::INIT_SPACE,
+        seeds = [b"clob_market", market_id.as_bytes()],
+        bump
+    )]
+    pub market: Account<'info, ClobMarket>,
+    
+    #[account(
+        init,
+        payer = authority,
+        space = config.order_book_space(),
+        seeds = [b"order_book", market.key().as_ref()],
+        bump
+    )]
+    pub order_book: Account<'info, OrderBook>,
+    
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + PriceHistory::INIT_SPACE,
+        seeds = [b"price_history", market.key().as_ref()],
+        bump
+    )]
+    pub price_history: Account<'info, PriceHistory>,
+    
+    #[account(
+        init_if_needed,
+        payer = authority,
+        space = 8 + MarketRegistry::INIT_SPACE,
+        seeds = [b"market_registry", authority.key().as_ref()],
+        bump
+    )]
+    pub registry: Account<'info, MarketRegistry>,
+    
+    /// CHECK: Vault PDA - initialized here to hold collateral
+    #[account(
+        mut,
+        seeds = [b"vault", market.key().as_ref()],
+        bump
+    )]
+    pub vault: AccountInfo<'info>,
+    
+    #[account(
+        init_if_needed,
+        payer = authority,
+        space = 8 + ClobPosition::INIT_SPACE,
+        seeds = [b"clob_position", market.key().as_ref(), authority.key().as_ref()],
+        bump
+    )]
+    pub position: Account<'info, ClobPosition>,
+    
+    #[account(
+        init_if_needed,
+        payer = authority,
+        space = 8 + RealizedPnl::INIT_SPACE,
+        seeds = [b"realized_pnl", authority.key().as_ref()],
+        bump
+    )]
+    pub realized_pnl: Account<'info, RealizedPnl>,
+    
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct PlaceOrder<'info> {
+    #[account(mut, constraint = market.mint == Pubkey::default() @ ClobError::CollateralMismatch)]
+    pub market: Account<'info, ClobMarket>,
+    
+    #[account(
+        mut,
+        seeds = [b"order_book", market.key().as_ref()],
+        bump = order_book.bump
+    )]
+    pub order_book: Account<'info, OrderBook>,
+    
+    #[account(
+        mut,
+        seeds = [b"price_history", market.key().as_ref()],
+        bump = price_history.bump
+    )]
+    pub price_history: Account<'info, PriceHistory>,
+    
+    /// CHECK: Vault PDA that holds collateral
+    #[account(
+        mut,
+        seeds = [b"vault", market.key().as_ref()],
+        bump
+    )]
+    pub vault: AccountInfo<'info>,
+    
+    #[account(
+        init_if_needed,
+        payer = trader,
+        space = 8 + ClobPosition::INIT_SPACE,
+        seeds = [b"clob_position", market.key().as_ref(), trader.key().as_ref()],
+        bump
+    )]
+    pub position: Account<'info, ClobPosition>,
+    
+    #[account(
+        init_if_needed,
+        payer = trader,
+        space = 8 + RealizedPnl::INIT_SPACE,
+        seeds = [b"realized_pnl", trader.key().as_ref()],
+        bump
+    )]
+    pub realized_pnl: Account<'info, RealizedPnl>,
+    
+    #[account(mut)]
+    pub trader: Signer<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CancelOrder<'info> {
+    #[account(constraint = market.mint == Pubkey::default() @ ClobError::CollateralMismatch)]
+    pub market: Account<'info, ClobMarket>,
+    
+    #[account(
+        mut,
+        seeds = [b"order_book", market.key().as_ref()],
+        bump = order_book.bump
+    )]
+    pub order_book: Account<'info, OrderBook>,
+    
+    /// CHECK: Vault PDA
+    #[account(
+        mut,
+        seeds = [b"vault", market.key().as_ref()],
+        bump
+    )]
+    pub vault: AccountInfo<'info>,
+    
+    #[account(
+        init_if_needed,
+        payer = trader,
+        space = 8 + RealizedPnl::INIT_SPACE,
+        seeds = [b"realized_pnl", trader.key().as_ref()],
+        bump
+    )]
+    pub realized_pnl: Account<'info, RealizedPnl>,
+    
+    #[account(mut)]
+    pub trader: Signer<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct AmendOrder<'info> {
+    #[account(constraint = market.mint == Pubkey::default() @ ClobError::CollateralMismatch)]
+    pub market: Account<'info, ClobMarket>,
+    
+    #[account(
+        mut,
+        seeds = [b"order_book", market.key().as_ref()],
+        bump = order_book.bump
+    )]
+    pub order_book: Account<'info, OrderBook>,
+    
+    /// CHECK: Vault PDA
+    #[account(
+        mut,
+        seeds = [b"vault", market.key().as_ref()],
+        bump
+    )]
+    pub vault: AccountInfo<'info>,
+    
+    #[account(
+        mut,
+        seeds = [b"clob_position", market.key().as_ref(), trader.key().as_ref()],
+        bump = position.bump,
+        constraint = position.owner == trader.key()
+    )]
+    pub position: Account<'info, ClobPosition>,
+    
+    #[account(
+        init_if_needed,
+        payer = trader,
+        space = 8 + RealizedPnl::INIT_SPACE,
+        seeds = [b"realized_pnl", trader.key().as_ref()],
+        bump
+    )]
+    pub realized_pnl: Account<'info, RealizedPnl>,
+    
+    #[account(mut)]
+    pub trader: Signer<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(group_id: String)]
+pub struct CreateMarketGroup<'info> {
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + MarketGroup::INIT_SPACE,
+        seeds = [b"market_group", authority.key().as_ref(), group_id.as_bytes()],
+        bump
+    )]
+    pub group: Account<'info, MarketGroup>,
+    
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct AddToMarketGroup<'info> {
+    #[account(mut, has_one = authority @ ClobError::Unauthorized)]
+    pub group: Account<'info, MarketGroup>,
+    
+    #[account(mut)]
+    pub market: Account<'info, ClobMarket>,
+    
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct GroupResolve<'info> {
+    #[account(mut, has_one = authority @ ClobError::Unauthorized)]
+    pub group: Account<'info, MarketGroup>,
+    
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SettleOrderBook<'info> {
+    #[account(constraint = market.mint == Pubkey::default() @ ClobError::CollateralMismatch)]
+    pub market: Account<'info, ClobMarket>,
+    
+    #[account(
+        mut,
+        seeds = [b"order_book", market.key().as_ref()],
+        bump = order_book.bump
+    )]
+    pub order_book: Account<'info, OrderBook>,
+    
+    /// CHECK: Vault PDA
+    #[account(
+        mut,
+        seeds = [b"vault", market.key().as_ref()],
+        bump
+    )]
+    pub vault: AccountInfo<'info>,
+    
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ResolveClobMarket<'info> {
+    #[account(mut)]
+    pub market: Account<'info, ClobMarket>,
+    
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ResolveFromOracle<'info> {
+    #[account(mut)]
+    pub market: Account<'info, ClobMarket>,
+    
+    /// CHECK: Only read as raw bytes; must be the feed bound at creation
+    #[account(address = market.oracle @ ClobError::OracleMismatch)]
+    pub oracle: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+pub struct VoteClobResolution<'info> {
+    #[account(mut)]
+    pub market: Account<'info, ClobMarket>,
+    
+    #[account(
+        init_if_needed,
+        payer = resolver,
+        space = 8 + ResolutionVotes::INIT_SPACE,
+        seeds = [b"resolution_votes", market.key().as_ref()],
+        bump
+    )]
+    pub votes: Account<'info, ResolutionVotes>,
+    
+    #[account(mut)]
+    pub resolver: Signer<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct AcceptClobAuthority<'info> {
+    #[account(mut)]
+    pub market: Account<'info, ClobMarket>,
+    
+    pub new_authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(trader: Pubkey)]
+pub struct AddTrader<'info> {
+    pub market: Account<'info, ClobMarket>,
+    
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + AllowlistEntry::INIT_SPACE,
+        seeds = [b"allowlist", market.key().as_ref(), trader.as_ref()],
+        bump
+    )]
+    pub allowlist_entry: Account<'info, AllowlistEntry>,
+    
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(trader: Pubkey)]
+pub struct RemoveTrader<'info> {
+    pub market: Account<'info, ClobMarket>,
+    
+    #[account(
+        mut,
+        close = authority,
+        seeds = [b"allowlist", market.key().as_ref(), trader.as_ref()],
+        bump = allowlist_entry.bump
+    )]
+    pub allowlist_entry: Account<'info, AllowlistEntry>,
+    
+    #[account(mut)]
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimClobWinnings<'info> {
+    #[account(mut, constraint = market.mint == Pubkey::default() @ ClobError::CollateralMismatch)]
+    pub market: Account<'info, ClobMarket>,
+    
+    /// CHECK: Vault PDA
+    #[account(
+        mut,
+        seeds = [b"vault", market.key().as_ref()],
+        bump
+    )]
+    pub vault: AccountInfo<'info>,
+    
+    #[account(
+        mut,
+        seeds = [b"clob_position", market.key().as_ref(), claimer.key().as_ref()],
+        bump = position.bump,
+        constraint = position.owner == claimer.key()
+    )]
+    pub position: Account<'info, ClobPosition>,
+    
+    #[account(
+        init_if_needed,
+        payer = claimer,
+        space = 8 + RealizedPnl::INIT_SPACE,
+        seeds = [b"realized_pnl", claimer.key().as_ref()],
+        bump
+    )]
+    pub realized_pnl: Account<'info, RealizedPnl>,
+    
+    #[account(mut)]
+    pub claimer: Signer<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawClobFees<'info> {
+    #[account(mut, constraint = market.mint == Pubkey::default() @ ClobError::CollateralMismatch)]
+    pub market: Account<'info, ClobMarket>,
+    
+    /// CHECK: Vault PDA
+    #[account(
+        mut,
+        seeds = [b"vault", market.key().as_ref()],
+        bump
+    )]
+    pub vault: AccountInfo<'info>,
+    
+    /// CHECK: Any account chosen by the authority to receive the fees
+    #[account(mut)]
+    pub destination: AccountInfo<'info>,
+    
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ClobInsurance<'info> {
+    #[account(mut, constraint = market.mint == Pubkey::default() @ ClobError::CollateralMismatch)]
+    pub market: Account<'info, ClobMarket>,
+    
+    /// CHECK: Vault PDA
+    #[account(
+        mut,
+        seeds = [b"vault", market.key().as_ref()],
+        bump
+    )]
+    pub vault: AccountInfo<'info>,
+    
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CloseClobMarket<'info> {
+    #[account(
+        mut,
+        constraint = market.mint == Pubkey::default() @ ClobError::CollateralMismatch
+    )]
+    pub market: Account<'info, ClobMarket>,
+    
+    #[account(
+        mut,
+        close = authority,
+        seeds = [b"order_book", market.key().as_ref()],
+        bump = order_book.bump
+    )]
+    pub order_book: Account<'info, OrderBook>,
+    
+    #[account(
+        mut,
+        close = authority,
+        seeds = [b"price_history", market.key().as_ref()],
+        bump = price_history.bump
+    )]
+    pub price_history: Account<'info, PriceHistory>,
+    
+    /// CHECK: Vault PDA
+    #[account(
+        mut,
+        seeds = [b"vault", market.key().as_ref()],
+        bump
+    )]
+    pub vault: AccountInfo<'info>,
+    
+    #[account(mut)]
+    pub authority: Signer<'info>,
+}
 
-#[account]
-#[derive(InitSpace)]
-pub struct ClobMarket {
-    pub authority: Pubkey,
-    #[max_len(32)]
-    pub market_id: String,
-    #[max_len(256)]
-    pub question: String,
-    pub resolution_time: i64,
-    pub resolved: bool,
-    pub winning_side: Option<u8>,
-    pub created_at: i64,
-    pub total_yes_volume: u64,
-    pub total_no_volume: u64,
-    pub bump: u8,
+#[derive(Accounts)]
+pub struct EmergencyWithdraw<'info> {
+    #[account(constraint = market.mint == Pubkey::default() @ ClobError::CollateralMismatch)]
+    pub market: Account<'info, ClobMarket>,
+    
+    /// CHECK: Vault PDA
+    #[account(
+        mut,
+        seeds = [b"vault", market.key().as_ref()],
+        bump
+    )]
+    pub vault: AccountInfo<'info>,
+    
+    #[account(mut)]
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(market_id: String, question: String, resolution_time: i64, config: ClobMarketConfig)]
+pub struct CreateTokenClobMarket<'info> {
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + ClobMarket::INIT_SPACE,
+        seeds = [b"clob_market", market_id.as_bytes()],
+        bump
+    )]
+    pub market: Account<'info, ClobMarket>,
+    
+    #[account(
+        init,
+        payer = authority,
+        space = config.order_book_space(),
+        seeds = [b"order_book", market.key().as_ref()],
+        bump
+    )]
+    pub order_book: Account<'info, OrderBook>,
+    
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + PriceHistory::INIT_SPACE,
+        seeds = [b"price_history", market.key().as_ref()],
+        bump
+    )]
+    pub price_history: Account<'info, PriceHistory>,
+    
+    #[account(
+        init_if_needed,
+        payer = authority,
+        space = 8 + MarketRegistry::INIT_SPACE,
+        seeds = [b"market_registry", authority.key().as_ref()],
+        bump
+    )]
+    pub registry: Account<'info, MarketRegistry>,
+    
+    pub mint: Account<'info, Mint>,
+    
+    #[account(
+        init,
+        payer = authority,
+        associated_token::mint = mint,
+        associated_token::authority = market
+    )]
+    pub vault: Account<'info, TokenAccount>,
+    
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct PlaceOrderToken<'info> {
+    #[account(mut, has_one = mint @ ClobError::CollateralMismatch)]
+    pub market: Account<'info, ClobMarket>,
+    
+    pub mint: Account<'info, Mint>,
+    
+    #[account(
+        mut,
+        seeds = [b"order_book", market.key().as_ref()],
+        bump = order_book.bump
+    )]
+    pub order_book: Account<'info, OrderBook>,
+    
+    #[account(
+        mut,
+        seeds = [b"price_history", market.key().as_ref()],
+        bump = price_history.bump
+    )]
+    pub price_history: Account<'info, PriceHistory>,
+    
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = market
+    )]
+    pub vault: Account<'info, TokenAccount>,
+    
+    #[account(
+        mut,
+        token::mint = mint,
+        token::authority = trader
+    )]
+    pub trader_token_account: Account<'info, TokenAccount>,
+    
+    #[account(
+        init_if_needed,
+        payer = trader,
+        space = 8 + ClobPosition::INIT_SPACE,
+        seeds = [b"clob_position", market.key().as_ref(), trader.key().as_ref()],
+        bump
+    )]
+    pub position: Account<'info, ClobPosition>,
+    
+    #[account(mut)]
+    pub trader: Signer<'info>,
+    
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CancelOrderToken<'info> {
+    #[account(has_one = mint @ ClobError::CollateralMismatch)]
+    pub market: Account<'info, ClobMarket>,
+    
+    pub mint: Account<'info, Mint>,
+    
+    #[account(
+        mut,
+        seeds = [b"order_book", market.key().as_ref()],
+        bump = order_book.bump
+    )]
+    pub order_book: Account<'info, OrderBook>,
+    
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = market
+    )]
+    pub vault: Account<'info, TokenAccount>,
+    
+    #[account(
+        mut,
+        token::mint = mint,
+        token::authority = trader
+    )]
+    pub trader_token_account: Account<'info, TokenAccount>,
+    
+    pub trader: Signer<'info>,
+    
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct AmendOrderToken<'info> {
+    #[account(has_one = mint @ ClobError::CollateralMismatch)]
+    pub market: Account<'info, ClobMarket>,
+    
+    pub mint: Account<'info, Mint>,
+    
+    #[account(
+        mut,
+        seeds = [b"order_book", market.key().as_ref()],
+        bump = order_book.bump
+    )]
+    pub order_book: Account<'info, OrderBook>,
+    
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = market
+    )]
+    pub vault: Account<'info, TokenAccount>,
+    
+    #[account(
+        mut,
+        seeds = [b"clob_position", market.key().as_ref(), trader.key().as_ref()],
+        bump = position.bump,
+        constraint = position.owner == trader.key()
+    )]
+    pub position: Account<'info, ClobPosition>,
+    
+    #[account(
+        mut,
+        token::mint = mint,
+        token::authority = trader
+    )]
+    pub trader_token_account: Account<'info, TokenAccount>,
+    
+    pub trader: Signer<'info>,
+    
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimClobWinningsToken<'info> {
+    #[account(mut, has_one = mint @ ClobError::CollateralMismatch)]
+    pub market: Account<'info, ClobMarket>,
+    
+    pub mint: Account<'info, Mint>,
+    
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = market
+    )]
+    pub vault: Account<'info, TokenAccount>,
+    
+    #[account(
+        mut,
+        seeds = [b"clob_position", market.key().as_ref(), claimer.key().as_ref()],
+        bump = position.bump,
+        constraint = position.owner == claimer.key()
+    )]
+    pub position: Account<'info, ClobPosition>,
+    
+    #[account(
+        mut,
+        token::mint = mint,
+        token::authority = claimer
+    )]
+    pub claimer_token_account: Account<'info, TokenAccount>,
+    
+    pub claimer: Signer<'info>,
+    
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawClobFeesToken<'info> {
+    #[account(mut, has_one = mint @ ClobError::CollateralMismatch)]
+    pub market: Account<'info, ClobMarket>,
+    
+    pub mint: Account<'info, Mint>,
+    
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = market
+    )]
+    pub vault: Account<'info, TokenAccount>,
+    
+    #[account(mut, token::mint = mint)]
+    pub destination: Account<'info, TokenAccount>,
+    
+    pub authority: Signer<'info>,
+    
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimRebates<'info> {
+    #[account(mut, constraint = market.mint == Pubkey::default() @ ClobError::CollateralMismatch)]
+    pub market: Account<'info, ClobMarket>,
+    
+    /// CHECK: Vault PDA
+    #[account(
+        mut,
+        seeds = [b"vault", market.key().as_ref()],
+        bump
+    )]
+    pub vault: AccountInfo<'info>,
+    
+    #[account(
+        mut,
+        seeds = [b"clob_position", market.key().as_ref(), owner.key().as_ref()],
+        bump = position.bump,
+        constraint = position.owner == owner.key()
+    )]
+    pub position: Account<'info, ClobPosition>,
+    
+    #[account(mut)]
+    pub owner: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimRebatesToken<'info> {
+    #[account(mut, has_one = mint @ ClobError::CollateralMismatch)]
+    pub market: Account<'info, ClobMarket>,
+    
+    pub mint: Account<'info, Mint>,
+    
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = market
+    )]
+    pub vault: Account<'info, TokenAccount>,
+    
+    #[account(
+        mut,
+        seeds = [b"clob_position", market.key().as_ref(), owner.key().as_ref()],
+        bump = position.bump,
+        constraint = position.owner == owner.key()
+    )]
+    pub position: Account<'info, ClobPosition>,
+    
+    #[account(
+        mut,
+        token::mint = mint,
+        token::authority = owner
+    )]
+    pub owner_token_account: Account<'info, TokenAccount>,
+    
+    pub owner: Signer<'info>,
+    
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct ViewOrderBook<'info> {
+    pub market: Account<'info, ClobMarket>,
+    
+    #[account(
+        seeds = [b"order_book", market.key().as_ref()],
+        bump = order_book.bump
+    )]
+    pub order_book: Account<'info, OrderBook>,
 }
 
-#[account]
-#[derive(InitSpace)]
-pub struct OrderBook {
-    pub market: Pubkey,
-    #[max_len(50)]
-    pub yes_bids: Vec<Order>,
-    #[max_len(50)]
-    pub yes_asks: Vec<Order>,
-    pub bump: u8,
+#[derive(Accounts)]
+pub struct BatchEstimate<'info> {
+    pub owner: Signer<'info>,
 }
 
-#[derive(AnchorSerialize, AnchorDeserialize, Clone, InitSpace)]
-pub struct Order {
-    pub owner: Pubkey,
-    pub price: u64,
-    pub size: u64,
-    pub timestamp: i64,
-    pub order_id: u64,
+#[derive(Accounts)]
+pub struct RemoveFromRegistry<'info> {
+    #[account(
+        mut,
+        seeds = [b"market_registry", authority.key().as_ref()],
+        bump = registry.bump
+    )]
+    pub registry: Account<'info, MarketRegistry>,
+    
+    /// CHECK: Only read to decide whether the market is still live; it may
+    /// already be closed
+    pub market: UncheckedAccount<'info>,
+    
+    pub authority: Signer<'info>,
 }
 
-#[account]
-#[derive(InitSpace)]
-pub struct ClobPosition {
-    pub owner: Pubkey,
-    pub market: Pubkey,
-    pub yes_shares: u64,
-    pub no_shares: u64,
-    pub bump: u8,
+#[derive(Accounts)]
+pub struct MigrateMarket<'info> {
+    /// CHECK: A Market, ClobMarket or OrderBook; the handler checks the
+    /// owner and discriminator
+    #[account(mut)]
+    pub market: UncheckedAccount<'info>,
+    
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    
+    pub system_program: Program<'info, System>,
 }
 
-// ===========================================
-// PARIMUTUEL CONTEXTS
-// ===========================================
-
 #[derive(Accounts)]
-#[instruction(market_id: String)]
-pub struct CreateMarket<'info> {
+pub struct InitReferral<'info> {
     #[account(
         init,
-        payer = authority,
-        space = 8 + 32 + 36 + 260 + 4 + 10*36 + 4 + 10*8 + 8 + 8 + 1 + 2 + 8 + 1,
-        seeds = [b"market", market_id.as_bytes()],
+        payer = referrer,
+        space = 8 + ReferralAccount::INIT_SPACE,
+        seeds = [b"referral", referrer.key().as_ref()],
         bump
     )]
-    pub market: Account<'info, Market>,
+    pub referral: Account<'info, ReferralAccount>,
     
     #[account(mut)]
-    pub authority: Signer<'info>,
+    pub referrer: Signer<'info>,
     
     pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
-pub struct BuyShares<'info> {
-    #[account(mut)]
-    pub market: Account<'info, Market>,
-    
+pub struct ClaimReferralFees<'info> {
     #[account(
-        init_if_needed,
-        payer = buyer,
-        space = 8 + 32 + 32 + 4 + 10*8 + 1,
-        seeds = [b"position", market.key().as_ref(), buyer.key().as_ref()],
-        bump
+        mut,
+        seeds = [b"referral", referrer.key().as_ref()],
+        bump = referral.bump,
+        has_one = referrer
     )]
-    pub position: Account<'info, Position>,
+    pub referral: Account<'info, ReferralAccount>,
     
     #[account(mut)]
-    pub buyer: Signer<'info>,
-    
-    pub system_program: Program<'info, System>,
+    pub referrer: Signer<'info>,
 }
 
 #[derive(Accounts)]
-pub struct ResolveMarket<'info> {
-    #[account(mut)]
+pub struct ViewMarket<'info> {
     pub market: Account<'info, Market>,
-    
-    pub authority: Signer<'info>,
 }
 
 #[derive(Accounts)]
-pub struct ClaimWinnings<'info> {
-    #[account(mut)]
+pub struct ViewPosition<'info> {
     pub market: Account<'info, Market>,
     
     #[account(
-        mut,
-        seeds = [b"position", market.key().as_ref(), claimer.key().as_ref()],
-        bump = position.bump,
-        constraint = position.owner == claimer.key()
+        seeds = [b"position", market.key().as_ref(), position.owner.as_ref()],
+        bump = position.bump
     )]
     pub position: Account<'info, Position>,
-    
-    #[account(mut)]
-    pub claimer: Signer<'info>,
 }
 
-// ===========================================
-// CLOB CONTEXTS
-// ===========================================
-
 #[derive(Accounts)]
-#[instruction(market_id: String)]
-pub struct CreateClobMarket<'info> {
+pub struct ViewClobPosition<'info> {
+    pub market: Account<'info, ClobMarket>,
+    
     #[account(
-        init,
-        payer = authority,
-        space = 8 + ClobMarket::INIT_SPACE,
-        seeds = [b"clob_market", market_id.as_bytes()],
-        bump
+        seeds = [b"clob_position", market.key().as_ref(), position.owner.as_ref()],
+        bump = position.bump
     )]
+    pub position: Account<'info, ClobPosition>,
+}
+
+#[derive(Accounts)]
+pub struct ViewClobPositionValue<'info> {
     pub market: Account<'info, ClobMarket>,
     
     #[account(
-        init,
-        payer = authority,
-        space = 8 + OrderBook::INIT_SPACE,
         seeds = [b"order_book", market.key().as_ref()],
-        bump
+        bump = order_book.bump
     )]
     pub order_book: Account<'info, OrderBook>,
     
-    /// CHECK: Vault PDA - initialized here to hold collateral
+    #[account(
+        seeds = [b"clob_position", market.key().as_ref(), position.owner.as_ref()],
+        bump = position.bump
+    )]
+    pub position: Account<'info, ClobPosition>,
+}
+
+#[derive(Accounts)]
+#[instruction(new_owner: Pubkey)]
+pub struct TransferClobPosition<'info> {
+    pub market: Account<'info, ClobMarket>,
+    
     #[account(
         mut,
-        seeds = [b"vault", market.key().as_ref()],
+        seeds = [b"clob_position", market.key().as_ref(), owner.key().as_ref()],
+        bump = source.bump,
+        constraint = source.owner == owner.key()
+    )]
+    pub source: Account<'info, ClobPosition>,
+    
+    #[account(
+        init_if_needed,
+        payer = owner,
+        space = 8 + ClobPosition::INIT_SPACE,
+        seeds = [b"clob_position", market.key().as_ref(), new_owner.as_ref()],
         bump
     )]
-    pub vault: AccountInfo<'info>,
+    pub destination: Account<'info, ClobPosition>,
     
     #[account(mut)]
-    pub authority: Signer<'info>,
+    pub owner: Signer<'info>,
     
     pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
-pub struct PlaceOrder<'info> {
-    #[account(mut)]
+pub struct CompleteSet<'info> {
+    #[account(mut, constraint = market.mint == Pubkey::default() @ ClobError::CollateralMismatch)]
     pub market: Account<'info, ClobMarket>,
     
-    #[account(
-        mut,
-        seeds = [b"order_book", market.key().as_ref()],
-        bump = order_book.bump
-    )]
-    pub order_book: Account<'info, OrderBook>,
-    
     /// CHECK: Vault PDA that holds collateral
     #[account(
         mut,
@@ -662,6 +6648,15 @@ pub struct PlaceOrder<'info> {
     )]
     pub position: Account<'info, ClobPosition>,
     
+    #[account(
+        init_if_needed,
+        payer = trader,
+        space = 8 + RealizedPnl::INIT_SPACE,
+        seeds = [b"realized_pnl", trader.key().as_ref()],
+        bump
+    )]
+    pub realized_pnl: Account<'info, RealizedPnl>,
+    
     #[account(mut)]
     pub trader: Signer<'info>,
     
@@ -669,58 +6664,207 @@ pub struct PlaceOrder<'info> {
 }
 
 #[derive(Accounts)]
-pub struct CancelOrder<'info> {
+pub struct CompleteSetToken<'info> {
+    #[account(mut, has_one = mint @ ClobError::CollateralMismatch)]
     pub market: Account<'info, ClobMarket>,
     
+    pub mint: Account<'info, Mint>,
+    
     #[account(
         mut,
-        seeds = [b"order_book", market.key().as_ref()],
-        bump = order_book.bump
+        associated_token::mint = mint,
+        associated_token::authority = market
     )]
-    pub order_book: Account<'info, OrderBook>,
+    pub vault: Account<'info, TokenAccount>,
     
-    /// CHECK: Vault PDA
     #[account(
         mut,
-        seeds = [b"vault", market.key().as_ref()],
+        token::mint = mint,
+        token::authority = trader
+    )]
+    pub trader_token_account: Account<'info, TokenAccount>,
+    
+    #[account(
+        init_if_needed,
+        payer = trader,
+        space = 8 + ClobPosition::INIT_SPACE,
+        seeds = [b"clob_position", market.key().as_ref(), trader.key().as_ref()],
         bump
     )]
-    pub vault: AccountInfo<'info>,
+    pub position: Account<'info, ClobPosition>,
     
     #[account(mut)]
     pub trader: Signer<'info>,
+    
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
 }
 
-#[derive(Accounts)]
-pub struct ResolveClobMarket<'info> {
-    #[account(mut)]
-    pub market: Account<'info, ClobMarket>,
-    
-    pub authority: Signer<'info>,
+// ===========================================
+// EVENTS
+// ===========================================
+
+#[event]
+pub struct MarketCreated {
+    pub market: Pubkey,
+    pub market_id: String,
+    pub authority: Pubkey,
+    pub resolution_time: i64,
 }
 
-#[derive(Accounts)]
-pub struct ClaimClobWinnings<'info> {
-    pub market: Account<'info, ClobMarket>,
-    
-    /// CHECK: Vault PDA
-    #[account(
-        mut,
-        seeds = [b"vault", market.key().as_ref()],
-        bump
-    )]
-    pub vault: AccountInfo<'info>,
-    
-    #[account(
-        mut,
-        seeds = [b"clob_position", market.key().as_ref(), claimer.key().as_ref()],
-        bump = position.bump,
-        constraint = position.owner == claimer.key()
-    )]
-    pub position: Account<'info, ClobPosition>,
-    
-    #[account(mut)]
-    pub claimer: Signer<'info>,
+#[event]
+pub struct MarketResolved {
+    pub market: Pubkey,
+    pub market_id: String,
+    pub authority: Pubkey,
+    pub winning_outcome: u8,
+    pub resolved_at: i64,
+    pub resolution_uri: String, // Empty when the resolver gave none
+    pub resolution_source: Pubkey, // Pubkey::default() when the resolver gave none
+}
+
+#[event]
+pub struct ScalarMarketResolved {
+    pub market: Pubkey,
+    pub market_id: String,
+    pub authority: Pubkey,
+    pub settlement_value: i64,
+    pub resolved_at: i64,
+}
+
+/// Emitted for both parimutuel and CLOB markets
+#[event]
+pub struct MarketPauseChanged {
+    pub market: Pubkey,
+    pub paused: bool,
+}
+
+/// Emitted for both parimutuel and CLOB markets
+#[event]
+pub struct AuthorityTransferred {
+    pub market: Pubkey,
+    pub previous_authority: Pubkey,
+    pub new_authority: Pubkey,
+}
+
+/// Emitted for both parimutuel and CLOB markets
+#[event]
+pub struct ResolutionTimeUpdated {
+    pub market: Pubkey,
+    pub previous_resolution_time: i64,
+    pub resolution_time: i64,
+}
+
+#[event]
+pub struct MarketCancelled {
+    pub market: Pubkey,
+    pub market_id: String,
+    pub authority: Pubkey,
+    pub cancelled_at: i64,
+}
+
+#[event]
+pub struct ClobMarketCreated {
+    pub market: Pubkey,
+    pub market_id: String,
+    pub authority: Pubkey,
+    pub resolution_time: i64,
+}
+
+#[event]
+pub struct ClobMarketResolved {
+    pub market: Pubkey,
+    pub market_id: String,
+    pub authority: Pubkey,
+    pub winning_side: u8,
+    pub resolved_at: i64,
+    pub resolution_uri: String, // Empty when the resolver gave none
+    pub resolution_source: Pubkey, // The feed for oracle resolutions, Pubkey::default() when none given
+}
+
+#[event]
+pub struct ResolutionOverridden {
+    pub market: Pubkey,
+    pub previous_winning_side: u8,
+    pub winning_side: u8,
+}
+
+#[event]
+pub struct ClobMarketVoided {
+    pub market: Pubkey,
+    pub market_id: String,
+    pub authority: Pubkey,
+    pub voided_at: i64,
+}
+
+#[event]
+pub struct OrderFilled {
+    pub market: Pubkey,
+    pub maker: Pubkey,
+    pub taker: Pubkey,
+    pub order_id: u64, // Resting order that was hit
+    pub price: u64,    // YES price in bps
+    pub size: u64,
+    pub taker_buys_yes: bool,
+}
+
+#[event]
+pub struct OrdersPlaced {
+    pub market: Pubkey,
+    pub trader: Pubkey,
+    pub summaries: Vec<OrderSummary>, // One per order, in batch order
+}
+
+#[event]
+pub struct CompleteSetsChanged {
+    pub market: Pubkey,
+    pub owner: Pubkey,
+    pub sets: u64,
+    pub minted: bool, // false when redeemed
+}
+
+/// A position's share balances after a buy, fill, sell, claim or refund
+#[event]
+pub struct PositionChanged {
+    pub market: Pubkey,
+    pub owner: Pubkey,
+    pub shares: Vec<u64>, // Per outcome for parimutuel, [yes, no] for CLOB
+    pub reason: u8, // POSITION_CHANGE_*
+}
+
+#[event]
+pub struct OrderAmended {
+    pub market: Pubkey,
+    pub owner: Pubkey,
+    pub order_id: u64,
+    pub is_bid: bool,
+    pub old_price: u64,
+    pub old_size: u64,
+    pub new_price: u64,
+    pub new_size: u64,
+    pub filled: u64, // Already filled, unchanged by the amend
+}
+
+#[event]
+pub struct OrderCancelled {
+    pub market: Pubkey,
+    pub owner: Pubkey,
+    pub order_id: u64,
+    pub is_bid: bool,
+    pub size: u64, // Unfilled size removed from the book
+    pub filled: u64, // Filled before the cancel, 0 for an untouched order
+    pub refund: u64,
+}
+
+/// A CLOB vault drained by emergency_withdraw. Positions still owed
+/// anything (open_interest > 0) can no longer be paid from it.
+#[event]
+pub struct EmergencyWithdrawal {
+    pub market: Pubkey,
+    pub authority: Pubkey,
+    pub amount: u64,
+    pub open_interest: u64, // Shares outstanding when the vault was drained
+    pub timestamp: i64,
 }
 
 // ===========================================
@@ -737,6 +6881,8 @@ pub enum ErrorCode {
     QuestionTooLong,
     #[msg("Invalid outcome index")]
     InvalidOutcome,
+    #[msg("Outcome names must be unique, non-empty and at most 32 chars")]
+    InvalidOutcomeName,
     #[msg("Market already resolved")]
     MarketAlreadyResolved,
     #[msg("Market not yet resolved")]
@@ -747,6 +6893,104 @@ pub enum ErrorCode {
     NoWinningShares,
     #[msg("Unauthorized")]
     Unauthorized,
+    #[msg("Buy would exceed the outcome's share cap")]
+    OutcomeShareCapExceeded,
+    #[msg("Buy amount is below the market's minimum bet")]
+    BelowMinimumBet,
+    #[msg("Resolution delay must not be negative")]
+    InvalidResolutionDelay,
+    #[msg("Resolution time must be in the future")]
+    ResolutionTimeInPast,
+    #[msg("Resolution time has not been reached")]
+    ResolutionTimeNotReached,
+    #[msg("Market passed its resolution deadline and is void")]
+    MarketVoid,
+    #[msg("Arithmetic overflow")]
+    Overflow,
+    #[msg("No fees available to withdraw")]
+    NoFeesToWithdraw,
+    #[msg("Market has expired")]
+    MarketExpired,
+    #[msg("Position still has shares to claim")]
+    PositionNotSettled,
+    #[msg("Market uses a different collateral")]
+    CollateralMismatch,
+    #[msg("Market has been cancelled")]
+    MarketCancelled,
+    #[msg("Market is neither cancelled nor void")]
+    MarketNotCancelled,
+    #[msg("Resolution time can only move later")]
+    InvalidResolutionTime,
+    #[msg("Signer is not the pending authority")]
+    NotPendingAuthority,
+    #[msg("Market is paused")]
+    MarketPaused,
+    #[msg("Buy would exceed the market's total pool cap")]
+    PoolCapExceeded,
+    #[msg("Invalid market kind")]
+    InvalidMarketKind,
+    #[msg("Scalar range must satisfy scalar_min < scalar_max")]
+    InvalidScalarRange,
+    #[msg("Fee must be at most 10000 bps")]
+    InvalidFee,
+    #[msg("Instruction does not apply to this market kind")]
+    WrongMarketKind,
+    #[msg("Invalid size (must be > 0)")]
+    InvalidSize,
+    #[msg("Position holds fewer shares of this outcome than requested")]
+    InsufficientShares,
+    #[msg("Market registry is full")]
+    RegistryFull,
+    #[msg("Market is not in the registry")]
+    MarketNotInRegistry,
+    #[msg("Market can still trade or settle")]
+    MarketStillActive,
+    #[msg("Winners can still claim; wait out the claim window")]
+    ClaimWindowOpen,
+    #[msg("No dust to sweep")]
+    NoDustToSweep,
+    #[msg("Position would exceed the market's max_trader_notional")]
+    PositionLimitExceeded,
+    #[msg("Remaining accounts must be (market, position) pairs")]
+    InvalidAccountPairs,
+    #[msg("Referrer's referral account missing from remaining accounts")]
+    ReferralAccountMissing,
+    #[msg("No referral fees to claim")]
+    NoReferralFees,
+    #[msg("Cannot refer yourself")]
+    SelfReferral,
+    #[msg("Position does not belong to this market")]
+    PositionMarketMismatch,
+    #[msg("Not a Market, ClobMarket or OrderBook account")]
+    UnknownAccountType,
+    #[msg("Account is already at the current version")]
+    AlreadyMigrated,
+    #[msg("Outcome weights must be empty or one per outcome, each 1..=10000 bps, on a categorical market")]
+    InvalidOutcomeWeights,
+    #[msg("The market authority may not trade in this market")]
+    AuthorityCannotTrade,
+    #[msg("Resolution URI too long (max 200 chars)")]
+    ResolutionUriTooLong,
+    #[msg("Commit phase needs reveal_period, a start before resolution time, no early resolution, no outcome share cap, and any void delay longer than the reveal period")]
+    InvalidCommitPhase,
+    #[msg("Buys must be committed with commit_buy during the commit phase")]
+    CommitPhaseActive,
+    #[msg("Market is not in its commit phase")]
+    NotInCommitPhase,
+    #[msg("Commitments can only be revealed between resolution time and the reveal deadline")]
+    NotInRevealPhase,
+    #[msg("Revealed outcome, amount and nonce don't match the commitment")]
+    CommitmentMismatch,
+    #[msg("Market can't resolve until the reveal phase ends")]
+    RevealPhaseActive,
+    #[msg("Market account can't cover this payout")]
+    VaultInsolvent,
+    #[msg("Can't transfer a position to its own owner")]
+    InvalidRecipient,
+    #[msg("Resolution time is further out than MAX_MARKET_DURATION")]
+    ResolutionTimeTooFar,
+    #[msg("Account predates layout versions and can't be migrated")]
+    UnsupportedLayout,
 }
 
 #[error_code]
@@ -765,6 +7009,24 @@ pub enum ClobError {
     MarketExpired,
     #[msg("Order book is full")]
     OrderBookFull,
+    #[msg("Batch must hold 1 to MAX_ORDERS orders")]
+    InvalidBatchSize,
+    #[msg("Price band must satisfy 1 <= min_price <= max_price <= 9999")]
+    InvalidPriceBand,
+    #[msg("Price is outside the market's price band")]
+    PriceOutOfBand,
+    #[msg("Size is not a multiple of the market's lot size")]
+    InvalidLot,
+    #[msg("Price is not a multiple of the market's tick size")]
+    InvalidTick,
+    #[msg("Order size is below the market's minimum order size")]
+    BelowMinOrderSize,
+    #[msg("Owner account of a dust order missing from remaining accounts")]
+    DustOwnerMissing,
+    #[msg("Trader is not on this market's allowlist")]
+    TraderNotAllowed,
+    #[msg("Position of a maker owed a rebate missing from remaining accounts")]
+    MakerPositionMissing,
     #[msg("Invalid order index")]
     InvalidOrderIndex,
     #[msg("Not the order owner")]
@@ -781,4 +7043,118 @@ pub enum ClobError {
     NoWinnings,
     #[msg("Arithmetic overflow")]
     Overflow,
+    #[msg("Invalid recipient")]
+    InvalidRecipient,
+    #[msg("Position does not belong to this market")]
+    PositionMarketMismatch,
+    #[msg("Position has no shares")]
+    EmptyPosition,
+    #[msg("Position holds fewer shares than requested")]
+    InsufficientShares,
+    #[msg("Payout would drop the vault below its rent reserve")]
+    VaultReserveBreached,
+    #[msg("Vault cannot cover this payout")]
+    VaultInsolvent,
+    #[msg("Invalid order type")]
+    InvalidOrderType,
+    #[msg("Invalid matching mode")]
+    InvalidMatchingMode,
+    #[msg("Resolution delay must not be negative")]
+    InvalidResolutionDelay,
+    #[msg("Resolution time must be in the future")]
+    ResolutionTimeInPast,
+    #[msg("Dispute period must not be negative")]
+    InvalidDisputePeriod,
+    #[msg("Resolution is still in its dispute period")]
+    DisputePeriodActive,
+    #[msg("Dispute period is over")]
+    DisputePeriodOver,
+    #[msg("Resolvers must be unique, at most MAX_RESOLVERS, with a threshold of 1 to their count")]
+    InvalidResolverSet,
+    #[msg("Market is resolved by resolver votes")]
+    ResolverVoteRequired,
+    #[msg("Signer is not one of the market's resolvers")]
+    NotResolver,
+    #[msg("Invalid oracle comparison, or an oracle combined with resolvers")]
+    InvalidOracleConfig,
+    #[msg("Market has no oracle")]
+    NoOracle,
+    #[msg("Oracle account is not the market's oracle")]
+    OracleMismatch,
+    #[msg("Oracle account data too short for the configured offset")]
+    InvalidOracleData,
+    #[msg("Resolution time has not been reached")]
+    ResolutionTimeNotReached,
+    #[msg("Market is void")]
+    MarketVoid,
+    #[msg("Market is not void")]
+    MarketNotVoid,
+    #[msg("Resolution time can only move later")]
+    InvalidResolutionTime,
+    #[msg("Signer is not the pending authority")]
+    NotPendingAuthority,
+    #[msg("Market is paused")]
+    MarketPaused,
+    #[msg("Fee must be at most 10000 bps")]
+    InvalidFee,
+    #[msg("Maker rebate must not exceed the taker fee")]
+    InvalidRebate,
+    #[msg("Order book capacity exceeds MAX_ORDER_BOOK_CAPACITY")]
+    InvalidCapacity,
+    #[msg("Order expiry must be 0 or in the future")]
+    InvalidExpiry,
+    #[msg("Fill-or-kill order could not be fully filled")]
+    FillOrKillUnfilled,
+    #[msg("Average fill price is worse than the order's limit")]
+    SlippageExceeded,
+    #[msg("Position would exceed the market's max_trader_notional")]
+    PositionLimitExceeded,
+    #[msg("Reduce-only order has no opposing position to reduce")]
+    ReduceOnlyNothingToReduce,
+    #[msg("Cannot refer yourself")]
+    SelfReferral,
+    #[msg("Post-only order would cross the book")]
+    PostOnlyWouldCross,
+    #[msg("No resting order with this ID belongs to the trader")]
+    OrderNotFound,
+    #[msg("Payout per share must be a multiple of 10000")]
+    InvalidPayoutPerShare,
+    #[msg("Market uses a different collateral")]
+    CollateralMismatch,
+    #[msg("No fees available to withdraw")]
+    NoFeesToWithdraw,
+    #[msg("No maker rebates to claim")]
+    NoRebatesToClaim,
+    #[msg("Market still has open interest, unclaimed rebates or resting orders")]
+    MarketNotSettled,
+    #[msg("Seed liquidity needs a positive half-spread")]
+    InvalidSeed,
+    #[msg("The market authority may not trade in this market")]
+    AuthorityCannotTrade,
+    #[msg("Emergency withdrawals are disabled for this market")]
+    EmergencyWithdrawDisabled,
+    #[msg("Emergency withdrawals open EMERGENCY_WITHDRAW_DELAY after resolution time")]
+    EmergencyTimelockActive,
+    #[msg("No insurance buffer to withdraw")]
+    NoInsuranceToWithdraw,
+    #[msg("Resolution URI too long (max 200 chars)")]
+    ResolutionUriTooLong,
+    #[msg("Owner account of a settled order missing from remaining accounts")]
+    OrderOwnerMissing,
+    #[msg("Grouped markets resolve through group_resolve")]
+    MarketInGroup,
+    #[msg("Market already belongs to a group")]
+    MarketAlreadyGrouped,
+    #[msg("Markets with resolvers or an oracle can't join a group")]
+    GroupedMarketResolver,
+    #[msg("Market group is full")]
+    GroupFull,
+    #[msg("Remaining accounts must be the group's markets, in group order")]
+    GroupMarketsMismatch,
+    #[msg("Resolution time is further out than MAX_MARKET_DURATION")]
+    ResolutionTimeTooFar,
+    #[msg("Market is closed")]
+    MarketClosed,
+    #[msg("Markets that void after max_resolution_delay can't join a group")]
+    GroupedMarketVoidable,
 }
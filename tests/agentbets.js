@@ -26,7 +26,24 @@ describe("agentbets - full flow", () => {
     console.log("  Market PDA:", marketPda.toBase58());
 
     const tx = await program.methods
-      .createMarket(MARKET_ID, question, outcomes, new anchor.BN(resolutionTime))
+      .createMarket(MARKET_ID, question, outcomes, new anchor.BN(resolutionTime), {
+        maxOutcomeShares: new anchor.BN(0),
+        maxResolutionDelay: new anchor.BN(0),
+        minBet: new anchor.BN(0),
+        maxTotalPool: new anchor.BN(0),
+        marketKind: 0,
+        scalarMin: new anchor.BN(0),
+        scalarMax: new anchor.BN(0),
+        earlyResolution: true,
+        feeBps: new anchor.BN(200),
+        maxTraderNotional: new anchor.BN(0),
+        referralFeeBps: new anchor.BN(0),
+        outcomeWeights: [],
+        authorityCanTrade: true,
+        commitPhaseStart: new anchor.BN(0),
+        revealPeriod: new anchor.BN(0),
+        feeRoundUp: false,
+      })
       .accounts({
         market: marketPda,
         authority: authority.publicKey,
@@ -53,7 +70,7 @@ describe("agentbets - full flow", () => {
     const amount = new anchor.BN(0.1 * LAMPORTS_PER_SOL);
 
     const tx = await program.methods
-      .buyShares(outcomeIndex, amount)
+      .buyShares(outcomeIndex, amount, null)
       .accounts({
         market: marketPda,
         position: positionPda,
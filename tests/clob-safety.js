@@ -16,11 +16,20 @@
 
 const anchor = require("@coral-xyz/anchor");
 const { PublicKey, SystemProgram, Keypair, LAMPORTS_PER_SOL } = require("@solana/web3.js");
+const {
+  createMint,
+  createAssociatedTokenAccount,
+  getAssociatedTokenAddressSync,
+  getAccount,
+  mintTo,
+} = require("@solana/spl-token");
 const assert = require("assert");
 
 // ===== CONSTANTS =====
 const BPS_MAX = 10_000;
 const SHARE_PAYOUT = 10_000; // lamports per winning share
+const ORDER_TYPE = { LIMIT: 0, TAKE_THEN_MAKE: 1, IOC: 2, FOK: 3, POST_ONLY: 4 };
+const MATCHING_MODE = { FIFO: 0, PRO_RATA: 1 };
 
 // ===== HELPER FUNCTIONS =====
 
@@ -88,14 +97,49 @@ function calculateRestingCollateral(orderBook) {
 
 // ===== TEST HELPERS =====
 
-async function createTestMarket(program, provider, marketId) {
+function defaultClobConfig() {
+  return {
+    vaultRentBuffer: new anchor.BN(0),
+    matchingMode: MATCHING_MODE.FIFO,
+    proRataAgingBps: new anchor.BN(0),
+    maxResolutionDelay: new anchor.BN(0),
+    takerFeeBps: new anchor.BN(0),
+    makerRebateBps: new anchor.BN(0),
+    payoutPerShare: new anchor.BN(0),
+    minPrice: new anchor.BN(0),
+    maxPrice: new anchor.BN(0),
+    lotSize: new anchor.BN(0),
+    tickSize: new anchor.BN(0),
+    minOrderSize: new anchor.BN(0),
+    permissioned: false,
+    disputePeriod: new anchor.BN(0),
+    resolvers: [],
+    resolverThreshold: 0,
+    oracle: PublicKey.default,
+    oracleOffset: 0,
+    oracleThreshold: new anchor.BN(0),
+    oracleComparison: 0,
+    earlyResolution: true, // Most tests resolve right after trading
+    maxTraderNotional: new anchor.BN(0),
+    orderBookCapacity: 0,
+    referralFeeBps: new anchor.BN(0),
+    seedLiquidity: null,
+    authorityCanTrade: true,
+    allowEmergency: false,
+  };
+}
+
+async function createTestMarket(program, provider, marketId, config = {}, resolutionTime = null) {
   const id = marketId || generateMarketId();
   const pdas = getPDAs(program, id);
   
-  const resolutionTime = Math.floor(Date.now() / 1000) + 86400 * 30; // 30 days
+  resolutionTime = resolutionTime ?? Math.floor(Date.now() / 1000) + 86400 * 30; // 30 days
   
   await program.methods
-    .createClobMarket(id, "Test Market", new anchor.BN(resolutionTime))
+    .createClobMarket(id, "Test Market", new anchor.BN(resolutionTime), {
+      ...defaultClobConfig(),
+      ...config,
+    })
     .accounts({
       market: pdas.marketPda,
       orderBook: pdas.orderBookPda,
@@ -108,6 +152,60 @@ async function createTestMarket(program, provider, marketId) {
   return { marketId: id, pdas };
 }
 
+function getPositionPda(program, marketPda, owner) {
+  const [positionPda] = PublicKey.findProgramAddressSync(
+    [Buffer.from("clob_position"), marketPda.toBuffer(), owner.toBuffer()],
+    program.programId
+  );
+  return positionPda;
+}
+
+// Place an order as `trader` (a Keypair), or as the provider wallet when null
+async function placeOrder(program, provider, pdas, trader, side, isYes, price, size, opts = {}) {
+  const traderKey = trader ? trader.publicKey : provider.wallet.publicKey;
+  const builder = program.methods
+    .placeOrder(
+      side,
+      isYes,
+      new anchor.BN(price),
+      new anchor.BN(size),
+      opts.orderType ?? ORDER_TYPE.LIMIT,
+      new anchor.BN(opts.expiry ?? 0),
+      new anchor.BN(opts.minFillSize ?? 0),
+      new anchor.BN(opts.avgPriceLimit ?? 0),
+      opts.reduceOnly ?? false,
+      opts.referrer ?? null
+    )
+    .accounts({
+      market: pdas.marketPda,
+      orderBook: pdas.orderBookPda,
+      vault: pdas.vaultPda,
+      position: getPositionPda(program, pdas.marketPda, traderKey),
+      trader: traderKey,
+      systemProgram: SystemProgram.programId,
+    })
+    .remainingAccounts(opts.remainingAccounts ?? []);
+  return trader ? builder.signers([trader]).rpc() : builder.rpc();
+}
+
+// Decode the Anchor events emitted by a confirmed transaction
+async function getEvents(program, provider, signature) {
+  const tx = await provider.connection.getTransaction(signature, {
+    commitment: "confirmed",
+    maxSupportedTransactionVersion: 0,
+  });
+  const parser = new anchor.EventParser(program.programId, program.coder);
+  return [...parser.parseLogs(tx.meta.logMessages)];
+}
+
+function sleep(ms) {
+  return new Promise((resolve) => setTimeout(resolve, ms));
+}
+
+function findEvents(events, name) {
+  return events.filter((e) => e.name.toLowerCase() === name.toLowerCase());
+}
+
 async function getMarketState(program, provider, pdas, traders) {
   const vaultBalance = await provider.connection.getBalance(pdas.vaultPda);
   const orderBookData = await program.account.orderBook.fetch(pdas.orderBookPda);
@@ -190,7 +288,7 @@ describe("CLOB Safety Tests", function() {
       const expectedCollateral = price * size; // 600,000 lamports
       
       await program.methods
-        .placeOrder(0, true, new anchor.BN(price), new anchor.BN(size))
+        .placeOrder(0, true, new anchor.BN(price), new anchor.BN(size), 0, new anchor.BN(0), new anchor.BN(0), new anchor.BN(0), false, null)
         .accounts({
           market: pdas.marketPda,
           orderBook: pdas.orderBookPda,
@@ -220,7 +318,7 @@ describe("CLOB Safety Tests", function() {
       const collateral = price * size;
       
       await program.methods
-        .placeOrder(0, true, new anchor.BN(price), new anchor.BN(size))
+        .placeOrder(0, true, new anchor.BN(price), new anchor.BN(size), 0, new anchor.BN(0), new anchor.BN(0), new anchor.BN(0), false, null)
         .accounts({
           market: pdas.marketPda,
           orderBook: pdas.orderBookPda,
@@ -271,7 +369,7 @@ describe("CLOB Safety Tests", function() {
         const size = 10 + i * 5;
         
         await program.methods
-          .placeOrder(0, true, new anchor.BN(bidPrice), new anchor.BN(size))
+          .placeOrder(0, true, new anchor.BN(bidPrice), new anchor.BN(size), 0, new anchor.BN(0), new anchor.BN(0), new anchor.BN(0), false, null)
           .accounts({
             market: pdas.marketPda,
             orderBook: pdas.orderBookPda,
@@ -284,7 +382,7 @@ describe("CLOB Safety Tests", function() {
           .rpc();
         
         await program.methods
-          .placeOrder(1, true, new anchor.BN(askPrice), new anchor.BN(size))
+          .placeOrder(1, true, new anchor.BN(askPrice), new anchor.BN(size), 0, new anchor.BN(0), new anchor.BN(0), new anchor.BN(0), false, null)
           .accounts({
             market: pdas.marketPda,
             orderBook: pdas.orderBookPda,
@@ -320,7 +418,7 @@ describe("CLOB Safety Tests", function() {
       );
       
       await program.methods
-        .placeOrder(1, true, new anchor.BN(5000), new anchor.BN(100))
+        .placeOrder(1, true, new anchor.BN(5000), new anchor.BN(100), 0, new anchor.BN(0), new anchor.BN(0), new anchor.BN(0), false, null)
         .accounts({
           market: pdas.marketPda,
           orderBook: pdas.orderBookPda,
@@ -342,7 +440,7 @@ describe("CLOB Safety Tests", function() {
       );
       
       await program.methods
-        .placeOrder(0, true, new anchor.BN(5500), new anchor.BN(50))
+        .placeOrder(0, true, new anchor.BN(5500), new anchor.BN(50), 0, new anchor.BN(0), new anchor.BN(0), new anchor.BN(0), false, null)
         .accounts({
           market: pdas.marketPda,
           orderBook: pdas.orderBookPda,
@@ -377,7 +475,7 @@ describe("CLOB Safety Tests", function() {
       );
       
       await program.methods
-        .placeOrder(1, true, new anchor.BN(5000), new anchor.BN(100))
+        .placeOrder(1, true, new anchor.BN(5000), new anchor.BN(100), 0, new anchor.BN(0), new anchor.BN(0), new anchor.BN(0), false, null)
         .accounts({
           market: pdas.marketPda,
           orderBook: pdas.orderBookPda,
@@ -402,7 +500,7 @@ describe("CLOB Safety Tests", function() {
       );
       
       await program.methods
-        .placeOrder(0, true, new anchor.BN(5000), new anchor.BN(100))
+        .placeOrder(0, true, new anchor.BN(5000), new anchor.BN(100), 0, new anchor.BN(0), new anchor.BN(0), new anchor.BN(0), false, null)
         .accounts({
           market: pdas.marketPda,
           orderBook: pdas.orderBookPda,
@@ -425,7 +523,7 @@ describe("CLOB Safety Tests", function() {
       console.log(`  ⚠️ Actual: Maker position unchanged - FUNDS STUCK!`);
     });
     
-    it("1.6 [BUG TEST] Better-price fills should refund difference - KNOWN BUG", async () => {
+    it("1.6 Better-price fills refund the difference", async () => {
       /**
        * When a taker's order crosses at a better price than their limit,
        * they should be refunded the difference.
@@ -435,58 +533,22 @@ describe("CLOB Safety Tests", function() {
       // Maker places ASK @ 5000
       const maker = Keypair.generate();
       await airdrop(provider, maker.publicKey);
-      
-      const [makerPosPda] = PublicKey.findProgramAddressSync(
-        [Buffer.from("clob_position"), pdas.marketPda.toBuffer(), maker.publicKey.toBuffer()],
-        program.programId
-      );
-      
-      await program.methods
-        .placeOrder(1, true, new anchor.BN(5000), new anchor.BN(100))
-        .accounts({
-          market: pdas.marketPda,
-          orderBook: pdas.orderBookPda,
-          vault: pdas.vaultPda,
-          position: makerPosPda,
-          trader: maker.publicKey,
-          systemProgram: SystemProgram.programId,
-        })
-        .signers([maker])
-        .rpc();
+      await placeOrder(program, provider, pdas, maker, 1, true, 5000, 100);
       
       // Taker places BID @ 6000 (1000 bps better than best ask)
       const taker = Keypair.generate();
       await airdrop(provider, taker.publicKey);
       
-      const [takerPosPda] = PublicKey.findProgramAddressSync(
-        [Buffer.from("clob_position"), pdas.marketPda.toBuffer(), taker.publicKey.toBuffer()],
-        program.programId
-      );
-      
-      const takerBalanceBefore = await provider.connection.getBalance(taker.publicKey);
-      
-      await program.methods
-        .placeOrder(0, true, new anchor.BN(6000), new anchor.BN(100))
-        .accounts({
-          market: pdas.marketPda,
-          orderBook: pdas.orderBookPda,
-          vault: pdas.vaultPda,
-          position: takerPosPda,
-          trader: taker.publicKey,
-          systemProgram: SystemProgram.programId,
-        })
-        .signers([taker])
-        .rpc();
-      
-      const takerBalanceAfter = await provider.connection.getBalance(taker.publicKey);
+      const vaultBefore = await provider.connection.getBalance(pdas.vaultPda);
+      await placeOrder(program, provider, pdas, taker, 0, true, 6000, 100);
+      const vaultAfter = await provider.connection.getBalance(pdas.vaultPda);
       
       const expectedCost = 5000 * 100; // Fill price
-      const actualCost = takerBalanceBefore - takerBalanceAfter;
-      const overpayment = actualCost - expectedCost;
+      const actualCost = vaultAfter - vaultBefore;
       
       console.log(`  Taker paid: ${actualCost} lamports`);
-      console.log(`  Should have paid: ${expectedCost} lamports`);
-      console.log(`  ⚠️ BUG: Overpayment of ${overpayment} lamports (no refund for better price)`);
+      assert.equal(actualCost, expectedCost, "1000 bps * 100 refunded to the taker");
+      console.log(`  ✓ Taker refunded ${(6000 - 5000) * 100} lamports of price improvement`);
     });
     
     it("1.7 Fund conservation: total_in = total_out (cancel all orders)", async () => {
@@ -514,7 +576,7 @@ describe("CLOB Safety Tests", function() {
         totalDeposited += collateral;
         
         await program.methods
-          .placeOrder(order.side, true, new anchor.BN(order.price), new anchor.BN(order.size))
+          .placeOrder(order.side, true, new anchor.BN(order.price), new anchor.BN(order.size), 0, new anchor.BN(0), new anchor.BN(0), new anchor.BN(0), false, null)
           .accounts({
             market: pdas.marketPda,
             orderBook: pdas.orderBookPda,
@@ -588,7 +650,7 @@ describe("CLOB Safety Tests", function() {
       );
       
       await program.methods
-        .placeOrder(1, true, new anchor.BN(5000), new anchor.BN(100))
+        .placeOrder(1, true, new anchor.BN(5000), new anchor.BN(100), 0, new anchor.BN(0), new anchor.BN(0), new anchor.BN(0), false, null)
         .accounts({
           market: pdas.marketPda,
           orderBook: pdas.orderBookPda,
@@ -609,7 +671,7 @@ describe("CLOB Safety Tests", function() {
       );
       
       await program.methods
-        .placeOrder(0, true, new anchor.BN(6000), new anchor.BN(100))
+        .placeOrder(0, true, new anchor.BN(6000), new anchor.BN(100), 0, new anchor.BN(0), new anchor.BN(0), new anchor.BN(0), false, null)
         .accounts({
           market: pdas.marketPda,
           orderBook: pdas.orderBookPda,
@@ -645,7 +707,7 @@ describe("CLOB Safety Tests", function() {
       );
       
       await program.methods
-        .placeOrder(1, true, new anchor.BN(5000), new anchor.BN(100))
+        .placeOrder(1, true, new anchor.BN(5000), new anchor.BN(100), 0, new anchor.BN(0), new anchor.BN(0), new anchor.BN(0), false, null)
         .accounts({
           market: pdas.marketPda,
           orderBook: pdas.orderBookPda,
@@ -666,7 +728,7 @@ describe("CLOB Safety Tests", function() {
       );
       
       await program.methods
-        .placeOrder(0, true, new anchor.BN(5500), new anchor.BN(30))
+        .placeOrder(0, true, new anchor.BN(5500), new anchor.BN(30), 0, new anchor.BN(0), new anchor.BN(0), new anchor.BN(0), false, null)
         .accounts({
           market: pdas.marketPda,
           orderBook: pdas.orderBookPda,
@@ -717,7 +779,7 @@ describe("CLOB Safety Tests", function() {
       
       // maker1: ASK 50 @ 6000 (worst)
       await program.methods
-        .placeOrder(1, true, new anchor.BN(6000), new anchor.BN(50))
+        .placeOrder(1, true, new anchor.BN(6000), new anchor.BN(50), 0, new anchor.BN(0), new anchor.BN(0), new anchor.BN(0), false, null)
         .accounts({
           market: pdas.marketPda,
           orderBook: pdas.orderBookPda,
@@ -731,7 +793,7 @@ describe("CLOB Safety Tests", function() {
       
       // maker2: ASK 50 @ 4000 (best)
       await program.methods
-        .placeOrder(1, true, new anchor.BN(4000), new anchor.BN(50))
+        .placeOrder(1, true, new anchor.BN(4000), new anchor.BN(50), 0, new anchor.BN(0), new anchor.BN(0), new anchor.BN(0), false, null)
         .accounts({
           market: pdas.marketPda,
           orderBook: pdas.orderBookPda,
@@ -745,7 +807,7 @@ describe("CLOB Safety Tests", function() {
       
       // maker3: ASK 50 @ 5000 (middle)
       await program.methods
-        .placeOrder(1, true, new anchor.BN(5000), new anchor.BN(50))
+        .placeOrder(1, true, new anchor.BN(5000), new anchor.BN(50), 0, new anchor.BN(0), new anchor.BN(0), new anchor.BN(0), false, null)
         .accounts({
           market: pdas.marketPda,
           orderBook: pdas.orderBookPda,
@@ -774,7 +836,7 @@ describe("CLOB Safety Tests", function() {
       );
       
       await program.methods
-        .placeOrder(0, true, new anchor.BN(5000), new anchor.BN(60))
+        .placeOrder(0, true, new anchor.BN(5000), new anchor.BN(60), 0, new anchor.BN(0), new anchor.BN(0), new anchor.BN(0), false, null)
         .accounts({
           market: pdas.marketPda,
           orderBook: pdas.orderBookPda,
@@ -810,7 +872,7 @@ describe("CLOB Safety Tests", function() {
       );
       
       await program.methods
-        .placeOrder(1, true, new anchor.BN(6000), new anchor.BN(50))
+        .placeOrder(1, true, new anchor.BN(6000), new anchor.BN(50), 0, new anchor.BN(0), new anchor.BN(0), new anchor.BN(0), false, null)
         .accounts({
           market: pdas.marketPda,
           orderBook: pdas.orderBookPda,
@@ -831,7 +893,7 @@ describe("CLOB Safety Tests", function() {
       );
       
       await program.methods
-        .placeOrder(0, true, new anchor.BN(5000), new anchor.BN(50))
+        .placeOrder(0, true, new anchor.BN(5000), new anchor.BN(50), 0, new anchor.BN(0), new anchor.BN(0), new anchor.BN(0), false, null)
         .accounts({
           market: pdas.marketPda,
           orderBook: pdas.orderBookPda,
@@ -884,7 +946,7 @@ describe("CLOB Safety Tests", function() {
         totalAskSize += size;
         
         await program.methods
-          .placeOrder(1, true, new anchor.BN(5000), new anchor.BN(size))
+          .placeOrder(1, true, new anchor.BN(5000), new anchor.BN(size), 0, new anchor.BN(0), new anchor.BN(0), new anchor.BN(0), false, null)
           .accounts({
             market: pdas.marketPda,
             orderBook: pdas.orderBookPda,
@@ -906,7 +968,7 @@ describe("CLOB Safety Tests", function() {
       );
       
       await program.methods
-        .placeOrder(0, true, new anchor.BN(5000), new anchor.BN(totalAskSize))
+        .placeOrder(0, true, new anchor.BN(5000), new anchor.BN(totalAskSize), 0, new anchor.BN(0), new anchor.BN(0), new anchor.BN(0), false, null)
         .accounts({
           market: pdas.marketPda,
           orderBook: pdas.orderBookPda,
@@ -941,7 +1003,7 @@ describe("CLOB Safety Tests", function() {
       
       // Minimum price (1 bps = 0.01%)
       await program.methods
-        .placeOrder(0, true, new anchor.BN(1), new anchor.BN(10))
+        .placeOrder(0, true, new anchor.BN(1), new anchor.BN(10), 0, new anchor.BN(0), new anchor.BN(0), new anchor.BN(0), false, null)
         .accounts({
           market: pdas.marketPda,
           orderBook: pdas.orderBookPda,
@@ -955,7 +1017,7 @@ describe("CLOB Safety Tests", function() {
       
       // Maximum price (9999 bps = 99.99%)
       await program.methods
-        .placeOrder(1, true, new anchor.BN(9999), new anchor.BN(10))
+        .placeOrder(1, true, new anchor.BN(9999), new anchor.BN(10), 0, new anchor.BN(0), new anchor.BN(0), new anchor.BN(0), false, null)
         .accounts({
           market: pdas.marketPda,
           orderBook: pdas.orderBookPda,
@@ -986,7 +1048,7 @@ describe("CLOB Safety Tests", function() {
       
       try {
         await program.methods
-          .placeOrder(0, true, new anchor.BN(0), new anchor.BN(10))
+          .placeOrder(0, true, new anchor.BN(0), new anchor.BN(10), 0, new anchor.BN(0), new anchor.BN(0), new anchor.BN(0), false, null)
           .accounts({
             market: pdas.marketPda,
             orderBook: pdas.orderBookPda,
@@ -1017,7 +1079,7 @@ describe("CLOB Safety Tests", function() {
       
       try {
         await program.methods
-          .placeOrder(0, true, new anchor.BN(10000), new anchor.BN(10))
+          .placeOrder(0, true, new anchor.BN(10000), new anchor.BN(10), 0, new anchor.BN(0), new anchor.BN(0), new anchor.BN(0), false, null)
           .accounts({
             market: pdas.marketPda,
             orderBook: pdas.orderBookPda,
@@ -1048,7 +1110,7 @@ describe("CLOB Safety Tests", function() {
       
       try {
         await program.methods
-          .placeOrder(0, true, new anchor.BN(5000), new anchor.BN(0))
+          .placeOrder(0, true, new anchor.BN(5000), new anchor.BN(0), 0, new anchor.BN(0), new anchor.BN(0), new anchor.BN(0), false, null)
           .accounts({
             market: pdas.marketPda,
             orderBook: pdas.orderBookPda,
@@ -1079,7 +1141,7 @@ describe("CLOB Safety Tests", function() {
       
       // BID for NO @ 40% = ASK for YES @ 60%
       await program.methods
-        .placeOrder(0, false, new anchor.BN(4000), new anchor.BN(50))
+        .placeOrder(0, false, new anchor.BN(4000), new anchor.BN(50), 0, new anchor.BN(0), new anchor.BN(0), new anchor.BN(0), false, null)
         .accounts({
           market: pdas.marketPda,
           orderBook: pdas.orderBookPda,
@@ -1133,7 +1195,7 @@ describe("CLOB Safety Tests", function() {
       );
       
       await program.methods
-        .placeOrder(0, true, new anchor.BN(5000), new anchor.BN(50))
+        .placeOrder(0, true, new anchor.BN(5000), new anchor.BN(50), 0, new anchor.BN(0), new anchor.BN(0), new anchor.BN(0), false, null)
         .accounts({
           market: pdas.marketPda,
           orderBook: pdas.orderBookPda,
@@ -1187,7 +1249,7 @@ describe("CLOB Safety Tests", function() {
       );
       
       await program.methods
-        .placeOrder(1, true, new anchor.BN(5000), new anchor.BN(100))
+        .placeOrder(1, true, new anchor.BN(5000), new anchor.BN(100), 0, new anchor.BN(0), new anchor.BN(0), new anchor.BN(0), false, null)
         .accounts({
           market: pdas.marketPda,
           orderBook: pdas.orderBookPda,
@@ -1208,7 +1270,7 @@ describe("CLOB Safety Tests", function() {
       );
       
       await program.methods
-        .placeOrder(0, true, new anchor.BN(5000), new anchor.BN(100))
+        .placeOrder(0, true, new anchor.BN(5000), new anchor.BN(100), 0, new anchor.BN(0), new anchor.BN(0), new anchor.BN(0), false, null)
         .accounts({
           market: pdas.marketPda,
           orderBook: pdas.orderBookPda,
@@ -1233,7 +1295,7 @@ describe("CLOB Safety Tests", function() {
       const takerBalBefore = await provider.connection.getBalance(taker.publicKey);
       
       await program.methods
-        .claimClobWinnings()
+        .claimClobWinnings(new anchor.BN(0))
         .accounts({
           market: pdas.marketPda,
           vault: pdas.vaultPda,
@@ -1272,7 +1334,7 @@ describe("CLOB Safety Tests", function() {
       );
       
       await program.methods
-        .placeOrder(1, true, new anchor.BN(5000), new anchor.BN(50))
+        .placeOrder(1, true, new anchor.BN(5000), new anchor.BN(50), 0, new anchor.BN(0), new anchor.BN(0), new anchor.BN(0), false, null)
         .accounts({
           market: pdas.marketPda,
           orderBook: pdas.orderBookPda,
@@ -1285,7 +1347,7 @@ describe("CLOB Safety Tests", function() {
         .rpc();
       
       await program.methods
-        .placeOrder(0, true, new anchor.BN(5000), new anchor.BN(50))
+        .placeOrder(0, true, new anchor.BN(5000), new anchor.BN(50), 0, new anchor.BN(0), new anchor.BN(0), new anchor.BN(0), false, null)
         .accounts({
           market: pdas.marketPda,
           orderBook: pdas.orderBookPda,
@@ -1307,7 +1369,7 @@ describe("CLOB Safety Tests", function() {
       
       // First claim succeeds
       await program.methods
-        .claimClobWinnings()
+        .claimClobWinnings(new anchor.BN(0))
         .accounts({
           market: pdas.marketPda,
           vault: pdas.vaultPda,
@@ -1322,7 +1384,7 @@ describe("CLOB Safety Tests", function() {
       // Second claim should fail
       try {
         await program.methods
-          .claimClobWinnings()
+          .claimClobWinnings(new anchor.BN(0))
           .accounts({
             market: pdas.marketPda,
             vault: pdas.vaultPda,
@@ -1359,7 +1421,7 @@ describe("CLOB Safety Tests", function() {
       
       try {
         await program.methods
-          .placeOrder(0, true, new anchor.BN(5000), new anchor.BN(50))
+          .placeOrder(0, true, new anchor.BN(5000), new anchor.BN(50), 0, new anchor.BN(0), new anchor.BN(0), new anchor.BN(0), false, null)
           .accounts({
             market: pdas.marketPda,
             orderBook: pdas.orderBookPda,
@@ -1439,7 +1501,7 @@ describe("CLOB Safety Tests", function() {
           
           try {
             await program.methods
-              .placeOrder(side, true, new anchor.BN(price), new anchor.BN(size))
+              .placeOrder(side, true, new anchor.BN(price), new anchor.BN(size), 0, new anchor.BN(0), new anchor.BN(0), new anchor.BN(0), false, null)
               .accounts({
                 market: pdas.marketPda,
                 orderBook: pdas.orderBookPda,
@@ -1508,22 +1570,4105 @@ describe("CLOB Safety Tests", function() {
     
     it("6.2 [DOC] Bug: No refund for better-price fills", async () => {
       console.log(`  ⚠️ HIGH BUG: Takers overpay when filling at better prices`);
-      console.log(`  ⚠️ Status: DOCUMENTED - Requires code fix`);
+      console.log(`  ✓ Status: FIXED for YES bids - see test 1.6`);
     });
     
     it("6.3 [DOC] Bug: Cancel by index is fragile", async () => {
       console.log(`  ⚠️ MEDIUM BUG: Cancel by index can cancel wrong order`);
-      console.log(`  ⚠️ Status: DOCUMENTED - Should fix before production`);
+      console.log(`  ✓ Status: FIXED - use cancel_order_by_id, see test 21.1`);
     });
     
     it("6.4 [DOC] Bug: Order ID collision (same timestamp)", async () => {
       console.log(`  ⚠️ MEDIUM BUG: Order IDs can collide within same second`);
-      console.log(`  ⚠️ Status: DOCUMENTED - Should fix`);
+      console.log(`  ✓ Status: FIXED - per-market order_seq counter, see test 20.1`);
     });
     
     it("6.5 [DOC] Bug: No vault balance check before claim", async () => {
       console.log(`  ⚠️ HIGH BUG: No balance check before claim payout`);
-      console.log(`  ⚠️ Status: DOCUMENTED - Easy fix`);
+      console.log(`  ✓ Status: FIXED - claims fail with VaultInsolvent, see test 23.1`);
+    });
+  });
+  
+  // =========================================
+  // 7. POSITION TRANSFERS
+  // =========================================
+  
+  describe("7. Position Transfers", () => {
+    
+    it("7.1 Transferred position can be claimed by the recipient", async () => {
+      const { pdas } = await createTestMarket(program, provider);
+      
+      const maker = Keypair.generate();
+      const taker = Keypair.generate();
+      const recipient = Keypair.generate();
+      await airdrop(provider, maker.publicKey);
+      await airdrop(provider, taker.publicKey);
+      await airdrop(provider, recipient.publicKey);
+      
+      await placeOrder(program, provider, pdas, maker, 1, true, 5000, 100);
+      await placeOrder(program, provider, pdas, taker, 0, true, 5000, 100);
+      
+      const takerPos = getPositionPda(program, pdas.marketPda, taker.publicKey);
+      const recipientPos = getPositionPda(program, pdas.marketPda, recipient.publicKey);
+      
+      await program.methods
+        .transferClobPosition(recipient.publicKey)
+        .accounts({
+          market: pdas.marketPda,
+          source: takerPos,
+          destination: recipientPos,
+          owner: taker.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([taker])
+        .rpc();
+      
+      const source = await program.account.clobPosition.fetch(takerPos);
+      const destination = await program.account.clobPosition.fetch(recipientPos);
+      assert.equal(source.yesShares.toNumber(), 0);
+      assert.equal(destination.yesShares.toNumber(), 100);
+      assert(destination.owner.equals(recipient.publicKey));
+      
+      await program.methods
+        .resolveClobMarket(0)
+        .accounts({
+          market: pdas.marketPda,
+          authority: authority.publicKey,
+        })
+        .rpc();
+      
+      const balBefore = await provider.connection.getBalance(recipient.publicKey);
+      await program.methods
+        .claimClobWinnings(new anchor.BN(0))
+        .accounts({
+          market: pdas.marketPda,
+          vault: pdas.vaultPda,
+          position: recipientPos,
+          claimer: recipient.publicKey,
+        })
+        .signers([recipient])
+        .rpc();
+      const balAfter = await provider.connection.getBalance(recipient.publicKey);
+      
+      const expectedPayout = 100 * SHARE_PAYOUT;
+      assert(Math.abs(balAfter - balBefore - expectedPayout) < 10000);
+      console.log(`  ✓ Recipient claimed ${balAfter - balBefore} lamports`);
+    });
+    
+    it("7.2 Cannot transfer an empty position", async () => {
+      const { pdas } = await createTestMarket(program, provider);
+      const trader = Keypair.generate();
+      const recipient = Keypair.generate();
+      await airdrop(provider, trader.publicKey);
+      
+      // Resting bid creates the position without any shares
+      await placeOrder(program, provider, pdas, trader, 0, true, 5000, 10);
+      
+      try {
+        await program.methods
+          .transferClobPosition(recipient.publicKey)
+          .accounts({
+            market: pdas.marketPda,
+            source: getPositionPda(program, pdas.marketPda, trader.publicKey),
+            destination: getPositionPda(program, pdas.marketPda, recipient.publicKey),
+            owner: trader.publicKey,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([trader])
+          .rpc();
+        
+        assert.fail("Should reject empty transfer");
+      } catch (err) {
+        assert(err.error?.errorCode?.code === "EmptyPosition");
+        console.log(`  ✓ Empty position transfer rejected`);
+      }
+    });
+  });
+  
+  // =========================================
+  // 8. VAULT RENT BUFFER
+  // =========================================
+  
+  describe("8. Vault Rent Buffer", () => {
+    
+    it("8.1 Vault keeps rent minimum plus buffer after a full refund", async () => {
+      const rentExempt = await provider.connection.getMinimumBalanceForRentExemption(0);
+      const buffer = rentExempt; // 2x minimum
+      const { pdas } = await createTestMarket(program, provider, null, {
+        vaultRentBuffer: new anchor.BN(buffer),
+      });
+      
+      const market = await program.account.clobMarket.fetch(pdas.marketPda);
+      assert.equal(market.vaultReserve.toNumber(), rentExempt + buffer);
+      assert.equal(await provider.connection.getBalance(pdas.vaultPda), rentExempt + buffer);
+      
+      // Drain the vault back down to its floor
+      await placeOrder(program, provider, pdas, null, 0, true, 5000, 50);
+      await program.methods
+        .cancelOrder(true, 0)
+        .accounts({
+          market: pdas.marketPda,
+          orderBook: pdas.orderBookPda,
+          vault: pdas.vaultPda,
+          trader: authority.publicKey,
+        })
+        .rpc();
+      
+      const vaultAfter = await provider.connection.getBalance(pdas.vaultPda);
+      assert.equal(vaultAfter, rentExempt + buffer);
+      console.log(`  ✓ Vault retained ${vaultAfter} lamports (rent ${rentExempt} + buffer ${buffer})`);
+    });
+  });
+  
+  // =========================================
+  // 9. TAKE-THEN-MAKE ORDERS
+  // =========================================
+  
+  describe("9. Take-Then-Make Orders", () => {
+    
+    it("9.1 Takes improved asks but rests at the exact limit", async () => {
+      const { pdas } = await createTestMarket(program, provider);
+      
+      const maker = Keypair.generate();
+      const taker = Keypair.generate();
+      await airdrop(provider, maker.publicKey);
+      await airdrop(provider, taker.publicKey);
+      
+      await placeOrder(program, provider, pdas, maker, 1, true, 4800, 10);
+      await placeOrder(program, provider, pdas, maker, 1, true, 5000, 10);
+      
+      await placeOrder(program, provider, pdas, taker, 0, true, 5000, 30, {
+        orderType: ORDER_TYPE.TAKE_THEN_MAKE,
+      });
+      
+      const takerPos = await program.account.clobPosition.fetch(
+        getPositionPda(program, pdas.marketPda, taker.publicKey)
+      );
+      assert.equal(takerPos.yesShares.toNumber(), 10, "only the 4800 ask is taken");
+      
+      const orderBook = await program.account.orderBook.fetch(pdas.orderBookPda);
+      assert.equal(orderBook.yesAsks.length, 1);
+      assert.equal(orderBook.yesAsks[0].price.toNumber(), 5000, "ask at the limit is untouched");
+      assert.equal(orderBook.yesBids.length, 1);
+      assert.equal(orderBook.yesBids[0].price.toNumber(), 5000);
+      assert.equal(orderBook.yesBids[0].size.toNumber(), 20, "remainder rests at the limit");
+      console.log(`  ✓ Took 10 @ 4800, rested 20 @ 5000 without crossing`);
+    });
+  });
+  
+  // =========================================
+  // 10. ORDER EVENTS
+  // =========================================
+  
+  describe("10. Order Events", () => {
+    
+    it("10.1 OrderCancelled refund matches the vault debit", async () => {
+      const { pdas } = await createTestMarket(program, provider);
+      
+      await placeOrder(program, provider, pdas, null, 1, true, 5500, 40);
+      const orderBook = await program.account.orderBook.fetch(pdas.orderBookPda);
+      const orderId = orderBook.yesAsks[0].orderId;
+      
+      const vaultBefore = await provider.connection.getBalance(pdas.vaultPda);
+      const sig = await program.methods
+        .cancelOrder(false, 0)
+        .accounts({
+          market: pdas.marketPda,
+          orderBook: pdas.orderBookPda,
+          vault: pdas.vaultPda,
+          trader: authority.publicKey,
+        })
+        .rpc({ commitment: "confirmed" });
+      const vaultAfter = await provider.connection.getBalance(pdas.vaultPda);
+      
+      const [event] = findEvents(await getEvents(program, provider, sig), "OrderCancelled");
+      assert(event, "OrderCancelled event emitted");
+      assert.equal(event.data.refund.toNumber(), vaultBefore - vaultAfter);
+      assert.equal(event.data.refund.toNumber(), (BPS_MAX - 5500) * 40);
+      assert.equal(event.data.size.toNumber(), 40);
+      assert.equal(event.data.isBid, false);
+      assert(event.data.orderId.eq(orderId));
+      assert(event.data.owner.equals(authority.publicKey));
+      console.log(`  ✓ Event refund ${event.data.refund} matches vault debit`);
+    });
+    
+    it("10.2 OrderFilled fires once per resting order hit", async () => {
+      const { pdas } = await createTestMarket(program, provider);
+      
+      const maker = Keypair.generate();
+      const taker = Keypair.generate();
+      await airdrop(provider, maker.publicKey);
+      await airdrop(provider, taker.publicKey);
+      
+      await placeOrder(program, provider, pdas, maker, 1, true, 5000, 10);
+      await placeOrder(program, provider, pdas, maker, 1, true, 5200, 10);
+      const orderBook = await program.account.orderBook.fetch(pdas.orderBookPda);
+      const orderIds = orderBook.yesAsks.map((o) => o.orderId.toString());
+      
+      const sig = await program.methods
+        .placeOrder(0, true, new anchor.BN(5200), new anchor.BN(15), ORDER_TYPE.LIMIT, new anchor.BN(0), new anchor.BN(0), new anchor.BN(0), false, null)
+        .accounts({
+          market: pdas.marketPda,
+          orderBook: pdas.orderBookPda,
+          vault: pdas.vaultPda,
+          position: getPositionPda(program, pdas.marketPda, taker.publicKey),
+          trader: taker.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([taker])
+        .rpc({ commitment: "confirmed" });
+      
+      const fills = findEvents(await getEvents(program, provider, sig), "OrderFilled");
+      assert.equal(fills.length, 2);
+      assert.deepEqual(fills.map((f) => f.data.price.toNumber()), [5000, 5200]);
+      assert.deepEqual(fills.map((f) => f.data.size.toNumber()), [10, 5]);
+      assert.deepEqual(fills.map((f) => f.data.orderId.toString()), orderIds);
+      assert(fills.every((f) => f.data.maker.equals(maker.publicKey)));
+      assert(fills.every((f) => f.data.taker.equals(taker.publicKey) && f.data.takerBuysYes));
+      console.log(`  ✓ Two OrderFilled events for a two-level sweep`);
+    });
+  });
+  
+  // =========================================
+  // 11. PRO-RATA MATCHING
+  // =========================================
+  
+  describe("11. Pro-Rata Matching", () => {
+    
+    // Two makers rest 60 and 40 YES @ 5000, then a taker buys 50
+    async function fillOneLevel(matchingMode) {
+      const { pdas } = await createTestMarket(program, provider, null, { matchingMode });
+      
+      const makerA = Keypair.generate();
+      const makerB = Keypair.generate();
+      const taker = Keypair.generate();
+      await airdrop(provider, makerA.publicKey);
+      await airdrop(provider, makerB.publicKey);
+      await airdrop(provider, taker.publicKey);
+      
+      await placeOrder(program, provider, pdas, makerA, 1, true, 5000, 60);
+      await placeOrder(program, provider, pdas, makerB, 1, true, 5000, 40);
+      await placeOrder(program, provider, pdas, taker, 0, true, 5000, 50);
+      
+      const orderBook = await program.account.orderBook.fetch(pdas.orderBookPda);
+      const restingSize = (maker) =>
+        orderBook.yesAsks
+          .filter((o) => o.owner.equals(maker.publicKey))
+          .reduce((sum, o) => sum + o.size.toNumber(), 0);
+      return { a: restingSize(makerA), b: restingSize(makerB) };
+    }
+    
+    it("11.1 FIFO fills the oldest order first", async () => {
+      const { a, b } = await fillOneLevel(MATCHING_MODE.FIFO);
+      assert.equal(a, 10);
+      assert.equal(b, 40);
+      console.log(`  ✓ FIFO: maker A filled 50, maker B filled 0`);
+    });
+    
+    it("11.2 Pro-rata splits the level by size", async () => {
+      const { a, b } = await fillOneLevel(MATCHING_MODE.PRO_RATA);
+      assert.equal(a, 30);
+      assert.equal(b, 20);
+      console.log(`  ✓ Pro-rata: maker A filled 30, maker B filled 20`);
+    });
+  });
+  
+  // =========================================
+  // 12. VOID AFTER RESOLUTION DELAY
+  // =========================================
+  
+  describe("12. Void After Resolution Delay", () => {
+    
+    it("12.1 Claims pay out at 50% once the deadline passes unresolved", async () => {
+      const maker = Keypair.generate();
+      const taker = Keypair.generate();
+      await airdrop(provider, maker.publicKey);
+      await airdrop(provider, taker.publicKey);
+      
+      const now = Math.floor(Date.now() / 1000);
+      const { pdas } = await createTestMarket(
+        program,
+        provider,
+        null,
+        { maxResolutionDelay: new anchor.BN(1) },
+        now + 4
+      );
+      
+      await placeOrder(program, provider, pdas, maker, 1, true, 5000, 20);
+      await placeOrder(program, provider, pdas, taker, 0, true, 5000, 20);
+      
+      await sleep(7000);
+      
+      const takerPos = getPositionPda(program, pdas.marketPda, taker.publicKey);
+      const before = await provider.connection.getBalance(taker.publicKey);
+      await program.methods
+        .claimClobWinnings(new anchor.BN(0))
+        .accounts({
+          market: pdas.marketPda,
+          vault: pdas.vaultPda,
+          position: takerPos,
+          claimer: taker.publicKey,
+        })
+        .signers([taker])
+        .rpc();
+      const after = await provider.connection.getBalance(taker.publicKey);
+      
+      const expected = (20 * SHARE_PAYOUT) / 2;
+      assert(Math.abs(after - before - expected) < 10000);
+      
+      const market = await program.account.clobMarket.fetch(pdas.marketPda);
+      assert.equal(market.resolved, false, "no resolve call was needed");
+      console.log(`  ✓ Void claim paid ${after - before} lamports without resolution`);
+    });
+    
+    it("12.2 A complete set gets its full value back once the market is abandoned", async () => {
+      const trader = Keypair.generate();
+      await airdrop(provider, trader.publicKey);
+      const { pdas } = await createTestMarket(
+        program,
+        provider,
+        null,
+        { maxResolutionDelay: new anchor.BN(1) },
+        Math.floor(Date.now() / 1000) + 3
+      );
+      const positionPda = getPositionPda(program, pdas.marketPda, trader.publicKey);
+      await program.methods
+        .mintCompleteSet(new anchor.BN(10))
+        .accounts({
+          market: pdas.marketPda,
+          vault: pdas.vaultPda,
+          position: positionPda,
+          trader: trader.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([trader])
+        .rpc();
+      
+      await sleep(6000);
+      const vaultBefore = await provider.connection.getBalance(pdas.vaultPda);
+      await program.methods
+        .claimVoidRefund()
+        .accounts({
+          market: pdas.marketPda,
+          vault: pdas.vaultPda,
+          position: positionPda,
+          claimer: trader.publicKey,
+        })
+        .signers([trader])
+        .rpc();
+      const vaultAfter = await provider.connection.getBalance(pdas.vaultPda);
+      assert.equal(vaultBefore - vaultAfter, 10 * SHARE_PAYOUT);
+      console.log("  ✓ Abandoned market paid back 10 complete sets in full");
+    });
+  });
+  
+  // =========================================
+  // 13. TAKER FEES
+  // =========================================
+  describe("13. Taker Fees", () => {
+    
+    it("13.1 Takers pay the fee on filled collateral and the authority withdraws it", async () => {
+      const maker = Keypair.generate();
+      const taker = Keypair.generate();
+      await airdrop(provider, maker.publicKey);
+      await airdrop(provider, taker.publicKey);
+      
+      const { pdas } = await createTestMarket(program, provider, null, {
+        takerFeeBps: new anchor.BN(100), // 1%
+      });
+      
+      await placeOrder(program, provider, pdas, maker, 1, true, 5000, 100);
+      await placeOrder(program, provider, pdas, taker, 0, true, 5000, 100);
+      
+      // 1% of 5000 * 100 lamports of filled collateral
+      let market = await program.account.clobMarket.fetch(pdas.marketPda);
+      assert.equal(market.feesAccrued.toNumber(), 5000);
+      
+      const vaultBefore = await provider.connection.getBalance(pdas.vaultPda);
+      await program.methods
+        .withdrawClobFees()
+        .accounts({
+          market: pdas.marketPda,
+          vault: pdas.vaultPda,
+          destination: provider.wallet.publicKey,
+          authority: provider.wallet.publicKey,
+        })
+        .rpc();
+      const vaultAfter = await provider.connection.getBalance(pdas.vaultPda);
+      
+      market = await program.account.clobMarket.fetch(pdas.marketPda);
+      assert.equal(market.feesAccrued.toNumber(), 0);
+      assert.equal(vaultBefore - vaultAfter, 5000);
+      console.log(`  ✓ Withdrew ${vaultBefore - vaultAfter} lamports of taker fees`);
+    });
+    
+    it("13.2 Rejects fee withdrawal when nothing has accrued", async () => {
+      const { pdas } = await createTestMarket(program, provider, null, {
+        takerFeeBps: new anchor.BN(100),
+      });
+      
+      try {
+        await program.methods
+          .withdrawClobFees()
+          .accounts({
+            market: pdas.marketPda,
+            vault: pdas.vaultPda,
+            destination: provider.wallet.publicKey,
+            authority: provider.wallet.publicKey,
+          })
+          .rpc();
+        assert.fail("Should have rejected empty withdrawal");
+      } catch (err) {
+        assert(err.error?.errorCode?.code === "NoFeesToWithdraw");
+        console.log("  ✓ Empty fee withdrawal rejected");
+      }
+    });
+  });
+  
+  // =========================================
+  // 14. ORDER EXPIRY
+  // =========================================
+  describe("14. Order Expiry", () => {
+    
+    it("14.1 Expired asks are skipped by matching and refundable on cancel", async () => {
+      const maker = Keypair.generate();
+      const taker = Keypair.generate();
+      await airdrop(provider, maker.publicKey);
+      await airdrop(provider, taker.publicKey);
+      
+      const { pdas } = await createTestMarket(program, provider);
+      
+      const expiry = Math.floor(Date.now() / 1000) + 2;
+      await placeOrder(program, provider, pdas, maker, 1, true, 5000, 50, { expiry });
+      
+      await sleep(4000);
+      
+      // The only ask has expired, so this bid rests instead of filling
+      await placeOrder(program, provider, pdas, taker, 0, true, 5000, 50);
+      
+      const book = await program.account.orderBook.fetch(pdas.orderBookPda);
+      assert.equal(book.yesAsks.length, 1, "expired ask stays until cancelled");
+      assert.equal(book.yesAsks[0].size.toNumber(), 50);
+      assert.equal(book.yesBids.length, 1, "bid did not match the expired ask");
+      
+      const vaultBefore = await provider.connection.getBalance(pdas.vaultPda);
+      await program.methods
+        .cancelOrder(false, 0)
+        .accounts({
+          market: pdas.marketPda,
+          orderBook: pdas.orderBookPda,
+          vault: pdas.vaultPda,
+          trader: maker.publicKey,
+        })
+        .signers([maker])
+        .rpc();
+      const vaultAfter = await provider.connection.getBalance(pdas.vaultPda);
+      
+      assert.equal(vaultBefore - vaultAfter, (SHARE_PAYOUT - 5000) * 50);
+      console.log(`  ✓ Expired ask refunded ${vaultBefore - vaultAfter} lamports on cancel`);
+    });
+    
+    it("14.2 Rejects an expiry that is already in the past", async () => {
+      const { pdas } = await createTestMarket(program, provider);
+      
+      try {
+        await placeOrder(program, provider, pdas, null, 0, true, 5000, 10, {
+          expiry: Math.floor(Date.now() / 1000) - 60,
+        });
+        assert.fail("Should have rejected past expiry");
+      } catch (err) {
+        assert(err.error?.errorCode?.code === "InvalidExpiry");
+        console.log("  ✓ Past expiry rejected");
+      }
+    });
+  });
+  
+  // =========================================
+  // 15. IMMEDIATE-OR-CANCEL / FILL-OR-KILL
+  // =========================================
+  describe("15. Immediate-or-Cancel / Fill-or-Kill", () => {
+    
+    it("15.1 IOC fills what it can and refunds the remainder", async () => {
+      const maker = Keypair.generate();
+      const taker = Keypair.generate();
+      await airdrop(provider, maker.publicKey);
+      await airdrop(provider, taker.publicKey);
+      
+      const { pdas } = await createTestMarket(program, provider);
+      await placeOrder(program, provider, pdas, maker, 1, true, 5000, 30);
+      
+      const vaultBefore = await provider.connection.getBalance(pdas.vaultPda);
+      await placeOrder(program, provider, pdas, taker, 0, true, 5000, 100, {
+        orderType: ORDER_TYPE.IOC,
+      });
+      const vaultAfter = await provider.connection.getBalance(pdas.vaultPda);
+      
+      const book = await program.account.orderBook.fetch(pdas.orderBookPda);
+      assert.equal(book.yesBids.length, 0, "IOC remainder must not rest");
+      assert.equal(book.yesAsks.length, 0);
+      
+      const position = await program.account.clobPosition.fetch(
+        getPositionPda(program, pdas.marketPda, taker.publicKey)
+      );
+      assert.equal(position.yesShares.toNumber(), 30);
+      assert.equal(vaultAfter - vaultBefore, 5000 * 30, "only filled collateral stays");
+      console.log("  ✓ IOC filled 30 of 100 and refunded the rest");
+    });
+    
+    it("15.2 FOK reverts when the full size is not available", async () => {
+      const maker = Keypair.generate();
+      const taker = Keypair.generate();
+      await airdrop(provider, maker.publicKey);
+      await airdrop(provider, taker.publicKey);
+      
+      const { pdas } = await createTestMarket(program, provider);
+      await placeOrder(program, provider, pdas, maker, 1, true, 5000, 30);
+      
+      try {
+        await placeOrder(program, provider, pdas, taker, 0, true, 5000, 100, {
+          orderType: ORDER_TYPE.FOK,
+        });
+        assert.fail("Should have rejected unfillable FOK");
+      } catch (err) {
+        assert(err.error?.errorCode?.code === "FillOrKillUnfilled");
+      }
+      
+      const book = await program.account.orderBook.fetch(pdas.orderBookPda);
+      assert.equal(book.yesAsks[0].size.toNumber(), 30, "resting ask untouched");
+      
+      await placeOrder(program, provider, pdas, taker, 0, true, 5000, 30, {
+        orderType: ORDER_TYPE.FOK,
+      });
+      const after = await program.account.orderBook.fetch(pdas.orderBookPda);
+      assert.equal(after.yesAsks.length, 0);
+      console.log("  ✓ FOK reverted when short, filled when fully available");
+    });
+  });
+  
+  // =========================================
+  // 16. POST-ONLY ORDERS
+  // =========================================
+  describe("16. Post-Only Orders", () => {
+    
+    it("16.1 Rejects a post-only bid at 5100 when the best ask is 5000", async () => {
+      const maker = Keypair.generate();
+      await airdrop(provider, maker.publicKey);
+      
+      const { pdas } = await createTestMarket(program, provider);
+      await placeOrder(program, provider, pdas, maker, 1, true, 5000, 50);
+      
+      try {
+        await placeOrder(program, provider, pdas, null, 0, true, 5100, 50, {
+          orderType: ORDER_TYPE.POST_ONLY,
+        });
+        assert.fail("Should have rejected crossing post-only bid");
+      } catch (err) {
+        assert(err.error?.errorCode?.code === "PostOnlyWouldCross");
+      }
+      
+      const book = await program.account.orderBook.fetch(pdas.orderBookPda);
+      assert.equal(book.yesAsks[0].size.toNumber(), 50, "ask untouched");
+      assert.equal(book.yesBids.length, 0);
+      console.log("  ✓ Crossing post-only bid rejected");
+    });
+    
+    it("16.2 Rests a post-only bid below the best ask", async () => {
+      const maker = Keypair.generate();
+      await airdrop(provider, maker.publicKey);
+      
+      const { pdas } = await createTestMarket(program, provider);
+      await placeOrder(program, provider, pdas, maker, 1, true, 5000, 50);
+      await placeOrder(program, provider, pdas, null, 0, true, 4900, 50, {
+        orderType: ORDER_TYPE.POST_ONLY,
+      });
+      
+      const book = await program.account.orderBook.fetch(pdas.orderBookPda);
+      assert.equal(book.yesBids.length, 1);
+      assert.equal(book.yesBids[0].price.toNumber(), 4900);
+      console.log("  ✓ Non-crossing post-only bid rests");
+    });
+  });
+  
+  // =========================================
+  // 17. SELF-TRADE PREVENTION
+  // =========================================
+  describe("17. Self-Trade Prevention", () => {
+    
+    it("17.1 Skips the taker's own ask and keeps matching past it", async () => {
+      const trader = Keypair.generate();
+      const maker = Keypair.generate();
+      await airdrop(provider, trader.publicKey);
+      await airdrop(provider, maker.publicKey);
+      
+      const { pdas } = await createTestMarket(program, provider);
+      await placeOrder(program, provider, pdas, trader, 1, true, 5000, 50);
+      await placeOrder(program, provider, pdas, maker, 1, true, 5100, 50);
+      
+      await placeOrder(program, provider, pdas, trader, 0, true, 5200, 50);
+      
+      const book = await program.account.orderBook.fetch(pdas.orderBookPda);
+      assert.equal(book.yesAsks.length, 1, "only the own ask remains");
+      assert(book.yesAsks[0].owner.equals(trader.publicKey));
+      assert.equal(book.yesAsks[0].size.toNumber(), 50);
+      assert.equal(book.yesBids.length, 0, "bid filled fully against the other maker");
+      
+      const position = await program.account.clobPosition.fetch(
+        getPositionPda(program, pdas.marketPda, trader.publicKey)
+      );
+      assert.equal(position.yesShares.toNumber(), 50);
+      console.log("  ✓ Own ask skipped, filled against the next level");
+    });
+  });
+  
+  // =========================================
+  // 18. RECLAIMING ORDERS AFTER RESOLUTION
+  // =========================================
+  describe("18. Reclaiming Orders After Resolution", () => {
+    
+    it("18.1 Resting bid and ask collateral is refundable once resolved", async () => {
+      const trader = Keypair.generate();
+      await airdrop(provider, trader.publicKey);
+      
+      const { pdas } = await createTestMarket(program, provider);
+      await placeOrder(program, provider, pdas, trader, 0, true, 4000, 20);
+      await placeOrder(program, provider, pdas, trader, 1, true, 6000, 30);
+      
+      await program.methods
+        .resolveClobMarket(1)
+        .accounts({
+          market: pdas.marketPda,
+          authority: authority.publicKey,
+        })
+        .rpc();
+      
+      for (const [isBid, expected] of [[true, 4000 * 20], [false, (SHARE_PAYOUT - 6000) * 30]]) {
+        const vaultBefore = await provider.connection.getBalance(pdas.vaultPda);
+        await program.methods
+          .cancelOrder(isBid, 0)
+          .accounts({
+            market: pdas.marketPda,
+            orderBook: pdas.orderBookPda,
+            vault: pdas.vaultPda,
+            trader: trader.publicKey,
+          })
+          .signers([trader])
+          .rpc();
+        const vaultAfter = await provider.connection.getBalance(pdas.vaultPda);
+        assert.equal(vaultBefore - vaultAfter, expected);
+      }
+      
+      const book = await program.account.orderBook.fetch(pdas.orderBookPda);
+      assert.equal(book.yesBids.length + book.yesAsks.length, 0);
+      console.log("  ✓ Post-resolution cancels refunded the reserved collateral");
+    });
+  });
+  
+  // =========================================
+  // 19. CANCEL ALL ORDERS
+  // =========================================
+  describe("19. Cancel All Orders", () => {
+    
+    it("19.1 Removes only the trader's orders and refunds their total", async () => {
+      const trader = Keypair.generate();
+      const other = Keypair.generate();
+      await airdrop(provider, trader.publicKey);
+      await airdrop(provider, other.publicKey);
+      
+      const { pdas } = await createTestMarket(program, provider);
+      // Interleave owners so adjacent removals can't skip elements
+      await placeOrder(program, provider, pdas, trader, 0, true, 4000, 10);
+      await placeOrder(program, provider, pdas, trader, 0, true, 4000, 10);
+      await placeOrder(program, provider, pdas, other, 0, true, 3900, 10);
+      await placeOrder(program, provider, pdas, trader, 0, true, 3800, 10);
+      await placeOrder(program, provider, pdas, trader, 1, true, 6000, 5);
+      await placeOrder(program, provider, pdas, other, 1, true, 6100, 5);
+      
+      const expected = 4000 * 10 * 2 + 3800 * 10 + (SHARE_PAYOUT - 6000) * 5;
+      
+      const vaultBefore = await provider.connection.getBalance(pdas.vaultPda);
+      await program.methods
+        .cancelAllOrders()
+        .accounts({
+          market: pdas.marketPda,
+          orderBook: pdas.orderBookPda,
+          vault: pdas.vaultPda,
+          trader: trader.publicKey,
+        })
+        .signers([trader])
+        .rpc();
+      const vaultAfter = await provider.connection.getBalance(pdas.vaultPda);
+      
+      assert.equal(vaultBefore - vaultAfter, expected);
+      
+      const book = await program.account.orderBook.fetch(pdas.orderBookPda);
+      assert.equal(book.yesBids.length, 1);
+      assert.equal(book.yesAsks.length, 1);
+      assert(book.yesBids[0].owner.equals(other.publicKey));
+      assert(book.yesAsks[0].owner.equals(other.publicKey));
+      console.log(`  ✓ Cancelled 4 orders, refunded ${expected} lamports`);
+    });
+  });
+  
+  // =========================================
+  // 20. ORDER IDS
+  // =========================================
+  describe("20. Order IDs", () => {
+    
+    it("20.1 Orders placed in the same second get unique sequential IDs", async () => {
+      const { pdas } = await createTestMarket(program, provider);
+      
+      await Promise.all([
+        placeOrder(program, provider, pdas, null, 0, true, 4000, 10),
+        placeOrder(program, provider, pdas, null, 0, true, 4100, 10),
+        placeOrder(program, provider, pdas, null, 1, true, 6000, 10),
+      ]);
+      
+      const book = await program.account.orderBook.fetch(pdas.orderBookPda);
+      const ids = [...book.yesBids, ...book.yesAsks].map(o => o.orderId.toNumber()).sort();
+      assert.deepEqual(ids, [0, 1, 2]);
+      assert.equal(book.orderSeq.toNumber(), 3);
+      console.log(`  ✓ Order IDs ${ids.join(", ")} are unique`);
+    });
+  });
+  
+  // =========================================
+  // 21. CANCEL BY ORDER ID
+  // =========================================
+  describe("21. Cancel By Order ID", () => {
+    
+    it("21.1 Cancels the right order after indices shift", async () => {
+      const trader = Keypair.generate();
+      const other = Keypair.generate();
+      await airdrop(provider, trader.publicKey);
+      await airdrop(provider, other.publicKey);
+      
+      const { pdas } = await createTestMarket(program, provider);
+      await placeOrder(program, provider, pdas, trader, 0, true, 4000, 10);
+      await placeOrder(program, provider, pdas, trader, 0, true, 3000, 10);
+      
+      let book = await program.account.orderBook.fetch(pdas.orderBookPda);
+      const targetId = book.yesBids[1].orderId;
+      
+      // A better bid lands ahead of ours and shifts every index
+      await placeOrder(program, provider, pdas, other, 0, true, 4500, 10);
+      
+      const cancelById = (signer, orderId) =>
+        program.methods
+          .cancelOrderById(orderId)
+          .accounts({
+            market: pdas.marketPda,
+            orderBook: pdas.orderBookPda,
+            vault: pdas.vaultPda,
+            trader: signer.publicKey,
+          })
+          .signers([signer])
+          .rpc();
+      
+      const vaultBefore = await provider.connection.getBalance(pdas.vaultPda);
+      await cancelById(trader, targetId);
+      const vaultAfter = await provider.connection.getBalance(pdas.vaultPda);
+      assert.equal(vaultBefore - vaultAfter, 3000 * 10);
+      
+      book = await program.account.orderBook.fetch(pdas.orderBookPda);
+      assert.deepEqual(book.yesBids.map(o => o.price.toNumber()), [4500, 4000]);
+      
+      // Someone else's order ID is not found for this trader
+      try {
+        await cancelById(trader, book.yesBids[0].orderId);
+        assert.fail("Should not cancel another trader's order");
+      } catch (err) {
+        assert(err.error?.errorCode?.code === "OrderNotFound");
+      }
+      console.log("  ✓ Cancelled by ID despite shifted indices");
+    });
+  });
+  
+  // =========================================
+  // 22. TOKEN COLLATERAL
+  // =========================================
+  describe("22. Token Collateral", () => {
+    
+    it("22.1 Trades and settles in the market's SPL token", async () => {
+      const payer = authority.payer;
+      const mint = await createMint(provider.connection, payer, authority.publicKey, null, 6);
+      const payoutPerShare = 1_000_000; // 1 token per winning share
+      const unit = payoutPerShare / BPS_MAX;
+      
+      const id = generateMarketId();
+      const { marketPda, orderBookPda } = getPDAs(program, id);
+      const vault = getAssociatedTokenAddressSync(mint, marketPda, true);
+      
+      await program.methods
+        .createTokenClobMarket(
+          id,
+          "Token CLOB",
+          new anchor.BN(Math.floor(Date.now() / 1000) + 3600),
+          { ...defaultClobConfig(), payoutPerShare: new anchor.BN(payoutPerShare) }
+        )
+        .accounts({ market: marketPda, orderBook: orderBookPda, mint, vault, authority: authority.publicKey })
+        .rpc();
+      
+      const traders = {};
+      for (const name of ["maker", "taker"]) {
+        const user = Keypair.generate();
+        await airdrop(provider, user.publicKey);
+        const tokenAccount = await createAssociatedTokenAccount(provider.connection, payer, mint, user.publicKey);
+        await mintTo(provider.connection, payer, mint, tokenAccount, payer, 10 * payoutPerShare);
+        traders[name] = { user, tokenAccount };
+      }
+      
+      const placeTokenOrder = ({ user, tokenAccount }, side, price, size) =>
+        program.methods
+          .placeOrderToken(side, true, new anchor.BN(price), new anchor.BN(size), ORDER_TYPE.LIMIT, new anchor.BN(0), new anchor.BN(0), new anchor.BN(0), false)
+          .accounts({
+            market: marketPda,
+            mint,
+            orderBook: orderBookPda,
+            vault,
+            traderTokenAccount: tokenAccount,
+            position: getPDAs(program, id, user.publicKey).positionPda,
+            trader: user.publicKey,
+          })
+          .signers([user])
+          .rpc();
+      
+      await placeTokenOrder(traders.maker, 1, 6000, 5);
+      await placeTokenOrder(traders.taker, 0, 6000, 5);
+      
+      // Both sides of 5 shares are fully collateralized in tokens
+      assert.equal(Number((await getAccount(provider.connection, vault)).amount), 5 * payoutPerShare);
+      assert.equal(
+        Number((await getAccount(provider.connection, traders.taker.tokenAccount)).amount),
+        10 * payoutPerShare - 6000 * 5 * unit
+      );
+      
+      // The lamport path refuses token markets
+      try {
+        await placeOrder(program, provider, getPDAs(program, id), traders.taker.user, 0, true, 5000, 1);
+        assert.fail("Lamport order on a token market should fail");
+      } catch (err) {
+        assert(err.error?.errorCode?.code === "CollateralMismatch");
+      }
+      
+      await program.methods
+        .resolveClobMarket(0)
+        .accounts({ market: marketPda, authority: authority.publicKey })
+        .rpc();
+      
+      await program.methods
+        .claimClobWinningsToken(new anchor.BN(0))
+        .accounts({
+          market: marketPda,
+          mint,
+          vault,
+          position: getPDAs(program, id, traders.taker.user.publicKey).positionPda,
+          claimerTokenAccount: traders.taker.tokenAccount,
+          claimer: traders.taker.user.publicKey,
+        })
+        .signers([traders.taker.user])
+        .rpc();
+      
+      assert.equal(Number((await getAccount(provider.connection, vault)).amount), 0);
+      assert.equal(
+        Number((await getAccount(provider.connection, traders.taker.tokenAccount)).amount),
+        10 * payoutPerShare + 4000 * 5 * unit
+      );
+      console.log("  ✓ Token market collateralized and paid out exactly");
+    });
+    
+    it("22.2 Rejects a payout per share that isn't a multiple of 10000", async () => {
+      try {
+        await createTestMarket(program, provider, null, { payoutPerShare: new anchor.BN(15_000) });
+        assert.fail("Should reject payout per share");
+      } catch (err) {
+        assert(err.error?.errorCode?.code === "InvalidPayoutPerShare");
+      }
+    });
+    
+    it("22.3 Lamport markets scale collateral, refunds and payouts with payout_per_share", async () => {
+      const payoutPerShare = 2 * SHARE_PAYOUT;
+      const unit = payoutPerShare / BPS_MAX;
+      const { pdas } = await createTestMarket(program, provider, null, {
+        payoutPerShare: new anchor.BN(payoutPerShare),
+      });
+      const maker = Keypair.generate();
+      await airdrop(provider, maker.publicKey);
+      
+      let vaultBefore = await provider.connection.getBalance(pdas.vaultPda);
+      await placeOrder(program, provider, pdas, maker, 1, true, 6000, 10);
+      let vaultAfter = await provider.connection.getBalance(pdas.vaultPda);
+      assert.equal(vaultAfter - vaultBefore, 4000 * 10 * unit);
+      
+      await placeOrder(program, provider, pdas, null, 0, true, 6000, 10);
+      await program.methods
+        .resolveClobMarket(0)
+        .accounts({ market: pdas.marketPda, authority: provider.wallet.publicKey })
+        .rpc();
+      vaultBefore = await provider.connection.getBalance(pdas.vaultPda);
+      await program.methods
+        .claimClobWinnings(new anchor.BN(0))
+        .accounts({
+          market: pdas.marketPda,
+          vault: pdas.vaultPda,
+          position: getPositionPda(program, pdas.marketPda, provider.wallet.publicKey),
+          claimer: provider.wallet.publicKey,
+        })
+        .rpc();
+      vaultAfter = await provider.connection.getBalance(pdas.vaultPda);
+      assert.equal(vaultBefore - vaultAfter, 10 * payoutPerShare);
+      console.log("  ✓ Double-size shares collateralized and paid out exactly");
+    });
+  });
+  
+  // =========================================
+  // 23. VAULT SOLVENCY ON CLAIM
+  // =========================================
+  describe("23. Vault Solvency On Claim", () => {
+    
+    it("23.1 Claims on an imbalanced book leave resting collateral refundable", async () => {
+      const maker = Keypair.generate();
+      const taker = Keypair.generate();
+      const bidder = Keypair.generate();
+      for (const kp of [maker, taker, bidder]) {
+        await airdrop(provider, kp.publicKey);
+      }
+      
+      const { pdas } = await createTestMarket(program, provider);
+      const { vaultReserve } = await program.account.clobMarket.fetch(pdas.marketPda);
+      
+      // Partial fill leaves resting collateral on both sides of the book
+      await placeOrder(program, provider, pdas, maker, 1, true, 5000, 100);
+      await placeOrder(program, provider, pdas, taker, 0, true, 5000, 60);
+      await placeOrder(program, provider, pdas, bidder, 0, true, 4000, 30);
+      
+      await program.methods
+        .resolveClobMarket(0)
+        .accounts({ market: pdas.marketPda, authority: authority.publicKey })
+        .rpc();
+      
+      const claim = (signer) =>
+        program.methods
+          .claimClobWinnings(new anchor.BN(0))
+          .accounts({
+            market: pdas.marketPda,
+            vault: pdas.vaultPda,
+            position: getPositionPda(program, pdas.marketPda, signer.publicKey),
+            claimer: signer.publicKey,
+          })
+          .signers([signer])
+          .rpc();
+      await claim(taker);
+      
+      // The winning claim is paid without touching resting collateral or
+      // the reserve; a vault that couldn't cover it would fail with
+      // VaultInsolvent before any lamports move
+      const state = await getMarketState(program, provider, pdas, []);
+      assertVaultSolvency(state, "after claim");
+      assert(state.vaultBalance >= vaultReserve.toNumber() + calculateRestingCollateral(state.orderBook));
+      
+      for (const trader of [maker, bidder]) {
+        await program.methods
+          .cancelAllOrders()
+          .accounts({
+            market: pdas.marketPda,
+            orderBook: pdas.orderBookPda,
+            vault: pdas.vaultPda,
+            trader: trader.publicKey,
+          })
+          .signers([trader])
+          .rpc();
+      }
+      assert(await provider.connection.getBalance(pdas.vaultPda) >= vaultReserve.toNumber());
+      console.log("  ✓ Claim and post-resolution refunds stayed within the vault");
+    });
+  });
+  
+  // =========================================
+  // 24. MANUAL VOID
+  // =========================================
+  describe("24. Manual Void", () => {
+    
+    it("24.1 A voided market pays half per share and keeps resting orders refundable", async () => {
+      const maker = Keypair.generate();
+      const taker = Keypair.generate();
+      await airdrop(provider, maker.publicKey);
+      await airdrop(provider, taker.publicKey);
+      
+      const { pdas } = await createTestMarket(program, provider);
+      await placeOrder(program, provider, pdas, maker, 1, true, 6000, 30);
+      await placeOrder(program, provider, pdas, taker, 0, true, 6000, 20);
+      
+      await program.methods
+        .voidClobMarket()
+        .accounts({ market: pdas.marketPda, authority: authority.publicKey })
+        .rpc();
+      
+      // Voiding and resolving are mutually exclusive
+      try {
+        await program.methods
+          .resolveClobMarket(0)
+          .accounts({ market: pdas.marketPda, authority: authority.publicKey })
+          .rpc();
+        assert.fail("Resolving a voided market should fail");
+      } catch (err) {
+        assert(err.error?.errorCode?.code === "MarketVoid");
+      }
+      try {
+        await placeOrder(program, provider, pdas, taker, 0, true, 6000, 1);
+        assert.fail("Trading on a voided market should fail");
+      } catch (err) {
+        assert(err.error?.errorCode?.code === "MarketVoid");
+      }
+      
+      const vaultBefore = await provider.connection.getBalance(pdas.vaultPda);
+      await program.methods
+        .claimVoidRefund()
+        .accounts({
+          market: pdas.marketPda,
+          vault: pdas.vaultPda,
+          position: getPositionPda(program, pdas.marketPda, taker.publicKey),
+          claimer: taker.publicKey,
+        })
+        .signers([taker])
+        .rpc();
+      const vaultAfterClaim = await provider.connection.getBalance(pdas.vaultPda);
+      assert.equal(vaultBefore - vaultAfterClaim, (20 * SHARE_PAYOUT) / 2);
+      
+      // The maker's unfilled 10 shares are still on the book
+      await program.methods
+        .cancelAllOrders()
+        .accounts({
+          market: pdas.marketPda,
+          orderBook: pdas.orderBookPda,
+          vault: pdas.vaultPda,
+          trader: maker.publicKey,
+        })
+        .signers([maker])
+        .rpc();
+      const vaultAfterCancel = await provider.connection.getBalance(pdas.vaultPda);
+      assert.equal(vaultAfterClaim - vaultAfterCancel, (BPS_MAX - 6000) * 10);
+      console.log("  ✓ Void refund and resting collateral both paid out");
+    });
+    
+    it("24.2 A resolved market can't be voided", async () => {
+      const { pdas } = await createTestMarket(program, provider);
+      await program.methods
+        .resolveClobMarket(1)
+        .accounts({ market: pdas.marketPda, authority: authority.publicKey })
+        .rpc();
+      
+      try {
+        await program.methods
+          .voidClobMarket()
+          .accounts({ market: pdas.marketPda, authority: authority.publicKey })
+          .rpc();
+        assert.fail("Voiding a resolved market should fail");
+      } catch (err) {
+        assert(err.error?.errorCode?.code === "AlreadyResolved");
+      }
+    });
+  });
+  
+  // =========================================
+  // 25. RESOLUTION TIME UPDATES
+  // =========================================
+  describe("25. Resolution Time Updates", () => {
+    
+    it("25.1 Extends resolution_time and rejects moving it earlier", async () => {
+      const resolutionTime = Math.floor(Date.now() / 1000) + 3600;
+      const { pdas } = await createTestMarket(program, provider, null, {}, resolutionTime);
+      
+      const update = (time) =>
+        program.methods
+          .updateClobResolutionTime(new anchor.BN(time))
+          .accounts({ market: pdas.marketPda, authority: authority.publicKey })
+          .rpc({ commitment: "confirmed" });
+      
+      const sig = await update(resolutionTime + 600);
+      const [event] = findEvents(await getEvents(program, provider, sig), "ResolutionTimeUpdated");
+      assert.equal(event.data.previousResolutionTime.toNumber(), resolutionTime);
+      assert.equal(event.data.resolutionTime.toNumber(), resolutionTime + 600);
+      
+      try {
+        await update(resolutionTime + 300);
+        assert.fail("Moving resolution_time earlier should fail");
+      } catch (err) {
+        assert(err.error?.errorCode?.code === "InvalidResolutionTime");
+      }
+      
+      const market = await program.account.clobMarket.fetch(pdas.marketPda);
+      assert.equal(market.resolutionTime.toNumber(), resolutionTime + 600);
+      console.log("  ✓ Resolution time extended, shortening rejected");
+    });
+  });
+  
+  // =========================================
+  // 26. AUTHORITY TRANSFER
+  // =========================================
+  describe("26. Authority Transfer", () => {
+    
+    it("26.1 The proposed authority must sign to take over", async () => {
+      const { pdas } = await createTestMarket(program, provider);
+      const newAuthority = Keypair.generate();
+      
+      await program.methods
+        .proposeClobAuthority(newAuthority.publicKey)
+        .accounts({ market: pdas.marketPda, authority: authority.publicKey })
+        .rpc();
+      
+      let market = await program.account.clobMarket.fetch(pdas.marketPda);
+      assert(market.authority.equals(authority.publicKey));
+      
+      await program.methods
+        .acceptClobAuthority()
+        .accounts({ market: pdas.marketPda, newAuthority: newAuthority.publicKey })
+        .signers([newAuthority])
+        .rpc();
+      
+      market = await program.account.clobMarket.fetch(pdas.marketPda);
+      assert(market.authority.equals(newAuthority.publicKey));
+      assert(market.pendingAuthority === null);
+      
+      // A second accept has nothing pending
+      try {
+        await program.methods
+          .acceptClobAuthority()
+          .accounts({ market: pdas.marketPda, newAuthority: newAuthority.publicKey })
+          .signers([newAuthority])
+          .rpc();
+        assert.fail("Nothing should be pending");
+      } catch (err) {
+        assert(err.error?.errorCode?.code === "NotPendingAuthority");
+      }
+      console.log("  ✓ CLOB authority transferred after acceptance");
+    });
+  });
+  
+  // =========================================
+  // 27. PAUSE
+  // =========================================
+  describe("27. Pause", () => {
+    
+    it("27.1 Rejects new orders while paused but allows cancels and claims", async () => {
+      const maker = Keypair.generate();
+      const taker = Keypair.generate();
+      await airdrop(provider, maker.publicKey);
+      await airdrop(provider, taker.publicKey);
+      
+      const { pdas } = await createTestMarket(program, provider);
+      await placeOrder(program, provider, pdas, maker, 1, true, 5000, 20);
+      await placeOrder(program, provider, pdas, taker, 0, true, 5000, 10);
+      
+      await program.methods
+        .pauseClobMarket()
+        .accounts({ market: pdas.marketPda, authority: authority.publicKey })
+        .rpc();
+      
+      try {
+        await placeOrder(program, provider, pdas, taker, 0, true, 5000, 10);
+        assert.fail("Order while paused should fail");
+      } catch (err) {
+        assert(err.error?.errorCode?.code === "MarketPaused");
+      }
+      
+      // The maker can still pull the unfilled remainder
+      await program.methods
+        .cancelAllOrders()
+        .accounts({
+          market: pdas.marketPda,
+          orderBook: pdas.orderBookPda,
+          vault: pdas.vaultPda,
+          trader: maker.publicKey,
+        })
+        .signers([maker])
+        .rpc();
+      
+      await program.methods
+        .resolveClobMarket(0)
+        .accounts({ market: pdas.marketPda, authority: authority.publicKey })
+        .rpc();
+      await program.methods
+        .claimClobWinnings(new anchor.BN(0))
+        .accounts({
+          market: pdas.marketPda,
+          vault: pdas.vaultPda,
+          position: getPositionPda(program, pdas.marketPda, taker.publicKey),
+          claimer: taker.publicKey,
+        })
+        .signers([taker])
+        .rpc();
+      
+      const position = await program.account.clobPosition.fetch(
+        getPositionPda(program, pdas.marketPda, taker.publicKey)
+      );
+      assert.equal(position.yesShares.toNumber(), 0);
+      console.log("  ✓ Paused market blocked orders, exits still worked");
+    });
+  });
+  
+  // =========================================
+  // 28. TOP OF BOOK
+  // =========================================
+  describe("28. Top Of Book", () => {
+    
+    it("28.1 Reports best bid/ask with level size, None when a side is empty", async () => {
+      const { pdas } = await createTestMarket(program, provider);
+      const topOfBook = () =>
+        program.methods
+          .getTopOfBook()
+          .accounts({ market: pdas.marketPda, orderBook: pdas.orderBookPda })
+          .view();
+      
+      let top = await topOfBook();
+      assert.equal(top.bestBid, null);
+      assert.equal(top.bestAsk, null);
+      assert.equal(top.bestBidSize.toNumber(), 0);
+      assert.equal(top.bestAskSize.toNumber(), 0);
+      
+      await placeOrder(program, provider, pdas, null, 0, true, 4000, 10);
+      await placeOrder(program, provider, pdas, null, 0, true, 4000, 5);
+      await placeOrder(program, provider, pdas, null, 0, true, 3500, 50);
+      await placeOrder(program, provider, pdas, null, 1, true, 6000, 7);
+      
+      top = await topOfBook();
+      assert.equal(top.bestBid.toNumber(), 4000);
+      assert.equal(top.bestBidSize.toNumber(), 15);
+      assert.equal(top.bestAsk.toNumber(), 6000);
+      assert.equal(top.bestAskSize.toNumber(), 7);
+      console.log("  ✓ Top of book: 15 @ 4000 / 7 @ 6000");
+    });
+  });
+  
+  // =========================================
+  // 29. MID PRICE
+  // =========================================
+  describe("29. Mid Price", () => {
+    
+    it("29.1 Mid price falls back to one side and tracks the last trade", async () => {
+      const taker = Keypair.generate();
+      await airdrop(provider, taker.publicKey);
+      
+      const { pdas } = await createTestMarket(program, provider);
+      const midPrice = () =>
+        program.methods
+          .getMidPrice()
+          .accounts({ market: pdas.marketPda, orderBook: pdas.orderBookPda })
+          .view();
+      
+      let mid = await midPrice();
+      assert.equal(mid.midPrice, null);
+      assert.equal(mid.lastTradePrice.toNumber(), 0);
+      
+      // One-sided book quotes the lone bid
+      await placeOrder(program, provider, pdas, null, 0, true, 4000, 10);
+      mid = await midPrice();
+      assert.equal(mid.midPrice.toNumber(), 4000);
+      
+      await placeOrder(program, provider, pdas, null, 1, true, 6000, 10);
+      mid = await midPrice();
+      assert.equal(mid.midPrice.toNumber(), 5000);
+      
+      // A taker lifts the ask, leaving only the bid
+      await placeOrder(program, provider, pdas, taker, 0, true, 6000, 10);
+      mid = await midPrice();
+      assert.equal(mid.lastTradePrice.toNumber(), 6000);
+      assert.equal(mid.midPrice.toNumber(), 4000);
+      console.log("  ✓ Mid price and last trade tracked");
+    });
+  });
+  
+  // =========================================
+  // 30. DEPTH AND OPEN INTEREST
+  // =========================================
+  describe("30. Depth And Open Interest", () => {
+    
+    it("30.1 Depth totals track the book and open interest tracks matched pairs", async () => {
+      const maker = Keypair.generate();
+      const taker = Keypair.generate();
+      await airdrop(provider, maker.publicKey);
+      await airdrop(provider, taker.publicKey);
+      
+      const { pdas } = await createTestMarket(program, provider);
+      const sumSizes = (orders) => orders.reduce((acc, o) => acc + o.size.toNumber(), 0);
+      const assertDepth = async () => {
+        const book = await program.account.orderBook.fetch(pdas.orderBookPda);
+        assert.equal(book.totalBidSize.toNumber(), sumSizes(book.yesBids));
+        assert.equal(book.totalAskSize.toNumber(), sumSizes(book.yesAsks));
+        return book;
+      };
+      
+      await placeOrder(program, provider, pdas, maker, 1, true, 6000, 30);
+      await placeOrder(program, provider, pdas, maker, 0, true, 4000, 20);
+      await placeOrder(program, provider, pdas, taker, 0, true, 6000, 12);
+      let book = await assertDepth();
+      assert.equal(book.totalAskSize.toNumber(), 18);
+      assert.equal(book.totalBidSize.toNumber(), 20);
+      
+      let market = await program.account.clobMarket.fetch(pdas.marketPda);
+      assert.equal(market.openInterest.toNumber(), 12);
+      
+      await program.methods
+        .cancelOrder(true, 0)
+        .accounts({
+          market: pdas.marketPda,
+          orderBook: pdas.orderBookPda,
+          vault: pdas.vaultPda,
+          trader: maker.publicKey,
+        })
+        .signers([maker])
+        .rpc();
+      book = await assertDepth();
+      assert.equal(book.totalBidSize.toNumber(), 0);
+      
+      await program.methods
+        .resolveClobMarket(0)
+        .accounts({ market: pdas.marketPda, authority: authority.publicKey })
+        .rpc();
+      await program.methods
+        .claimClobWinnings(new anchor.BN(0))
+        .accounts({
+          market: pdas.marketPda,
+          vault: pdas.vaultPda,
+          position: getPositionPda(program, pdas.marketPda, taker.publicKey),
+          claimer: taker.publicKey,
+        })
+        .signers([taker])
+        .rpc();
+      
+      market = await program.account.clobMarket.fetch(pdas.marketPda);
+      assert.equal(market.openInterest.toNumber(), 0);
+      console.log("  ✓ Depth and open interest stayed in step");
+    });
+  });
+  
+  // =========================================
+  // 31. BATCH ORDERS
+  // =========================================
+  describe("31. Batch Orders", () => {
+    
+    it("31.1 Places a quote ladder in one transaction with one deposit", async () => {
+      const maker = Keypair.generate();
+      const seller = Keypair.generate();
+      await airdrop(provider, maker.publicKey);
+      await airdrop(provider, seller.publicKey);
+      
+      const { pdas } = await createTestMarket(program, provider);
+      await placeOrder(program, provider, pdas, seller, 1, true, 4500, 5);
+      
+      const ladder = [
+        { side: 0, isYes: true, price: 4500, size: 5 }, // crosses the resting ask
+        { side: 0, isYes: true, price: 4000, size: 10 },
+        { side: 1, isYes: true, price: 6000, size: 10 },
+      ].map((o) => ({ ...o, price: new anchor.BN(o.price), size: new anchor.BN(o.size) }));
+      
+      const vaultBefore = await provider.connection.getBalance(pdas.vaultPda);
+      const sig = await program.methods
+        .placeOrders(ladder)
+        .accounts({
+          market: pdas.marketPda,
+          orderBook: pdas.orderBookPda,
+          vault: pdas.vaultPda,
+          position: getPositionPda(program, pdas.marketPda, maker.publicKey),
+          trader: maker.publicKey,
+        })
+        .signers([maker])
+        .rpc({ commitment: "confirmed" });
+      const vaultAfter = await provider.connection.getBalance(pdas.vaultPda);
+      
+      assert.equal(vaultAfter - vaultBefore, 4500 * 5 + 4000 * 10 + (BPS_MAX - 6000) * 10);
+      
+      const [event] = findEvents(await getEvents(program, provider, sig), "OrdersPlaced");
+      const summaries = event.data.summaries;
+      assert.equal(summaries[0].filled.toNumber(), 5);
+      assert.equal(summaries[0].restingOrderId, null);
+      assert.equal(summaries[1].filled.toNumber(), 0);
+      assert(summaries[1].restingOrderId !== null);
+      assert(summaries[2].restingOrderId !== null);
+      
+      const top = await program.methods
+        .getTopOfBook()
+        .accounts({ market: pdas.marketPda, orderBook: pdas.orderBookPda })
+        .view();
+      assert.equal(top.bestBid.toNumber(), 4000);
+      assert.equal(top.bestAsk.toNumber(), 6000);
+      console.log("  ✓ Ladder placed: 1 filled, 2 resting");
+    });
+    
+    it("31.2 Rejects an empty batch", async () => {
+      const { pdas } = await createTestMarket(program, provider);
+      try {
+        await program.methods
+          .placeOrders([])
+          .accounts({
+            market: pdas.marketPda,
+            orderBook: pdas.orderBookPda,
+            vault: pdas.vaultPda,
+            position: getPositionPda(program, pdas.marketPda, authority.publicKey),
+            trader: authority.publicKey,
+          })
+          .rpc();
+        assert.fail("Empty batch should fail");
+      } catch (err) {
+        assert(err.error?.errorCode?.code === "InvalidBatchSize");
+      }
+    });
+    
+    it("31.3 Leaves a refunded remainder out of the batch's realized stake", async () => {
+      const { pdas } = await createTestMarket(program, provider, null, {
+        minOrderSize: new anchor.BN(10),
+      });
+      const seller = Keypair.generate();
+      const buyer = Keypair.generate();
+      await airdrop(provider, seller.publicKey);
+      await airdrop(provider, buyer.publicKey);
+      await placeOrder(program, provider, pdas, seller, 1, true, 5000, 15);
+      
+      // 15 of 20 fill and the other 5 are below min_order_size, so refunded
+      await program.methods
+        .placeOrders([{ side: 0, isYes: true, price: new anchor.BN(5000), size: new anchor.BN(20) }])
+        .accounts({
+          market: pdas.marketPda,
+          orderBook: pdas.orderBookPda,
+          vault: pdas.vaultPda,
+          position: getPositionPda(program, pdas.marketPda, buyer.publicKey),
+          trader: buyer.publicKey,
+        })
+        .signers([buyer])
+        .rpc();
+      
+      const [pnlPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("realized_pnl"), buyer.publicKey.toBuffer()],
+        program.programId
+      );
+      const pnl = await program.account.realizedPnl.fetch(pnlPda);
+      assert.equal(pnl.totalStaked.toNumber(), calculateCollateral(0, 5000, 15));
+      console.log("  ✓ Only the 15 filled shares counted as staked");
+    });
+  });
+  
+  // =========================================
+  // 32. PRICE BANDS
+  // =========================================
+  describe("32. Price Bands", () => {
+    
+    it("32.1 Rejects orders whose YES price is outside the band", async () => {
+      const { pdas } = await createTestMarket(program, provider, null, {
+        minPrice: new anchor.BN(2000),
+        maxPrice: new anchor.BN(8000),
+      });
+      
+      for (const [isYes, price] of [[true, 1999], [true, 8001], [false, 8500]]) {
+        try {
+          await placeOrder(program, provider, pdas, null, 0, isYes, price, 1);
+          assert.fail(`Order at ${price} should be out of band`);
+        } catch (err) {
+          assert(err.error?.errorCode?.code === "PriceOutOfBand");
+        }
+      }
+      
+      // Band edges are inclusive; a NO bid at 7000 is a YES ask at 3000
+      await placeOrder(program, provider, pdas, null, 0, true, 2000, 1);
+      await placeOrder(program, provider, pdas, null, 1, true, 8000, 1);
+      await placeOrder(program, provider, pdas, null, 0, false, 7000, 1);
+      console.log("  ✓ Out-of-band orders rejected");
+    });
+    
+    it("32.2 Rejects an inverted band at creation", async () => {
+      try {
+        await createTestMarket(program, provider, null, {
+          minPrice: new anchor.BN(6000),
+          maxPrice: new anchor.BN(4000),
+        });
+        assert.fail("Inverted band should fail");
+      } catch (err) {
+        assert(err.error?.errorCode?.code === "InvalidPriceBand");
+      }
+    });
+  });
+  
+  // =========================================
+  // 33. LOT AND TICK SIZE
+  // =========================================
+  describe("33. Lot And Tick Size", () => {
+    
+    it("33.1 Enforces lot and tick multiples", async () => {
+      const { pdas } = await createTestMarket(program, provider, null, {
+        lotSize: new anchor.BN(10),
+        tickSize: new anchor.BN(100),
+      });
+      
+      try {
+        await placeOrder(program, provider, pdas, null, 0, true, 4000, 15);
+        assert.fail("Size off the lot grid should fail");
+      } catch (err) {
+        assert(err.error?.errorCode?.code === "InvalidLot");
+      }
+      try {
+        await placeOrder(program, provider, pdas, null, 0, true, 4050, 10);
+        assert.fail("Price off the tick grid should fail");
+      } catch (err) {
+        assert(err.error?.errorCode?.code === "InvalidTick");
+      }
+      
+      await placeOrder(program, provider, pdas, null, 0, true, 4100, 20);
+      const book = await program.account.orderBook.fetch(pdas.orderBookPda);
+      assert.equal(book.yesBids.length, 1);
+      console.log("  ✓ Orders must sit on the lot and tick grid");
+    });
+    
+    it("33.2 Rejects a tick size that doesn't divide 10000", async () => {
+      try {
+        await createTestMarket(program, provider, null, { tickSize: new anchor.BN(300) });
+        assert.fail("Tick size 300 should fail");
+      } catch (err) {
+        assert(err.error?.errorCode?.code === "InvalidTick");
+      }
+    });
+  });
+  
+  // =========================================
+  // 34. MIN FILL AND DUST
+  // =========================================
+  describe("34. Min Fill And Dust", () => {
+    
+    it("34.1 min_fill_size skips resting orders too small to fill", async () => {
+      const { pdas } = await createTestMarket(program, provider);
+      const small = Keypair.generate();
+      const large = Keypair.generate();
+      await airdrop(provider, small.publicKey);
+      await airdrop(provider, large.publicKey);
+      await placeOrder(program, provider, pdas, small, 1, true, 5000, 5);
+      await placeOrder(program, provider, pdas, large, 1, true, 5100, 50);
+      
+      await placeOrder(program, provider, pdas, null, 0, true, 5200, 20, {
+        orderType: ORDER_TYPE.IOC,
+        minFillSize: 10,
+      });
+      
+      const book = await program.account.orderBook.fetch(pdas.orderBookPda);
+      assert.equal(book.yesAsks.length, 2);
+      assert.equal(book.yesAsks[0].size.toNumber(), 5);
+      assert.equal(book.yesAsks[1].size.toNumber(), 30);
+      const position = await program.account.clobPosition.fetch(
+        getPositionPda(program, pdas.marketPda, provider.wallet.publicKey)
+      );
+      assert.equal(position.yesShares.toNumber(), 20);
+      
+      // FOK still has to fill completely, and the 5-share ask can't help it
+      try {
+        await placeOrder(program, provider, pdas, null, 0, true, 5200, 35, {
+          orderType: ORDER_TYPE.FOK,
+          minFillSize: 10,
+        });
+        assert.fail("FOK should fail when the skipped ask is needed");
+      } catch (err) {
+        assert(err.error?.errorCode?.code === "FillOrKillUnfilled");
+      }
+      console.log("  ✓ The 5-share ask was skipped, not filled");
+    });
+    
+    it("34.2 Cancels and refunds maker dust below min_order_size", async () => {
+      const { pdas } = await createTestMarket(program, provider, null, {
+        minOrderSize: new anchor.BN(10),
+      });
+      const maker = Keypair.generate();
+      await airdrop(provider, maker.publicKey);
+      
+      try {
+        await placeOrder(program, provider, pdas, maker, 1, true, 5000, 5);
+        assert.fail("Order below min_order_size should fail");
+      } catch (err) {
+        assert(err.error?.errorCode?.code === "BelowMinOrderSize");
+      }
+      await placeOrder(program, provider, pdas, maker, 1, true, 5000, 25);
+      
+      // Leaves 5 shares resting, so the maker's wallet has to come along
+      try {
+        await placeOrder(program, provider, pdas, null, 0, true, 5000, 20);
+        assert.fail("Dust refund without the owner's wallet should fail");
+      } catch (err) {
+        assert(err.error?.errorCode?.code === "DustOwnerMissing");
+      }
+      
+      const makerBefore = await provider.connection.getBalance(maker.publicKey);
+      await placeOrder(program, provider, pdas, null, 0, true, 5000, 20, {
+        remainingAccounts: [{ pubkey: maker.publicKey, isWritable: true, isSigner: false }],
+      });
+      const makerAfter = await provider.connection.getBalance(maker.publicKey);
+      
+      const book = await program.account.orderBook.fetch(pdas.orderBookPda);
+      assert.equal(book.yesAsks.length, 0);
+      assert.equal(book.totalAskSize.toNumber(), 0);
+      assert.equal(makerAfter - makerBefore, calculateCollateral(1, 5000, 5));
+      
+      const state = await getMarketState(program, provider, pdas, [maker.publicKey, provider.wallet.publicKey]);
+      assertVaultSolvency(state, "after dust cancel");
+      console.log("  ✓ 5-share dust cancelled and refunded to the maker");
+    });
+    
+    it("34.3 Refunds a taker remainder below min_order_size", async () => {
+      const { pdas } = await createTestMarket(program, provider, null, {
+        minOrderSize: new anchor.BN(10),
+      });
+      const maker = Keypair.generate();
+      await airdrop(provider, maker.publicKey);
+      await placeOrder(program, provider, pdas, maker, 1, true, 5000, 25);
+      
+      await placeOrder(program, provider, pdas, null, 0, true, 5000, 30);
+      
+      const book = await program.account.orderBook.fetch(pdas.orderBookPda);
+      assert.equal(book.yesAsks.length, 0);
+      assert.equal(book.yesBids.length, 0);
+      const position = await program.account.clobPosition.fetch(
+        getPositionPda(program, pdas.marketPda, provider.wallet.publicKey)
+      );
+      assert.equal(position.yesShares.toNumber(), 25);
+      console.log("  ✓ 5-share remainder refunded instead of resting");
+    });
+  });
+  
+  // =========================================
+  // 35. COMPLETE SETS
+  // =========================================
+  describe("35. Complete Sets", () => {
+    
+    async function completeSet(pdas, method, sets) {
+      const trader = provider.wallet.publicKey;
+      return program.methods[method](new anchor.BN(sets))
+        .accounts({
+          market: pdas.marketPda,
+          vault: pdas.vaultPda,
+          position: getPositionPda(program, pdas.marketPda, trader),
+          trader,
+          systemProgram: SystemProgram.programId,
+        })
+        .rpc();
+    }
+    
+    it("35.1 Mints and redeems YES/NO pairs at payout_per_share", async () => {
+      const { pdas } = await createTestMarket(program, provider);
+      const positionPda = getPositionPda(program, pdas.marketPda, provider.wallet.publicKey);
+      
+      const vaultBefore = await provider.connection.getBalance(pdas.vaultPda);
+      await completeSet(pdas, "mintCompleteSet", 10);
+      let position = await program.account.clobPosition.fetch(positionPda);
+      assert.equal(position.yesShares.toNumber(), 10);
+      assert.equal(position.noShares.toNumber(), 10);
+      assert.equal(
+        (await provider.connection.getBalance(pdas.vaultPda)) - vaultBefore,
+        10 * SHARE_PAYOUT
+      );
+      let market = await program.account.clobMarket.fetch(pdas.marketPda);
+      assert.equal(market.openInterest.toNumber(), 10);
+      
+      await completeSet(pdas, "redeemCompleteSet", 4);
+      position = await program.account.clobPosition.fetch(positionPda);
+      assert.equal(position.yesShares.toNumber(), 6);
+      assert.equal(position.noShares.toNumber(), 6);
+      assert.equal(
+        (await provider.connection.getBalance(pdas.vaultPda)) - vaultBefore,
+        6 * SHARE_PAYOUT
+      );
+      market = await program.account.clobMarket.fetch(pdas.marketPda);
+      assert.equal(market.openInterest.toNumber(), 6);
+      console.log("  ✓ 10 sets minted, 4 redeemed");
+    });
+    
+    it("35.2 Can't redeem more sets than the position holds", async () => {
+      const { pdas } = await createTestMarket(program, provider);
+      // A filled bid leaves YES shares with no NO shares to pair them with
+      const maker = Keypair.generate();
+      await airdrop(provider, maker.publicKey);
+      await placeOrder(program, provider, pdas, maker, 1, true, 5000, 5);
+      await placeOrder(program, provider, pdas, null, 0, true, 5000, 5);
+      await completeSet(pdas, "mintCompleteSet", 2);
+      
+      try {
+        await completeSet(pdas, "redeemCompleteSet", 3);
+        assert.fail("Redeeming 3 sets with only 2 NO shares should fail");
+      } catch (err) {
+        assert(err.error?.errorCode?.code === "InsufficientShares");
+      }
+      await completeSet(pdas, "redeemCompleteSet", 2);
+      
+      const position = await program.account.clobPosition.fetch(
+        getPositionPda(program, pdas.marketPda, provider.wallet.publicKey)
+      );
+      assert.equal(position.yesShares.toNumber(), 5);
+      assert.equal(position.noShares.toNumber(), 0);
+      console.log("  ✓ Redeem limited to matched YES/NO pairs");
+    });
+  });
+  
+  // =========================================
+  // 36. PERMISSIONED MARKETS
+  // =========================================
+  describe("36. Permissioned Markets", () => {
+    
+    it("36.1 Only allowlisted traders can place orders", async () => {
+      const { pdas } = await createTestMarket(program, provider, null, { permissioned: true });
+      const trader = Keypair.generate();
+      await airdrop(provider, trader.publicKey);
+      const [entryPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("allowlist"), pdas.marketPda.toBuffer(), trader.publicKey.toBuffer()],
+        program.programId
+      );
+      const allowlist = [{ pubkey: entryPda, isWritable: false, isSigner: false }];
+      
+      try {
+        await placeOrder(program, provider, pdas, trader, 0, true, 4000, 10);
+        assert.fail("Trader without an allowlist entry should be rejected");
+      } catch (err) {
+        assert(err.error?.errorCode?.code === "TraderNotAllowed");
+      }
+      
+      await program.methods
+        .addTrader(trader.publicKey)
+        .accounts({
+          market: pdas.marketPda,
+          allowlistEntry: entryPda,
+          authority: provider.wallet.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .rpc();
+      await placeOrder(program, provider, pdas, trader, 0, true, 4000, 10, { remainingAccounts: allowlist });
+      
+      await program.methods
+        .removeTrader(trader.publicKey)
+        .accounts({
+          market: pdas.marketPda,
+          allowlistEntry: entryPda,
+          authority: provider.wallet.publicKey,
+        })
+        .rpc();
+      try {
+        await placeOrder(program, provider, pdas, trader, 0, true, 4000, 10, { remainingAccounts: allowlist });
+        assert.fail("Removed trader should be rejected");
+      } catch (err) {
+        assert(err.error?.errorCode?.code === "TraderNotAllowed");
+      }
+      
+      const book = await program.account.orderBook.fetch(pdas.orderBookPda);
+      assert.equal(book.yesBids.length, 1);
+      // Nor can they reprice or grow the order they left resting
+      try {
+        await program.methods
+          .amendOrder(book.yesBids[0].orderId, new anchor.BN(4500), new anchor.BN(20))
+          .accounts({
+            market: pdas.marketPda,
+            orderBook: pdas.orderBookPda,
+            vault: pdas.vaultPda,
+            position: getPositionPda(program, pdas.marketPda, trader.publicKey),
+            trader: trader.publicKey,
+          })
+          .remainingAccounts(allowlist)
+          .signers([trader])
+          .rpc();
+        assert.fail("Removed trader should not amend");
+      } catch (err) {
+        assert(err.error?.errorCode?.code === "TraderNotAllowed");
+      }
+      console.log("  ✓ Allowlist gates order placement and amendment");
+    });
+    
+    it("36.2 Only the authority can add traders", async () => {
+      const { pdas } = await createTestMarket(program, provider, null, { permissioned: true });
+      const outsider = Keypair.generate();
+      await airdrop(provider, outsider.publicKey);
+      const [entryPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("allowlist"), pdas.marketPda.toBuffer(), outsider.publicKey.toBuffer()],
+        program.programId
+      );
+      
+      try {
+        await program.methods
+          .addTrader(outsider.publicKey)
+          .accounts({
+            market: pdas.marketPda,
+            allowlistEntry: entryPda,
+            authority: outsider.publicKey,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([outsider])
+          .rpc();
+        assert.fail("Non-authority should not add traders");
+      } catch (err) {
+        assert(err.error?.errorCode?.code === "Unauthorized");
+      }
+    });
+    
+    it("36.3 Minting complete sets is gated like placing orders", async () => {
+      const { pdas } = await createTestMarket(program, provider, null, {
+        permissioned: true,
+        authorityCanTrade: false,
+      });
+      const mint = (trader, remainingAccounts = []) =>
+        program.methods
+          .mintCompleteSet(new anchor.BN(5))
+          .accounts({
+            market: pdas.marketPda,
+            vault: pdas.vaultPda,
+            position: getPositionPda(program, pdas.marketPda, trader.publicKey),
+            trader: trader.publicKey,
+            systemProgram: SystemProgram.programId,
+          })
+          .remainingAccounts(remainingAccounts)
+          .signers(trader.secretKey ? [trader] : [])
+          .rpc();
+      
+      const trader = Keypair.generate();
+      await airdrop(provider, trader.publicKey);
+      try {
+        await mint(trader);
+        assert.fail("Trader without an allowlist entry should not mint");
+      } catch (err) {
+        assert(err.error?.errorCode?.code === "TraderNotAllowed");
+      }
+      
+      const [entryPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("allowlist"), pdas.marketPda.toBuffer(), trader.publicKey.toBuffer()],
+        program.programId
+      );
+      await program.methods
+        .addTrader(trader.publicKey)
+        .accounts({
+          market: pdas.marketPda,
+          allowlistEntry: entryPda,
+          authority: provider.wallet.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .rpc();
+      await mint(trader, [{ pubkey: entryPda, isWritable: false, isSigner: false }]);
+      
+      try {
+        await mint(provider.wallet);
+        assert.fail("Authority that can't trade should not mint");
+      } catch (err) {
+        assert(err.error?.errorCode?.code === "AuthorityCannotTrade");
+      }
+      console.log("  ✓ Allowlist and authority_can_trade gate minting");
+    });
+  });
+  
+  // =========================================
+  // 37. VOLUME ATTRIBUTION
+  // =========================================
+  describe("37. Volume Attribution", () => {
+    
+    it("37.1 Credits filled notional to taker and maker positions", async () => {
+      const { pdas } = await createTestMarket(program, provider);
+      const maker = Keypair.generate();
+      await airdrop(provider, maker.publicKey);
+      const makerPositionPda = getPositionPda(program, pdas.marketPda, maker.publicKey);
+      const takerPositionPda = getPositionPda(program, pdas.marketPda, provider.wallet.publicKey);
+      await placeOrder(program, provider, pdas, maker, 1, true, 6000, 20);
+      
+      await placeOrder(program, provider, pdas, null, 0, true, 6000, 10, {
+        remainingAccounts: [{ pubkey: makerPositionPda, isWritable: true, isSigner: false }],
+      });
+      let makerPosition = await program.account.clobPosition.fetch(makerPositionPda);
+      let takerPosition = await program.account.clobPosition.fetch(takerPositionPda);
+      assert.equal(makerPosition.volumeTraded.toNumber(), 6000 * 10);
+      assert.equal(takerPosition.volumeTraded.toNumber(), 6000 * 10);
+      
+      // Without the maker's position only the taker is credited
+      await placeOrder(program, provider, pdas, null, 0, true, 6000, 5);
+      makerPosition = await program.account.clobPosition.fetch(makerPositionPda);
+      takerPosition = await program.account.clobPosition.fetch(takerPositionPda);
+      assert.equal(makerPosition.volumeTraded.toNumber(), 6000 * 10);
+      assert.equal(takerPosition.volumeTraded.toNumber(), 6000 * 15);
+      console.log("  ✓ Volume credited on both sides of the fill");
+    });
+  });
+  
+  // =========================================
+  // 38. DISPUTE PERIOD
+  // =========================================
+  describe("38. Dispute Period", () => {
+    
+    it("38.1 Claims wait out the dispute period, which allows an override", async () => {
+      const { pdas } = await createTestMarket(program, provider, null, {
+        disputePeriod: new anchor.BN(3),
+      });
+      const trader = provider.wallet.publicKey;
+      const positionPda = getPositionPda(program, pdas.marketPda, trader);
+      await program.methods
+        .mintCompleteSet(new anchor.BN(5))
+        .accounts({
+          market: pdas.marketPda,
+          vault: pdas.vaultPda,
+          position: positionPda,
+          trader,
+          systemProgram: SystemProgram.programId,
+        })
+        .rpc();
+      
+      await program.methods
+        .resolveClobMarket(0)
+        .accounts({ market: pdas.marketPda, authority: trader })
+        .rpc();
+      await program.methods
+        .overrideResolution(1)
+        .accounts({ market: pdas.marketPda, authority: trader })
+        .rpc();
+      
+      const claim = () =>
+        program.methods
+          .claimClobWinnings(new anchor.BN(0))
+          .accounts({
+            market: pdas.marketPda,
+            vault: pdas.vaultPda,
+            position: positionPda,
+            claimer: trader,
+          })
+          .rpc();
+      try {
+        await claim();
+        assert.fail("Claim inside the dispute period should fail");
+      } catch (err) {
+        assert(err.error?.errorCode?.code === "DisputePeriodActive");
+      }
+      
+      await sleep(4000);
+      
+      try {
+        await program.methods
+          .overrideResolution(0)
+          .accounts({ market: pdas.marketPda, authority: trader })
+          .rpc();
+        assert.fail("Override after the dispute period should fail");
+      } catch (err) {
+        assert(err.error?.errorCode?.code === "DisputePeriodOver");
+      }
+      
+      const vaultBefore = await provider.connection.getBalance(pdas.vaultPda);
+      await claim();
+      const vaultAfter = await provider.connection.getBalance(pdas.vaultPda);
+      assert.equal(vaultBefore - vaultAfter, 5 * SHARE_PAYOUT);
+      
+      const market = await program.account.clobMarket.fetch(pdas.marketPda);
+      assert.equal(market.winningSide, 1);
+      console.log("  ✓ Claim opened once the dispute period passed");
+    });
+  });
+  
+  // =========================================
+  // 39. RESOLVER VOTES
+  // =========================================
+  describe("39. Resolver Votes", () => {
+    
+    it("39.1 Resolves once 2 of 3 resolvers agree", async () => {
+      const resolvers = [Keypair.generate(), Keypair.generate(), Keypair.generate()];
+      for (const resolver of resolvers) {
+        await airdrop(provider, resolver.publicKey);
+      }
+      const { pdas } = await createTestMarket(program, provider, null, {
+        resolvers: resolvers.map((r) => r.publicKey),
+        resolverThreshold: 2,
+      });
+      const [votesPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("resolution_votes"), pdas.marketPda.toBuffer()],
+        program.programId
+      );
+      const vote = (resolver, side) =>
+        program.methods
+          .voteClobResolution(side)
+          .accounts({
+            market: pdas.marketPda,
+            votes: votesPda,
+            resolver: resolver.publicKey,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([resolver])
+          .rpc();
+      
+      try {
+        await program.methods
+          .resolveClobMarket(0)
+          .accounts({ market: pdas.marketPda, authority: provider.wallet.publicKey })
+          .rpc();
+        assert.fail("Authority should not resolve a market with resolvers");
+      } catch (err) {
+        assert(err.error?.errorCode?.code === "ResolverVoteRequired");
+      }
+      
+      // A split vote doesn't reach the threshold
+      await vote(resolvers[0], 1);
+      await vote(resolvers[1], 0);
+      let market = await program.account.clobMarket.fetch(pdas.marketPda);
+      assert.equal(market.resolved, false);
+      
+      await vote(resolvers[2], 1);
+      market = await program.account.clobMarket.fetch(pdas.marketPda);
+      assert.equal(market.resolved, true);
+      assert.equal(market.winningSide, 1);
+      console.log("  ✓ NO wins with 2 of 3 votes");
+    });
+    
+    it("39.2 Rejects votes from non-resolvers", async () => {
+      const resolver = Keypair.generate();
+      const outsider = Keypair.generate();
+      await airdrop(provider, outsider.publicKey);
+      const { pdas } = await createTestMarket(program, provider, null, {
+        resolvers: [resolver.publicKey],
+        resolverThreshold: 1,
+      });
+      const [votesPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("resolution_votes"), pdas.marketPda.toBuffer()],
+        program.programId
+      );
+      
+      try {
+        await program.methods
+          .voteClobResolution(0)
+          .accounts({
+            market: pdas.marketPda,
+            votes: votesPda,
+            resolver: outsider.publicKey,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([outsider])
+          .rpc();
+        assert.fail("Outsider vote should fail");
+      } catch (err) {
+        assert(err.error?.errorCode?.code === "NotResolver");
+      }
+    });
+    
+    it("39.3 Rejects a threshold above the resolver count", async () => {
+      try {
+        await createTestMarket(program, provider, null, {
+          resolvers: [Keypair.generate().publicKey],
+          resolverThreshold: 2,
+        });
+        assert.fail("Threshold 2 of 1 should fail");
+      } catch (err) {
+        assert(err.error?.errorCode?.code === "InvalidResolverSet");
+      }
+    });
+  });
+  
+  // =========================================
+  // 40. ORACLE RESOLUTION
+  // =========================================
+  describe("40. Oracle Resolution", () => {
+    
+    // A mint's supply is a little-endian u64 at byte 36, which makes it a
+    // convenient stand-in for a price feed the test controls
+    const MINT_SUPPLY_OFFSET = 36;
+    
+    it("40.1 Resolves from the feed once resolution_time passes", async () => {
+      const payer = authority.payer;
+      const feed = await createMint(provider.connection, payer, authority.publicKey, null, 0);
+      const holder = await createAssociatedTokenAccount(provider.connection, payer, feed, authority.publicKey);
+      await mintTo(provider.connection, payer, feed, holder, payer, 120);
+      
+      const resolutionTime = Math.floor(Date.now() / 1000) + 2;
+      const { pdas } = await createTestMarket(
+        program,
+        provider,
+        null,
+        {
+          oracle: feed,
+          oracleOffset: MINT_SUPPLY_OFFSET,
+          oracleThreshold: new anchor.BN(100),
+          oracleComparison: 0, // YES if supply > 100
+        },
+        resolutionTime
+      );
+      const resolve = (oracle) =>
+        program.methods.resolveFromOracle().accounts({ market: pdas.marketPda, oracle }).rpc();
+      
+      try {
+        await resolve(feed);
+        assert.fail("Oracle resolution before resolution_time should fail");
+      } catch (err) {
+        assert(err.error?.errorCode?.code === "ResolutionTimeNotReached");
+      }
+      
+      await sleep(3000);
+      
+      try {
+        await resolve(pdas.vaultPda);
+        assert.fail("A different feed account should be rejected");
+      } catch (err) {
+        assert(err.error?.errorCode?.code === "OracleMismatch");
+      }
+      
+      await resolve(feed);
+      const market = await program.account.clobMarket.fetch(pdas.marketPda);
+      assert.equal(market.resolved, true);
+      assert.equal(market.winningSide, 0);
+      console.log("  ✓ Supply 120 > 100, YES wins");
+    });
+  });
+  
+  // =========================================
+  // 41. MAKER REBATES
+  // =========================================
+  describe("41. Maker Rebates", () => {
+    
+    it("41.1 Credits makers a rebate out of the taker fee and pays it on claim", async () => {
+      const { pdas } = await createTestMarket(program, provider, null, {
+        takerFeeBps: new anchor.BN(100),
+        makerRebateBps: new anchor.BN(40),
+      });
+      const maker = Keypair.generate();
+      await airdrop(provider, maker.publicKey);
+      const makerPositionPda = getPositionPda(program, pdas.marketPda, maker.publicKey);
+      await placeOrder(program, provider, pdas, maker, 1, true, 5000, 100);
+      
+      // The maker is owed a rebate, so their position has to come along
+      try {
+        await placeOrder(program, provider, pdas, null, 0, true, 5000, 100);
+        assert.fail("Fill without the maker's position should fail");
+      } catch (err) {
+        assert(err.error?.errorCode?.code === "MakerPositionMissing");
+      }
+      
+      await placeOrder(program, provider, pdas, null, 0, true, 5000, 100, {
+        remainingAccounts: [{ pubkey: makerPositionPda, isWritable: true, isSigner: false }],
+      });
+      // 1% fee and 0.4% rebate on 5000 * 100 lamports of filled collateral
+      const market = await program.account.clobMarket.fetch(pdas.marketPda);
+      let makerPosition = await program.account.clobPosition.fetch(makerPositionPda);
+      assert.equal(market.feesAccrued.toNumber(), 3000);
+      assert.equal(makerPosition.rebatesOwed.toNumber(), 2000);
+      
+      const claim = () =>
+        program.methods
+          .claimRebates()
+          .accounts({
+            market: pdas.marketPda,
+            vault: pdas.vaultPda,
+            position: makerPositionPda,
+            owner: maker.publicKey,
+          })
+          .signers([maker])
+          .rpc();
+      const vaultBefore = await provider.connection.getBalance(pdas.vaultPda);
+      await claim();
+      const vaultAfter = await provider.connection.getBalance(pdas.vaultPda);
+      assert.equal(vaultBefore - vaultAfter, 2000);
+      makerPosition = await program.account.clobPosition.fetch(makerPositionPda);
+      assert.equal(makerPosition.rebatesOwed.toNumber(), 0);
+      
+      try {
+        await claim();
+        assert.fail("Second claim should fail");
+      } catch (err) {
+        assert(err.error?.errorCode?.code === "NoRebatesToClaim");
+      }
+      assertVaultSolvency(await getMarketState(program, provider, pdas, []), "after rebate claim");
+      console.log("  ✓ Maker claimed a 2000 lamport rebate");
+    });
+    
+    it("41.2 Rejects a rebate larger than the taker fee", async () => {
+      try {
+        await createTestMarket(program, provider, null, {
+          takerFeeBps: new anchor.BN(50),
+          makerRebateBps: new anchor.BN(60),
+        });
+        assert.fail("Rebate above the fee should be rejected");
+      } catch (err) {
+        assert(err.error?.errorCode?.code === "InvalidRebate");
+        console.log("  ✓ Rebate above the taker fee rejected");
+      }
+    });
+  });
+  
+  // =========================================
+  // 42. ORDER AMENDMENT
+  // =========================================
+  describe("42. Order Amendment", () => {
+    
+    const amend = (pdas, signer, orderId, price, size) =>
+      program.methods
+        .amendOrder(orderId, new anchor.BN(price), new anchor.BN(size))
+        .accounts({
+          market: pdas.marketPda,
+          orderBook: pdas.orderBookPda,
+          vault: pdas.vaultPda,
+          position: getPositionPda(program, pdas.marketPda, signer.publicKey),
+          trader: signer.publicKey,
+        })
+        .signers([signer])
+        .rpc();
+    
+    it("42.1 Size reductions keep priority, repricing re-queues and settles the difference", async () => {
+      const { pdas } = await createTestMarket(program, provider);
+      const first = Keypair.generate();
+      const second = Keypair.generate();
+      await airdrop(provider, first.publicKey);
+      await airdrop(provider, second.publicKey);
+      await placeOrder(program, provider, pdas, first, 0, true, 5000, 100);
+      await placeOrder(program, provider, pdas, second, 0, true, 5000, 100);
+      let book = await program.account.orderBook.fetch(pdas.orderBookPda);
+      const orderId = book.yesBids[0].orderId;
+      
+      let vaultBefore = await provider.connection.getBalance(pdas.vaultPda);
+      await amend(pdas, first, orderId, 5000, 60);
+      let vaultAfter = await provider.connection.getBalance(pdas.vaultPda);
+      assert.equal(vaultBefore - vaultAfter, 5000 * 40);
+      book = await program.account.orderBook.fetch(pdas.orderBookPda);
+      assert(book.yesBids[0].owner.equals(first.publicKey));
+      assert.equal(book.yesBids[0].size.toNumber(), 60);
+      assert.equal(book.totalBidSize.toNumber(), 160);
+      
+      // Growing the order at the same price sends it behind the other bid
+      await amend(pdas, first, orderId, 5000, 80);
+      book = await program.account.orderBook.fetch(pdas.orderBookPda);
+      assert(book.yesBids[0].owner.equals(second.publicKey));
+      assert(book.yesBids[1].owner.equals(first.publicKey));
+      
+      // A better price moves it to the front and collects the extra collateral
+      vaultBefore = await provider.connection.getBalance(pdas.vaultPda);
+      await amend(pdas, first, orderId, 5500, 80);
+      vaultAfter = await provider.connection.getBalance(pdas.vaultPda);
+      assert.equal(vaultAfter - vaultBefore, 500 * 80);
+      book = await program.account.orderBook.fetch(pdas.orderBookPda);
+      assert(book.yesBids[0].owner.equals(first.publicKey));
+      assert.equal(book.yesBids[0].price.toNumber(), 5500);
+      assert.equal(book.yesBids[0].orderId.toNumber(), orderId.toNumber());
+      assert.equal(book.totalBidSize.toNumber(), 180);
+      console.log("  ✓ Amended in place, then re-queued on repricing");
+    });
+    
+    it("42.2 Rejects amendments that would cross or touch another trader's order", async () => {
+      const { pdas } = await createTestMarket(program, provider);
+      const bidder = Keypair.generate();
+      const asker = Keypair.generate();
+      await airdrop(provider, bidder.publicKey);
+      await airdrop(provider, asker.publicKey);
+      await placeOrder(program, provider, pdas, bidder, 0, true, 5000, 10);
+      await placeOrder(program, provider, pdas, asker, 1, true, 6000, 10);
+      const book = await program.account.orderBook.fetch(pdas.orderBookPda);
+      const bidId = book.yesBids[0].orderId;
+      
+      try {
+        await amend(pdas, bidder, bidId, 6000, 10);
+        assert.fail("Crossing amendment should fail");
+      } catch (err) {
+        assert(err.error?.errorCode?.code === "PostOnlyWouldCross");
+      }
+      try {
+        await amend(pdas, asker, bidId, 4000, 10);
+        assert.fail("Amending someone else's order should fail");
+      } catch (err) {
+        assert(err.error?.errorCode?.code === "OrderNotFound");
+        console.log("  ✓ Crossing and foreign amendments rejected");
+      }
+    });
+    
+    it("42.3 Counts a size increase against max_trader_notional", async () => {
+      const { pdas } = await createTestMarket(program, provider, null, {
+        maxTraderNotional: new anchor.BN(100000),
+      });
+      const trader = Keypair.generate();
+      await airdrop(provider, trader.publicKey);
+      await placeOrder(program, provider, pdas, trader, 0, true, 5000, 10);
+      const book = await program.account.orderBook.fetch(pdas.orderBookPda);
+      const orderId = book.yesBids[0].orderId;
+      
+      // 50000 committed; growing to 30 would commit another 100000
+      try {
+        await amend(pdas, trader, orderId, 5000, 30);
+        assert.fail("Amending past the cap should fail");
+      } catch (err) {
+        assert(err.error?.errorCode?.code === "PositionLimitExceeded");
+      }
+      await amend(pdas, trader, orderId, 5000, 20);
+      const position = await program.account.clobPosition.fetch(
+        getPositionPda(program, pdas.marketPda, trader.publicKey)
+      );
+      assert.equal(position.notional.toNumber(), 100000);
+      console.log("  ✓ Amendment capped at the position's notional limit");
+    });
+  });
+  
+  // =========================================
+  // 43. PRICE HISTORY
+  // =========================================
+  describe("43. Price History", () => {
+    
+    it("43.1 Records each fill's price, size and time", async () => {
+      const { pdas } = await createTestMarket(program, provider);
+      const [priceHistoryPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("price_history"), pdas.marketPda.toBuffer()],
+        program.programId
+      );
+      let history = await program.account.priceHistory.fetch(priceHistoryPda);
+      assert(history.market.equals(pdas.marketPda));
+      assert.equal(history.points.length, 0);
+      
+      const maker = Keypair.generate();
+      await airdrop(provider, maker.publicKey);
+      await placeOrder(program, provider, pdas, maker, 1, true, 6000, 10);
+      await placeOrder(program, provider, pdas, maker, 1, true, 6500, 10);
+      // One sweep across both levels records two points
+      await placeOrder(program, provider, pdas, null, 0, true, 6500, 15);
+      
+      history = await program.account.priceHistory.fetch(priceHistoryPda);
+      assert.deepEqual(
+        history.points.map((p) => [p.price.toNumber(), p.size.toNumber()]),
+        [[6000, 10], [6500, 5]]
+      );
+      assert(history.points[0].timestamp.toNumber() > 0);
+      assert.equal(history.next, 0);
+      console.log("  ✓ Two fills recorded in order");
+    });
+  });
+  
+  // =========================================
+  // 44. RESOLUTION TIME VALIDATION
+  // =========================================
+  describe("44. Resolution Time Validation", () => {
+    
+    it("44.1 Rejects markets whose resolution_time has already passed", async () => {
+      const now = Math.floor(Date.now() / 1000);
+      for (const time of [now - 3600, now - 5]) {
+        try {
+          await createTestMarket(program, provider, null, {}, time);
+          assert.fail("Market resolving in the past should fail");
+        } catch (err) {
+          assert(err.error?.errorCode?.code === "ResolutionTimeInPast");
+        }
+      }
+      console.log("  ✓ Past resolution times rejected");
+    });
+  });
+  
+  // =========================================
+  // 45. EARLY RESOLUTION
+  // =========================================
+  describe("45. Early Resolution", () => {
+    
+    const resolve = (pdas) =>
+      program.methods
+        .resolveClobMarket(0)
+        .accounts({ market: pdas.marketPda, authority: provider.wallet.publicKey })
+        .rpc();
+    
+    it("45.1 Rejects resolution before resolution_time by default", async () => {
+      const { pdas } = await createTestMarket(
+        program,
+        provider,
+        null,
+        { earlyResolution: false },
+        Math.floor(Date.now() / 1000) + 3
+      );
+      try {
+        await resolve(pdas);
+        assert.fail("Resolving before resolution_time should fail");
+      } catch (err) {
+        assert(err.error?.errorCode?.code === "ResolutionTimeNotReached");
+      }
+      
+      await sleep(5000);
+      await resolve(pdas);
+      const market = await program.account.clobMarket.fetch(pdas.marketPda);
+      assert.equal(market.winningSide, 0);
+      console.log("  ✓ Resolved only once resolution_time passed");
+    });
+    
+    it("45.2 Markets created with early resolution resolve right away", async () => {
+      const { pdas } = await createTestMarket(program, provider, null, { earlyResolution: true });
+      await resolve(pdas);
+      const market = await program.account.clobMarket.fetch(pdas.marketPda);
+      assert.equal(market.resolved, true);
+      console.log("  ✓ Early resolution allowed by the market's config");
+    });
+  });
+  
+  // =========================================
+  // 46. WINNINGS ESTIMATES
+  // =========================================
+  describe("46. Winnings Estimates", () => {
+    
+    it("46.1 estimate_clob_winnings matches what the claim pays", async () => {
+      const { pdas } = await createTestMarket(program, provider);
+      const maker = Keypair.generate();
+      await airdrop(provider, maker.publicKey);
+      await placeOrder(program, provider, pdas, maker, 1, true, 6000, 10);
+      await placeOrder(program, provider, pdas, null, 0, true, 6000, 10);
+      await program.methods
+        .resolveClobMarket(0)
+        .accounts({ market: pdas.marketPda, authority: provider.wallet.publicKey })
+        .rpc();
+      
+      const positionPda = getPositionPda(program, pdas.marketPda, provider.wallet.publicKey);
+      const estimate = await program.methods
+        .estimateClobWinnings()
+        .accounts({ market: pdas.marketPda, position: positionPda })
+        .view();
+      assert.equal(estimate.payout.toNumber(), 10 * SHARE_PAYOUT);
+      assert.equal(estimate.fee.toNumber(), 0);
+      assert.equal(estimate.netPayout.toNumber(), 10 * SHARE_PAYOUT);
+      
+      const vaultBefore = await provider.connection.getBalance(pdas.vaultPda);
+      await program.methods
+        .claimClobWinnings(new anchor.BN(0))
+        .accounts({
+          market: pdas.marketPda,
+          vault: pdas.vaultPda,
+          position: positionPda,
+          claimer: provider.wallet.publicKey,
+        })
+        .rpc();
+      const vaultAfter = await provider.connection.getBalance(pdas.vaultPda);
+      assert.equal(vaultBefore - vaultAfter, estimate.netPayout.toNumber());
+      console.log(`  ✓ Estimated and paid ${estimate.netPayout.toNumber()} lamports`);
+    });
+  });
+  
+  // =========================================
+  // 47. MARKET ID COLLISIONS
+  // =========================================
+  describe("47. Market ID Collisions", () => {
+    
+    it("47.1 One market_id can name a parimutuel and a CLOB market, but not two CLOB markets", async () => {
+      const { marketId, pdas } = await createTestMarket(program, provider);
+      const [parimutuelPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("market"), Buffer.from(marketId)],
+        program.programId
+      );
+      await program.methods
+        .createMarket(
+          marketId,
+          "Same id, parimutuel",
+          ["Yes", "No"],
+          new anchor.BN(Math.floor(Date.now() / 1000) + 3600),
+          {
+            maxOutcomeShares: new anchor.BN(0),
+            maxResolutionDelay: new anchor.BN(0),
+            minBet: new anchor.BN(0),
+            maxTotalPool: new anchor.BN(0),
+            marketKind: 0,
+            scalarMin: new anchor.BN(0),
+            scalarMax: new anchor.BN(0),
+            earlyResolution: false,
+            feeBps: new anchor.BN(200),
+            maxTraderNotional: new anchor.BN(0),
+            referralFeeBps: new anchor.BN(0),
+            outcomeWeights: [],
+            authorityCanTrade: true,
+            commitPhaseStart: new anchor.BN(0),
+            revealPeriod: new anchor.BN(0),
+            feeRoundUp: false,
+          }
+        )
+        .accounts({
+          market: parimutuelPda,
+          authority: provider.wallet.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .rpc();
+      
+      const parimutuel = await program.account.market.fetch(parimutuelPda);
+      const clob = await program.account.clobMarket.fetch(pdas.marketPda);
+      assert.equal(parimutuel.marketId, marketId);
+      assert.equal(clob.marketId, marketId);
+      assert.equal(clob.question, "Test Market");
+      
+      try {
+        await createTestMarket(program, provider, marketId);
+        assert.fail("Reusing a CLOB market_id should fail");
+      } catch (err) {
+        assert((err.logs ?? []).some((log) => log.includes("already in use")));
+        console.log("  ✓ Ids shared across market types, rejected within one");
+      }
+    });
+  });
+  
+  // =========================================
+  // 48. PARTIAL CLAIMS
+  // =========================================
+  describe("48. Partial Claims", () => {
+    
+    it("48.1 Claims a chosen number of winning shares, then the rest", async () => {
+      const { pdas } = await createTestMarket(program, provider);
+      const maker = Keypair.generate();
+      await airdrop(provider, maker.publicKey);
+      await placeOrder(program, provider, pdas, maker, 1, true, 6000, 10);
+      await placeOrder(program, provider, pdas, null, 0, true, 6000, 10);
+      await program.methods
+        .resolveClobMarket(0)
+        .accounts({ market: pdas.marketPda, authority: provider.wallet.publicKey })
+        .rpc();
+      
+      const positionPda = getPositionPda(program, pdas.marketPda, provider.wallet.publicKey);
+      const claim = (shares) =>
+        program.methods
+          .claimClobWinnings(new anchor.BN(shares))
+          .accounts({
+            market: pdas.marketPda,
+            vault: pdas.vaultPda,
+            position: positionPda,
+            claimer: provider.wallet.publicKey,
+          })
+          .rpc();
+      
+      let vaultBefore = await provider.connection.getBalance(pdas.vaultPda);
+      await claim(4);
+      let vaultAfter = await provider.connection.getBalance(pdas.vaultPda);
+      assert.equal(vaultBefore - vaultAfter, 4 * SHARE_PAYOUT);
+      let position = await program.account.clobPosition.fetch(positionPda);
+      assert.equal(position.yesShares.toNumber(), 6);
+      let market = await program.account.clobMarket.fetch(pdas.marketPda);
+      assert.equal(market.openInterest.toNumber(), 6);
+      
+      try {
+        await claim(7);
+        assert.fail("Claiming more shares than held should fail");
+      } catch (err) {
+        assert(err.error?.errorCode?.code === "InsufficientShares");
+      }
+      
+      vaultBefore = await provider.connection.getBalance(pdas.vaultPda);
+      await claim(0);
+      vaultAfter = await provider.connection.getBalance(pdas.vaultPda);
+      assert.equal(vaultBefore - vaultAfter, 6 * SHARE_PAYOUT);
+      position = await program.account.clobPosition.fetch(positionPda);
+      assert.equal(position.yesShares.toNumber(), 0);
+      market = await program.account.clobMarket.fetch(pdas.marketPda);
+      assert.equal(market.openInterest.toNumber(), 0);
+      console.log("  ✓ Claimed 4 shares, then the remaining 6");
+    });
+  });
+  
+  // =========================================
+  // 49. POSITION CHANGE EVENTS
+  // =========================================
+  describe("49. Position Change Events", () => {
+    
+    it("49.1 Emits PositionChanged for a fill and a claim", async () => {
+      const { pdas } = await createTestMarket(program, provider);
+      const maker = Keypair.generate();
+      await airdrop(provider, maker.publicKey);
+      await placeOrder(program, provider, pdas, maker, 1, true, 6000, 10);
+      
+      const positionPda = getPositionPda(program, pdas.marketPda, provider.wallet.publicKey);
+      const fillSig = await program.methods
+        .placeOrder(0, true, new anchor.BN(6000), new anchor.BN(10), ORDER_TYPE.LIMIT, new anchor.BN(0), new anchor.BN(0), new anchor.BN(0), false, null)
+        .accounts({
+          market: pdas.marketPda,
+          orderBook: pdas.orderBookPda,
+          vault: pdas.vaultPda,
+          position: positionPda,
+          trader: provider.wallet.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .rpc({ commitment: "confirmed" });
+      
+      const [fill] = findEvents(await getEvents(program, provider, fillSig), "PositionChanged");
+      assert(fill.data.owner.equals(provider.wallet.publicKey));
+      assert(fill.data.market.equals(pdas.marketPda));
+      assert.deepEqual(fill.data.shares.map((s) => s.toNumber()), [10, 0]);
+      assert.equal(fill.data.reason, 1);
+      
+      await program.methods
+        .resolveClobMarket(0)
+        .accounts({ market: pdas.marketPda, authority: provider.wallet.publicKey })
+        .rpc();
+      const claimSig = await program.methods
+        .claimClobWinnings(new anchor.BN(0))
+        .accounts({
+          market: pdas.marketPda,
+          vault: pdas.vaultPda,
+          position: positionPda,
+          claimer: provider.wallet.publicKey,
+        })
+        .rpc({ commitment: "confirmed" });
+      
+      const [claim] = findEvents(await getEvents(program, provider, claimSig), "PositionChanged");
+      assert.deepEqual(claim.data.shares.map((s) => s.toNumber()), [0, 0]);
+      assert.equal(claim.data.reason, 2);
+      console.log(`  ✓ PositionChanged reports balances after fill and claim`);
+    });
+  });
+  
+  // =========================================
+  // 50. SLIPPAGE PROTECTION
+  // =========================================
+  describe("50. Slippage Protection", () => {
+    
+    it("50.1 Reverts a sweep whose average price exceeds avg_price_limit", async () => {
+      const { pdas } = await createTestMarket(program, provider);
+      const maker = Keypair.generate();
+      await airdrop(provider, maker.publicKey);
+      await placeOrder(program, provider, pdas, maker, 1, true, 5000, 10);
+      await placeOrder(program, provider, pdas, maker, 1, true, 7000, 10);
+      
+      // Sweeping both levels averages 6000 bps
+      try {
+        await placeOrder(program, provider, pdas, null, 0, true, 7000, 20, {
+          orderType: ORDER_TYPE.IOC,
+          avgPriceLimit: 5999,
+        });
+        assert.fail("Fill averaging above the limit should fail");
+      } catch (err) {
+        assert(err.error?.errorCode?.code === "SlippageExceeded");
+      }
+      let orderBook = await program.account.orderBook.fetch(pdas.orderBookPda);
+      assert.equal(orderBook.yesAsks.length, 2);
+      
+      await placeOrder(program, provider, pdas, null, 0, true, 7000, 20, {
+        orderType: ORDER_TYPE.IOC,
+        avgPriceLimit: 6000,
+      });
+      orderBook = await program.account.orderBook.fetch(pdas.orderBookPda);
+      assert.equal(orderBook.yesAsks.length, 0);
+      const position = await program.account.clobPosition.fetch(
+        getPositionPda(program, pdas.marketPda, provider.wallet.publicKey)
+      );
+      assert.equal(position.yesShares.toNumber(), 20);
+      console.log(`  ✓ Sweep reverted at a 5999 bps average limit, filled at 6000`);
+    });
+    
+    it("50.2 Applies the limit as a floor for sells, in the order's own terms", async () => {
+      const { pdas } = await createTestMarket(program, provider);
+      const maker = Keypair.generate();
+      await airdrop(provider, maker.publicKey);
+      // YES asks at 6000 and 8000 are NO bids at 4000 and 2000
+      await placeOrder(program, provider, pdas, maker, 1, true, 6000, 10);
+      await placeOrder(program, provider, pdas, maker, 1, true, 8000, 10);
+      
+      try {
+        await placeOrder(program, provider, pdas, null, 1, false, 2000, 20, {
+          orderType: ORDER_TYPE.IOC,
+          avgPriceLimit: 3001,
+        });
+        assert.fail("Sell averaging below the limit should fail");
+      } catch (err) {
+        assert(err.error?.errorCode?.code === "SlippageExceeded");
+      }
+      
+      await placeOrder(program, provider, pdas, null, 1, false, 2000, 20, {
+        orderType: ORDER_TYPE.IOC,
+        avgPriceLimit: 3000,
+      });
+      const orderBook = await program.account.orderBook.fetch(pdas.orderBookPda);
+      assert.equal(orderBook.yesAsks.length, 0);
+      console.log(`  ✓ NO sell averaging 3000 bps respects a 3000 floor`);
+    });
+  });
+  
+  // =========================================
+  // 51. ORDER RANK
+  // =========================================
+  describe("51. Order Rank", () => {
+    
+    it("51.1 Reports queue index and size ahead of a resting order", async () => {
+      const { pdas } = await createTestMarket(program, provider);
+      await placeOrder(program, provider, pdas, null, 0, true, 5000, 10);
+      await placeOrder(program, provider, pdas, null, 0, true, 6000, 5);
+      await placeOrder(program, provider, pdas, null, 0, true, 5000, 7);
+      
+      const orderBook = await program.account.orderBook.fetch(pdas.orderBookPda);
+      const last = orderBook.yesBids[2];
+      assert.equal(last.size.toNumber(), 7);
+      const rank = (side, orderId) =>
+        program.methods
+          .getOrderRank(side, orderId)
+          .accounts({ market: pdas.marketPda, orderBook: pdas.orderBookPda })
+          .view();
+      
+      let result = await rank(0, last.orderId);
+      assert.equal(result.index, 2);
+      assert.equal(result.sizeAhead.toNumber(), 15);
+      result = await rank(0, orderBook.yesBids[0].orderId);
+      assert.equal(result.index, 0);
+      assert.equal(result.sizeAhead.toNumber(), 0);
+      
+      try {
+        await rank(1, last.orderId);
+        assert.fail("Bid should not be found among asks");
+      } catch (err) {
+        // Views fail as a simulation; the error code is in its logs
+        const logs = (err.simulationResponse?.logs ?? err.logs ?? []).join("\n");
+        assert(logs.includes("OrderNotFound"));
+      }
+      console.log(`  ✓ Third bid ranks 2 with 15 shares ahead`);
+    });
+  });
+  
+  // =========================================
+  // 52. PRICE-TIME PRIORITY
+  // =========================================
+  describe("52. Price-Time Priority", () => {
+    
+    it("52.1 Fills equal-priced bids oldest first", async () => {
+      const { pdas } = await createTestMarket(program, provider);
+      const makers = [Keypair.generate(), Keypair.generate(), Keypair.generate()];
+      for (const maker of makers) {
+        await airdrop(provider, maker.publicKey);
+      }
+      for (const maker of makers) {
+        await placeOrder(program, provider, pdas, maker, 0, true, 5000, 10);
+        await sleep(1100);
+      }
+      
+      let orderBook = await program.account.orderBook.fetch(pdas.orderBookPda);
+      const times = orderBook.yesBids.map((o) => o.timestamp.toNumber());
+      assert(times[0] < times[1] && times[1] < times[2]);
+      
+      await placeOrder(program, provider, pdas, null, 1, true, 5000, 15, { orderType: ORDER_TYPE.IOC });
+      orderBook = await program.account.orderBook.fetch(pdas.orderBookPda);
+      assert.equal(orderBook.yesBids.length, 2);
+      assert(orderBook.yesBids[0].owner.equals(makers[1].publicKey));
+      assert.equal(orderBook.yesBids[0].size.toNumber(), 5);
+      assert(orderBook.yesBids[1].owner.equals(makers[2].publicKey));
+      assert.equal(orderBook.yesBids[1].size.toNumber(), 10);
+      console.log(`  ✓ Oldest bid filled fully, next partially, newest untouched`);
+    });
+  });
+  
+  // =========================================
+  // 53. TRADER NOTIONAL CAP
+  // =========================================
+  describe("53. Trader Notional Cap", () => {
+    
+    it("53.1 Accepts orders up to max_trader_notional exactly", async () => {
+      const cap = 10 * SHARE_PAYOUT / 2;
+      const { pdas } = await createTestMarket(program, provider, null, { maxTraderNotional: new anchor.BN(cap) });
+      await placeOrder(program, provider, pdas, null, 0, true, 2000, 10);
+      await placeOrder(program, provider, pdas, null, 0, true, 3000, 10);
+      
+      const position = await program.account.clobPosition.fetch(
+        getPositionPda(program, pdas.marketPda, provider.wallet.publicKey)
+      );
+      assert.equal(position.notional.toNumber(), cap);
+      
+      // Cancelling doesn't give the committed collateral back to the cap
+      await program.methods
+        .cancelAllOrders()
+        .accounts({
+          market: pdas.marketPda,
+          orderBook: pdas.orderBookPda,
+          vault: pdas.vaultPda,
+          trader: provider.wallet.publicKey,
+        })
+        .rpc();
+      try {
+        await placeOrder(program, provider, pdas, null, 0, true, 1, 1);
+        assert.fail("Order past the cap should fail");
+      } catch (err) {
+        assert(err.error?.errorCode?.code === "PositionLimitExceeded");
+      }
+      console.log(`  ✓ Orders committing exactly the cap accepted`);
+    });
+    
+    it("53.2 Rejects an order one lamport over the cap", async () => {
+      const cap = 10 * SHARE_PAYOUT / 2 - 1;
+      const { pdas } = await createTestMarket(program, provider, null, { maxTraderNotional: new anchor.BN(cap) });
+      try {
+        await placeOrder(program, provider, pdas, null, 0, true, 5000, 10);
+        assert.fail("Order past the cap should fail");
+      } catch (err) {
+        assert(err.error?.errorCode?.code === "PositionLimitExceeded");
+      }
+      const orderBook = await program.account.orderBook.fetch(pdas.orderBookPda);
+      assert.equal(orderBook.yesBids.length, 0);
+      console.log(`  ✓ Order one lamport over the cap rejected`);
+    });
+    
+    it("53.3 Counts minted complete sets toward the cap", async () => {
+      const cap = 10 * SHARE_PAYOUT;
+      const { pdas } = await createTestMarket(program, provider, null, { maxTraderNotional: new anchor.BN(cap) });
+      const mint = (sets) =>
+        program.methods
+          .mintCompleteSet(new anchor.BN(sets))
+          .accounts({
+            market: pdas.marketPda,
+            vault: pdas.vaultPda,
+            position: getPositionPda(program, pdas.marketPda, provider.wallet.publicKey),
+            trader: provider.wallet.publicKey,
+            systemProgram: SystemProgram.programId,
+          })
+          .rpc();
+      await mint(6);
+      try {
+        await mint(5);
+        assert.fail("Minting past the cap should fail");
+      } catch (err) {
+        assert(err.error?.errorCode?.code === "PositionLimitExceeded");
+      }
+      await mint(4);
+      
+      const position = await program.account.clobPosition.fetch(
+        getPositionPda(program, pdas.marketPda, provider.wallet.publicKey)
+      );
+      assert.equal(position.notional.toNumber(), cap);
+      console.log(`  ✓ Minted sets filled the cap exactly, then no more`);
+    });
+  });
+  
+  // =========================================
+  // 54. PARTIAL FILL COLLATERAL
+  // =========================================
+  describe("54. Partial Fill Collateral", () => {
+    
+    it("54.1 A bid that partly fills and rests pays the fill price for matched shares", async () => {
+      const { pdas } = await createTestMarket(program, provider);
+      const maker = Keypair.generate();
+      await airdrop(provider, maker.publicKey);
+      await placeOrder(program, provider, pdas, maker, 1, true, 5000, 10);
+      
+      const vaultBefore = await provider.connection.getBalance(pdas.vaultPda);
+      await placeOrder(program, provider, pdas, null, 0, true, 6000, 20);
+      const vaultAfter = await provider.connection.getBalance(pdas.vaultPda);
+      
+      // 10 matched at 5000, 10 resting at the 6000 limit
+      assert.equal(vaultAfter - vaultBefore, 5000 * 10 + 6000 * 10);
+      const state = await getMarketState(program, provider, pdas, []);
+      assertVaultSolvency(state, "after a partial fill that rests");
+      console.log(`  ✓ Bid paid 5000 for matched shares and 6000 for resting ones`);
+    });
+    
+    it("54.2 An ask that partly fills and rests pays BPS_MAX minus the bid price for matched shares", async () => {
+      const { pdas } = await createTestMarket(program, provider);
+      const maker = Keypair.generate();
+      await airdrop(provider, maker.publicKey);
+      await placeOrder(program, provider, pdas, maker, 0, true, 6000, 10);
+      
+      const vaultBefore = await provider.connection.getBalance(pdas.vaultPda);
+      await placeOrder(program, provider, pdas, null, 1, true, 5000, 20);
+      const vaultAfter = await provider.connection.getBalance(pdas.vaultPda);
+      
+      // 10 matched against the 6000 bid, 10 resting at the 5000 limit
+      assert.equal(vaultAfter - vaultBefore, (BPS_MAX - 6000) * 10 + (BPS_MAX - 5000) * 10);
+      const state = await getMarketState(program, provider, pdas, []);
+      assertVaultSolvency(state, "after a partial ask fill that rests");
+      console.log(`  ✓ Ask refunded the 1000 bps of improvement on matched shares`);
+    });
+  });
+  
+  // =========================================
+  // 55. BATCH ESTIMATE
+  // =========================================
+  describe("55. Batch Estimate", () => {
+    
+    it("55.1 Sums claimable payouts across markets and rejects others' positions", async () => {
+      const maker = Keypair.generate();
+      await airdrop(provider, maker.publicKey);
+      const pairs = [];
+      for (const [shares, resolve] of [[10, true], [5, true], [7, false]]) {
+        const { pdas } = await createTestMarket(program, provider);
+        await placeOrder(program, provider, pdas, maker, 1, true, 6000, shares);
+        await placeOrder(program, provider, pdas, null, 0, true, 6000, shares);
+        if (resolve) {
+          await program.methods
+            .resolveClobMarket(0)
+            .accounts({ market: pdas.marketPda, authority: provider.wallet.publicKey })
+            .rpc();
+        }
+        pairs.push({ pubkey: pdas.marketPda, isWritable: false, isSigner: false });
+        pairs.push({
+          pubkey: getPositionPda(program, pdas.marketPda, provider.wallet.publicKey),
+          isWritable: false,
+          isSigner: false,
+        });
+      }
+      
+      const estimate = (owner, accounts) =>
+        program.methods
+          .batchEstimate()
+          .accounts({ owner })
+          .remainingAccounts(accounts)
+          .view();
+      
+      // The unresolved market has nothing to claim yet
+      const total = await estimate(provider.wallet.publicKey, pairs);
+      assert.equal(total.payout.toNumber(), 15 * SHARE_PAYOUT);
+      assert.equal(total.fee.toNumber(), 0);
+      assert.equal(total.netPayout.toNumber(), 15 * SHARE_PAYOUT);
+      
+      try {
+        await estimate(maker.publicKey, pairs);
+        assert.fail("Positions of another owner should be rejected");
+      } catch (err) {
+        const logs = (err.simulationResponse?.logs ?? err.logs ?? []).join("\n");
+        assert(logs.includes("Unauthorized"));
+      }
+      console.log(`  ✓ Two resolved positions sum to ${15 * SHARE_PAYOUT}`);
+    });
+  });
+  
+  // =========================================
+  // 56. ORDER BOOK CAPACITY
+  // =========================================
+  describe("56. Order Book Capacity", () => {
+    
+    it("56.1 Fills up at the configured capacity", async () => {
+      const { pdas } = await createTestMarket(program, provider, null, { orderBookCapacity: 2 });
+      let orderBook = await program.account.orderBook.fetch(pdas.orderBookPda);
+      assert.equal(orderBook.capacity, 2);
+      
+      await placeOrder(program, provider, pdas, null, 0, true, 4000, 10);
+      await placeOrder(program, provider, pdas, null, 0, true, 4100, 10);
+      try {
+        await placeOrder(program, provider, pdas, null, 0, true, 4200, 10);
+        assert.fail("Third bid should not fit");
+      } catch (err) {
+        assert(err.error?.errorCode?.code === "OrderBookFull");
+      }
+      // The other side has its own capacity
+      await placeOrder(program, provider, pdas, null, 1, true, 6000, 10);
+      console.log(`  ✓ Two bids fit a capacity-2 book, the third is rejected`);
+    });
+    
+    it("56.2 Holds more than MAX_ORDERS up to the account size limit", async () => {
+      const { pdas } = await createTestMarket(program, provider, null, { orderBookCapacity: 63 });
+      const orderBook = await program.account.orderBook.fetch(pdas.orderBookPda);
+      assert.equal(orderBook.capacity, 63);
+      
+      try {
+        await createTestMarket(program, provider, null, { orderBookCapacity: 64 });
+        assert.fail("Capacity past the account size limit should fail");
+      } catch (err) {
+        assert(err.error?.errorCode?.code === "InvalidCapacity");
+      }
+      console.log(`  ✓ Capacity 63 accepted, 64 rejected`);
+    });
+    
+    it("56.3 Defaults to MAX_ORDERS", async () => {
+      const { pdas } = await createTestMarket(program, provider);
+      const orderBook = await program.account.orderBook.fetch(pdas.orderBookPda);
+      assert.equal(orderBook.capacity, 50);
+    });
+  });
+  
+  // =========================================
+  // 57. MULTI-LEVEL SWEEP COMPACTION
+  // =========================================
+  describe("57. Multi-Level Sweep Compaction", () => {
+    
+    it("57.1 A sweep across several levels leaves the rest of the book intact and in order", async () => {
+      const { pdas } = await createTestMarket(program, provider);
+      const maker = Keypair.generate();
+      await airdrop(provider, maker.publicKey);
+      for (const price of [5000, 5000, 5100, 5200, 5200, 5300]) {
+        await placeOrder(program, provider, pdas, maker, 1, true, price, 10);
+      }
+      
+      // Takes both 5000s, the 5100 and half of the first 5200
+      await placeOrder(program, provider, pdas, null, 0, true, 5300, 35, { orderType: ORDER_TYPE.IOC });
+      
+      const orderBook = await program.account.orderBook.fetch(pdas.orderBookPda);
+      assert.deepEqual(orderBook.yesAsks.map((o) => o.price.toNumber()), [5200, 5200, 5300]);
+      assert.deepEqual(orderBook.yesAsks.map((o) => o.size.toNumber()), [5, 10, 10]);
+      assert.equal(orderBook.totalAskSize.toNumber(), 25);
+      const position = await program.account.clobPosition.fetch(
+        getPositionPda(program, pdas.marketPda, provider.wallet.publicKey)
+      );
+      assert.equal(position.yesShares.toNumber(), 35);
+      console.log(`  ✓ Three and a half levels swept, remaining asks compacted in order`);
+    });
+  });
+  
+  // =========================================
+  // 58. REDUCE-ONLY ORDERS
+  // =========================================
+  describe("58. Reduce-Only Orders", () => {
+    
+    it("58.1 Cuts a reduce-only sell to the net position and never flips it", async () => {
+      const { pdas } = await createTestMarket(program, provider);
+      const maker = Keypair.generate();
+      await airdrop(provider, maker.publicKey);
+      
+      // Net long 10 YES
+      await placeOrder(program, provider, pdas, maker, 1, true, 5000, 10);
+      await placeOrder(program, provider, pdas, null, 0, true, 5000, 10);
+      
+      // A reduce-only buy would only add to the long
+      try {
+        await placeOrder(program, provider, pdas, null, 0, true, 4000, 5, { reduceOnly: true });
+        assert.fail("Reduce-only buy on a long position should fail");
+      } catch (err) {
+        assert(err.error?.errorCode?.code === "ReduceOnlyNothingToReduce");
+      }
+      
+      await placeOrder(program, provider, pdas, maker, 0, true, 5000, 20);
+      await placeOrder(program, provider, pdas, null, 1, true, 5000, 15, {
+        orderType: ORDER_TYPE.IOC,
+        reduceOnly: true,
+      });
+      const positionPda = getPositionPda(program, pdas.marketPda, provider.wallet.publicKey);
+      const position = await program.account.clobPosition.fetch(positionPda);
+      assert.equal(position.yesShares.toNumber(), 10);
+      assert.equal(position.noShares.toNumber(), 10);
+      const orderBook = await program.account.orderBook.fetch(pdas.orderBookPda);
+      assert.equal(orderBook.yesBids[0].size.toNumber(), 10);
+      
+      try {
+        await placeOrder(program, provider, pdas, null, 1, true, 5000, 1, { reduceOnly: true });
+        assert.fail("A flat position has nothing to reduce");
+      } catch (err) {
+        assert(err.error?.errorCode?.code === "ReduceOnlyNothingToReduce");
+      }
+      console.log(`  ✓ Reduce-only sell of 15 cut to the 10 held, then rejected once flat`);
+    });
+  });
+  
+  // =========================================
+  // 59. REFERRAL FEES
+  // =========================================
+  describe("59. Referral Fees", () => {
+    
+    it("59.1 Pays the referrer's cut of the taker fee and lets them claim it", async () => {
+      const maker = Keypair.generate();
+      const referrer = Keypair.generate();
+      await airdrop(provider, maker.publicKey);
+      await airdrop(provider, referrer.publicKey);
+      const [referralPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("referral"), referrer.publicKey.toBuffer()],
+        program.programId
+      );
+      await program.methods
+        .initReferral()
+        .accounts({ referral: referralPda, referrer: referrer.publicKey, systemProgram: SystemProgram.programId })
+        .signers([referrer])
+        .rpc();
+      
+      const { pdas } = await createTestMarket(program, provider, null, {
+        takerFeeBps: new anchor.BN(100), // 1%
+        referralFeeBps: new anchor.BN(2000), // 20% of the fee
+      });
+      await placeOrder(program, provider, pdas, maker, 1, true, 5000, 100);
+      
+      // The referral account has to come along once a cut is owed
+      try {
+        await placeOrder(program, provider, pdas, null, 0, true, 5000, 10, { referrer: referrer.publicKey });
+        assert.fail("Referred fill without the referral account should fail");
+      } catch (err) {
+        assert(err.error?.errorCode?.code === "ReferralAccountMissing");
+      }
+      
+      const vaultBefore = await provider.connection.getBalance(pdas.vaultPda);
+      await placeOrder(program, provider, pdas, null, 0, true, 5000, 100, {
+        referrer: referrer.publicKey,
+        remainingAccounts: [{ pubkey: referralPda, isWritable: true, isSigner: false }],
+      });
+      const vaultAfter = await provider.connection.getBalance(pdas.vaultPda);
+      
+      // 5000 lamports of fee: 1000 to the referrer, 4000 kept by the market
+      const market = await program.account.clobMarket.fetch(pdas.marketPda);
+      assert.equal(market.feesAccrued.toNumber(), 4000);
+      assert.equal(vaultAfter - vaultBefore, 5000 * 100 + 4000);
+      let referral = await program.account.referralAccount.fetch(referralPda);
+      assert.equal(referral.unclaimed.toNumber(), 1000);
+      assert.equal(referral.totalEarned.toNumber(), 1000);
+      
+      const balanceBefore = await provider.connection.getBalance(referrer.publicKey);
+      await program.methods
+        .claimReferralFees()
+        .accounts({ referral: referralPda, referrer: referrer.publicKey })
+        .signers([referrer])
+        .rpc();
+      const balanceAfter = await provider.connection.getBalance(referrer.publicKey);
+      referral = await program.account.referralAccount.fetch(referralPda);
+      assert.equal(referral.unclaimed.toNumber(), 0);
+      assert.equal(referral.totalEarned.toNumber(), 1000);
+      assert.equal(balanceAfter - balanceBefore, 1000); // The provider pays the tx fee
+      console.log(`  ✓ Referrer earned and claimed 1000 of a 5000 lamport taker fee`);
+    });
+    
+    it("59.2 Rejects naming yourself as referrer", async () => {
+      const { pdas } = await createTestMarket(program, provider, null, {
+        takerFeeBps: new anchor.BN(100),
+        referralFeeBps: new anchor.BN(2000),
+      });
+      try {
+        await placeOrder(program, provider, pdas, null, 0, true, 5000, 10, { referrer: provider.wallet.publicKey });
+        assert.fail("Self-referral should fail");
+      } catch (err) {
+        assert(err.error?.errorCode?.code === "SelfReferral");
+      }
+      console.log(`  ✓ Self-referral rejected`);
+    });
+  });
+  
+  // =========================================
+  // 60. CLOSING SETTLED MARKETS
+  // =========================================
+  describe("60. Closing Settled Markets", () => {
+    
+    it("60.1 Closes the market only once every share is claimed", async () => {
+      const { pdas } = await createTestMarket(program, provider);
+      const [priceHistoryPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("price_history"), pdas.marketPda.toBuffer()],
+        program.programId
+      );
+      const maker = Keypair.generate();
+      await airdrop(provider, maker.publicKey);
+      await placeOrder(program, provider, pdas, maker, 1, true, 6000, 10);
+      await placeOrder(program, provider, pdas, null, 0, true, 6000, 10);
+      await program.methods
+        .resolveClobMarket(0)
+        .accounts({ market: pdas.marketPda, authority: provider.wallet.publicKey })
+        .rpc();
+      
+      const close = () =>
+        program.methods
+          .closeClobMarket()
+          .accounts({
+            market: pdas.marketPda,
+            orderBook: pdas.orderBookPda,
+            priceHistory: priceHistoryPda,
+            vault: pdas.vaultPda,
+            authority: provider.wallet.publicKey,
+          })
+          .rpc();
+      try {
+        await close();
+        assert.fail("Close with unclaimed winning shares should fail");
+      } catch (err) {
+        assert(err.error?.errorCode?.code === "MarketNotSettled");
+      }
+      
+      await program.methods
+        .claimClobWinnings(new anchor.BN(0))
+        .accounts({
+          market: pdas.marketPda,
+          vault: pdas.vaultPda,
+          position: getPositionPda(program, pdas.marketPda, provider.wallet.publicKey),
+          claimer: provider.wallet.publicKey,
+        })
+        .rpc();
+      const market = await program.account.clobMarket.fetch(pdas.marketPda);
+      assert.equal(market.openInterest.toNumber(), 0);
+      
+      await close();
+      for (const pda of [pdas.orderBookPda, priceHistoryPda, pdas.vaultPda]) {
+        assert.equal(await provider.connection.getAccountInfo(pda), null);
+      }
+      const closed = await program.account.clobMarket.fetch(pdas.marketPda);
+      assert.equal(closed.closed, true);
+      console.log(`  ✓ Book, history and vault closed after the last claim; the market stays as a tombstone`);
+    });
+    
+    it("60.2 Refuses to close while orders rest on the book", async () => {
+      const { pdas } = await createTestMarket(program, provider);
+      const [priceHistoryPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("price_history"), pdas.marketPda.toBuffer()],
+        program.programId
+      );
+      await placeOrder(program, provider, pdas, null, 0, true, 4000, 10);
+      await program.methods
+        .resolveClobMarket(1)
+        .accounts({ market: pdas.marketPda, authority: provider.wallet.publicKey })
+        .rpc();
+      try {
+        await program.methods
+          .closeClobMarket()
+          .accounts({
+            market: pdas.marketPda,
+            orderBook: pdas.orderBookPda,
+            priceHistory: priceHistoryPda,
+            vault: pdas.vaultPda,
+            authority: provider.wallet.publicKey,
+          })
+          .rpc();
+        assert.fail("Close with a resting order should fail");
+      } catch (err) {
+        assert(err.error?.errorCode?.code === "MarketNotSettled");
+      }
+      console.log(`  ✓ Resting order kept the market open`);
+    });
+    
+    it("60.3 Closes once the winners have claimed, even when a taker lost", async () => {
+      const { pdas } = await createTestMarket(program, provider);
+      const [priceHistoryPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("price_history"), pdas.marketPda.toBuffer()],
+        program.programId
+      );
+      const maker = Keypair.generate();
+      await airdrop(provider, maker.publicKey);
+      await placeOrder(program, provider, pdas, maker, 0, true, 6000, 10);
+      // The taker buys NO into the maker's YES bid, then YES wins
+      await placeOrder(program, provider, pdas, null, 0, false, 4000, 10);
+      await program.methods
+        .resolveClobMarket(0)
+        .accounts({ market: pdas.marketPda, authority: provider.wallet.publicKey })
+        .rpc();
+      
+      const market = await program.account.clobMarket.fetch(pdas.marketPda);
+      assert.equal(market.yesOutstanding.toNumber(), 0);
+      assert.equal(market.noOutstanding.toNumber(), 10);
+      
+      await program.methods
+        .closeClobMarket()
+        .accounts({
+          market: pdas.marketPda,
+          orderBook: pdas.orderBookPda,
+          priceHistory: priceHistoryPda,
+          vault: pdas.vaultPda,
+          authority: provider.wallet.publicKey,
+        })
+        .rpc();
+      const closed = await program.account.clobMarket.fetch(pdas.marketPda);
+      assert.equal(closed.closed, true);
+      console.log(`  ✓ Losing NO shares didn't hold the market open`);
+    });
+    
+    it("60.4 Keeps a closed market's id from being created again", async () => {
+      const { marketId, pdas } = await createTestMarket(program, provider);
+      const [priceHistoryPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("price_history"), pdas.marketPda.toBuffer()],
+        program.programId
+      );
+      const maker = Keypair.generate();
+      await airdrop(provider, maker.publicKey);
+      await placeOrder(program, provider, pdas, maker, 0, true, 6000, 10);
+      await placeOrder(program, provider, pdas, null, 0, false, 4000, 10);
+      await program.methods
+        .resolveClobMarket(0)
+        .accounts({ market: pdas.marketPda, authority: provider.wallet.publicKey })
+        .rpc();
+      await program.methods
+        .closeClobMarket()
+        .accounts({
+          market: pdas.marketPda,
+          orderBook: pdas.orderBookPda,
+          priceHistory: priceHistoryPda,
+          vault: pdas.vaultPda,
+          authority: provider.wallet.publicKey,
+        })
+        .rpc();
+      
+      // Another creator can't take over the id and its leftover positions
+      const squatter = Keypair.generate();
+      await airdrop(provider, squatter.publicKey);
+      try {
+        await program.methods
+          .createClobMarket(marketId, "Test Market", new anchor.BN(Math.floor(Date.now() / 1000) + 86400), defaultClobConfig())
+          .accounts({
+            market: pdas.marketPda,
+            orderBook: pdas.orderBookPda,
+            vault: pdas.vaultPda,
+            authority: squatter.publicKey,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([squatter])
+          .rpc();
+        assert.fail("Re-creating a closed market's id should fail");
+      } catch (err) {
+        assert((err.logs ?? []).some((log) => log.includes("already in use")));
+      }
+      
+      // The losing NO shares left on the taker's position can't be claimed
+      try {
+        await program.methods
+          .claimClobWinnings(new anchor.BN(0))
+          .accounts({
+            market: pdas.marketPda,
+            vault: pdas.vaultPda,
+            position: getPositionPda(program, pdas.marketPda, provider.wallet.publicKey),
+            claimer: provider.wallet.publicKey,
+          })
+          .rpc();
+        assert.fail("Claim on a closed market should fail");
+      } catch (err) {
+        assert(err.error?.errorCode?.code === "MarketClosed");
+      }
+      console.log(`  ✓ Closed id can't be re-created and its old positions can't claim`);
+    });
+  });
+  
+  // =========================================
+  // 61. SEED LIQUIDITY
+  // =========================================
+  describe("61. Seed Liquidity", () => {
+    const seed = (price, halfSpread, size) => ({
+      seedLiquidity: {
+        price: new anchor.BN(price),
+        halfSpread: new anchor.BN(halfSpread),
+        size: new anchor.BN(size),
+      },
+    });
+    
+    it("61.1 Opens the book with the authority's bid and ask", async () => {
+      const { pdas } = await createTestMarket(program, provider, null, seed(6000, 500, 100));
+      const market = await program.account.clobMarket.fetch(pdas.marketPda);
+      assert.equal(market.seeded, true);
+      
+      const orderBook = await program.account.orderBook.fetch(pdas.orderBookPda);
+      assert.equal(orderBook.yesBids.length, 1);
+      assert.equal(orderBook.yesAsks.length, 1);
+      assert.equal(orderBook.yesBids[0].price.toNumber(), 5500);
+      assert.equal(orderBook.yesAsks[0].price.toNumber(), 6500);
+      assert(orderBook.yesAsks[0].owner.equals(provider.wallet.publicKey));
+      
+      // 5500 * 100 for the bid and (10000 - 6500) * 100 for the ask
+      const vault = await provider.connection.getBalance(pdas.vaultPda);
+      assert.equal(vault - market.vaultReserve.toNumber(), 5500 * 100 + 3500 * 100);
+      
+      const taker = Keypair.generate();
+      await airdrop(provider, taker.publicKey);
+      await placeOrder(program, provider, pdas, taker, 0, true, 6500, 10, { orderType: ORDER_TYPE.IOC });
+      const after = await program.account.orderBook.fetch(pdas.orderBookPda);
+      assert.equal(after.yesAsks[0].size.toNumber(), 90);
+      console.log(`  ✓ Seeded 100 @ 5500 / 6500 and a taker lifted 10 of the ask`);
+    });
+    
+    it("61.2 Rejects seeds that break the normal order rules", async () => {
+      const cases = [
+        [seed(5000, 0, 100), "InvalidSeed"],
+        [seed(300, 500, 100), "InvalidPrice"],
+        [{ ...seed(5000, 500, 15), lotSize: new anchor.BN(10) }, "InvalidLot"],
+        [{ ...seed(5000, 500, 100), maxPrice: new anchor.BN(5200) }, "PriceOutOfBand"],
+      ];
+      for (const [config, code] of cases) {
+        try {
+          await createTestMarket(program, provider, null, config);
+          assert.fail(`Seed should fail with ${code}`);
+        } catch (err) {
+          assert(err.error?.errorCode?.code === code, `expected ${code}`);
+        }
+      }
+      console.log(`  ✓ Zero spread, sub-zero bid, off-lot size and out-of-band ask rejected`);
+    });
+  });
+  
+  // =========================================
+  // 62. ACCOUNT VERSIONS
+  // =========================================
+  describe("62. Account Versions", () => {
+    
+    it("62.1 Stamps new accounts with the current version and refuses to migrate them", async () => {
+      const { pdas } = await createTestMarket(program, provider);
+      const market = await program.account.clobMarket.fetch(pdas.marketPda);
+      const orderBook = await program.account.orderBook.fetch(pdas.orderBookPda);
+      assert.equal(market.version, 8);
+      assert.equal(orderBook.version, 2);
+      
+      const migrate = (account) =>
+        program.methods
+          .migrateMarket()
+          .accounts({ market: account, payer: provider.wallet.publicKey, systemProgram: SystemProgram.programId })
+          .rpc();
+      for (const account of [pdas.marketPda, pdas.orderBookPda]) {
+        try {
+          await migrate(account);
+          assert.fail("Current accounts have nothing to migrate");
+        } catch (err) {
+          assert(err.error?.errorCode?.code === "AlreadyMigrated");
+        }
+      }
+      
+      // Positions aren't versioned
+      await placeOrder(program, provider, pdas, null, 0, true, 4000, 10);
+      try {
+        await migrate(getPositionPda(program, pdas.marketPda, provider.wallet.publicKey));
+        assert.fail("A position is not a migratable account");
+      } catch (err) {
+        assert(err.error?.errorCode?.code === "UnknownAccountType");
+      }
+      console.log(`  ✓ Market at version 8, book at version 2; migrate rejects current and unversioned accounts`);
+    });
+  });
+  
+  // =========================================
+  // 63. ORDER QUOTES
+  // =========================================
+  describe("63. Order Quotes", () => {
+    
+    it("63.1 Quotes fills, cost and average price without touching the book", async () => {
+      const { pdas } = await createTestMarket(program, provider, null, {
+        payoutPerShare: new anchor.BN(10_000),
+        takerFeeBps: new anchor.BN(100),
+      });
+      await placeOrder(program, provider, pdas, null, 1, true, 4000, 5);
+      await placeOrder(program, provider, pdas, null, 1, true, 5000, 10);
+      const taker = Keypair.generate().publicKey;
+      const quote = (side, isYes, price, size) =>
+        program.methods
+          .quoteOrder(side, isYes, new anchor.BN(price), new anchor.BN(size), taker)
+          .accounts({ market: pdas.marketPda, orderBook: pdas.orderBookPda })
+          .view();
+      
+      let result = await quote(0, true, 5000, 20);
+      assert.equal(result.filled.toNumber(), 15);
+      assert.equal(result.unfilled.toNumber(), 5);
+      assert.equal(result.cost.toNumber(), 4000 * 5 + 5000 * 10);
+      assert.equal(result.takerFee.toNumber(), 700);
+      assert.equal(result.avgPrice.toNumber(), 4666);
+      
+      // Selling NO at 6000 buys YES at up to 4000: only the first ask crosses
+      result = await quote(1, false, 6000, 8);
+      assert.equal(result.filled.toNumber(), 5);
+      assert.equal(result.cost.toNumber(), 4000 * 5);
+      assert.equal(result.avgPrice.toNumber(), 6000);
+      
+      // Nothing to buy NO against
+      result = await quote(0, false, 7000, 8);
+      assert.equal(result.filled.toNumber(), 0);
+      assert.equal(result.unfilled.toNumber(), 8);
+      assert.equal(result.avgPrice.toNumber(), 0);
+      
+      const orderBook = await program.account.orderBook.fetch(pdas.orderBookPda);
+      assert.equal(orderBook.yesAsks.length, 2);
+      assert.equal(orderBook.yesAsks[0].size.toNumber(), 5);
+      console.log(`  ✓ 15 of 20 quoted at avg 4666 bps; the book is unchanged`);
+    });
+  });
+  
+  // =========================================
+  // 64. AUTHORITY TRADING
+  // =========================================
+  describe("64. Authority Trading", () => {
+    
+    it("64.1 Rejects the authority's orders only when authority_can_trade is off", async () => {
+      const { pdas: open } = await createTestMarket(program, provider);
+      await placeOrder(program, provider, open, null, 0, true, 5000, 10);
+      
+      const { pdas } = await createTestMarket(program, provider, null, { authorityCanTrade: false });
+      try {
+        await placeOrder(program, provider, pdas, null, 0, true, 5000, 10);
+        assert.fail("The authority should not be able to trade");
+      } catch (err) {
+        assert(err.error?.errorCode?.code === "AuthorityCannotTrade");
+      }
+      const trader = Keypair.generate();
+      await airdrop(provider, trader.publicKey);
+      await placeOrder(program, provider, pdas, trader, 0, true, 5000, 10);
+      
+      try {
+        await createTestMarket(program, provider, null, {
+          authorityCanTrade: false,
+          seedLiquidity: { price: new anchor.BN(5000), halfSpread: new anchor.BN(500), size: new anchor.BN(10) },
+        });
+        assert.fail("Seeding is trading by the authority");
+      } catch (err) {
+        assert(err.error?.errorCode?.code === "AuthorityCannotTrade");
+      }
+      console.log(`  ✓ Authority orders allowed by default, rejected when barred, seeding included`);
+    });
+  });
+  
+  // =========================================
+  // 65. ORDER FILL TRACKING
+  // =========================================
+  describe("65. Order Fill Tracking", () => {
+    
+    it("65.1 Records fills on resting makers and on a taker's rested remainder", async () => {
+      const { pdas } = await createTestMarket(program, provider);
+      const maker = Keypair.generate();
+      await airdrop(provider, maker.publicKey);
+      await placeOrder(program, provider, pdas, maker, 1, true, 5000, 100);
+      let orderBook = await program.account.orderBook.fetch(pdas.orderBookPda);
+      assert.equal(orderBook.yesAsks[0].filled.toNumber(), 0);
+      
+      await placeOrder(program, provider, pdas, null, 0, true, 5000, 30);
+      orderBook = await program.account.orderBook.fetch(pdas.orderBookPda);
+      assert.equal(orderBook.yesAsks[0].size.toNumber(), 70);
+      assert.equal(orderBook.yesAsks[0].filled.toNumber(), 30);
+      
+      // Takes the other 70 and rests 30 that already count 70 filled
+      await placeOrder(program, provider, pdas, null, 0, true, 5500, 100);
+      orderBook = await program.account.orderBook.fetch(pdas.orderBookPda);
+      assert.equal(orderBook.yesAsks.length, 0);
+      const rested = orderBook.yesBids[0];
+      assert.equal(rested.size.toNumber(), 30);
+      assert.equal(rested.filled.toNumber(), 70);
+      
+      const sig = await program.methods
+        .cancelOrder(true, rested.orderId)
+        .accounts({
+          market: pdas.marketPda,
+          orderBook: pdas.orderBookPda,
+          vault: pdas.vaultPda,
+          trader: authority.publicKey,
+        })
+        .rpc({ commitment: "confirmed" });
+      const [event] = findEvents(await getEvents(program, provider, sig), "OrderCancelled");
+      assert.equal(event.data.size.toNumber(), 30);
+      assert.equal(event.data.filled.toNumber(), 70);
+      console.log(`  ✓ Maker shows 30 filled, 70 left; cancelled remainder reports 70 filled`);
+    });
+  });
+  
+  // =========================================
+  // 66. EMERGENCY WITHDRAW
+  // =========================================
+  describe("66. Emergency Withdraw", () => {
+    const emergencyWithdraw = (pdas, signer = null) => {
+      const builder = program.methods.emergencyWithdraw().accounts({
+        market: pdas.marketPda,
+        vault: pdas.vaultPda,
+        authority: signer ? signer.publicKey : authority.publicKey,
+      });
+      return signer ? builder.signers([signer]).rpc() : builder.rpc();
+    };
+    
+    it("66.1 Is refused on markets created without allow_emergency", async () => {
+      const { pdas } = await createTestMarket(program, provider);
+      const market = await program.account.clobMarket.fetch(pdas.marketPda);
+      assert.equal(market.allowEmergency, false);
+      try {
+        await emergencyWithdraw(pdas);
+        assert.fail("Emergency withdraw should be disabled");
+      } catch (err) {
+        assert(err.error?.errorCode?.code === "EmergencyWithdrawDisabled");
+      }
+      console.log(`  ✓ Disabled by default`);
+    });
+    
+    it("66.2 Stays locked until the timelock and to anyone but the authority", async () => {
+      const { pdas } = await createTestMarket(program, provider, null, { allowEmergency: true });
+      await placeOrder(program, provider, pdas, null, 0, true, 5000, 10);
+      const vaultBefore = await provider.connection.getBalance(pdas.vaultPda);
+      try {
+        await emergencyWithdraw(pdas);
+        assert.fail("The timelock runs from resolution time");
+      } catch (err) {
+        assert(err.error?.errorCode?.code === "EmergencyTimelockActive");
+      }
+      
+      const outsider = Keypair.generate();
+      await airdrop(provider, outsider.publicKey);
+      try {
+        await emergencyWithdraw(pdas, outsider);
+        assert.fail("Only the authority may withdraw");
+      } catch (err) {
+        assert(err.error?.errorCode?.code === "Unauthorized");
+      }
+      assert.equal(await provider.connection.getBalance(pdas.vaultPda), vaultBefore);
+      console.log(`  ✓ Enabled market's vault untouched before the timelock`);
+    });
+  });
+  
+  // =========================================
+  // 67. INSURANCE BUFFER
+  // =========================================
+  describe("67. Insurance Buffer", () => {
+    
+    it("67.1 Holds the buffer apart from collateral and returns it once settled", async () => {
+      const { pdas } = await createTestMarket(program, provider);
+      const insurance = (method, args = []) =>
+        program.methods[method](...args)
+          .accounts({
+            market: pdas.marketPda,
+            vault: pdas.vaultPda,
+            authority: provider.wallet.publicKey,
+            systemProgram: SystemProgram.programId,
+          })
+          .rpc();
+      const buffer = LAMPORTS_PER_SOL / 10;
+      
+      const vaultBefore = await provider.connection.getBalance(pdas.vaultPda);
+      await insurance("fundInsurance", [new anchor.BN(buffer)]);
+      assert.equal(await provider.connection.getBalance(pdas.vaultPda), vaultBefore + buffer);
+      let market = await program.account.clobMarket.fetch(pdas.marketPda);
+      assert.equal(market.insuranceBalance.toNumber(), buffer);
+      
+      try {
+        await insurance("withdrawInsurance");
+        assert.fail("The buffer stays until the market is settled");
+      } catch (err) {
+        assert(err.error?.errorCode?.code === "MarketNotSettled");
+      }
+      
+      const maker = Keypair.generate();
+      await airdrop(provider, maker.publicKey);
+      await placeOrder(program, provider, pdas, maker, 1, true, 6000, 10);
+      await placeOrder(program, provider, pdas, null, 0, true, 6000, 10);
+      await program.methods
+        .resolveClobMarket(0)
+        .accounts({ market: pdas.marketPda, authority: provider.wallet.publicKey })
+        .rpc();
+      await program.methods
+        .claimClobWinnings(new anchor.BN(0))
+        .accounts({
+          market: pdas.marketPda,
+          vault: pdas.vaultPda,
+          position: getPositionPda(program, pdas.marketPda, provider.wallet.publicKey),
+          claimer: provider.wallet.publicKey,
+        })
+        .rpc();
+      // Collateral covered the claim, so the buffer is untouched
+      market = await program.account.clobMarket.fetch(pdas.marketPda);
+      assert.equal(market.insuranceBalance.toNumber(), buffer);
+      
+      const vaultSettled = await provider.connection.getBalance(pdas.vaultPda);
+      await insurance("withdrawInsurance");
+      assert.equal(await provider.connection.getBalance(pdas.vaultPda), vaultSettled - buffer);
+      market = await program.account.clobMarket.fetch(pdas.marketPda);
+      assert.equal(market.insuranceBalance.toNumber(), 0);
+      try {
+        await insurance("withdrawInsurance");
+        assert.fail("Nothing left to withdraw");
+      } catch (err) {
+        assert(err.error?.errorCode?.code === "NoInsuranceToWithdraw");
+      }
+      console.log(`  ✓ ${buffer} lamport buffer untouched by the claim and withdrawn after settlement`);
+    });
+    
+    it("67.2 Returns the buffer when the only taker lost", async () => {
+      const { pdas } = await createTestMarket(program, provider);
+      const insurance = (method, args = []) =>
+        program.methods[method](...args)
+          .accounts({
+            market: pdas.marketPda,
+            vault: pdas.vaultPda,
+            authority: provider.wallet.publicKey,
+            systemProgram: SystemProgram.programId,
+          })
+          .rpc();
+      const buffer = LAMPORTS_PER_SOL / 10;
+      await insurance("fundInsurance", [new anchor.BN(buffer)]);
+      
+      const maker = Keypair.generate();
+      await airdrop(provider, maker.publicKey);
+      await placeOrder(program, provider, pdas, maker, 0, true, 6000, 10);
+      await placeOrder(program, provider, pdas, null, 0, false, 4000, 10);
+      await program.methods
+        .resolveClobMarket(0)
+        .accounts({ market: pdas.marketPda, authority: provider.wallet.publicKey })
+        .rpc();
+      
+      // The taker's NO shares lost and will never be claimed
+      const vaultBefore = await provider.connection.getBalance(pdas.vaultPda);
+      await insurance("withdrawInsurance");
+      assert.equal(await provider.connection.getBalance(pdas.vaultPda), vaultBefore - buffer);
+      console.log(`  ✓ Buffer withdrawn with only losing shares outstanding`);
+    });
+  });
+  
+  // =========================================
+  // 68. RESOLUTION METADATA
+  // =========================================
+  describe("68. Resolution Metadata", () => {
+    
+    it("68.1 Stores the resolution URI and source, within the length cap", async () => {
+      const { pdas } = await createTestMarket(program, provider);
+      const resolve = (uri, source) =>
+        program.methods
+          .resolveClobMarket(0, uri, source)
+          .accounts({ market: pdas.marketPda, authority: provider.wallet.publicKey })
+          .rpc();
+      
+      try {
+        await resolve("ar://" + "x".repeat(200), null);
+        assert.fail("URI over 200 chars should fail");
+      } catch (err) {
+        assert(err.error?.errorCode?.code === "ResolutionUriTooLong");
+      }
+      
+      const source = Keypair.generate().publicKey;
+      await resolve("ipfs://bafyresolution", source);
+      const market = await program.account.clobMarket.fetch(pdas.marketPda);
+      assert.equal(market.resolutionUri, "ipfs://bafyresolution");
+      assert(market.resolutionSource.equals(source));
+      
+      // Both are optional
+      const { pdas: plain } = await createTestMarket(program, provider);
+      await program.methods
+        .resolveClobMarket(1, null, null)
+        .accounts({ market: plain.marketPda, authority: provider.wallet.publicKey })
+        .rpc();
+      const plainMarket = await program.account.clobMarket.fetch(plain.marketPda);
+      assert.equal(plainMarket.resolutionUri, "");
+      assert(plainMarket.resolutionSource.equals(PublicKey.default));
+      console.log(`  ✓ URI and source recorded; omitted fields stay empty`);
+    });
+  });
+  
+  // =========================================
+  // 69. ORDER BOOK SETTLEMENT
+  // =========================================
+  describe("69. Order Book Settlement", () => {
+    
+    it("69.1 Refunds every resting order after resolution, a page at a time", async () => {
+      const { pdas } = await createTestMarket(program, provider);
+      const alice = Keypair.generate();
+      const bob = Keypair.generate();
+      await airdrop(provider, alice.publicKey);
+      await airdrop(provider, bob.publicKey);
+      await placeOrder(program, provider, pdas, alice, 0, true, 4000, 10);
+      await placeOrder(program, provider, pdas, bob, 0, true, 3000, 10);
+      await placeOrder(program, provider, pdas, alice, 1, true, 7000, 10);
+      
+      const wallets = [alice, bob].map((k) => ({ pubkey: k.publicKey, isWritable: true, isSigner: false }));
+      const settle = (maxOrders, owners = wallets) =>
+        program.methods
+          .settleOrderBook(maxOrders)
+          .accounts({
+            market: pdas.marketPda,
+            orderBook: pdas.orderBookPda,
+            vault: pdas.vaultPda,
+            authority: provider.wallet.publicKey,
+          })
+          .remainingAccounts(owners)
+          .rpc();
+      
+      try {
+        await settle(10);
+        assert.fail("Live books can't be settled");
+      } catch (err) {
+        assert(err.error?.errorCode?.code === "NotResolved");
+      }
+      await program.methods
+        .resolveClobMarket(0, null, null)
+        .accounts({ market: pdas.marketPda, authority: provider.wallet.publicKey })
+        .rpc();
+      try {
+        await settle(10, wallets.slice(0, 1));
+        assert.fail("Bob's wallet is needed for his refund");
+      } catch (err) {
+        assert(err.error?.errorCode?.code === "OrderOwnerMissing");
+      }
+      
+      const bobBefore = await provider.connection.getBalance(bob.publicKey);
+      await settle(2);
+      let orderBook = await program.account.orderBook.fetch(pdas.orderBookPda);
+      assert.equal(orderBook.yesBids.length, 0);
+      assert.equal(orderBook.yesAsks.length, 1);
+      assert.equal(await provider.connection.getBalance(bob.publicKey), bobBefore + 3000 * 10);
+      
+      const aliceBefore = await provider.connection.getBalance(alice.publicKey);
+      await settle(2);
+      orderBook = await program.account.orderBook.fetch(pdas.orderBookPda);
+      assert.equal(orderBook.yesAsks.length, 0);
+      assert.equal(orderBook.totalAskSize.toNumber(), 0);
+      assert.equal(await provider.connection.getBalance(alice.publicKey), aliceBefore + (BPS_MAX - 7000) * 10);
+      console.log(`  ✓ Three orders refunded over two pages`);
+    });
+  });
+  
+  // =========================================
+  // 70. MARKET GROUPS
+  // =========================================
+  describe("70. Market Groups", () => {
+    
+    it("70.1 Resolves exactly one market of a group YES", async () => {
+      const groupId = "grp-" + Date.now().toString(36);
+      const [groupPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("market_group"), provider.wallet.publicKey.toBuffer(), Buffer.from(groupId)],
+        program.programId
+      );
+      await program.methods
+        .createMarketGroup(groupId)
+        .accounts({ group: groupPda, authority: provider.wallet.publicKey, systemProgram: SystemProgram.programId })
+        .rpc();
+      
+      const markets = [];
+      for (let i = 0; i < 3; i++) {
+        const { pdas } = await createTestMarket(program, provider);
+        await program.methods
+          .addToMarketGroup()
+          .accounts({ group: groupPda, market: pdas.marketPda, authority: provider.wallet.publicKey })
+          .rpc();
+        markets.push(pdas.marketPda);
+      }
+      
+      try {
+        await program.methods
+          .resolveClobMarket(0, null, null)
+          .accounts({ market: markets[0], authority: provider.wallet.publicKey })
+          .rpc();
+        assert.fail("Grouped markets can't resolve alone");
+      } catch (err) {
+        assert(err.error?.errorCode?.code === "MarketInGroup");
+      }
+      
+      const groupResolve = (order) =>
+        program.methods
+          .groupResolve(1)
+          .accounts({ group: groupPda, authority: provider.wallet.publicKey })
+          .remainingAccounts(order.map((pubkey) => ({ pubkey, isWritable: true, isSigner: false })))
+          .rpc();
+      try {
+        await groupResolve([markets[1], markets[0], markets[2]]);
+        assert.fail("Markets must come in group order");
+      } catch (err) {
+        assert(err.error?.errorCode?.code === "GroupMarketsMismatch");
+      }
+      
+      await groupResolve(markets);
+      const sides = [];
+      for (const pda of markets) {
+        const market = await program.account.clobMarket.fetch(pda);
+        assert.equal(market.resolved, true);
+        assert(market.marketGroup.equals(groupPda));
+        sides.push(market.winningSide);
+      }
+      assert.deepEqual(sides, [1, 0, 1]);
+      const group = await program.account.marketGroup.fetch(groupPda);
+      assert(group.winner.equals(markets[1]));
+      
+      try {
+        await groupResolve(markets);
+        assert.fail("A group resolves once");
+      } catch (err) {
+        assert(err.error?.errorCode?.code === "AlreadyResolved");
+      }
+      console.log(`  ✓ Second of three grouped markets resolved YES, the others NO`);
+    });
+    
+    it("70.2 Keeps every grouped market resolvable", async () => {
+      const groupId = "grp-" + Date.now().toString(36);
+      const [groupPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("market_group"), provider.wallet.publicKey.toBuffer(), Buffer.from(groupId)],
+        program.programId
+      );
+      await program.methods
+        .createMarketGroup(groupId)
+        .accounts({ group: groupPda, authority: provider.wallet.publicKey, systemProgram: SystemProgram.programId })
+        .rpc();
+      
+      // A market that would void on its own after a delay can't join
+      const { pdas: voidable } = await createTestMarket(program, provider, null, {
+        maxResolutionDelay: new anchor.BN(3600),
+      });
+      try {
+        await program.methods
+          .addToMarketGroup()
+          .accounts({ group: groupPda, market: voidable.marketPda, authority: provider.wallet.publicKey })
+          .rpc();
+        assert.fail("A market with max_resolution_delay can't join a group");
+      } catch (err) {
+        assert(err.error?.errorCode?.code === "GroupedMarketVoidable");
+      }
+      
+      const markets = [];
+      for (let i = 0; i < 2; i++) {
+        const { pdas } = await createTestMarket(program, provider);
+        await program.methods
+          .addToMarketGroup()
+          .accounts({ group: groupPda, market: pdas.marketPda, authority: provider.wallet.publicKey })
+          .rpc();
+        markets.push(pdas.marketPda);
+      }
+      
+      // Voiding one member would leave the group unresolvable
+      try {
+        await program.methods
+          .voidClobMarket()
+          .accounts({ market: markets[0], authority: provider.wallet.publicKey })
+          .rpc();
+        assert.fail("A grouped market can't be voided alone");
+      } catch (err) {
+        assert(err.error?.errorCode?.code === "MarketInGroup");
+      }
+      
+      await program.methods
+        .groupResolve(0)
+        .accounts({ group: groupPda, authority: provider.wallet.publicKey })
+        .remainingAccounts(markets.map((pubkey) => ({ pubkey, isWritable: true, isSigner: false })))
+        .rpc();
+      const sides = [];
+      for (const pda of markets) {
+        sides.push((await program.account.clobMarket.fetch(pda)).winningSide);
+      }
+      assert.deepEqual(sides, [0, 1]);
+      console.log(`  ✓ Void attempt rejected and the group still resolved`);
+    });
+  });
+  
+  // =========================================
+  // 71. VAULT DRAINED TO ITS RESERVE
+  // =========================================
+  describe("71. Vault Drained To Its Reserve", () => {
+    
+    it("71.1 Pays the last claim down to exactly the vault reserve", async () => {
+      const { pdas } = await createTestMarket(program, provider);
+      const maker = Keypair.generate();
+      await airdrop(provider, maker.publicKey);
+      await placeOrder(program, provider, pdas, maker, 1, true, 6000, 10);
+      await placeOrder(program, provider, pdas, null, 0, true, 6000, 10);
+      await program.methods
+        .resolveClobMarket(0)
+        .accounts({ market: pdas.marketPda, authority: provider.wallet.publicKey })
+        .rpc();
+      
+      const claim = () =>
+        program.methods
+          .claimClobWinnings(new anchor.BN(0))
+          .accounts({
+            market: pdas.marketPda,
+            vault: pdas.vaultPda,
+            position: getPositionPda(program, pdas.marketPda, provider.wallet.publicKey),
+            claimer: provider.wallet.publicKey,
+          })
+          .rpc();
+      await claim();
+      
+      // Both sides' collateral backed exactly the ten winning shares
+      const market = await program.account.clobMarket.fetch(pdas.marketPda);
+      const vaultBalance = await provider.connection.getBalance(pdas.vaultPda);
+      assert.equal(vaultBalance, market.vaultReserve.toNumber());
+      
+      try {
+        await claim();
+        assert.fail("Nothing left to claim");
+      } catch (err) {
+        assert(err.error?.errorCode?.code === "NoWinnings");
+      }
+      assert.equal(await provider.connection.getBalance(pdas.vaultPda), vaultBalance);
+      console.log(`  ✓ Vault left holding its ${vaultBalance} lamport reserve`);
+    });
+  });
+  
+  // =========================================
+  // 72. MARKET STATE VIEW
+  // =========================================
+  describe("72. Market State View", () => {
+    
+    it("72.1 Combines status, top of book and implied probability", async () => {
+      const { pdas } = await createTestMarket(program, provider);
+      const marketState = () =>
+        program.methods
+          .getClobMarketState()
+          .accounts({ market: pdas.marketPda, orderBook: pdas.orderBookPda })
+          .view();
+      
+      let state = await marketState();
+      assert.equal(state.status, 0);
+      assert.equal(state.bestBid, null);
+      assert.equal(state.midPrice, null);
+      assert(state.secondsToResolution.toNumber() > 0);
+      
+      await placeOrder(program, provider, pdas, null, 0, true, 4000, 10);
+      await placeOrder(program, provider, pdas, null, 1, true, 6000, 10);
+      state = await marketState();
+      assert.equal(state.bestBid.toNumber(), 4000);
+      assert.equal(state.bestAsk.toNumber(), 6000);
+      assert.equal(state.midPrice.toNumber(), 5000);
+      
+      await program.methods
+        .resolveClobMarket(0)
+        .accounts({ market: pdas.marketPda, authority: provider.wallet.publicKey })
+        .rpc();
+      state = await marketState();
+      assert.equal(state.status, 3);
+      assert.equal(state.winningSide, 0);
+      console.log(`  ✓ Open with a 4000/6000 book (mid 5000), then resolved YES`);
+    });
+  });
+  
+  // =========================================
+  // 73. PARTIAL POSITION TRANSFERS
+  // =========================================
+  describe("73. Partial Position Transfers", () => {
+    
+    it("73.1 Moves only the requested shares and leaves the rest", async () => {
+      const { pdas } = await createTestMarket(program, provider);
+      const maker = Keypair.generate();
+      await airdrop(provider, maker.publicKey);
+      await placeOrder(program, provider, pdas, maker, 1, true, 5000, 10);
+      await placeOrder(program, provider, pdas, null, 0, true, 5000, 10);
+      
+      const recipient = Keypair.generate();
+      const sourcePos = getPositionPda(program, pdas.marketPda, provider.wallet.publicKey);
+      const recipientPos = getPositionPda(program, pdas.marketPda, recipient.publicKey);
+      const transfer = (yes, no) =>
+        program.methods
+          .transferClobShares(recipient.publicKey, new anchor.BN(yes), new anchor.BN(no))
+          .accounts({
+            market: pdas.marketPda,
+            source: sourcePos,
+            destination: recipientPos,
+            owner: provider.wallet.publicKey,
+            systemProgram: SystemProgram.programId,
+          })
+          .rpc();
+      
+      await transfer(4, 0);
+      let source = await program.account.clobPosition.fetch(sourcePos);
+      let destination = await program.account.clobPosition.fetch(recipientPos);
+      assert.equal(source.yesShares.toNumber(), 6);
+      assert.equal(destination.yesShares.toNumber(), 4);
+      assert(destination.owner.equals(recipient.publicKey));
+      
+      try {
+        await transfer(7, 0);
+        assert.fail("Can't send more than the position holds");
+      } catch (err) {
+        assert(err.error?.errorCode?.code === "InsufficientShares");
+      }
+      
+      // A second transfer adds to the existing recipient position
+      await transfer(6, 0);
+      source = await program.account.clobPosition.fetch(sourcePos);
+      destination = await program.account.clobPosition.fetch(recipientPos);
+      assert.equal(source.yesShares.toNumber(), 0);
+      assert.equal(destination.yesShares.toNumber(), 10);
+      console.log(`  ✓ 10 YES shares moved in two partial transfers`);
+    });
+  });
+  
+  // =========================================
+  // 74. MARKET HORIZON
+  // =========================================
+  describe("74. Market Horizon", () => {
+    const MAX_MARKET_DURATION = 5 * 365 * 24 * 60 * 60;
+    
+    it("74.1 Accepts resolution_time up to MAX_MARKET_DURATION out, and no further", async () => {
+      const now = Math.floor(Date.now() / 1000);
+      try {
+        await createTestMarket(program, provider, null, {}, now + MAX_MARKET_DURATION + 600);
+        assert.fail("Resolution time beyond the horizon should fail");
+      } catch (err) {
+        assert(err.error?.errorCode?.code === "ResolutionTimeTooFar");
+      }
+      
+      const { pdas } = await createTestMarket(program, provider, null, {}, now + MAX_MARKET_DURATION - 600);
+      const market = await program.account.clobMarket.fetch(pdas.marketPda);
+      assert.equal(market.resolutionTime.toNumber(), now + MAX_MARKET_DURATION - 600);
+      try {
+        await program.methods
+          .updateClobResolutionTime(new anchor.BN(now + MAX_MARKET_DURATION + 600))
+          .accounts({ market: pdas.marketPda, authority: provider.wallet.publicKey })
+          .rpc();
+        assert.fail("Extending beyond the horizon should fail");
+      } catch (err) {
+        assert(err.error?.errorCode?.code === "ResolutionTimeTooFar");
+      }
+      console.log(`  ✓ Five-year horizon enforced at creation and on extension`);
+    });
+  });
+  
+  // =========================================
+  // 75. POSITION VIEW
+  // =========================================
+  describe("75. Position View", () => {
+    
+    it("75.1 Reports a NO buy as net NO exposure valued at the mark", async () => {
+      const { pdas } = await createTestMarket(program, provider);
+      const maker = Keypair.generate();
+      await airdrop(provider, maker.publicKey);
+      await placeOrder(program, provider, pdas, maker, 0, true, 6000, 10);
+      // Buying NO at 4000 sells YES at 6000 into the maker's bid
+      await placeOrder(program, provider, pdas, null, 0, false, 4000, 10);
+      
+      const positionView = () =>
+        program.methods
+          .getClobPosition()
+          .accounts({
+            market: pdas.marketPda,
+            orderBook: pdas.orderBookPda,
+            position: getPositionPda(program, pdas.marketPda, provider.wallet.publicKey),
+          })
+          .view();
+      
+      // Empty book, so the last trade is the mark
+      let view = await positionView();
+      assert.equal(view.noShares.toNumber(), 10);
+      assert.equal(view.netNo.toNumber(), 10);
+      assert.equal(view.netYes.toNumber(), 0);
+      assert.equal(view.markPrice.toNumber(), 6000);
+      assert.equal(view.noValue.toNumber(), 4000 * 10);
+      assert.equal(view.unrealizedValue.toNumber(), 4000 * 10);
+      
+      await program.methods
+        .resolveClobMarket(1)
+        .accounts({ market: pdas.marketPda, authority: provider.wallet.publicKey })
+        .rpc();
+      view = await positionView();
+      assert.equal(view.markPrice.toNumber(), 0);
+      assert.equal(view.unrealizedValue.toNumber(), 10000 * 10);
+      console.log(`  ✓ 10 NO bought at 4000 marked at 40000, then 100000 once NO won`);
     });
   });
 });
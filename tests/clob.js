@@ -4,6 +4,38 @@ const assert = require("assert");
 
 const MARKET_ID = "clob-test-" + Date.now();
 
+function defaultClobConfig() {
+  return {
+    vaultRentBuffer: new anchor.BN(0),
+    matchingMode: 0, // FIFO
+    proRataAgingBps: new anchor.BN(0),
+    maxResolutionDelay: new anchor.BN(0),
+    takerFeeBps: new anchor.BN(0),
+    makerRebateBps: new anchor.BN(0),
+    payoutPerShare: new anchor.BN(0),
+    minPrice: new anchor.BN(0),
+    maxPrice: new anchor.BN(0),
+    lotSize: new anchor.BN(0),
+    tickSize: new anchor.BN(0),
+    minOrderSize: new anchor.BN(0),
+    permissioned: false,
+    disputePeriod: new anchor.BN(0),
+    resolvers: [],
+    resolverThreshold: 0,
+    oracle: PublicKey.default,
+    oracleOffset: 0,
+    oracleThreshold: new anchor.BN(0),
+    oracleComparison: 0,
+    earlyResolution: true,
+    maxTraderNotional: new anchor.BN(0),
+    orderBookCapacity: 0,
+    referralFeeBps: new anchor.BN(0),
+    seedLiquidity: null,
+    authorityCanTrade: true,
+    allowEmergency: false,
+  };
+}
+
 describe("agentbets CLOB - order book flow", () => {
   const provider = anchor.AnchorProvider.env();
   anchor.setProvider(provider);
@@ -37,7 +69,7 @@ describe("agentbets CLOB - order book flow", () => {
     console.log("  Vault PDA:", vaultPda.toBase58());
 
     const tx = await program.methods
-      .createClobMarket(MARKET_ID, question, new anchor.BN(resolutionTime))
+      .createClobMarket(MARKET_ID, question, new anchor.BN(resolutionTime), defaultClobConfig())
       .accounts({
         market: marketPda,
         orderBook: orderBookPda,
@@ -75,7 +107,7 @@ describe("agentbets CLOB - order book flow", () => {
     console.log("  Position PDA:", positionPda.toBase58());
 
     const tx = await program.methods
-      .placeOrder(side, isYes, price, size)
+      .placeOrder(side, isYes, price, size, 0, new anchor.BN(0), new anchor.BN(0), new anchor.BN(0), false, null)
       .accounts({
         market: marketPda,
         orderBook: orderBookPda,
@@ -121,7 +153,7 @@ describe("agentbets CLOB - order book flow", () => {
     const size = new anchor.BN(50); // 50 shares
 
     const tx = await program.methods
-      .placeOrder(side, isYes, price, size)
+      .placeOrder(side, isYes, price, size, 0, new anchor.BN(0), new anchor.BN(0), new anchor.BN(0), false, null)
       .accounts({
         market: marketPda,
         orderBook: orderBookPda,
@@ -151,7 +183,7 @@ describe("agentbets CLOB - order book flow", () => {
     const size = new anchor.BN(80); // 80 shares
 
     const tx = await program.methods
-      .placeOrder(side, isYes, price, size)
+      .placeOrder(side, isYes, price, size, 0, new anchor.BN(0), new anchor.BN(0), new anchor.BN(0), false, null)
       .accounts({
         market: marketPda,
         orderBook: orderBookPda,
@@ -195,7 +227,7 @@ describe("agentbets CLOB - order book flow", () => {
     const bestBidSizeBefore = orderBookBefore.yesBids[0].size.toNumber();
 
     const tx = await program.methods
-      .placeOrder(side, isYes, price, size)
+      .placeOrder(side, isYes, price, size, 0, new anchor.BN(0), new anchor.BN(0), new anchor.BN(0), false, null)
       .accounts({
         market: marketPda,
         orderBook: orderBookPda,
@@ -279,7 +311,7 @@ describe("agentbets CLOB - order book flow", () => {
       const balanceBefore = await provider.connection.getBalance(authority.publicKey);
 
       const tx = await program.methods
-        .claimClobWinnings()
+        .claimClobWinnings(new anchor.BN(0))
         .accounts({
           market: marketPda,
           vault: vaultPda,
@@ -335,7 +367,7 @@ describe("agentbets CLOB - NO shares via inversion", () => {
 
     // Create market
     await program.methods
-      .createClobMarket(MARKET_ID_2, question, new anchor.BN(resolutionTime))
+      .createClobMarket(MARKET_ID_2, question, new anchor.BN(resolutionTime), defaultClobConfig())
       .accounts({
         market: marketPda,
         orderBook: orderBookPda,
@@ -354,7 +386,7 @@ describe("agentbets CLOB - NO shares via inversion", () => {
     const size = new anchor.BN(50);
 
     const tx = await program.methods
-      .placeOrder(side, isYes, price, size)
+      .placeOrder(side, isYes, price, size, 0, new anchor.BN(0), new anchor.BN(0), new anchor.BN(0), false, null)
       .accounts({
         market: marketPda,
         orderBook: orderBookPda,
@@ -12,9 +12,104 @@
  */
 
 const anchor = require("@coral-xyz/anchor");
-const { PublicKey, SystemProgram, LAMPORTS_PER_SOL } = require("@solana/web3.js");
+const { PublicKey, SystemProgram, Keypair, LAMPORTS_PER_SOL } = require("@solana/web3.js");
+const {
+  createMint,
+  createAssociatedTokenAccount,
+  getAssociatedTokenAddressSync,
+  getAccount,
+  mintTo,
+} = require("@solana/spl-token");
 const assert = require("assert");
 
+function defaultMarketConfig() {
+  return {
+    maxOutcomeShares: new anchor.BN(0),
+    maxResolutionDelay: new anchor.BN(0),
+    minBet: new anchor.BN(0),
+    maxTotalPool: new anchor.BN(0),
+    marketKind: 0,
+    scalarMin: new anchor.BN(0),
+    scalarMax: new anchor.BN(0),
+    earlyResolution: true, // Most tests resolve right after trading
+    feeBps: new anchor.BN(200),
+    maxTraderNotional: new anchor.BN(0),
+    referralFeeBps: new anchor.BN(0),
+    outcomeWeights: [],
+    authorityCanTrade: true,
+    commitPhaseStart: new anchor.BN(0),
+    revealPeriod: new anchor.BN(0),
+    feeRoundUp: false,
+  };
+}
+
+async function airdrop(provider, pubkey, amount = 10 * LAMPORTS_PER_SOL) {
+  const sig = await provider.connection.requestAirdrop(pubkey, amount);
+  await provider.connection.confirmTransaction(sig);
+}
+
+function getPositionPda(program, marketPda, owner) {
+  const [positionPda] = PublicKey.findProgramAddressSync(
+    [Buffer.from("position"), marketPda.toBuffer(), owner.toBuffer()],
+    program.programId
+  );
+  return positionPda;
+}
+
+function getRealizedPnlPda(program, owner) {
+  const [pnlPda] = PublicKey.findProgramAddressSync(
+    [Buffer.from("realized_pnl"), owner.toBuffer()],
+    program.programId
+  );
+  return pnlPda;
+}
+
+function sleep(ms) {
+  return new Promise((resolve) => setTimeout(resolve, ms));
+}
+
+async function createMarket(
+  program,
+  authority,
+  marketId,
+  outcomes,
+  config = {},
+  resolutionTime = Math.floor(Date.now() / 1000) + 3600
+) {
+  const [marketPda] = PublicKey.findProgramAddressSync(
+    [Buffer.from("market"), Buffer.from(marketId)],
+    program.programId
+  );
+  await program.methods
+    .createMarket(
+      marketId,
+      "Test market",
+      outcomes,
+      new anchor.BN(resolutionTime),
+      { ...defaultMarketConfig(), ...config }
+    )
+    .accounts({
+      market: marketPda,
+      authority: authority.publicKey,
+      systemProgram: SystemProgram.programId,
+    })
+    .rpc();
+  return marketPda;
+}
+
+async function buyShares(program, marketPda, buyer, outcome, amount, referrer = null) {
+  return program.methods
+    .buyShares(outcome, new anchor.BN(amount), referrer)
+    .accounts({
+      market: marketPda,
+      position: getPositionPda(program, marketPda, buyer.publicKey),
+      buyer: buyer.publicKey,
+      systemProgram: SystemProgram.programId,
+    })
+    .signers([buyer])
+    .rpc();
+}
+
 describe("parimutuel-safety", () => {
   const provider = anchor.AnchorProvider.env();
   anchor.setProvider(provider);
@@ -41,7 +136,8 @@ describe("parimutuel-safety", () => {
           marketId,
           "Test market",
           ["Yes", "No"],
-          new anchor.BN(Math.floor(Date.now() / 1000) + 3600)
+          new anchor.BN(Math.floor(Date.now() / 1000) + 3600),
+          defaultMarketConfig()
         )
         .accounts({
           market: marketPda,
@@ -59,7 +155,7 @@ describe("parimutuel-safety", () => {
       );
 
       await program.methods
-        .buyShares(0, betAmount)
+        .buyShares(0, betAmount, null)
         .accounts({
           market: marketPda,
           position: positionPda,
@@ -140,7 +236,8 @@ describe("parimutuel-safety", () => {
           marketId,
           "Proportional test",
           ["A", "B"],
-          new anchor.BN(Math.floor(Date.now() / 1000) + 3600)
+          new anchor.BN(Math.floor(Date.now() / 1000) + 3600),
+          defaultMarketConfig()
         )
         .accounts({
           market: marketPda,
@@ -156,7 +253,7 @@ describe("parimutuel-safety", () => {
 
       // Bet 0.3 SOL on A
       await program.methods
-        .buyShares(0, new anchor.BN(0.3 * LAMPORTS_PER_SOL))
+        .buyShares(0, new anchor.BN(0.3 * LAMPORTS_PER_SOL), null)
         .accounts({
           market: marketPda,
           position: positionPda,
@@ -167,7 +264,7 @@ describe("parimutuel-safety", () => {
 
       // Bet another 0.7 SOL on A (total: 1 SOL on A, position has all)
       await program.methods
-        .buyShares(0, new anchor.BN(0.7 * LAMPORTS_PER_SOL))
+        .buyShares(0, new anchor.BN(0.7 * LAMPORTS_PER_SOL), null)
         .accounts({
           market: marketPda,
           position: positionPda,
@@ -230,7 +327,8 @@ describe("parimutuel-safety", () => {
           marketId,
           "Loser test",
           ["Win", "Lose"],
-          new anchor.BN(Math.floor(Date.now() / 1000) + 3600)
+          new anchor.BN(Math.floor(Date.now() / 1000) + 3600),
+          defaultMarketConfig()
         )
         .accounts({
           market: marketPda,
@@ -246,7 +344,7 @@ describe("parimutuel-safety", () => {
 
       // Bet on losing outcome (index 1)
       await program.methods
-        .buyShares(1, new anchor.BN(0.5 * LAMPORTS_PER_SOL))
+        .buyShares(1, new anchor.BN(0.5 * LAMPORTS_PER_SOL), null)
         .accounts({
           market: marketPda,
           position: positionPda,
@@ -303,7 +401,8 @@ describe("parimutuel-safety", () => {
           marketId,
           "Double claim test",
           ["Yes", "No"],
-          new anchor.BN(Math.floor(Date.now() / 1000) + 3600)
+          new anchor.BN(Math.floor(Date.now() / 1000) + 3600),
+          defaultMarketConfig()
         )
         .accounts({
           market: marketPda,
@@ -318,7 +417,7 @@ describe("parimutuel-safety", () => {
       );
 
       await program.methods
-        .buyShares(0, new anchor.BN(0.1 * LAMPORTS_PER_SOL))
+        .buyShares(0, new anchor.BN(0.1 * LAMPORTS_PER_SOL), null)
         .accounts({
           market: marketPda,
           position: positionPda,
@@ -373,6 +472,41 @@ describe("parimutuel-safety", () => {
         console.log("  ✓ Double claim prevented (shares already zeroed)");
       }
     });
+
+    it("cannot claim with a position from another market", async () => {
+      const winner = Keypair.generate();
+      await airdrop(provider, winner.publicKey);
+      const marketA = await createMarket(program, authority, "fa-" + Date.now().toString(36), ["Yes", "No"]);
+      const marketB = await createMarket(program, authority, "fb-" + Date.now().toString(36), ["Yes", "No"]);
+      await buyShares(program, marketA, winner, 0, 0.1 * LAMPORTS_PER_SOL);
+      await buyShares(program, marketB, winner, 0, 0.1 * LAMPORTS_PER_SOL);
+      await program.methods
+        .resolveMarket(0)
+        .accounts({ market: marketB, authority: authority.publicKey })
+        .rpc();
+
+      try {
+        await program.methods
+          .claimWinnings()
+          .accounts({
+            market: marketB,
+            position: getPositionPda(program, marketA, winner.publicKey),
+            claimer: winner.publicKey,
+          })
+          .signers([winner])
+          .rpc();
+        assert.fail("Claim with a foreign position should fail");
+      } catch (err) {
+        // The seeds constraint catches it before the handler's own check
+        assert(
+          ["ConstraintSeeds", "PositionMarketMismatch"].includes(err.error?.errorCode?.code),
+          `Unexpected error: ${err.message}`
+        );
+      }
+      const market = await program.account.market.fetch(marketB);
+      assert.equal(market.totalClaimed.toNumber(), 0);
+      console.log("  ✓ Foreign position rejected");
+    });
   });
 
   describe("Edge Cases", () => {
@@ -389,7 +523,8 @@ describe("parimutuel-safety", () => {
           marketId,
           "Both outcomes test",
           ["Yes", "No"],
-          new anchor.BN(Math.floor(Date.now() / 1000) + 3600)
+          new anchor.BN(Math.floor(Date.now() / 1000) + 3600),
+          defaultMarketConfig()
         )
         .accounts({
           market: marketPda,
@@ -405,7 +540,7 @@ describe("parimutuel-safety", () => {
 
       // Bet 0.3 SOL on Yes (index 0)
       await program.methods
-        .buyShares(0, new anchor.BN(0.3 * LAMPORTS_PER_SOL))
+        .buyShares(0, new anchor.BN(0.3 * LAMPORTS_PER_SOL), null)
         .accounts({
           market: marketPda,
           position: positionPda,
@@ -416,7 +551,7 @@ describe("parimutuel-safety", () => {
 
       // Bet 0.7 SOL on No (index 1)
       await program.methods
-        .buyShares(1, new anchor.BN(0.7 * LAMPORTS_PER_SOL))
+        .buyShares(1, new anchor.BN(0.7 * LAMPORTS_PER_SOL), null)
         .accounts({
           market: marketPda,
           position: positionPda,
@@ -480,7 +615,8 @@ describe("parimutuel-safety", () => {
           marketId,
           "Post-resolution test",
           ["Yes", "No"],
-          new anchor.BN(Math.floor(Date.now() / 1000) + 3600)
+          new anchor.BN(Math.floor(Date.now() / 1000) + 3600),
+          defaultMarketConfig()
         )
         .accounts({
           market: marketPda,
@@ -506,7 +642,7 @@ describe("parimutuel-safety", () => {
 
       try {
         await program.methods
-          .buyShares(0, new anchor.BN(0.1 * LAMPORTS_PER_SOL))
+          .buyShares(0, new anchor.BN(0.1 * LAMPORTS_PER_SOL), null)
           .accounts({
             market: marketPda,
             position: positionPda,
@@ -539,7 +675,8 @@ describe("parimutuel-safety", () => {
           marketId,
           "Double resolve test",
           ["A", "B"],
-          new anchor.BN(Math.floor(Date.now() / 1000) + 3600)
+          new anchor.BN(Math.floor(Date.now() / 1000) + 3600),
+          defaultMarketConfig()
         )
         .accounts({
           market: marketPda,
@@ -593,7 +730,8 @@ describe("parimutuel-safety", () => {
           marketId,
           "Solvency test",
           ["A", "B", "C"],
-          new anchor.BN(Math.floor(Date.now() / 1000) + 3600)
+          new anchor.BN(Math.floor(Date.now() / 1000) + 3600),
+          defaultMarketConfig()
         )
         .accounts({
           market: marketPda,
@@ -609,7 +747,7 @@ describe("parimutuel-safety", () => {
 
       // Place multiple bets
       await program.methods
-        .buyShares(0, new anchor.BN(0.5 * LAMPORTS_PER_SOL))
+        .buyShares(0, new anchor.BN(0.5 * LAMPORTS_PER_SOL), null)
         .accounts({
           market: marketPda,
           position: positionPda,
@@ -619,7 +757,7 @@ describe("parimutuel-safety", () => {
         .rpc();
 
       await program.methods
-        .buyShares(1, new anchor.BN(0.3 * LAMPORTS_PER_SOL))
+        .buyShares(1, new anchor.BN(0.3 * LAMPORTS_PER_SOL), null)
         .accounts({
           market: marketPda,
           position: positionPda,
@@ -629,7 +767,7 @@ describe("parimutuel-safety", () => {
         .rpc();
 
       await program.methods
-        .buyShares(2, new anchor.BN(0.2 * LAMPORTS_PER_SOL))
+        .buyShares(2, new anchor.BN(0.2 * LAMPORTS_PER_SOL), null)
         .accounts({
           market: marketPda,
           position: positionPda,
@@ -687,5 +825,1982 @@ describe("parimutuel-safety", () => {
       );
       console.log("  ✓ 2% protocol fee retained after claim");
     });
+
+    it("leaves only fees and rent once unequal winners have all claimed", async () => {
+      const marketPda = await createMarket(
+        program,
+        authority,
+        "dust-" + Date.now().toString(36),
+        ["Yes", "No"]
+      );
+
+      // Stakes chosen so every pro-rata payout rounds down
+      const winners = [];
+      for (const stake of [100_001, 200_003, 300_007]) {
+        const user = Keypair.generate();
+        await airdrop(provider, user.publicKey);
+        await buyShares(program, marketPda, user, 0, stake);
+        winners.push(user);
+      }
+      const loser = Keypair.generate();
+      await airdrop(provider, loser.publicKey);
+      await buyShares(program, marketPda, loser, 1, 333_333);
+
+      await program.methods
+        .resolveMarket(0)
+        .accounts({ market: marketPda, authority: authority.publicKey })
+        .rpc();
+
+      for (const user of winners) {
+        await program.methods
+          .claimWinnings()
+          .accounts({
+            market: marketPda,
+            position: getPositionPda(program, marketPda, user.publicKey),
+            claimer: user.publicKey,
+          })
+          .signers([user])
+          .rpc();
+      }
+
+      const market = await program.account.market.fetch(marketPda);
+      assert.equal(market.totalClaimed.toNumber(), market.totalPool.toNumber());
+
+      const info = await provider.connection.getAccountInfo(marketPda);
+      const rentExempt = await provider.connection.getMinimumBalanceForRentExemption(info.data.length);
+      assert.equal(info.lamports - rentExempt, market.feeCollected.toNumber());
+      console.log("  ✓ Last claimer swept the rounding dust");
+    });
+  });
+
+  describe("Realized PnL", () => {
+    it("records the net gain after a winning claim", async () => {
+      const marketId = "spn-" + Date.now().toString(36);
+      const [marketPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("market"), Buffer.from(marketId)],
+        program.programId
+      );
+
+      await program.methods
+        .createMarket(
+          marketId,
+          "Realized PnL test",
+          ["Yes", "No"],
+          new anchor.BN(Math.floor(Date.now() / 1000) + 3600),
+          defaultMarketConfig()
+        )
+        .accounts({
+          market: marketPda,
+          authority: authority.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .rpc();
+
+      const winner = Keypair.generate();
+      const loser = Keypair.generate();
+      await airdrop(provider, winner.publicKey);
+      await airdrop(provider, loser.publicKey);
+
+      await buyShares(program, marketPda, winner, 0, LAMPORTS_PER_SOL);
+      await buyShares(program, marketPda, loser, 1, LAMPORTS_PER_SOL);
+
+      await program.methods
+        .resolveMarket(0)
+        .accounts({
+          market: marketPda,
+          authority: authority.publicKey,
+        })
+        .rpc();
+
+      await program.methods
+        .claimWinnings()
+        .accounts({
+          market: marketPda,
+          position: getPositionPda(program, marketPda, winner.publicKey),
+          claimer: winner.publicKey,
+        })
+        .signers([winner])
+        .rpc();
+
+      // 2 SOL pool, 2% fee => 1.96 SOL claimed against a 1 SOL stake
+      const pnl = await program.account.realizedPnl.fetch(
+        getRealizedPnlPda(program, winner.publicKey)
+      );
+      assert.equal(pnl.totalStaked.toNumber(), LAMPORTS_PER_SOL);
+      assert.equal(pnl.totalClaimed.toNumber(), 1.96 * LAMPORTS_PER_SOL);
+      assert.equal(pnl.netResult.toNumber(), 0.96 * LAMPORTS_PER_SOL);
+
+      const loserPnl = await program.account.realizedPnl.fetch(
+        getRealizedPnlPda(program, loser.publicKey)
+      );
+      assert.equal(loserPnl.netResult.toNumber(), -LAMPORTS_PER_SOL);
+      console.log("  ✓ Realized PnL reflects the net gain and loss");
+    });
+  });
+
+  describe("Outcome Share Cap", () => {
+    it("rejects a buy that pushes one outcome over its cap", async () => {
+      const cap = 0.5 * LAMPORTS_PER_SOL;
+      const marketPda = await createMarket(
+        program,
+        authority,
+        "scap-" + Date.now().toString(36),
+        ["Yes", "No"],
+        { maxOutcomeShares: new anchor.BN(cap) }
+      );
+
+      const buyer = Keypair.generate();
+      await airdrop(provider, buyer.publicKey);
+
+      await buyShares(program, marketPda, buyer, 0, 0.4 * LAMPORTS_PER_SOL);
+
+      try {
+        await buyShares(program, marketPda, buyer, 0, 0.2 * LAMPORTS_PER_SOL);
+        assert.fail("Buy over the outcome cap should fail");
+      } catch (err) {
+        assert.equal(err.error?.errorCode?.code, "OutcomeShareCapExceeded");
+      }
+
+      // The other outcome is still open up to its own cap
+      await buyShares(program, marketPda, buyer, 1, cap);
+
+      const market = await program.account.market.fetch(marketPda);
+      assert.equal(market.outcomePools[0].toNumber(), 0.4 * LAMPORTS_PER_SOL);
+      assert.equal(market.outcomePools[1].toNumber(), cap);
+      console.log("  ✓ Capped outcome rejected, other outcome still open");
+    });
+  });
+
+  describe("Void After Resolution Delay", () => {
+    it("refunds every stake once the deadline passes without a resolve", async () => {
+      const alice = Keypair.generate();
+      const bob = Keypair.generate();
+      await airdrop(provider, alice.publicKey);
+      await airdrop(provider, bob.publicKey);
+
+      const now = Math.floor(Date.now() / 1000);
+      const marketPda = await createMarket(
+        program,
+        authority,
+        "svd-" + Date.now().toString(36),
+        ["Yes", "No"],
+        { maxResolutionDelay: new anchor.BN(1) },
+        now + 4
+      );
+
+      await buyShares(program, marketPda, alice, 0, 0.3 * LAMPORTS_PER_SOL);
+      await buyShares(program, marketPda, bob, 1, 0.7 * LAMPORTS_PER_SOL);
+
+      // Past resolution_time + max_resolution_delay, never resolved
+      await sleep(7000);
+
+      for (const [user, stake] of [[alice, 0.3], [bob, 0.7]]) {
+        const before = await provider.connection.getBalance(user.publicKey);
+        await program.methods
+          .claimWinnings()
+          .accounts({
+            market: marketPda,
+            position: getPositionPda(program, marketPda, user.publicKey),
+            claimer: user.publicKey,
+          })
+          .signers([user])
+          .rpc();
+        const after = await provider.connection.getBalance(user.publicKey);
+        const refunded = after - before;
+        assert(
+          Math.abs(refunded - stake * LAMPORTS_PER_SOL) < 10000,
+          `Expected ~${stake} SOL back, got ${refunded}`
+        );
+      }
+
+      try {
+        await program.methods
+          .resolveMarket(0)
+          .accounts({
+            market: marketPda,
+            authority: authority.publicKey,
+          })
+          .rpc();
+        assert.fail("Void market should not be resolvable");
+      } catch (err) {
+        assert.equal(err.error?.errorCode?.code, "MarketVoid");
+      }
+      console.log("  ✓ Void claims refunded both stakes without a resolve call");
+    });
+
+    it("lets claim_refund return the stake of an abandoned market", async () => {
+      const user = Keypair.generate();
+      await airdrop(provider, user.publicKey);
+
+      const now = Math.floor(Date.now() / 1000);
+      const marketPda = await createMarket(
+        program,
+        authority,
+        "sab-" + Date.now().toString(36),
+        ["Yes", "No"],
+        { maxResolutionDelay: new anchor.BN(1) },
+        now + 3
+      );
+      const stake = 0.2 * LAMPORTS_PER_SOL;
+      await buyShares(program, marketPda, user, 0, stake);
+
+      const refund = () =>
+        program.methods
+          .claimRefund()
+          .accounts({
+            market: marketPda,
+            position: getPositionPda(program, marketPda, user.publicKey),
+            claimer: user.publicKey,
+          })
+          .signers([user])
+          .rpc();
+      try {
+        await refund();
+        assert.fail("Refund before the deadline should fail");
+      } catch (err) {
+        assert.equal(err.error?.errorCode?.code, "MarketNotCancelled");
+      }
+
+      await sleep(6000);
+      const before = await provider.connection.getBalance(marketPda);
+      await refund();
+      const after = await provider.connection.getBalance(marketPda);
+      assert.equal(before - after, stake);
+      console.log("  ✓ Abandoned market refunded the full stake");
+    });
+  });
+
+  describe("Fee Withdrawal", () => {
+    it("authority withdraws exactly the accrued fees and nothing owed", async () => {
+      const marketPda = await createMarket(
+        program,
+        authority,
+        "sfw-" + Date.now().toString(36),
+        ["Yes", "No"]
+      );
+
+      const winnerA = Keypair.generate();
+      const winnerB = Keypair.generate();
+      const treasury = Keypair.generate();
+      await airdrop(provider, winnerA.publicKey);
+      await airdrop(provider, winnerB.publicKey);
+
+      await buyShares(program, marketPda, winnerA, 0, 0.5 * LAMPORTS_PER_SOL);
+      await buyShares(program, marketPda, winnerB, 0, 0.5 * LAMPORTS_PER_SOL);
+
+      await program.methods
+        .resolveMarket(0)
+        .accounts({ market: marketPda, authority: authority.publicKey })
+        .rpc();
+
+      // Only A claims; B's winnings are still owed
+      await program.methods
+        .claimWinnings()
+        .accounts({
+          market: marketPda,
+          position: getPositionPda(program, marketPda, winnerA.publicKey),
+          claimer: winnerA.publicKey,
+        })
+        .signers([winnerA])
+        .rpc();
+
+      let market = await program.account.market.fetch(marketPda);
+      const fee = 0.01 * LAMPORTS_PER_SOL; // 2% of A's 0.5 SOL payout
+      assert.equal(market.feeCollected.toNumber(), fee);
+
+      await program.methods
+        .withdrawFees()
+        .accounts({
+          market: marketPda,
+          destination: treasury.publicKey,
+          authority: authority.publicKey,
+        })
+        .rpc();
+
+      assert.equal(await provider.connection.getBalance(treasury.publicKey), fee);
+      market = await program.account.market.fetch(marketPda);
+      assert.equal(market.feeCollected.toNumber(), 0);
+
+      try {
+        await program.methods
+          .withdrawFees()
+          .accounts({
+            market: marketPda,
+            destination: treasury.publicKey,
+            authority: authority.publicKey,
+          })
+          .rpc();
+        assert.fail("Nothing left to withdraw");
+      } catch (err) {
+        assert.equal(err.error?.errorCode?.code, "NoFeesToWithdraw");
+      }
+
+      // B can still claim in full
+      await program.methods
+        .claimWinnings()
+        .accounts({
+          market: marketPda,
+          position: getPositionPda(program, marketPda, winnerB.publicKey),
+          claimer: winnerB.publicKey,
+        })
+        .signers([winnerB])
+        .rpc();
+      console.log("  ✓ Fees withdrawn without touching unclaimed winnings");
+    });
+  });
+
+  describe("Trading Cutoff", () => {
+    it("rejects buys once resolution_time has passed", async () => {
+      const buyer = Keypair.generate();
+      await airdrop(provider, buyer.publicKey);
+
+      const now = Math.floor(Date.now() / 1000);
+      const marketPda = await createMarket(
+        program,
+        authority,
+        "stc-" + Date.now().toString(36),
+        ["Yes", "No"],
+        {},
+        now + 2
+      );
+
+      await sleep(4000);
+
+      try {
+        await buyShares(program, marketPda, buyer, 0, 0.1 * LAMPORTS_PER_SOL);
+        assert.fail("Buy after resolution_time should be rejected");
+      } catch (err) {
+        assert.equal(err.error?.errorCode?.code, "MarketExpired");
+      }
+      console.log("  ✓ Buy after resolution_time rejected");
+    });
+  });
+
+  describe("Close Position", () => {
+    it("closes only after the winning shares are claimed", async () => {
+      const marketPda = await createMarket(
+        program,
+        authority,
+        "scp-" + Date.now().toString(36),
+        ["Yes", "No"]
+      );
+
+      const user = Keypair.generate();
+      await airdrop(provider, user.publicKey);
+      await buyShares(program, marketPda, user, 0, 0.2 * LAMPORTS_PER_SOL);
+      await buyShares(program, marketPda, user, 1, 0.1 * LAMPORTS_PER_SOL);
+      const positionPda = getPositionPda(program, marketPda, user.publicKey);
+
+      const closePosition = () =>
+        program.methods
+          .closePosition()
+          .accounts({ market: marketPda, position: positionPda, owner: user.publicKey })
+          .signers([user])
+          .rpc();
+
+      try {
+        await closePosition();
+        assert.fail("Unresolved position should not close");
+      } catch (err) {
+        assert.equal(err.error?.errorCode?.code, "MarketNotResolved");
+      }
+
+      await program.methods
+        .resolveMarket(0)
+        .accounts({ market: marketPda, authority: authority.publicKey })
+        .rpc();
+
+      try {
+        await closePosition();
+        assert.fail("Unclaimed winning shares should block close");
+      } catch (err) {
+        assert.equal(err.error?.errorCode?.code, "PositionNotSettled");
+      }
+
+      await program.methods
+        .claimWinnings()
+        .accounts({ market: marketPda, position: positionPda, claimer: user.publicKey })
+        .signers([user])
+        .rpc();
+
+      const rent = await provider.connection.getBalance(positionPda);
+      const before = await provider.connection.getBalance(user.publicKey);
+      await closePosition();
+      const after = await provider.connection.getBalance(user.publicKey);
+
+      assert.equal(await provider.connection.getAccountInfo(positionPda), null);
+      assert(after - before > rent - 10000, "rent returned to owner");
+      console.log(`  ✓ Position closed, ${rent} lamports of rent returned`);
+    });
+  });
+
+  describe("Token Markets", () => {
+    it("takes stakes and pays winnings in the market's SPL token", async () => {
+      const payer = authority.payer;
+      const mint = await createMint(provider.connection, payer, authority.publicKey, null, 6);
+
+      const marketId = "stk-" + Date.now().toString(36);
+      const [marketPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("market"), Buffer.from(marketId)],
+        program.programId
+      );
+      const vault = getAssociatedTokenAddressSync(mint, marketPda, true);
+
+      await program.methods
+        .createTokenMarket(
+          marketId,
+          "Token market",
+          ["Yes", "No"],
+          new anchor.BN(Math.floor(Date.now() / 1000) + 3600),
+          defaultMarketConfig()
+        )
+        .accounts({ market: marketPda, mint, vault, authority: authority.publicKey })
+        .rpc();
+
+      const market = await program.account.market.fetch(marketPda);
+      assert(market.mint.equals(mint));
+
+      const traders = [];
+      for (const [outcome, stake] of [[0, 600_000], [1, 400_000]]) {
+        const user = Keypair.generate();
+        await airdrop(provider, user.publicKey);
+        const tokenAccount = await createAssociatedTokenAccount(
+          provider.connection,
+          payer,
+          mint,
+          user.publicKey
+        );
+        await mintTo(provider.connection, payer, mint, tokenAccount, payer, stake);
+        await program.methods
+          .buySharesToken(outcome, new anchor.BN(stake))
+          .accounts({
+            market: marketPda,
+            mint,
+            vault,
+            buyerTokenAccount: tokenAccount,
+            position: getPositionPda(program, marketPda, user.publicKey),
+            buyer: user.publicKey,
+          })
+          .signers([user])
+          .rpc();
+        traders.push({ user, tokenAccount });
+      }
+      assert.equal(Number((await getAccount(provider.connection, vault)).amount), 1_000_000);
+
+      // The lamport path refuses token markets
+      try {
+        await buyShares(program, marketPda, traders[0].user, 0, 1000);
+        assert.fail("Lamport buy on a token market should fail");
+      } catch (err) {
+        assert.equal(err.error?.errorCode?.code, "CollateralMismatch");
+      }
+
+      await program.methods
+        .resolveMarket(0)
+        .accounts({ market: marketPda, authority: authority.publicKey })
+        .rpc();
+
+      const winner = traders[0];
+      await program.methods
+        .claimWinningsToken()
+        .accounts({
+          market: marketPda,
+          mint,
+          vault,
+          position: getPositionPda(program, marketPda, winner.user.publicKey),
+          claimerTokenAccount: winner.tokenAccount,
+          claimer: winner.user.publicKey,
+        })
+        .signers([winner.user])
+        .rpc();
+
+      // Whole 1_000_000 pool less the 2% fee, which stays in the vault
+      const balance = await getAccount(provider.connection, winner.tokenAccount);
+      assert.equal(Number(balance.amount), 980_000);
+      assert.equal(Number((await getAccount(provider.connection, vault)).amount), 20_000);
+      console.log("  ✓ Token stakes paid out 980000 units to the winner");
+    });
+  });
+
+  describe("Outcome Validation", () => {
+    const rejects = async (outcomes) => {
+      try {
+        await createMarket(program, authority, "ov-" + Date.now().toString(36), outcomes);
+        assert.fail("Market with invalid outcomes should fail");
+      } catch (err) {
+        assert.equal(err.error?.errorCode?.code, "InvalidOutcomeName");
+      }
+    };
+
+    it("rejects duplicate outcome names", async () => {
+      await rejects(["Yes", "No", "Yes"]);
+    });
+
+    it("rejects empty outcome names", async () => {
+      await rejects(["Yes", ""]);
+    });
+
+    it("rejects outcome names longer than 32 chars", async () => {
+      await rejects(["Yes", "N".repeat(33)]);
+    });
+  });
+
+  describe("Minimum Bet", () => {
+    it("rejects buys below the market's min_bet", async () => {
+      const minBet = 0.01 * LAMPORTS_PER_SOL;
+      const marketPda = await createMarket(
+        program,
+        authority,
+        "minb-" + Date.now().toString(36),
+        ["Yes", "No"],
+        { minBet: new anchor.BN(minBet) }
+      );
+
+      const buyer = Keypair.generate();
+      await airdrop(provider, buyer.publicKey);
+
+      try {
+        await buyShares(program, marketPda, buyer, 0, minBet - 1);
+        assert.fail("Buy below min_bet should fail");
+      } catch (err) {
+        assert.equal(err.error?.errorCode?.code, "BelowMinimumBet");
+      }
+
+      await buyShares(program, marketPda, buyer, 0, minBet);
+      const market = await program.account.market.fetch(marketPda);
+      assert.equal(market.outcomePools[0].toNumber(), minBet);
+      console.log("  ✓ Dust buy rejected, min_bet accepted");
+    });
+
+    it("rejects zero-amount buys even without a min_bet", async () => {
+      const marketPda = await createMarket(
+        program,
+        authority,
+        "zero-" + Date.now().toString(36),
+        ["Yes", "No"]
+      );
+
+      const buyer = Keypair.generate();
+      await airdrop(provider, buyer.publicKey);
+
+      try {
+        await buyShares(program, marketPda, buyer, 0, 0);
+        assert.fail("Zero buy should fail");
+      } catch (err) {
+        assert.equal(err.error?.errorCode?.code, "InvalidSize");
+      }
+      const position = await provider.connection.getAccountInfo(
+        getPositionPda(program, marketPda, buyer.publicKey)
+      );
+      assert.equal(position, null);
+      console.log("  ✓ Zero buy rejected without creating a position");
+    });
+  });
+
+  describe("Market Cancellation", () => {
+    it("refunds each position's exact principal after cancel_market", async () => {
+      const marketPda = await createMarket(
+        program,
+        authority,
+        "cncl-" + Date.now().toString(36),
+        ["Yes", "No"]
+      );
+
+      const users = [];
+      for (const [outcome, stake] of [[0, 0.3 * LAMPORTS_PER_SOL], [1, 0.7 * LAMPORTS_PER_SOL]]) {
+        const user = Keypair.generate();
+        await airdrop(provider, user.publicKey);
+        await buyShares(program, marketPda, user, outcome, stake);
+        users.push({ user, stake });
+      }
+
+      await program.methods
+        .cancelMarket()
+        .accounts({ market: marketPda, authority: authority.publicKey })
+        .rpc();
+
+      // Cancelled markets take no more stakes and can't be resolved
+      try {
+        await buyShares(program, marketPda, users[0].user, 0, 1000);
+        assert.fail("Buy on a cancelled market should fail");
+      } catch (err) {
+        assert.equal(err.error?.errorCode?.code, "MarketCancelled");
+      }
+      try {
+        await program.methods
+          .resolveMarket(0)
+          .accounts({ market: marketPda, authority: authority.publicKey })
+          .rpc();
+        assert.fail("Resolving a cancelled market should fail");
+      } catch (err) {
+        assert.equal(err.error?.errorCode?.code, "MarketCancelled");
+      }
+
+      for (const { user, stake } of users) {
+        const before = await provider.connection.getBalance(marketPda);
+        await program.methods
+          .claimRefund()
+          .accounts({
+            market: marketPda,
+            position: getPositionPda(program, marketPda, user.publicKey),
+            claimer: user.publicKey,
+          })
+          .signers([user])
+          .rpc();
+        const after = await provider.connection.getBalance(marketPda);
+        assert.equal(before - after, stake);
+      }
+
+      const market = await program.account.market.fetch(marketPda);
+      assert.equal(market.feeCollected.toNumber(), 0);
+      console.log("  ✓ Both sides got their principal back with no fee");
+    });
+
+    it("rejects cancel_market on a resolved market", async () => {
+      const marketPda = await createMarket(
+        program,
+        authority,
+        "cncr-" + Date.now().toString(36),
+        ["Yes", "No"]
+      );
+      await program.methods
+        .resolveMarket(0)
+        .accounts({ market: marketPda, authority: authority.publicKey })
+        .rpc();
+
+      try {
+        await program.methods
+          .cancelMarket()
+          .accounts({ market: marketPda, authority: authority.publicKey })
+          .rpc();
+        assert.fail("Cancelling a resolved market should fail");
+      } catch (err) {
+        assert.equal(err.error?.errorCode?.code, "MarketAlreadyResolved");
+      }
+    });
+  });
+
+  describe("Resolution Time Updates", () => {
+    it("only lets the authority move resolution_time later", async () => {
+      const resolutionTime = Math.floor(Date.now() / 1000) + 3600;
+      const marketPda = await createMarket(
+        program,
+        authority,
+        "rtu-" + Date.now().toString(36),
+        ["Yes", "No"],
+        {},
+        resolutionTime
+      );
+
+      const update = (time) =>
+        program.methods
+          .updateResolutionTime(new anchor.BN(time))
+          .accounts({ market: marketPda, authority: authority.publicKey })
+          .rpc();
+
+      await update(resolutionTime + 600);
+      let market = await program.account.market.fetch(marketPda);
+      assert.equal(market.resolutionTime.toNumber(), resolutionTime + 600);
+
+      for (const time of [resolutionTime, resolutionTime + 600]) {
+        try {
+          await update(time);
+          assert.fail("Shortening or keeping resolution_time should fail");
+        } catch (err) {
+          assert.equal(err.error?.errorCode?.code, "InvalidResolutionTime");
+        }
+      }
+
+      await program.methods
+        .resolveMarket(0)
+        .accounts({ market: marketPda, authority: authority.publicKey })
+        .rpc();
+      try {
+        await update(resolutionTime + 1200);
+        assert.fail("Extending a resolved market should fail");
+      } catch (err) {
+        assert.equal(err.error?.errorCode?.code, "MarketAlreadyResolved");
+      }
+      console.log("  ✓ Resolution time only extends while unresolved");
+    });
+  });
+
+  describe("Authority Transfer", () => {
+    it("hands off authority only once the proposed key accepts", async () => {
+      const marketPda = await createMarket(
+        program,
+        authority,
+        "auth-" + Date.now().toString(36),
+        ["Yes", "No"]
+      );
+      const newAuthority = Keypair.generate();
+      const stranger = Keypair.generate();
+
+      await program.methods
+        .proposeAuthority(newAuthority.publicKey)
+        .accounts({ market: marketPda, authority: authority.publicKey })
+        .rpc();
+
+      // Proposing alone changes nothing
+      let market = await program.account.market.fetch(marketPda);
+      assert(market.authority.equals(authority.publicKey));
+      assert(market.pendingAuthority.equals(newAuthority.publicKey));
+
+      try {
+        await program.methods
+          .acceptAuthority()
+          .accounts({ market: marketPda, newAuthority: stranger.publicKey })
+          .signers([stranger])
+          .rpc();
+        assert.fail("Only the pending authority can accept");
+      } catch (err) {
+        assert.equal(err.error?.errorCode?.code, "NotPendingAuthority");
+      }
+
+      await program.methods
+        .acceptAuthority()
+        .accounts({ market: marketPda, newAuthority: newAuthority.publicKey })
+        .signers([newAuthority])
+        .rpc();
+
+      market = await program.account.market.fetch(marketPda);
+      assert(market.authority.equals(newAuthority.publicKey));
+      assert.equal(market.pendingAuthority, null);
+
+      // The old authority can no longer resolve
+      try {
+        await program.methods
+          .resolveMarket(0)
+          .accounts({ market: marketPda, authority: authority.publicKey })
+          .rpc();
+        assert.fail("Previous authority should be rejected");
+      } catch (err) {
+        assert.equal(err.error?.errorCode?.code, "Unauthorized");
+      }
+      console.log("  ✓ Two-step authority transfer completed");
+    });
+  });
+
+  describe("Pause", () => {
+    it("blocks buys while paused but still lets winners claim", async () => {
+      const marketPda = await createMarket(
+        program,
+        authority,
+        "paus-" + Date.now().toString(36),
+        ["Yes", "No"]
+      );
+      const buyer = Keypair.generate();
+      await airdrop(provider, buyer.publicKey);
+      await buyShares(program, marketPda, buyer, 0, 0.1 * LAMPORTS_PER_SOL);
+
+      const setPaused = (paused) =>
+        program.methods[paused ? "pauseMarket" : "unpauseMarket"]()
+          .accounts({ market: marketPda, authority: authority.publicKey })
+          .rpc();
+
+      await setPaused(true);
+      try {
+        await buyShares(program, marketPda, buyer, 0, 0.1 * LAMPORTS_PER_SOL);
+        assert.fail("Buy while paused should fail");
+      } catch (err) {
+        assert.equal(err.error?.errorCode?.code, "MarketPaused");
+      }
+
+      // Unpausing reopens trading
+      await setPaused(false);
+      await buyShares(program, marketPda, buyer, 1, 0.1 * LAMPORTS_PER_SOL);
+      await setPaused(true);
+
+      await program.methods
+        .resolveMarket(0)
+        .accounts({ market: marketPda, authority: authority.publicKey })
+        .rpc();
+      await program.methods
+        .claimWinnings()
+        .accounts({
+          market: marketPda,
+          position: getPositionPda(program, marketPda, buyer.publicKey),
+          claimer: buyer.publicKey,
+        })
+        .signers([buyer])
+        .rpc();
+
+      const position = await program.account.position.fetch(
+        getPositionPda(program, marketPda, buyer.publicKey)
+      );
+      assert.equal(position.shares[0].toNumber(), 0);
+      console.log("  ✓ Paused market rejected buys and still paid out");
+    });
+  });
+
+  describe("Pool Limits", () => {
+    it("rejects buys past max_total_pool", async () => {
+      const cap = 0.5 * LAMPORTS_PER_SOL;
+      const marketPda = await createMarket(
+        program,
+        authority,
+        "pcap-" + Date.now().toString(36),
+        ["Yes", "No"],
+        { maxTotalPool: new anchor.BN(cap) }
+      );
+      const buyer = Keypair.generate();
+      await airdrop(provider, buyer.publicKey);
+
+      await buyShares(program, marketPda, buyer, 0, 0.3 * LAMPORTS_PER_SOL);
+      try {
+        // Each outcome is under the cap, but the pool as a whole isn't
+        await buyShares(program, marketPda, buyer, 1, 0.3 * LAMPORTS_PER_SOL);
+        assert.fail("Buy over the pool cap should fail");
+      } catch (err) {
+        assert.equal(err.error?.errorCode?.code, "PoolCapExceeded");
+      }
+      await buyShares(program, marketPda, buyer, 1, 0.2 * LAMPORTS_PER_SOL);
+    });
+
+    it("fails with Overflow instead of wrapping near u64::MAX", async () => {
+      // Only a token market can hold a pool this large
+      const payer = authority.payer;
+      const mint = await createMint(provider.connection, payer, authority.publicKey, null, 0);
+      const marketId = "ovf-" + Date.now().toString(36);
+      const [marketPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("market"), Buffer.from(marketId)],
+        program.programId
+      );
+      const vault = getAssociatedTokenAddressSync(mint, marketPda, true);
+      await program.methods
+        .createTokenMarket(
+          marketId,
+          "Overflow market",
+          ["Yes", "No"],
+          new anchor.BN(Math.floor(Date.now() / 1000) + 3600),
+          defaultMarketConfig()
+        )
+        .accounts({ market: marketPda, mint, vault, authority: authority.publicKey })
+        .rpc();
+
+      const buyer = Keypair.generate();
+      await airdrop(provider, buyer.publicKey);
+      const buyerTokenAccount = await createAssociatedTokenAccount(
+        provider.connection,
+        payer,
+        mint,
+        buyer.publicKey
+      );
+      const nearMax = (1n << 64n) - 2n;
+      await mintTo(provider.connection, payer, mint, buyerTokenAccount, payer, nearMax);
+
+      const buy = (outcome, amount) =>
+        program.methods
+          .buySharesToken(outcome, new anchor.BN(amount.toString()))
+          .accounts({
+            market: marketPda,
+            mint,
+            vault,
+            buyerTokenAccount,
+            position: getPositionPda(program, marketPda, buyer.publicKey),
+            buyer: buyer.publicKey,
+          })
+          .signers([buyer])
+          .rpc();
+
+      await buy(0, nearMax);
+      try {
+        await buy(1, 10n);
+        assert.fail("Pool overflow should be rejected");
+      } catch (err) {
+        assert.equal(err.error?.errorCode?.code, "Overflow");
+      }
+
+      const market = await program.account.market.fetch(marketPda);
+      assert.equal(market.totalPool.toString(), nearMax.toString());
+      console.log("  ✓ Pool overflow rejected with Overflow");
+    });
+  });
+
+  describe("Scalar Markets", () => {
+    it("splits the pool between the buckets either side of the value", async () => {
+      // Four buckets over [0, 100), centred at 12.5, 37.5, 62.5 and 87.5
+      const marketPda = await createMarket(
+        program,
+        authority,
+        "scalar-" + Date.now().toString(36),
+        ["0-25", "25-50", "50-75", "75-100"],
+        { marketKind: 1, scalarMin: new anchor.BN(0), scalarMax: new anchor.BN(100) }
+      );
+      const buyers = [Keypair.generate(), Keypair.generate(), Keypair.generate()];
+      for (const [i, buyer] of buyers.entries()) {
+        await airdrop(provider, buyer.publicKey);
+        await buyShares(program, marketPda, buyer, i + 1, LAMPORTS_PER_SOL);
+      }
+
+      try {
+        await program.methods
+          .resolveMarket(1)
+          .accounts({ market: marketPda, authority: authority.publicKey })
+          .rpc();
+        assert.fail("Categorical resolution of a scalar market should fail");
+      } catch (err) {
+        assert.equal(err.error?.errorCode?.code, "WrongMarketKind");
+      }
+      // Halfway between the 37.5 and 62.5 centres
+      await program.methods
+        .resolveScalarMarket(new anchor.BN(50))
+        .accounts({ market: marketPda, authority: authority.publicKey })
+        .rpc();
+
+      const claim = (buyer) =>
+        program.methods
+          .claimWinnings()
+          .accounts({
+            market: marketPda,
+            position: getPositionPda(program, marketPda, buyer.publicKey),
+            claimer: buyer.publicKey,
+          })
+          .signers([buyer])
+          .rpc();
+      for (const buyer of buyers.slice(0, 2)) {
+        const before = await provider.connection.getBalance(marketPda);
+        await claim(buyer);
+        const paid = before - (await provider.connection.getBalance(marketPda));
+        assert.equal(paid, 1.5 * LAMPORTS_PER_SOL * 0.98);
+      }
+      try {
+        await claim(buyers[2]);
+        assert.fail("The 75-100 bucket gets no weight at 50");
+      } catch (err) {
+        assert.equal(err.error?.errorCode?.code, "NoWinningShares");
+      }
+      console.log("  ✓ 25-50 and 50-75 split the pool evenly");
+    });
+
+    it("rejects an empty scalar range", async () => {
+      try {
+        await createMarket(
+          program,
+          authority,
+          "scalar-bad-" + Date.now().toString(36),
+          ["Low", "High"],
+          { marketKind: 1, scalarMin: new anchor.BN(10), scalarMax: new anchor.BN(10) }
+        );
+        assert.fail("scalar_min == scalar_max should fail");
+      } catch (err) {
+        assert.equal(err.error?.errorCode?.code, "InvalidScalarRange");
+      }
+    });
+  });
+
+  describe("Selling Shares", () => {
+    it("returns the stake less a 1% fee and shrinks the pools", async () => {
+      const marketPda = await createMarket(
+        program,
+        authority,
+        "sell-" + Date.now().toString(36),
+        ["Yes", "No"]
+      );
+      const seller = Keypair.generate();
+      const other = Keypair.generate();
+      await airdrop(provider, seller.publicKey);
+      await airdrop(provider, other.publicKey);
+      await buyShares(program, marketPda, seller, 0, LAMPORTS_PER_SOL);
+      await buyShares(program, marketPda, other, 1, LAMPORTS_PER_SOL);
+
+      const sell = (amount) =>
+        program.methods
+          .sellShares(0, new anchor.BN(amount))
+          .accounts({
+            market: marketPda,
+            position: getPositionPda(program, marketPda, seller.publicKey),
+            claimer: seller.publicKey,
+          })
+          .signers([seller])
+          .rpc();
+
+      const before = await provider.connection.getBalance(marketPda);
+      await sell(0.4 * LAMPORTS_PER_SOL);
+      const paid = before - (await provider.connection.getBalance(marketPda));
+      assert.equal(paid, 0.4 * LAMPORTS_PER_SOL * 0.99);
+
+      const market = await program.account.market.fetch(marketPda);
+      assert.equal(market.outcomePools[0].toNumber(), 0.6 * LAMPORTS_PER_SOL);
+      assert.equal(market.totalPool.toNumber(), 1.6 * LAMPORTS_PER_SOL);
+      assert.equal(market.feeCollected.toNumber(), 0.004 * LAMPORTS_PER_SOL);
+      const position = await program.account.position.fetch(
+        getPositionPda(program, marketPda, seller.publicKey)
+      );
+      assert.equal(position.shares[0].toNumber(), 0.6 * LAMPORTS_PER_SOL);
+
+      try {
+        await sell(0.7 * LAMPORTS_PER_SOL);
+        assert.fail("Selling more than the position holds should fail");
+      } catch (err) {
+        assert.equal(err.error?.errorCode?.code, "InsufficientShares");
+      }
+      console.log("  ✓ Sold 0.4 SOL of stake for 0.396 SOL");
+    });
+  });
+
+  describe("Resolution Time Validation", () => {
+    it("rejects markets whose resolution_time has already passed", async () => {
+      const now = Math.floor(Date.now() / 1000);
+      for (const time of [now - 3600, now - 5]) {
+        try {
+          await createMarket(
+            program,
+            authority,
+            "rtp-" + Date.now().toString(36),
+            ["Yes", "No"],
+            {},
+            time
+          );
+          assert.fail("Market resolving in the past should fail");
+        } catch (err) {
+          assert.equal(err.error?.errorCode?.code, "ResolutionTimeInPast");
+        }
+      }
+      console.log("  ✓ Past resolution times rejected");
+    });
+  });
+
+  describe("Early Resolution", () => {
+    const resolve = (marketPda) =>
+      program.methods
+        .resolveMarket(0)
+        .accounts({ market: marketPda, authority: authority.publicKey })
+        .rpc();
+
+    it("waits for resolution_time unless early resolution is allowed", async () => {
+      const now = Math.floor(Date.now() / 1000);
+      const marketPda = await createMarket(
+        program,
+        authority,
+        "ers-" + Date.now().toString(36),
+        ["Yes", "No"],
+        { earlyResolution: false },
+        now + 3
+      );
+
+      try {
+        await resolve(marketPda);
+        assert.fail("Resolving before resolution_time should fail");
+      } catch (err) {
+        assert.equal(err.error?.errorCode?.code, "ResolutionTimeNotReached");
+      }
+
+      await sleep(5000);
+      await resolve(marketPda);
+      const market = await program.account.market.fetch(marketPda);
+      assert.equal(market.resolved, true);
+      console.log("  ✓ Resolved only once resolution_time passed");
+    });
+
+    it("lets markets created with early resolution resolve right away", async () => {
+      const marketPda = await createMarket(
+        program,
+        authority,
+        "ere-" + Date.now().toString(36),
+        ["Yes", "No"],
+        { earlyResolution: true }
+      );
+      await resolve(marketPda);
+      const market = await program.account.market.fetch(marketPda);
+      assert.equal(market.earlyResolution, true);
+      assert.equal(market.winningOutcome, 0);
+      console.log("  ✓ Early resolution allowed by the market's config");
+    });
+  });
+
+  describe("Winnings Estimates", () => {
+    it("estimate_winnings matches what claim_winnings pays", async () => {
+      const alice = Keypair.generate();
+      const bob = Keypair.generate();
+      await airdrop(provider, alice.publicKey);
+      await airdrop(provider, bob.publicKey);
+      const marketPda = await createMarket(
+        program,
+        authority,
+        "est-" + Date.now().toString(36),
+        ["Yes", "No"]
+      );
+      await buyShares(program, marketPda, alice, 0, 0.3 * LAMPORTS_PER_SOL);
+      await buyShares(program, marketPda, bob, 1, 0.7 * LAMPORTS_PER_SOL);
+      await program.methods
+        .resolveMarket(0)
+        .accounts({ market: marketPda, authority: authority.publicKey })
+        .rpc();
+
+      const positionPda = getPositionPda(program, marketPda, alice.publicKey);
+      const estimate = await program.methods
+        .estimateWinnings()
+        .accounts({ market: marketPda, position: positionPda })
+        .view();
+      assert.equal(estimate.payout.toNumber(), LAMPORTS_PER_SOL);
+      assert.equal(estimate.fee.toNumber(), 0.02 * LAMPORTS_PER_SOL);
+      assert.equal(estimate.netPayout.toNumber(), 0.98 * LAMPORTS_PER_SOL);
+
+      const before = await provider.connection.getBalance(marketPda);
+      await program.methods
+        .claimWinnings()
+        .accounts({ market: marketPda, position: positionPda, claimer: alice.publicKey })
+        .signers([alice])
+        .rpc();
+      const after = await provider.connection.getBalance(marketPda);
+      assert.equal(before - after, estimate.netPayout.toNumber());
+      console.log(`  ✓ Estimated and paid ${estimate.netPayout.toNumber()} lamports`);
+    });
+  });
+
+  describe("Configurable Claim Fee", () => {
+    const claimedAt = async (feeBps) => {
+      const alice = Keypair.generate();
+      const bob = Keypair.generate();
+      await airdrop(provider, alice.publicKey);
+      await airdrop(provider, bob.publicKey);
+      const marketPda = await createMarket(
+        program,
+        authority,
+        "fee-" + Date.now().toString(36),
+        ["Yes", "No"],
+        { feeBps: new anchor.BN(feeBps) }
+      );
+      await buyShares(program, marketPda, alice, 0, 0.4 * LAMPORTS_PER_SOL);
+      await buyShares(program, marketPda, bob, 1, 0.6 * LAMPORTS_PER_SOL);
+      await program.methods
+        .resolveMarket(0)
+        .accounts({ market: marketPda, authority: authority.publicKey })
+        .rpc();
+
+      const before = await provider.connection.getBalance(marketPda);
+      await program.methods
+        .claimWinnings()
+        .accounts({
+          market: marketPda,
+          position: getPositionPda(program, marketPda, alice.publicKey),
+          claimer: alice.publicKey,
+        })
+        .signers([alice])
+        .rpc();
+      const after = await provider.connection.getBalance(marketPda);
+      const market = await program.account.market.fetch(marketPda);
+      return { paid: before - after, fee: market.feeCollected.toNumber() };
+    };
+
+    it("charges no fee at 0 bps", async () => {
+      const { paid, fee } = await claimedAt(0);
+      assert.equal(paid, LAMPORTS_PER_SOL);
+      assert.equal(fee, 0);
+      console.log("  ✓ Whole pool paid at 0 bps");
+    });
+
+    it("charges 5% at 500 bps", async () => {
+      const { paid, fee } = await claimedAt(500);
+      assert.equal(paid, 0.95 * LAMPORTS_PER_SOL);
+      assert.equal(fee, 0.05 * LAMPORTS_PER_SOL);
+      console.log("  ✓ 5% kept as fee at 500 bps");
+    });
+
+    it("rejects fees above 10000 bps", async () => {
+      try {
+        await createMarket(
+          program,
+          authority,
+          "feex-" + Date.now().toString(36),
+          ["Yes", "No"],
+          { feeBps: new anchor.BN(10001) }
+        );
+        assert.fail("Fee above 100% should be rejected");
+      } catch (err) {
+        assert.equal(err.error?.errorCode?.code, "InvalidFee");
+      }
+    });
+  });
+
+  describe("Claim And Close", () => {
+    it("pays a cross-outcome position's winnings and returns its rent", async () => {
+      const alice = Keypair.generate();
+      const bob = Keypair.generate();
+      await airdrop(provider, alice.publicKey);
+      await airdrop(provider, bob.publicKey);
+      const marketPda = await createMarket(
+        program,
+        authority,
+        "cac-" + Date.now().toString(36),
+        ["Yes", "No"],
+        { feeBps: new anchor.BN(0) }
+      );
+      // Alice hedges across both outcomes; only her Yes shares are worth anything
+      await buyShares(program, marketPda, alice, 0, 0.2 * LAMPORTS_PER_SOL);
+      await buyShares(program, marketPda, alice, 1, 0.1 * LAMPORTS_PER_SOL);
+      await buyShares(program, marketPda, bob, 1, 0.7 * LAMPORTS_PER_SOL);
+      await program.methods
+        .resolveMarket(0)
+        .accounts({ market: marketPda, authority: authority.publicKey })
+        .rpc();
+
+      const positionPda = getPositionPda(program, marketPda, alice.publicKey);
+      const estimate = await program.methods
+        .estimateWinnings()
+        .accounts({ market: marketPda, position: positionPda })
+        .view();
+      assert.equal(estimate.netPayout.toNumber(), LAMPORTS_PER_SOL);
+
+      const rent = await provider.connection.getBalance(positionPda);
+      const before = await provider.connection.getBalance(marketPda);
+      await program.methods
+        .claimAndClose()
+        .accounts({ market: marketPda, position: positionPda, claimer: alice.publicKey })
+        .signers([alice])
+        .rpc();
+      const after = await provider.connection.getBalance(marketPda);
+      assert.equal(before - after, LAMPORTS_PER_SOL);
+      assert(rent > 0);
+      assert.equal(await provider.connection.getAccountInfo(positionPda), null);
+      console.log("  ✓ Winnings paid and position rent returned in one call");
+    });
+  });
+
+  describe("Market Registry", () => {
+    it("lists created markets and drops them once cancelled", async () => {
+      const marketPda = await createMarket(
+        program,
+        authority,
+        "reg-" + Date.now().toString(36),
+        ["Yes", "No"]
+      );
+      const [registryPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("market_registry"), authority.publicKey.toBuffer()],
+        program.programId
+      );
+      let registry = await program.account.marketRegistry.fetch(registryPda);
+      assert(registry.authority.equals(authority.publicKey));
+      assert(registry.markets.some((m) => m.equals(marketPda)));
+
+      const remove = () =>
+        program.methods
+          .removeFromRegistry()
+          .accounts({ registry: registryPda, market: marketPda, authority: authority.publicKey })
+          .rpc();
+      try {
+        await remove();
+        assert.fail("Removing a live market should fail");
+      } catch (err) {
+        assert.equal(err.error?.errorCode?.code, "MarketStillActive");
+      }
+
+      await program.methods
+        .cancelMarket()
+        .accounts({ market: marketPda, authority: authority.publicKey })
+        .rpc();
+      await remove();
+      registry = await program.account.marketRegistry.fetch(registryPda);
+      assert(!registry.markets.some((m) => m.equals(marketPda)));
+
+      try {
+        await remove();
+        assert.fail("Removing an unlisted market should fail");
+      } catch (err) {
+        assert.equal(err.error?.errorCode?.code, "MarketNotInRegistry");
+      }
+      console.log("  ✓ Registry tracks the market until it is cancelled");
+    });
+  });
+
+  describe("Dust Sweep", () => {
+    it("sweeps only lamports nobody is owed, once every winner has claimed", async () => {
+      const marketPda = await createMarket(
+        program,
+        authority,
+        "dust-" + Date.now().toString(36),
+        ["Yes", "No"]
+      );
+
+      const winner = Keypair.generate();
+      const treasury = Keypair.generate();
+      await airdrop(provider, winner.publicKey);
+      await buyShares(program, marketPda, winner, 0, 0.5 * LAMPORTS_PER_SOL);
+      await program.methods
+        .resolveMarket(0)
+        .accounts({ market: marketPda, authority: authority.publicKey })
+        .rpc();
+
+      // Stray lamports sent straight to the market belong to no position
+      const stray = 0.01 * LAMPORTS_PER_SOL;
+      await provider.sendAndConfirm(
+        new anchor.web3.Transaction().add(
+          SystemProgram.transfer({
+            fromPubkey: authority.publicKey,
+            toPubkey: marketPda,
+            lamports: stray,
+          })
+        )
+      );
+
+      const sweep = () =>
+        program.methods
+          .sweepDust()
+          .accounts({ market: marketPda, treasury: treasury.publicKey, authority: authority.publicKey })
+          .rpc();
+      try {
+        await sweep();
+        assert.fail("Sweep before the winner claims should fail");
+      } catch (err) {
+        assert.equal(err.error?.errorCode?.code, "ClaimWindowOpen");
+      }
+
+      await program.methods
+        .claimWinnings()
+        .accounts({
+          market: marketPda,
+          position: getPositionPda(program, marketPda, winner.publicKey),
+          claimer: winner.publicKey,
+        })
+        .signers([winner])
+        .rpc();
+      await sweep();
+
+      // The claim fee stays behind for withdraw_fees
+      assert.equal(await provider.connection.getBalance(treasury.publicKey), stray);
+      const market = await program.account.market.fetch(marketPda);
+      assert.equal(market.feeCollected.toNumber(), 0.01 * LAMPORTS_PER_SOL);
+
+      try {
+        await sweep();
+        assert.fail("Nothing left to sweep");
+      } catch (err) {
+        assert.equal(err.error?.errorCode?.code, "NoDustToSweep");
+      }
+      console.log("  ✓ Stray lamports swept, fees and payouts untouched");
+    });
+  });
+
+  describe("Trader Notional Cap", () => {
+    const cap = 0.5 * LAMPORTS_PER_SOL;
+    const cappedMarket = (prefix) =>
+      createMarket(
+        program,
+        authority,
+        prefix + Date.now().toString(36),
+        ["Yes", "No"],
+        { maxTraderNotional: new anchor.BN(cap) }
+      );
+
+    it("accepts buys that reach the cap exactly", async () => {
+      const marketPda = await cappedMarket("capa-");
+      const buyer = Keypair.generate();
+      await airdrop(provider, buyer.publicKey);
+
+      await buyShares(program, marketPda, buyer, 0, 0.3 * LAMPORTS_PER_SOL);
+      await buyShares(program, marketPda, buyer, 1, 0.2 * LAMPORTS_PER_SOL);
+      const position = await program.account.position.fetch(
+        getPositionPda(program, marketPda, buyer.publicKey)
+      );
+      assert.equal(position.staked.toNumber(), cap);
+      console.log("  ✓ Buys across outcomes filled the cap exactly");
+    });
+
+    it("rejects a buy one lamport over the cap", async () => {
+      const marketPda = await cappedMarket("capb-");
+      const buyer = Keypair.generate();
+      await airdrop(provider, buyer.publicKey);
+
+      await buyShares(program, marketPda, buyer, 0, cap);
+      try {
+        await buyShares(program, marketPda, buyer, 0, 1);
+        assert.fail("Buy past the cap should fail");
+      } catch (err) {
+        assert.equal(err.error?.errorCode?.code, "PositionLimitExceeded");
+      }
+      const market = await program.account.market.fetch(marketPda);
+      assert.equal(market.totalPool.toNumber(), cap);
+      console.log("  ✓ Buy one lamport over the cap rejected");
+    });
+  });
+
+  describe("Referral Fees", () => {
+    it("splits the claim fee with the position's referrer", async () => {
+      const marketPda = await createMarket(
+        program,
+        authority,
+        "ref-" + Date.now().toString(36),
+        ["Yes", "No"],
+        { referralFeeBps: new anchor.BN(2500) } // A quarter of the 2% fee
+      );
+      const winner = Keypair.generate();
+      const referrer = Keypair.generate();
+      const other = Keypair.generate();
+      await airdrop(provider, winner.publicKey);
+      await airdrop(provider, referrer.publicKey);
+      const [referralPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("referral"), referrer.publicKey.toBuffer()],
+        program.programId
+      );
+      await program.methods
+        .initReferral()
+        .accounts({ referral: referralPda, referrer: referrer.publicKey, systemProgram: SystemProgram.programId })
+        .signers([referrer])
+        .rpc();
+
+      // Only the first referrer named sticks to the position
+      await buyShares(program, marketPda, winner, 0, 0.25 * LAMPORTS_PER_SOL, referrer.publicKey);
+      await buyShares(program, marketPda, winner, 0, 0.25 * LAMPORTS_PER_SOL, other.publicKey);
+      const positionPda = getPositionPda(program, marketPda, winner.publicKey);
+      const position = await program.account.position.fetch(positionPda);
+      assert(position.referrer.equals(referrer.publicKey));
+      await program.methods
+        .resolveMarket(0)
+        .accounts({ market: marketPda, authority: authority.publicKey })
+        .rpc();
+
+      const claim = (remainingAccounts) =>
+        program.methods
+          .claimWinnings()
+          .accounts({ market: marketPda, position: positionPda, claimer: winner.publicKey })
+          .remainingAccounts(remainingAccounts)
+          .signers([winner])
+          .rpc();
+      try {
+        await claim([]);
+        assert.fail("Claim without the referral account should fail");
+      } catch (err) {
+        assert.equal(err.error?.errorCode?.code, "ReferralAccountMissing");
+      }
+      await claim([{ pubkey: referralPda, isWritable: true, isSigner: false }]);
+
+      const fee = 0.01 * LAMPORTS_PER_SOL;
+      const market = await program.account.market.fetch(marketPda);
+      assert.equal(market.feeCollected.toNumber(), fee * 0.75);
+      const referral = await program.account.referralAccount.fetch(referralPda);
+      assert.equal(referral.unclaimed.toNumber(), fee * 0.25);
+      console.log("  ✓ Referrer credited a quarter of the claim fee");
+    });
+
+    it("rejects a buyer naming themselves as referrer", async () => {
+      const marketPda = await createMarket(
+        program,
+        authority,
+        "refs-" + Date.now().toString(36),
+        ["Yes", "No"]
+      );
+      const buyer = Keypair.generate();
+      await airdrop(provider, buyer.publicKey);
+      try {
+        await buyShares(program, marketPda, buyer, 0, 0.1 * LAMPORTS_PER_SOL, buyer.publicKey);
+        assert.fail("Self-referral should fail");
+      } catch (err) {
+        assert.equal(err.error?.errorCode?.code, "SelfReferral");
+      }
+      console.log("  ✓ Self-referral rejected");
+    });
+  });
+
+  describe("Outcome Weights", () => {
+    const weights = [new anchor.BN(10000), new anchor.BN(5000)]; // Outcome 1 handicapped to half the pool
+
+    it("pays an unweighted winner more per share than a handicapped one for the same stake", async () => {
+      const claimedWhenWins = async (outcome) => {
+        const marketPda = await createMarket(
+          program,
+          authority,
+          "ow" + outcome + "-" + Date.now().toString(36),
+          ["Yes", "No"],
+          { outcomeWeights: weights }
+        );
+        const yes = Keypair.generate();
+        const no = Keypair.generate();
+        await airdrop(provider, yes.publicKey);
+        await airdrop(provider, no.publicKey);
+        await buyShares(program, marketPda, yes, 0, 0.3 * LAMPORTS_PER_SOL);
+        await buyShares(program, marketPda, no, 1, 0.3 * LAMPORTS_PER_SOL);
+        await program.methods
+          .resolveMarket(outcome)
+          .accounts({ market: marketPda, authority: authority.publicKey })
+          .rpc();
+
+        const winner = outcome === 0 ? yes : no;
+        await program.methods
+          .claimWinnings()
+          .accounts({
+            market: marketPda,
+            position: getPositionPda(program, marketPda, winner.publicKey),
+            claimer: winner.publicKey,
+          })
+          .signers([winner])
+          .rpc();
+        const market = await program.account.market.fetch(marketPda);
+        return market.totalClaimed.toNumber();
+      };
+
+      const unweighted = await claimedWhenWins(0);
+      const handicapped = await claimedWhenWins(1);
+      assert.equal(unweighted, 0.6 * LAMPORTS_PER_SOL);
+      assert.equal(handicapped, 0.3 * LAMPORTS_PER_SOL);
+      console.log(`  ✓ Same 0.3 SOL stake paid ${unweighted} at full weight, ${handicapped} at half`);
+    });
+
+    it("rejects weights that don't match the outcomes", async () => {
+      try {
+        await createMarket(
+          program,
+          authority,
+          "owx-" + Date.now().toString(36),
+          ["A", "B", "C"],
+          { outcomeWeights: weights }
+        );
+        assert.fail("Two weights for three outcomes should fail");
+      } catch (err) {
+        assert.equal(err.error?.errorCode?.code, "InvalidOutcomeWeights");
+      }
+      console.log("  ✓ Mismatched weights rejected");
+    });
+  });
+
+
+  describe("Authority Trading", () => {
+    it("blocks the authority's buys only when authority_can_trade is off", async () => {
+      const authorityBuy = (marketPda) =>
+        program.methods
+          .buyShares(0, new anchor.BN(0.1 * LAMPORTS_PER_SOL), null)
+          .accounts({
+            market: marketPda,
+            position: getPositionPda(program, marketPda, authority.publicKey),
+            buyer: authority.publicKey,
+            systemProgram: SystemProgram.programId,
+          })
+          .rpc();
+
+      const open = await createMarket(program, authority, "at1-" + Date.now().toString(36), ["Yes", "No"]);
+      await authorityBuy(open);
+
+      const barred = await createMarket(
+        program,
+        authority,
+        "at0-" + Date.now().toString(36),
+        ["Yes", "No"],
+        { authorityCanTrade: false }
+      );
+      try {
+        await authorityBuy(barred);
+        assert.fail("The authority should not be able to buy");
+      } catch (err) {
+        assert.equal(err.error?.errorCode?.code, "AuthorityCannotTrade");
+      }
+      const buyer = Keypair.generate();
+      await airdrop(provider, buyer.publicKey);
+      await buyShares(program, barred, buyer, 0, 0.1 * LAMPORTS_PER_SOL);
+      const market = await program.account.market.fetch(barred);
+      assert.equal(market.totalPool.toNumber(), 0.1 * LAMPORTS_PER_SOL);
+      console.log("  ✓ Authority buys allowed by default, rejected when barred; others unaffected");
+    });
+  });
+
+
+  describe("Resolution Metadata", () => {
+    it("records the resolution URI and source on the market", async () => {
+      const marketPda = await createMarket(program, authority, "rm-" + Date.now().toString(36), ["Yes", "No"]);
+      const source = Keypair.generate().publicKey;
+      try {
+        await program.methods
+          .resolveMarket(0, "x".repeat(201), source)
+          .accounts({ market: marketPda, authority: authority.publicKey })
+          .rpc();
+        assert.fail("URI over 200 chars should fail");
+      } catch (err) {
+        assert.equal(err.error?.errorCode?.code, "ResolutionUriTooLong");
+      }
+
+      await program.methods
+        .resolveMarket(0, "ar://resolution-proof", source)
+        .accounts({ market: marketPda, authority: authority.publicKey })
+        .rpc();
+      const market = await program.account.market.fetch(marketPda);
+      assert.equal(market.resolutionUri, "ar://resolution-proof");
+      assert(market.resolutionSource.equals(source));
+      console.log("  ✓ Resolution URI and source stored");
+    });
+  });
+
+
+  describe("Commit-Reveal Buys", () => {
+    const { createHash, randomBytes } = require("crypto");
+    const commitmentHash = (outcome, amount, nonce) =>
+      createHash("sha256")
+        .update(Buffer.concat([Buffer.from([outcome]), new anchor.BN(amount).toArrayLike(Buffer, "le", 8), nonce]))
+        .digest();
+
+    it("hides buys until the reveal phase and holds resolution until it ends", async () => {
+      const now = Math.floor(Date.now() / 1000);
+      const marketPda = await createMarket(
+        program,
+        authority,
+        "cr-" + Date.now().toString(36),
+        ["Yes", "No"],
+        { commitPhaseStart: new anchor.BN(now - 60), revealPeriod: new anchor.BN(4), earlyResolution: false },
+        now + 4
+      );
+      const buyer = Keypair.generate();
+      await airdrop(provider, buyer.publicKey);
+      const amount = 0.2 * LAMPORTS_PER_SOL;
+
+      try {
+        await buyShares(program, marketPda, buyer, 0, amount);
+        assert.fail("Open buys are closed during the commit phase");
+      } catch (err) {
+        assert.equal(err.error?.errorCode?.code, "CommitPhaseActive");
+      }
+
+      const [commitmentPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("commitment"), marketPda.toBuffer(), buyer.publicKey.toBuffer()],
+        program.programId
+      );
+      const nonce = randomBytes(32);
+      await program.methods
+        .commitBuy([...commitmentHash(1, amount, nonce)], new anchor.BN(amount))
+        .accounts({
+          market: marketPda,
+          commitment: commitmentPda,
+          position: getPositionPda(program, marketPda, buyer.publicKey),
+          buyer: buyer.publicKey,
+        })
+        .signers([buyer])
+        .rpc();
+      let market = await program.account.market.fetch(marketPda);
+      assert.equal(market.pendingCommitments.toNumber(), amount);
+      assert.equal(market.totalPool.toNumber(), 0);
+
+      const reveal = (outcome, revealNonce) =>
+        program.methods
+          .revealBuy(outcome, [...revealNonce])
+          .accounts({
+            market: marketPda,
+            commitment: commitmentPda,
+            position: getPositionPda(program, marketPda, buyer.publicKey),
+            buyer: buyer.publicKey,
+          })
+          .signers([buyer])
+          .rpc();
+      const resolve = () =>
+        program.methods
+          .resolveMarket(1, null, null)
+          .accounts({ market: marketPda, authority: authority.publicKey })
+          .rpc();
+
+      try {
+        await reveal(1, nonce);
+        assert.fail("Reveals open at resolution time");
+      } catch (err) {
+        assert.equal(err.error?.errorCode?.code, "NotInRevealPhase");
+      }
+
+      await sleep(5000);
+      try {
+        await resolve();
+        assert.fail("Resolution waits for the reveal phase");
+      } catch (err) {
+        assert.equal(err.error?.errorCode?.code, "RevealPhaseActive");
+      }
+      try {
+        await reveal(0, nonce);
+        assert.fail("A different outcome doesn't match the commitment");
+      } catch (err) {
+        assert.equal(err.error?.errorCode?.code, "CommitmentMismatch");
+      }
+      await reveal(1, nonce);
+
+      market = await program.account.market.fetch(marketPda);
+      assert.equal(market.pendingCommitments.toNumber(), 0);
+      assert.equal(market.outcomePools[1].toNumber(), amount);
+      const position = await program.account.position.fetch(getPositionPda(program, marketPda, buyer.publicKey));
+      assert.equal(position.shares[1].toNumber(), amount);
+      assert.equal(await provider.connection.getAccountInfo(commitmentPda), null);
+
+      await sleep(4000);
+      await resolve();
+      console.log("  ✓ Committed buy revealed after trading closed, then the market resolved");
+    });
+
+    it("applies max_trader_notional when the buy is committed", async () => {
+      const now = Math.floor(Date.now() / 1000);
+      const marketPda = await createMarket(
+        program,
+        authority,
+        "crcap-" + Date.now().toString(36),
+        ["Yes", "No"],
+        {
+          commitPhaseStart: new anchor.BN(now - 60),
+          revealPeriod: new anchor.BN(60),
+          earlyResolution: false,
+          maxTraderNotional: new anchor.BN(0.1 * LAMPORTS_PER_SOL),
+        },
+        now + 3600
+      );
+      const buyer = Keypair.generate();
+      await airdrop(provider, buyer.publicKey);
+      const amount = 0.2 * LAMPORTS_PER_SOL;
+      const [commitmentPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("commitment"), marketPda.toBuffer(), buyer.publicKey.toBuffer()],
+        program.programId
+      );
+
+      // Rejected up front rather than accepted and left unrevealable
+      try {
+        await program.methods
+          .commitBuy([...commitmentHash(0, amount, randomBytes(32))], new anchor.BN(amount))
+          .accounts({
+            market: marketPda,
+            commitment: commitmentPda,
+            position: getPositionPda(program, marketPda, buyer.publicKey),
+            buyer: buyer.publicKey,
+          })
+          .signers([buyer])
+          .rpc();
+        assert.fail("Commitment over the notional cap should fail");
+      } catch (err) {
+        assert.equal(err.error?.errorCode?.code, "PositionLimitExceeded");
+      }
+      const market = await program.account.market.fetch(marketPda);
+      assert.equal(market.pendingCommitments.toNumber(), 0);
+      console.log("  ✓ Commitment over max_trader_notional rejected at commit time");
+    });
+
+    it("forfeits a commitment never revealed, for sweep_dust to collect", async () => {
+      const now = Math.floor(Date.now() / 1000);
+      const marketPda = await createMarket(
+        program,
+        authority,
+        "crlost-" + Date.now().toString(36),
+        ["Yes", "No"],
+        { commitPhaseStart: new anchor.BN(now - 60), revealPeriod: new anchor.BN(3), earlyResolution: false },
+        now + 3
+      );
+      const buyer = Keypair.generate();
+      const treasury = Keypair.generate();
+      await airdrop(provider, buyer.publicKey);
+      const amount = 0.2 * LAMPORTS_PER_SOL;
+      const [commitmentPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("commitment"), marketPda.toBuffer(), buyer.publicKey.toBuffer()],
+        program.programId
+      );
+      const positionPda = getPositionPda(program, marketPda, buyer.publicKey);
+      await program.methods
+        .commitBuy([...commitmentHash(0, amount, randomBytes(32))], new anchor.BN(amount))
+        .accounts({ market: marketPda, commitment: commitmentPda, position: positionPda, buyer: buyer.publicKey })
+        .signers([buyer])
+        .rpc();
+
+      const forfeit = () =>
+        program.methods
+          .forfeitCommitment()
+          .accounts({
+            market: marketPda,
+            commitment: commitmentPda,
+            position: positionPda,
+            buyer: buyer.publicKey,
+            authority: authority.publicKey,
+          })
+          .rpc();
+      const sweep = () =>
+        program.methods
+          .sweepDust()
+          .accounts({ market: marketPda, treasury: treasury.publicKey, authority: authority.publicKey })
+          .rpc();
+
+      try {
+        await forfeit();
+        assert.fail("Forfeit waits for resolution");
+      } catch (err) {
+        assert.equal(err.error?.errorCode?.code, "MarketNotResolved");
+      }
+
+      // The buyer lets the reveal phase pass without revealing
+      await sleep(7000);
+      await program.methods
+        .resolveMarket(1, null, null)
+        .accounts({ market: marketPda, authority: authority.publicKey })
+        .rpc();
+      try {
+        await sweep();
+        assert.fail("Pending commitments aren't dust");
+      } catch (err) {
+        assert.equal(err.error?.errorCode?.code, "NoDustToSweep");
+      }
+
+      await forfeit();
+      const market = await program.account.market.fetch(marketPda);
+      assert.equal(market.pendingCommitments.toNumber(), 0);
+      const position = await program.account.position.fetch(positionPda);
+      assert.equal(position.staked.toNumber(), 0);
+      assert.equal(await provider.connection.getAccountInfo(commitmentPda), null);
+
+      await sweep();
+      assert.equal(await provider.connection.getBalance(treasury.publicKey), amount);
+      console.log("  ✓ Unrevealed commitment forfeited after resolution and swept");
+    });
+  });
+
+  describe("Market State", () => {
+    it("get_market_state reports odds, status and time remaining", async () => {
+      const alice = Keypair.generate();
+      const bob = Keypair.generate();
+      await airdrop(provider, alice.publicKey);
+      await airdrop(provider, bob.publicKey);
+      const marketPda = await createMarket(
+        program,
+        authority,
+        "state-" + Date.now().toString(36),
+        ["Yes", "No"],
+        { earlyResolution: true }
+      );
+      const marketState = () =>
+        program.methods.getMarketState().accounts({ market: marketPda }).view();
+
+      let state = await marketState();
+      assert.equal(state.status, 0);
+      assert.deepEqual(state.oddsBps.map(Number), [0, 0]);
+      assert(state.secondsToResolution.toNumber() > 0);
+
+      await buyShares(program, marketPda, alice, 0, 0.25 * LAMPORTS_PER_SOL);
+      await buyShares(program, marketPda, bob, 1, 0.75 * LAMPORTS_PER_SOL);
+      state = await marketState();
+      assert.equal(state.totalPool.toNumber(), LAMPORTS_PER_SOL);
+      assert.deepEqual(state.outcomePools.map(Number), [0.25 * LAMPORTS_PER_SOL, 0.75 * LAMPORTS_PER_SOL]);
+      assert.deepEqual(state.oddsBps.map(Number), [2500, 7500]);
+      assert.equal(state.winningOutcome, null);
+
+      await program.methods
+        .resolveMarket(1, null, null)
+        .accounts({ market: marketPda, authority: authority.publicKey })
+        .rpc();
+      state = await marketState();
+      assert.equal(state.status, 3);
+      assert.equal(state.winningOutcome, 1);
+      console.log("  ✓ 25/75 pool reported as 2500/7500 bps, then as resolved");
+    });
+  });
+
+  describe("Position Transfers", () => {
+    it("transfer_position moves shares of one outcome without touching pools", async () => {
+      const alice = Keypair.generate();
+      const bob = Keypair.generate();
+      await airdrop(provider, alice.publicKey);
+      const marketPda = await createMarket(
+        program,
+        authority,
+        "xfer-" + Date.now().toString(36),
+        ["Yes", "No"]
+      );
+      await buyShares(program, marketPda, alice, 0, 0.5 * LAMPORTS_PER_SOL);
+      const alicePos = getPositionPda(program, marketPda, alice.publicKey);
+      const bobPos = getPositionPda(program, marketPda, bob.publicKey);
+      const transfer = (recipient, outcome, amount) =>
+        program.methods
+          .transferPosition(recipient, outcome, new anchor.BN(amount))
+          .accounts({
+            market: marketPda,
+            source: alicePos,
+            destination: getPositionPda(program, marketPda, recipient),
+            owner: alice.publicKey,
+          })
+          .signers([alice])
+          .rpc();
+
+      await transfer(bob.publicKey, 0, 0.2 * LAMPORTS_PER_SOL);
+      const sender = await program.account.position.fetch(alicePos);
+      const receiver = await program.account.position.fetch(bobPos);
+      assert.equal(sender.shares[0].toNumber(), 0.3 * LAMPORTS_PER_SOL);
+      assert.equal(receiver.shares[0].toNumber(), 0.2 * LAMPORTS_PER_SOL);
+      assert(receiver.owner.equals(bob.publicKey));
+      const market = await program.account.market.fetch(marketPda);
+      assert.equal(market.totalPool.toNumber(), 0.5 * LAMPORTS_PER_SOL);
+
+      try {
+        await transfer(bob.publicKey, 1, 1);
+        assert.fail("Alice holds no shares of outcome 1");
+      } catch (err) {
+        assert.equal(err.error?.errorCode?.code, "InsufficientShares");
+      }
+      console.log("  ✓ 0.2 SOL of outcome 0 shares moved, pool unchanged");
+    });
+  });
+
+  describe("Market Horizon", () => {
+    const MAX_MARKET_DURATION = 5 * 365 * 24 * 60 * 60;
+
+    it("rejects a resolution_time past MAX_MARKET_DURATION", async () => {
+      const now = Math.floor(Date.now() / 1000);
+      try {
+        await createMarket(
+          program,
+          authority,
+          "far-" + Date.now().toString(36),
+          ["Yes", "No"],
+          {},
+          now + MAX_MARKET_DURATION + 600
+        );
+        assert.fail("Resolution time beyond the horizon should fail");
+      } catch (err) {
+        assert.equal(err.error?.errorCode?.code, "ResolutionTimeTooFar");
+      }
+
+      // Just inside the horizon is accepted, but can't be pushed past it
+      const marketPda = await createMarket(
+        program,
+        authority,
+        "edge-" + Date.now().toString(36),
+        ["Yes", "No"],
+        {},
+        now + MAX_MARKET_DURATION - 600
+      );
+      try {
+        await program.methods
+          .updateResolutionTime(new anchor.BN(now + MAX_MARKET_DURATION + 600))
+          .accounts({ market: marketPda, authority: authority.publicKey })
+          .rpc();
+        assert.fail("Extending beyond the horizon should fail");
+      } catch (err) {
+        assert.equal(err.error?.errorCode?.code, "ResolutionTimeTooFar");
+      }
+      console.log("  ✓ Markets bounded to five years out, at creation and on extension");
+    });
+  });
+
+  describe("Claim Fee Rounding", () => {
+    // 1,234,567 lamports at 200 bps is a fee of 24,691.34 lamports
+    const stake = 1_234_567;
+
+    for (const [feeRoundUp, expectedFee] of [[false, 24_691], [true, 24_692]]) {
+      it(`rounds an uneven claim fee ${feeRoundUp ? "up" : "down"} with feeRoundUp = ${feeRoundUp}`, async () => {
+        const alice = Keypair.generate();
+        await airdrop(provider, alice.publicKey);
+        const marketPda = await createMarket(
+          program,
+          authority,
+          "round-" + Date.now().toString(36),
+          ["Yes", "No"],
+          { feeRoundUp }
+        );
+        await buyShares(program, marketPda, alice, 0, stake);
+        await program.methods
+          .resolveMarket(0, null, null)
+          .accounts({ market: marketPda, authority: authority.publicKey })
+          .rpc();
+
+        const positionPda = getPositionPda(program, marketPda, alice.publicKey);
+        const estimate = await program.methods
+          .estimateWinnings()
+          .accounts({ market: marketPda, position: positionPda })
+          .view();
+        assert.equal(estimate.fee.toNumber(), expectedFee);
+        assert.equal(estimate.netPayout.toNumber(), stake - expectedFee);
+
+        await program.methods
+          .claimWinnings()
+          .accounts({ market: marketPda, position: positionPda, claimer: alice.publicKey })
+          .signers([alice])
+          .rpc();
+        const market = await program.account.market.fetch(marketPda);
+        assert.equal(market.feeRoundUp, feeRoundUp);
+        assert.equal(market.feeCollected.toNumber(), expectedFee);
+        console.log(`  ✓ Fee of ${expectedFee} lamports on a ${stake} lamport payout`);
+      });
+    }
   });
 });